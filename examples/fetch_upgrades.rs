@@ -1,8 +1,5 @@
 use anyhow::Context;
-use apt_cmd::{
-    fetch::{EventKind, FetcherExt},
-    AptGet,
-};
+use apt_cmd::{fetch::EventKind, AptGet, PackageFetcher};
 
 use std::{path::Path, sync::Arc};
 use tokio_stream::wrappers::ReceiverStream;
@@ -19,10 +16,10 @@ async fn main() -> anyhow::Result<()> {
         tokio::fs::create_dir_all(path).await.unwrap();
     }
 
-    let (fetcher, mut events) = async_fetcher::Fetcher::default()
-        .connections_per_file(4)
-        .into_package_fetcher()
+    let (fetcher, mut events) = PackageFetcher::builder()
+        .fetcher(async_fetcher::Fetcher::default().connections_per_file(4))
         .concurrent(CONCURRENT_FETCHES)
+        .build()
         .fetch(packages, Arc::from(path));
 
     // Fetch a list of packages that need to be fetched, and send them on their way
@@ -56,7 +53,13 @@ async fn main() -> anyhow::Result<()> {
     };
 
     let fetcher = async move {
-        fetcher.await;
+        let metrics = fetcher.await;
+        eprintln!(
+            "fetched {} packages ({} bytes) in {:?}",
+            metrics.fetched.len(),
+            metrics.bytes(),
+            metrics.elapsed
+        );
         Ok(())
     };
 
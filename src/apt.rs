@@ -1,18 +1,22 @@
 // Copyright 2021-2022 System76 <info@system76.com>
 // SPDX-License-Identifier: MPL-2.0
 
+use crate::utils::{LineReader, DEFAULT_BUFFER_CAPACITY};
+use crate::{PackageChange, PackageStatus, UnmetDependency};
 use anyhow::Context;
+use as_result::MapResult;
 use futures::stream::{Stream, StreamExt};
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
 use std::pin::Pin;
 use std::process::Stdio;
-use tokio::io::{AsyncBufReadExt, BufReader};
 use tokio::process::{Child, Command};
-use tokio_stream::wrappers::LinesStream;
 
 pub type Packages = Pin<Box<dyn Stream<Item = String> + Send>>;
 
+pub type SystemChanges = Pin<Box<dyn Stream<Item = SystemChange> + Send>>;
+
 /// It is orphaned if the only source is `/var/lib/dpkg/status`.
 fn is_orphaned_version(sources: &[String]) -> bool {
     sources.len() == 1 && sources[0].contains("/var/lib/dpkg/status")
@@ -106,10 +110,16 @@ pub async fn remoteless_packages() -> anyhow::Result<Vec<String>> {
     Ok(packages)
 }
 
+/// `apt` with these arguments, whose output is not localized, so that it may be parsed.
+pub(crate) fn apt_command(args: &[&str]) -> Command {
+    let mut command = crate::utils::command("apt");
+    command.args(args);
+    command
+}
+
 /// Fetch all upgradeable debian packages from system apt repositories.
 pub async fn upgradable_packages() -> anyhow::Result<(Child, Packages)> {
-    let mut child = Command::new("apt")
-        .args(["list", "--upgradable"])
+    let mut child = apt_command(&["list", "--upgradable"])
         .stdout(Stdio::piped())
         .stderr(Stdio::null())
         .spawn()
@@ -118,9 +128,10 @@ pub async fn upgradable_packages() -> anyhow::Result<(Child, Packages)> {
     let stdout = child.stdout.take().unwrap();
 
     let stream = Box::pin(async_stream::stream! {
-        let mut lines = LinesStream::new(BufReader::new(stdout).lines()).skip(1);
+        let lines = crate::utils::lossy_lines(stdout).skip(1);
+        futures::pin_mut!(lines);
 
-        while let Some(Ok(line)) = lines.next().await {
+        while let Some(line) = lines.next().await {
             if let Some(package) = line.split('/').next() {
                 yield package.into();
             }
@@ -132,8 +143,7 @@ pub async fn upgradable_packages() -> anyhow::Result<(Child, Packages)> {
 
 /// Fetch debian packages which are necessary security updates, only.
 pub async fn security_updates() -> anyhow::Result<(Child, Packages)> {
-    let mut child = Command::new("apt")
-        .args(["-s", "dist-upgrade"])
+    let mut child = apt_command(&["-s", "dist-upgrade"])
         .stdout(Stdio::piped())
         .stderr(Stdio::null())
         .spawn()
@@ -145,9 +155,10 @@ pub async fn security_updates() -> anyhow::Result<(Child, Packages)> {
         .context("`apt` didn't have stdout pipe")?;
 
     let stream = Box::pin(async_stream::stream! {
-        let mut lines = LinesStream::new(BufReader::new(stdout).lines()).skip(1);
+        let lines = crate::utils::lossy_lines(stdout).skip(1);
+        futures::pin_mut!(lines);
 
-        while let Some(Ok(line)) = lines.next().await {
+        while let Some(line) = lines.next().await {
             if let Some(package) = parse_security_update(&line) {
                 yield package.into()
             }
@@ -157,7 +168,1042 @@ pub async fn security_updates() -> anyhow::Result<(Child, Packages)> {
     Ok((child, stream))
 }
 
-fn parse_security_update(simulated_line: &str) -> Option<&str> {
+/// Selects upgradable packages by where their new version comes from.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum OriginFilter {
+    /// Suites ending with this pocket, such as `security` for `jammy-security`.
+    Pocket(String),
+    /// This exact suite, such as `jammy-updates`.
+    Suite(String),
+    /// Repositories whose URI begins with this, such as
+    /// `https://ppa.launchpadcontent.net/mozillateam/ppa`.
+    Uri(String),
+}
+
+impl OriginFilter {
+    pub fn matches(&self, package: &UpgradablePackage) -> bool {
+        match self {
+            OriginFilter::Pocket(pocket) => package.suites.iter().any(|suite| {
+                suite
+                    .rsplit_once('-')
+                    .is_some_and(|(_, suffix)| suffix == pocket)
+            }),
+            OriginFilter::Suite(name) => package.suites.iter().any(|suite| suite == name),
+            OriginFilter::Uri(uri) => package
+                .origins
+                .iter()
+                .any(|origin| origin.starts_with(uri.as_str())),
+        }
+    }
+}
+
+/// A package which can be upgraded, from `apt list --upgradable`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct UpgradablePackage {
+    pub package: String,
+    pub version: String,
+    pub architecture: String,
+    /// The installed version which would be upgraded.
+    pub installed: String,
+    /// The suites which provide the new version, such as `jammy-security`.
+    pub suites: Vec<String>,
+    /// The repositories which provide the new version, as with [`PackageProfile::origins`].
+    pub origins: Vec<String>,
+}
+
+/// Upgradable packages whose new version comes from any of the `origins`, or all
+/// upgradable packages if `origins` is empty.
+pub async fn upgradable_from(origins: &[OriginFilter]) -> anyhow::Result<Vec<UpgradablePackage>> {
+    let output = apt_command(&["list", "--upgradable"])
+        .stderr(Stdio::null())
+        .output()
+        .await
+        .context("failed to launch `apt list --upgradable`")?;
+
+    let mut packages: Vec<UpgradablePackage> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(parse_upgradable)
+        .collect();
+
+    if packages.is_empty() {
+        return Ok(packages);
+    }
+
+    let names: Vec<&str> = packages.iter().map(|p| p.package.as_str()).collect();
+    let (mut child, policies) = crate::AptCache::new().policy(&names).await?;
+    let policies: Vec<crate::Policy> = policies.collect().await;
+
+    let _ = child
+        .wait()
+        .await
+        .context("`apt-cache policy` exited in error")?;
+
+    for package in &mut packages {
+        if let Some(policy) = policies.iter().find(|p| p.package == package.package) {
+            package.origins = version_origins(&policy.version_table, &package.version);
+        }
+    }
+
+    packages.retain(|package| {
+        origins.is_empty() || origins.iter().any(|origin| origin.matches(package))
+    });
+
+    Ok(packages)
+}
+
+/// Parses a line such as
+/// `firefox/jammy-updates,jammy-security 120.0 amd64 [upgradable from: 119.0]`.
+fn parse_upgradable(line: &str) -> Option<UpgradablePackage> {
+    let (package, rest) = line.split_once('/')?;
+    let mut fields = rest.split_ascii_whitespace();
+
+    let suites = fields.next()?.split(',').map(String::from).collect();
+    let version = fields.next()?.to_owned();
+    let architecture = fields.next()?.to_owned();
+
+    let installed = rest
+        .split_once("[upgradable from: ")?
+        .1
+        .trim_end_matches(']')
+        .to_owned();
+
+    Some(UpgradablePackage {
+        package: package.to_owned(),
+        version,
+        architecture,
+        installed,
+        suites,
+        origins: Vec::new(),
+    })
+}
+
+/// Packages which would be removed by `apt-get autoremove --purge`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct AutoremovePlan {
+    /// Kernel images, headers, and modules.
+    pub kernel: Vec<String>,
+    /// All other packages.
+    pub other: Vec<String>,
+}
+
+/// Simulates `apt-get autoremove --purge`, separating kernel packages from the rest.
+pub async fn autoremovable_packages() -> anyhow::Result<AutoremovePlan> {
+    let (mut child, stdout) = crate::AptGet::new()
+        .noninteractive()
+        .simulate()
+        .purge()
+        .autoremove()
+        .spawn_with_stdout()
+        .await
+        .context("failed to launch `apt-get -s autoremove --purge`")?;
+
+    let mut lines = LineReader::with_capacity(DEFAULT_BUFFER_CAPACITY, stdout);
+    let mut plan = AutoremovePlan::default();
+
+    while let Some(line) = lines.next_line().await {
+        if let Some(package) = parse_simulated_removal(line) {
+            if is_kernel_package(package) {
+                plan.kernel.push(package.to_owned());
+            } else {
+                plan.other.push(package.to_owned());
+            }
+        }
+    }
+
+    let _ = child
+        .wait()
+        .await
+        .context("`apt-get -s autoremove` exited in error")?;
+
+    Ok(plan)
+}
+
+pub(crate) fn parse_simulated_removal(simulated_line: &str) -> Option<&str> {
+    if simulated_line.starts_with("Purg ") || simulated_line.starts_with("Remv ") {
+        simulated_line.split_ascii_whitespace().nth(1)
+    } else {
+        None
+    }
+}
+
+/// Whether the package belongs to a kernel, such as `linux-image-6.2.6-76060206-generic`.
+fn is_kernel_package(package: &str) -> bool {
+    const PREFIXES: &[&str] = &[
+        "linux-buildinfo-",
+        "linux-cloud-tools-",
+        "linux-headers-",
+        "linux-hwe-",
+        "linux-image-",
+        "linux-modules-",
+        "linux-objects-",
+        "linux-signatures-",
+        "linux-tools-",
+    ];
+
+    let name = package.split(':').next().unwrap_or(package);
+    PREFIXES.iter().any(|prefix| name.starts_with(prefix))
+}
+
+/// How [`pin_to_installed`] keeps a package at its installed version.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PinStrategy {
+    /// Write a `Pin-Priority: 1001` entry to `/etc/apt/preferences.d`.
+    Preferences,
+
+    /// Mark the package as held with `apt-mark hold`.
+    Hold,
+}
+
+const PREFERENCES_DIR: &str = "/etc/apt/preferences.d";
+
+/// The main preferences file, read before those of `PREFERENCES_DIR`.
+const PREFERENCES_FILE: &str = "/etc/apt/preferences";
+
+/// Prevents a package from being upgraded beyond its installed version.
+///
+/// The package may be qualified with an architecture, but not a version or release.
+pub async fn pin_to_installed(package: &str, strategy: PinStrategy) -> anyhow::Result<()> {
+    validate_pin_package(package)?;
+
+    match strategy {
+        PinStrategy::Hold => crate::AptMark::new()
+            .hold([package])
+            .await
+            .with_context(|| format!("failed to hold {}", package)),
+
+        PinStrategy::Preferences => {
+            let version = installed_version(package)
+                .await?
+                .with_context(|| format!("{} is not installed", package))?;
+
+            tokio::fs::write(pin_path(package), pin_preferences(package, &version))
+                .await
+                .with_context(|| format!("failed to write apt preferences for {}", package))
+        }
+    }
+}
+
+/// Reverts [`pin_to_installed`] for a package, regardless of the strategy used.
+pub async fn unpin(package: &str) -> anyhow::Result<()> {
+    validate_pin_package(package)?;
+
+    match tokio::fs::remove_file(pin_path(package)).await {
+        Err(why) if why.kind() != std::io::ErrorKind::NotFound => {
+            return Err(why)
+                .with_context(|| format!("failed to remove apt preferences for {}", package));
+        }
+        _ => (),
+    }
+
+    crate::AptMark::new()
+        .unhold([package])
+        .await
+        .with_context(|| format!("failed to unhold {}", package))
+}
+
+async fn installed_version(package: &str) -> anyhow::Result<Option<String>> {
+    let (mut child, mut stream) = crate::AptCache::new().policy(&[package]).await?;
+
+    let mut version = None;
+    while let Some(policy) = stream.next().await {
+        if policy.package == package && policy.installed != "(none)" {
+            version = Some(policy.installed);
+        }
+    }
+
+    let _ = child
+        .wait()
+        .await
+        .context("`apt-cache policy` exited in error")?;
+
+    Ok(version)
+}
+
+/// Preference file names may only contain alphanumerics, `-`, `_`, and `.`, and must end
+/// in `.pref` if they contain a `.`. Any other character is escaped as `_` and its hex
+/// code, as is `_` itself, so that each package has a distinct file.
+/// Validates a package to be written to a preferences file, which names the package
+/// alone, so a version or release is refused.
+fn validate_pin_package(package: &str) -> Result<(), crate::InvalidPackageName> {
+    crate::validate_package_name(package)?;
+
+    if package.contains(['=', '/']) {
+        return Err(crate::InvalidPackageName(package.to_owned()));
+    }
+
+    Ok(())
+}
+
+fn pin_path(package: &str) -> std::path::PathBuf {
+    use std::fmt::Write;
+
+    let mut name = String::with_capacity(package.len());
+    for byte in package.bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'.' => name.push(byte as char),
+            _ => {
+                let _ = write!(name, "_{:02x}", byte);
+            }
+        }
+    }
+
+    std::path::Path::new(PREFERENCES_DIR).join(format!("apt-cmd-{}.pref", name))
+}
+
+fn pin_preferences(package: &str, version: &str) -> String {
+    format!(
+        "Package: {}\nPin: version {}\nPin-Priority: 1001\n",
+        package, version
+    )
+}
+
+/// The changes which a simulated upgrade or install would make.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SimulationPlan {
+    /// Packages which would replace an installed version.
+    pub upgrades: Vec<PackageChange>,
+    /// Packages which would be newly installed.
+    pub installs: Vec<PackageChange>,
+    /// Packages which would be removed, with their installed version.
+    pub removals: Vec<PackageChange>,
+    /// Upgradable packages which would not be upgraded, such as those which are held.
+    pub kept_back: Vec<String>,
+    /// Packages which are recommended, but would not be installed, as with
+    /// `AptGet::no_install_recommends`.
+    pub recommended: Vec<String>,
+    /// Packages which are suggested, but would not be installed, unless with
+    /// `AptGet::install_suggests`.
+    pub suggested: Vec<String>,
+}
+
+/// A list of packages which spans the indented lines following its heading.
+#[derive(Clone, Copy)]
+enum PlanSection {
+    KeptBack,
+    Recommended,
+    Suggested,
+}
+
+impl SimulationPlan {
+    /// Parses the output of `apt-get -s`, as from `AptGet::simulate_install`.
+    pub fn parse(output: &str) -> Self {
+        let mut plan = SimulationPlan::default();
+        let mut section = None;
+
+        for line in output.lines() {
+            if let Some(current) = section {
+                if line.starts_with(' ') {
+                    let packages = match current {
+                        PlanSection::KeptBack => &mut plan.kept_back,
+                        PlanSection::Recommended => &mut plan.recommended,
+                        PlanSection::Suggested => &mut plan.suggested,
+                    };
+
+                    // Alternatives are listed as `foo | bar`.
+                    packages.extend(
+                        line.split_ascii_whitespace()
+                            .filter(|package| *package != "|")
+                            .map(String::from),
+                    );
+                    continue;
+                }
+
+                section = None;
+            }
+
+            if line.starts_with("The following packages have been kept back") {
+                section = Some(PlanSection::KeptBack);
+            } else if line.starts_with("Recommended packages:") {
+                section = Some(PlanSection::Recommended);
+            } else if line.starts_with("Suggested packages:") {
+                section = Some(PlanSection::Suggested);
+            } else if let Some(rest) = line.strip_prefix("Inst ") {
+                let (package, previous, version) = match parse_simulated_inst(rest) {
+                    Some(change) => change,
+                    None => continue,
+                };
+
+                let change = PackageChange {
+                    package: package.into(),
+                    version: version.into(),
+                    previous: previous.map(String::from),
+                };
+
+                if change.previous.is_some() {
+                    plan.upgrades.push(change);
+                } else {
+                    plan.installs.push(change);
+                }
+            } else if let Some(package) = parse_simulated_removal(line) {
+                let version = line
+                    .split_once(" [")
+                    .and_then(|(_, version)| version.split(']').next())
+                    .unwrap_or_default();
+
+                plan.removals.push(PackageChange {
+                    package: package.into(),
+                    version: version.into(),
+                    previous: None,
+                });
+            }
+        }
+
+        plan
+    }
+}
+
+/// The package, installed version, and new version of an `Inst` line, such as
+/// `Inst firefox [119.0] (120.0 Pop:22.04/jammy [amd64])`.
+fn parse_simulated_inst(line: &str) -> Option<(&str, Option<&str>, &str)> {
+    let (package, rest) = line.split_once(' ')?;
+
+    let (previous, rest) = match rest.strip_prefix('[') {
+        Some(rest) => {
+            let (previous, rest) = rest.split_once("] ")?;
+            (Some(previous), rest)
+        }
+        None => (None, rest),
+    };
+
+    let version = rest.strip_prefix('(')?.split_ascii_whitespace().next()?;
+
+    Some((package, previous, version))
+}
+
+/// Simulates `apt-get full-upgrade` without upgrading the `exclude` packages.
+///
+/// The excluded packages are pinned to their installed versions for the simulation
+/// only, by preferences passed to apt, so that the system is not changed.
+pub async fn plan_upgrade(exclude: &[&str]) -> anyhow::Result<SimulationPlan> {
+    let dir = crate::utils::TempDir::new("apt-cmd-plan-upgrade")
+        .context("failed to create a directory for the simulation's preferences")?;
+
+    // The pins are added to those of the system's main preferences file, which the
+    // simulation reads in place of it.
+    let mut preferences = match tokio::fs::read_to_string(PREFERENCES_FILE).await {
+        Ok(preferences) => preferences + "\n",
+        Err(why) if why.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(why) => return Err(why).context("failed to read apt preferences"),
+    };
+
+    for package in exclude {
+        crate::validate_package_name(package)?;
+
+        if let Some(version) = installed_version(package).await? {
+            preferences.push_str(&pin_preferences(package, &version));
+            preferences.push('\n');
+        }
+    }
+
+    let path = dir.path().join("preferences");
+    tokio::fs::write(&path, preferences)
+        .await
+        .context("failed to write the simulation's preferences")?;
+
+    let mut apt_get = crate::AptGet::new().noninteractive().simulate();
+    apt_get.args(["-o", &format!("Dir::Etc::Preferences={}", path.display())]);
+    apt_get.arg("full-upgrade");
+
+    let output = apt_get
+        .output()
+        .await
+        .context("failed to launch `apt-get -s full-upgrade`")?;
+
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "`apt-get -s full-upgrade` exited with {}",
+            output.status
+        ));
+    }
+
+    Ok(SimulationPlan::parse(&String::from_utf8_lossy(
+        &output.stdout,
+    )))
+}
+
+/// Everything a package details page shows about a package.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct PackageProfile {
+    pub package: String,
+    /// The status of the package in the dpkg database, if it has one.
+    pub status: Option<PackageStatus>,
+    pub installed: Option<String>,
+    pub candidate: Option<String>,
+    /// The sources which provide the installed version, or else the candidate, such as
+    /// `http://apt.pop-os.org/release jammy/main amd64 Packages`.
+    pub origins: Vec<String>,
+    /// The number of packages which depend on this package.
+    pub reverse_dependencies: usize,
+    pub auto_installed: bool,
+    pub held: bool,
+}
+
+/// Gathers the dpkg status, policy, origins, reverse dependencies, and marks of a package.
+pub async fn package_profile(package: &str) -> anyhow::Result<PackageProfile> {
+    crate::validate_package_name(package)?;
+
+    let policy = async {
+        let (mut child, mut policies) = crate::AptCache::new().policy(&[package]).await?;
+        let policy = policies.next().await.unwrap_or_default();
+        let _ = child.wait().await;
+        Ok::<_, anyhow::Error>(policy)
+    };
+
+    let status = async {
+        let (mut child, mut statuses) = crate::DpkgQuery::new()
+            .show_status(&[package])
+            .await
+            .context("failed to launch `dpkg-query`")?;
+
+        let mut status = None;
+        while let Some(result) = statuses.next().await {
+            if let Ok((name, package_status)) = result {
+                if name == package {
+                    status = Some(package_status);
+                }
+            }
+        }
+
+        // dpkg-query exits in error for packages it does not know.
+        let _ = child.wait().await;
+        Ok(status)
+    };
+
+    let reverse_dependencies = async {
+        let (mut child, dependents) = crate::AptCache::new()
+            .rdepends(&[package])
+            .await
+            .context("failed to launch `apt-cache rdepends`")?;
+
+        let dependents: HashSet<String> = dependents.collect().await;
+        let _ = child.wait().await;
+        Ok(dependents.len())
+    };
+
+    let marked = |mark: &'static str| async move {
+        let packages =
+            crate::apt_mark::scrape_packages(crate::AptMark::new().args([mark, "--", package]))
+                .await?;
+
+        Ok::<_, anyhow::Error>(packages.iter().any(|name| name == package))
+    };
+
+    let (policy, status, reverse_dependencies, auto_installed, held) = futures::try_join!(
+        policy,
+        status,
+        reverse_dependencies,
+        marked("showauto"),
+        marked("showhold")
+    )?;
+
+    if policy.package.is_empty() {
+        return Err(anyhow::anyhow!("package not found: {}", package));
+    }
+
+    let version = |version: &str| Some(version.to_owned()).filter(|version| version != "(none)");
+    let installed = version(&policy.installed);
+    let candidate = version(&policy.candidate);
+
+    let origins = installed
+        .as_deref()
+        .or(candidate.as_deref())
+        .map(|version| version_origins(&policy.version_table, version))
+        .unwrap_or_default();
+
+    Ok(PackageProfile {
+        package: policy.package,
+        status,
+        installed,
+        candidate,
+        origins,
+        reverse_dependencies,
+        auto_installed,
+        held,
+    })
+}
+
+/// The repositories of a version in the version table, without their priorities.
+fn version_origins(version_table: &HashMap<String, Vec<String>>, version: &str) -> Vec<String> {
+    version_table
+        .iter()
+        .filter(|(entry, _)| entry.split_ascii_whitespace().next() == Some(version))
+        .flat_map(|(_, sources)| sources)
+        .filter(|source| !source.contains("/var/lib/dpkg/status"))
+        .map(|source| match source.split_once(' ') {
+            Some((priority, origin)) if priority.parse::<i32>().is_ok() => origin.to_owned(),
+            _ => source.clone(),
+        })
+        .collect()
+}
+
+/// The number of levels of reverse dependencies which `why` walks before giving up.
+const WHY_MAX_DEPTH: usize = 16;
+
+/// Explains why a package is installed, with the chains of installed packages which
+/// depend or recommend it, from each of the nearest manually-installed packages.
+///
+/// Each chain begins with a manually-installed package, and ends with `package`. If
+/// `package` was installed manually, the only chain is `package` itself. If nothing
+/// installed requires it, there are no chains, and it is safe to remove.
+pub async fn why(package: &str) -> anyhow::Result<Vec<Vec<String>>> {
+    crate::validate_package_name(package)?;
+
+    let manual: HashSet<String> = crate::AptMark::manually_installed()
+        .await?
+        .into_iter()
+        .collect();
+
+    let mut walk = WhyWalk::new(package, &manual);
+
+    for _ in 0..WHY_MAX_DEPTH {
+        if walk.is_done() {
+            break;
+        }
+
+        let (mut child, stream) = crate::AptCache::new()
+            .installed()
+            .installing_relations()
+            .rdepends_tagged(walk.frontier())
+            .await
+            .context("failed to launch `apt-cache rdepends`")?;
+
+        let dependencies = stream.collect::<Vec<_>>().await;
+
+        let _ = child
+            .wait()
+            .await
+            .context("`apt-cache rdepends` exited in error")?;
+
+        walk.advance(dependencies, &manual);
+    }
+
+    Ok(walk.chains())
+}
+
+/// A breadth-first walk of reverse dependencies, one level at a time.
+struct WhyWalk {
+    /// The package which each visited package was reached from.
+    parents: HashMap<String, String>,
+    frontier: Vec<String>,
+    roots: Vec<String>,
+}
+
+impl WhyWalk {
+    fn new(package: &str, manual: &HashSet<String>) -> Self {
+        let mut walk = WhyWalk {
+            parents: HashMap::new(),
+            frontier: Vec::new(),
+            roots: Vec::new(),
+        };
+
+        walk.parents.insert(package.to_owned(), String::new());
+
+        if manual.contains(package) {
+            walk.roots.push(package.to_owned());
+        } else {
+            walk.frontier.push(package.to_owned());
+        }
+
+        walk
+    }
+
+    fn frontier(&self) -> &[String] {
+        &self.frontier
+    }
+
+    /// Whether the nearest roots were found, or there is nothing left to walk.
+    fn is_done(&self) -> bool {
+        !self.roots.is_empty() || self.frontier.is_empty()
+    }
+
+    fn advance(&mut self, dependencies: Vec<crate::ReverseDependency>, manual: &HashSet<String>) {
+        let mut frontier = Vec::new();
+
+        for dependency in dependencies {
+            // Multiarch dependents may be listed by their architecture-qualified name.
+            let dependent = match dependency.dependent.split_once(':') {
+                Some((name, _)) if manual.contains(name) => name.to_owned(),
+                _ => dependency.dependent,
+            };
+
+            if self.parents.contains_key(&dependent) {
+                continue;
+            }
+
+            self.parents.insert(dependent.clone(), dependency.package);
+
+            if manual.contains(&dependent) {
+                self.roots.push(dependent);
+            } else {
+                frontier.push(dependent);
+            }
+        }
+
+        self.frontier = frontier;
+    }
+
+    fn chains(&self) -> Vec<Vec<String>> {
+        self.roots
+            .iter()
+            .map(|root| {
+                let mut chain = vec![root.clone()];
+                let mut current = root;
+
+                while let Some(parent) = self.parents.get(current).filter(|p| !p.is_empty()) {
+                    chain.push(parent.clone());
+                    current = parent;
+                }
+
+                chain
+            })
+            .collect()
+    }
+}
+
+/// Explains why a package cannot be installed, by simulating its installation.
+///
+/// Returns the unmet dependencies which apt reported, or nothing if the package can be
+/// installed.
+pub async fn why_not(package: &str) -> anyhow::Result<Vec<UnmetDependency>> {
+    crate::validate_package_name(package)?;
+
+    let mut apt_get = crate::AptGet::new().noninteractive().simulate();
+    apt_get.args(["install", "--", package]);
+
+    let output = apt_get
+        .output()
+        .await
+        .context("failed to launch `apt-get -s install`")?;
+
+    if output.status.success() {
+        return Ok(Vec::new());
+    }
+
+    let unmet = crate::depends::parse_unmet_dependencies(&String::from_utf8_lossy(&output.stdout));
+
+    if unmet.is_empty() {
+        return Err(anyhow::anyhow!(
+            "`apt-get -s install` exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(unmet)
+}
+
+/// Updates the indexes of a single repository, without fetching those of the others,
+/// and returns the `(package, version)` of each package it provides, sorted.
+///
+/// `source_entry` is a one-line entry, such as
+/// `deb http://ppa.launchpadcontent.net/foo/bar/ubuntu jammy main`, or a deb822 stanza.
+/// This answers whether a package is available from a newly-added repository far
+/// sooner than a full `apt-get update`.
+pub async fn update_repo(source_entry: &str) -> anyhow::Result<Vec<(String, String)>> {
+    let source_entry = source_entry.trim();
+    let deb822 = source_entry.contains("URIs:");
+
+    if !deb822 && (source_entry.contains('\n') || !source_entry.starts_with("deb")) {
+        return Err(anyhow::anyhow!("invalid source entry: {:?}", source_entry));
+    }
+
+    // Written into a private directory, so that no other user may replace the entry
+    // before apt reads it, nor have it written through a link of theirs.
+    let dir = crate::utils::TempDir::new("apt-cmd-update-repo")
+        .context("failed to create temporary sources directory")?;
+    let path = dir
+        .path()
+        .join(if deb822 { "repo.sources" } else { "repo.list" });
+
+    tokio::fs::write(&path, [source_entry, "\n"].concat())
+        .await
+        .with_context(|| format!("failed to write {}", path.display()))?;
+
+    async {
+        crate::AptGet::new()
+            .noninteractive()
+            .scoped_sources(&path)
+            .update()
+            .await
+            .context("failed to update the repository")?;
+
+        let (mut child, records) = crate::AptCache::new()
+            .scoped_sources(&path)
+            .dumpavail()
+            .await
+            .context("failed to launch `apt-cache dumpavail`")?;
+
+        // Records without a filename are of installed packages, rather than the repository.
+        let mut packages: Vec<(String, String)> = records
+            .filter(|record| futures::future::ready(record.filename.is_some()))
+            .map(|record| (record.package, record.version))
+            .collect()
+            .await;
+
+        child
+            .wait()
+            .await
+            .map_result()
+            .context("bad status from `apt-cache dumpavail`")?;
+
+        packages.sort_unstable();
+        packages.dedup();
+        Ok(packages)
+    }
+    .await
+}
+
+/// The build dependencies of a source package, from the contents of its `debian/control`
+/// or `.dsc` file, as relations for `AptGet::satisfy`.
+///
+/// Build conflicts are included as `Conflicts:` relations.
+pub fn build_dependencies(contents: &str) -> Vec<String> {
+    // The source stanza is the first, followed by those of each binary package.
+    let stanza = match crate::deb822::parse(contents).next() {
+        Some(stanza) => stanza,
+        None => return Vec::new(),
+    };
+
+    let mut dependencies = Vec::new();
+
+    for field in &["Build-Depends", "Build-Depends-Arch", "Build-Depends-Indep"] {
+        dependencies.extend(stanza.get_relations(field));
+    }
+
+    for field in &[
+        "Build-Conflicts",
+        "Build-Conflicts-Arch",
+        "Build-Conflicts-Indep",
+    ] {
+        dependencies.extend(
+            stanza
+                .get_relations(field)
+                .into_iter()
+                .map(|relation| ["Conflicts: ", &relation].concat()),
+        );
+    }
+
+    dependencies
+}
+
+/// Installs the build dependencies of the source package described by the
+/// `debian/control` or `.dsc` file at `path`, such as to provision a CI environment.
+pub async fn install_build_dependencies(path: &std::path::Path) -> anyhow::Result<()> {
+    let contents = tokio::fs::read_to_string(path)
+        .await
+        .with_context(|| format!("failed to read {}", path.display()))?;
+
+    let dependencies = build_dependencies(&contents);
+
+    if dependencies.is_empty() {
+        return Ok(());
+    }
+
+    crate::AptGet::new()
+        .noninteractive()
+        .force()
+        .satisfy(&dependencies)
+        .await
+        .context("failed to satisfy build dependencies")
+}
+
+/// The disk space used by an installed package.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PackageDiskUsage {
+    pub package: String,
+    pub architecture: String,
+    /// The `Installed-Size` recorded by the package, in bytes.
+    pub installed_size: u64,
+    /// The sum of the sizes of the package's files on disk, in bytes, if measured.
+    pub measured_size: Option<u64>,
+}
+
+impl PackageDiskUsage {
+    /// The measured size, if measured, or else the recorded size.
+    pub fn size(&self) -> u64 {
+        self.measured_size.unwrap_or(self.installed_size)
+    }
+}
+
+/// Sorts by size, with the largest packages first.
+pub fn sort_largest_first(usage: &mut [PackageDiskUsage]) {
+    usage.sort_by(|a, b| {
+        b.size()
+            .cmp(&a.size())
+            .then_with(|| a.package.cmp(&b.package))
+    });
+}
+
+/// The disk space used by each installed package, sorted by name, such as for a view of
+/// the largest packages to remove.
+///
+/// Sizes are from the `Installed-Size` which each package records, which is estimated
+/// when it is built. If `measure` is set, the files listed by dpkg for each package are
+/// also measured, which is accurate, but reads the metadata of every installed file.
+pub async fn disk_usage_by_package(measure: bool) -> anyhow::Result<Vec<PackageDiskUsage>> {
+    let admindir = std::path::Path::new(crate::dpkg::status::ADMINDIR);
+    let stanzas = crate::dpkg::status::read(admindir)
+        .await
+        .context("failed to read the dpkg status database")?;
+
+    let mut usage: Vec<PackageDiskUsage> = stanzas
+        .iter()
+        .filter(|stanza| stanza.is_installed())
+        .filter_map(|stanza| {
+            Some(PackageDiskUsage {
+                package: stanza.package()?.to_owned(),
+                architecture: stanza.get("Architecture").unwrap_or_default().to_owned(),
+                installed_size: stanza
+                    .get("Installed-Size")
+                    .and_then(|size| size.parse::<u64>().ok())
+                    .unwrap_or(0)
+                    * 1024,
+                measured_size: None,
+            })
+        })
+        .collect();
+
+    if measure {
+        let info = admindir.join("info");
+        usage = tokio::task::spawn_blocking(move || {
+            for package in &mut usage {
+                package.measured_size = measure_package(&info, package);
+            }
+
+            usage
+        })
+        .await
+        .context("failed to measure package files")?;
+    }
+
+    usage.sort_by(|a, b| a.package.cmp(&b.package));
+    Ok(usage)
+}
+
+/// Sums the sizes of the regular files in the dpkg file list of a package.
+fn measure_package(info: &std::path::Path, package: &PackageDiskUsage) -> Option<u64> {
+    // Packages which are co-installable between architectures qualify their lists.
+    let list = std::fs::read_to_string(info.join(format!("{}.list", package.package)))
+        .or_else(|_| {
+            std::fs::read_to_string(
+                info.join(format!("{}:{}.list", package.package, package.architecture)),
+            )
+        })
+        .ok()?;
+
+    Some(sum_file_sizes(&list))
+}
+
+fn sum_file_sizes(list: &str) -> u64 {
+    list.lines()
+        .filter_map(|path| std::fs::symlink_metadata(path).ok())
+        .filter(|meta| meta.is_file())
+        .map(|meta| meta.len())
+        .sum()
+}
+
+/// A change to the package state of the system, made by any process.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum SystemChange {
+    /// The dpkg status database was written, as when packages are installed or removed.
+    DpkgStatus,
+    /// The package lists were updated.
+    Lists,
+    /// The sources were modified.
+    Sources,
+}
+
+/// A directory to watch, and the files within it which signal a change.
+struct ChangeTarget {
+    directory: PathBuf,
+    matches: fn(&str) -> bool,
+    change: SystemChange,
+}
+
+/// Watches for changes to the dpkg status database, package lists, and sources, as when
+/// another tool installs a package or updates the lists, so that UIs may refresh.
+///
+/// Changes are coalesced, so that an `apt-get update` writing many lists at once yields
+/// one `Lists` change. The inotify watches are read on the tokio runtime, and removed
+/// when the stream is dropped.
+pub fn watch_system_changes() -> anyhow::Result<SystemChanges> {
+    watch_changes(vec![
+        ChangeTarget {
+            directory: PathBuf::from(crate::dpkg::status::ADMINDIR),
+            matches: |name| name == "status",
+            change: SystemChange::DpkgStatus,
+        },
+        ChangeTarget {
+            directory: PathBuf::from("/var/lib/apt/lists"),
+            matches: |name| name != "lock",
+            change: SystemChange::Lists,
+        },
+        ChangeTarget {
+            directory: PathBuf::from("/etc/apt"),
+            matches: |name| name == "sources.list",
+            change: SystemChange::Sources,
+        },
+        ChangeTarget {
+            directory: PathBuf::from(crate::sources::SOURCES_DIR),
+            matches: |name| name.ends_with(".list") || name.ends_with(".sources"),
+            change: SystemChange::Sources,
+        },
+    ])
+}
+
+fn watch_changes(targets: Vec<ChangeTarget>) -> anyhow::Result<SystemChanges> {
+    let inotify = crate::inotify::Inotify::new().context("failed to initialize inotify")?;
+
+    // Files are written in place, or renamed over the previous file.
+    let mask = libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO | libc::IN_DELETE;
+
+    let mut watches = Vec::with_capacity(targets.len());
+    for target in targets {
+        let wd = inotify
+            .add_watch(&target.directory, mask)
+            .with_context(|| format!("failed to watch {}", target.directory.display()))?;
+
+        watches.push((wd, target));
+    }
+
+    Ok(Box::pin(async_stream::stream! {
+        let inotify = match crate::inotify::AsyncInotify::new(inotify) {
+            Ok(inotify) => inotify,
+            Err(_) => return,
+        };
+
+        let mut buffer = vec![0; 16 * 1024];
+
+        while let Ok(events) = inotify.read_events(&mut buffer).await {
+            let mut changes: Vec<SystemChange> = Vec::new();
+
+            for event in events {
+                let name = match event.name {
+                    Some(ref name) => name.to_string_lossy(),
+                    None => continue,
+                };
+
+                let change = watches
+                    .iter()
+                    .find(|(wd, target)| *wd == event.wd && (target.matches)(&name))
+                    .map(|(_, target)| target.change);
+
+                if let Some(change) = change {
+                    if !changes.contains(&change) {
+                        changes.push(change);
+                    }
+                }
+            }
+
+            for change in changes {
+                yield change;
+            }
+        }
+    }))
+}
+
+/// The package of an `Inst` line from a simulated upgrade, if it is from a security pocket.
+pub(crate) fn parse_security_update(simulated_line: &str) -> Option<&str> {
     if simulated_line.starts_with("Inst") && simulated_line.contains("-security") {
         simulated_line.split_ascii_whitespace().nth(1)
     } else {
@@ -179,4 +1225,327 @@ mod tests {
             super::parse_security_update("Conf libcaca0:i386 [0.99.beta19-2.2ubuntu2] (0.99.beta19-2.2ubuntu2.1 Ubuntu:21.10/impish-security, Ubuntu:21.10/impish-updates [amd64])")
         );
     }
+
+    #[test]
+    fn simulation_plan() {
+        let output = "Reading package lists...
+The following packages have been kept back:
+  nvidia-driver-535 nvidia-utils-535
+The following packages will be upgraded:
+  firefox
+Inst firefox [119.0+build2-0ubuntu0.22.04.1~mt1] (120.0+build2-0ubuntu0.22.04.1~mt1 Pop:22.04/jammy [amd64])
+Inst libnew1 (1.0-1 Ubuntu:22.04/jammy [amd64])
+Remv libold1 [0.9-1]
+Conf firefox (120.0+build2-0ubuntu0.22.04.1~mt1 Pop:22.04/jammy [amd64])
+";
+
+        let plan = super::SimulationPlan::parse(output);
+
+        assert_eq!(
+            vec!["nvidia-driver-535", "nvidia-utils-535"],
+            plan.kept_back
+        );
+        assert_eq!(
+            vec![super::PackageChange {
+                package: "firefox".into(),
+                version: "120.0+build2-0ubuntu0.22.04.1~mt1".into(),
+                previous: Some("119.0+build2-0ubuntu0.22.04.1~mt1".into()),
+            }],
+            plan.upgrades
+        );
+        assert_eq!("libnew1", plan.installs[0].package);
+        assert_eq!(None, plan.installs[0].previous);
+        assert_eq!("libold1", plan.removals[0].package);
+        assert_eq!("0.9-1", plan.removals[0].version);
+    }
+
+    #[test]
+    fn simulation_plan_without_recommends() {
+        let output = "Reading package lists...
+Suggested packages:
+  vlc-plugin-pipewire | vlc-plugin-jack
+Recommended packages:
+  vlc-l10n
+The following NEW packages will be installed:
+  vlc vlc-bin
+Inst vlc-bin (3.0.16-1build7 Ubuntu:22.04/jammy [amd64])
+Inst vlc (3.0.16-1build7 Ubuntu:22.04/jammy [amd64])
+";
+
+        let plan = super::SimulationPlan::parse(output);
+
+        assert_eq!(vec!["vlc-l10n"], plan.recommended);
+        assert_eq!(
+            vec!["vlc-plugin-pipewire", "vlc-plugin-jack"],
+            plan.suggested
+        );
+        assert_eq!(2, plan.installs.len());
+    }
+
+    #[test]
+    fn upgradable_from() {
+        use super::OriginFilter;
+
+        assert_eq!(None, super::parse_upgradable("Listing..."));
+
+        let mut package = super::parse_upgradable(
+            "firefox/jammy-updates,jammy-security 120.0+build2 amd64 [upgradable from: 119.0]",
+        )
+        .unwrap();
+
+        assert_eq!("firefox", package.package);
+        assert_eq!("120.0+build2", package.version);
+        assert_eq!("119.0", package.installed);
+        assert_eq!(vec!["jammy-updates", "jammy-security"], package.suites);
+
+        package.origins =
+            vec!["https://ppa.launchpadcontent.net/mozillateam/ppa/ubuntu jammy/main".into()];
+
+        assert!(OriginFilter::Pocket("security".into()).matches(&package));
+        assert!(!OriginFilter::Pocket("backports".into()).matches(&package));
+        assert!(OriginFilter::Suite("jammy-updates".into()).matches(&package));
+        assert!(!OriginFilter::Suite("jammy".into()).matches(&package));
+        assert!(
+            OriginFilter::Uri("https://ppa.launchpadcontent.net/mozillateam/".into())
+                .matches(&package)
+        );
+    }
+
+    #[tokio::test]
+    async fn watch_changes() {
+        use futures::StreamExt;
+
+        let temp = crate::utils::test_dir("changes");
+        let dir = temp.path();
+        std::fs::create_dir_all(dir.join("lists")).unwrap();
+
+        let mut changes = super::watch_changes(vec![
+            super::ChangeTarget {
+                directory: dir.to_path_buf(),
+                matches: |name| name == "status",
+                change: super::SystemChange::DpkgStatus,
+            },
+            super::ChangeTarget {
+                directory: dir.join("lists"),
+                matches: |name| name != "lock",
+                change: super::SystemChange::Lists,
+            },
+        ])
+        .unwrap();
+
+        std::fs::write(dir.join("unrelated"), "").unwrap();
+        std::fs::write(dir.join("status-new"), "Package: hello\n").unwrap();
+        std::fs::rename(dir.join("status-new"), dir.join("status")).unwrap();
+        assert_eq!(Some(super::SystemChange::DpkgStatus), changes.next().await);
+
+        std::fs::write(dir.join("lists/lock"), "").unwrap();
+        std::fs::write(dir.join("lists/jammy_InRelease"), "").unwrap();
+        assert_eq!(Some(super::SystemChange::Lists), changes.next().await);
+    }
+
+    #[test]
+    fn build_dependencies() {
+        let control = "# Comments are ignored
+Source: hello
+Section: devel
+Build-Depends: debhelper-compat (= 13),
+               libgtk-3-dev (>= 3.24),
+               cargo | rustc
+Build-Depends-Indep: texinfo
+Build-Conflicts: autoconf2.13
+
+Package: hello
+Architecture: any
+Depends: ${shlibs:Depends}, ${misc:Depends}
+";
+
+        assert_eq!(
+            vec![
+                "debhelper-compat (= 13)",
+                "libgtk-3-dev (>= 3.24)",
+                "cargo | rustc",
+                "texinfo",
+                "Conflicts: autoconf2.13",
+            ],
+            super::build_dependencies(control)
+        );
+
+        let dsc = "-----BEGIN PGP SIGNED MESSAGE-----
+Hash: SHA512
+
+Format: 3.0 (quilt)
+Source: hello
+Build-Depends: debhelper-compat (= 13)
+
+-----BEGIN PGP SIGNATURE-----
+iQIzBAEBCgAdFiEE
+-----END PGP SIGNATURE-----
+";
+
+        assert_eq!(
+            vec!["debhelper-compat (= 13)"],
+            super::build_dependencies(dsc)
+        );
+    }
+
+    #[test]
+    fn disk_usage() {
+        let temp = crate::utils::test_dir("disk-usage");
+        let dir = temp.path();
+        std::fs::write(dir.join("a"), "1234").unwrap();
+        std::fs::write(dir.join("b"), "12").unwrap();
+
+        let list = format!(
+            "{dir}\n{dir}/a\n{dir}/b\n{dir}/missing\n",
+            dir = dir.display()
+        );
+        assert_eq!(6, super::sum_file_sizes(&list));
+
+        let usage = |package: &str, installed_size, measured_size| super::PackageDiskUsage {
+            package: package.into(),
+            architecture: "amd64".into(),
+            installed_size,
+            measured_size,
+        };
+
+        let mut packages = vec![
+            usage("hello", 100, None),
+            usage("firefox", 200, Some(50)),
+            usage("libc6", 300, None),
+        ];
+
+        super::sort_largest_first(&mut packages);
+        let names: Vec<_> = packages.iter().map(|p| p.package.as_str()).collect();
+        assert_eq!(vec!["libc6", "hello", "firefox"], names);
+    }
+
+    #[test]
+    fn why_walk() {
+        use crate::ReverseDependency;
+
+        let manual: super::HashSet<String> = vec!["firefox".to_owned(), "gnome-shell".to_owned()]
+            .into_iter()
+            .collect();
+
+        let rdepend = |package: &str, dependent: &str| ReverseDependency {
+            package: package.into(),
+            dependent: dependent.into(),
+            kind: None,
+        };
+
+        let mut walk = super::WhyWalk::new("libfoo1", &manual);
+        assert_eq!(vec!["libfoo1"], walk.frontier());
+
+        walk.advance(vec![rdepend("libfoo1", "libgtk-3-0")], &manual);
+        assert!(!walk.is_done());
+
+        walk.advance(
+            vec![
+                rdepend("libgtk-3-0", "firefox"),
+                rdepend("libgtk-3-0", "gnome-shell"),
+                rdepend("libgtk-3-0", "libgtk-3-bin"),
+            ],
+            &manual,
+        );
+
+        assert!(walk.is_done());
+        assert_eq!(
+            vec![
+                vec!["firefox", "libgtk-3-0", "libfoo1"],
+                vec!["gnome-shell", "libgtk-3-0", "libfoo1"],
+            ],
+            walk.chains()
+        );
+
+        let walk = super::WhyWalk::new("firefox", &manual);
+        assert!(walk.is_done());
+        assert_eq!(vec![vec!["firefox"]], walk.chains());
+    }
+
+    #[test]
+    fn version_origins() {
+        let mut version_table = std::collections::HashMap::new();
+        version_table.insert(
+            "120.0 1001".to_owned(),
+            vec![
+                "500 http://apt.pop-os.org/release jammy/main amd64 Packages".to_owned(),
+                "100 /var/lib/dpkg/status".to_owned(),
+            ],
+        );
+        version_table.insert(
+            "119.0 500".to_owned(),
+            vec!["500 http://archive.ubuntu.com/ubuntu jammy/main amd64 Packages".to_owned()],
+        );
+
+        assert_eq!(
+            vec!["http://apt.pop-os.org/release jammy/main amd64 Packages"],
+            super::version_origins(&version_table, "120.0")
+        );
+        assert!(super::version_origins(&version_table, "118.0").is_empty());
+    }
+
+    #[test]
+    fn parse_simulated_removal() {
+        assert_eq!(
+            Some("linux-image-5.15.0-60-generic"),
+            super::parse_simulated_removal("Purg linux-image-5.15.0-60-generic [5.15.0-60.66]")
+        );
+
+        assert_eq!(
+            Some("libfoo1:amd64"),
+            super::parse_simulated_removal("Remv libfoo1:amd64 [1.0-1]")
+        );
+
+        assert_eq!(None, super::parse_simulated_removal("Inst libfoo1 (1.0-1)"));
+    }
+
+    #[test]
+    fn is_kernel_package() {
+        assert!(super::is_kernel_package("linux-headers-5.15.0-60"));
+        assert!(super::is_kernel_package(
+            "linux-modules-extra-5.15.0-60-generic:amd64"
+        ));
+        assert!(!super::is_kernel_package("linux-firmware"));
+        assert!(!super::is_kernel_package("libfoo1"));
+    }
+
+    #[test]
+    fn pin_preferences() {
+        assert_eq!(
+            "Package: libstdc++6\nPin: version 12.3.0-1ubuntu1~22.04\nPin-Priority: 1001\n",
+            super::pin_preferences("libstdc++6", "12.3.0-1ubuntu1~22.04")
+        );
+
+        assert_eq!(
+            std::path::Path::new("/etc/apt/preferences.d/apt-cmd-libstdc_2b_2b6_3aamd64.pref"),
+            super::pin_path("libstdc++6:amd64")
+        );
+        assert_eq!(
+            std::path::Path::new("/etc/apt/preferences.d/apt-cmd-linux-image-6.2.0.pref"),
+            super::pin_path("linux-image-6.2.0")
+        );
+        assert_ne!(super::pin_path("libstdc++6"), super::pin_path("libstdc__6"));
+    }
+
+    #[tokio::test]
+    async fn pins_reject_invalid_packages() {
+        use super::PinStrategy;
+
+        for package in [
+            "firefox\nPin: version *",
+            "../../sources.list.d/evil",
+            "firefox=119.0",
+            "firefox/jammy",
+        ] {
+            for strategy in [PinStrategy::Preferences, PinStrategy::Hold] {
+                let why = super::pin_to_installed(package, strategy)
+                    .await
+                    .unwrap_err();
+                assert!(why.is::<crate::InvalidPackageName>(), "{:?}", package);
+            }
+
+            let why = super::unpin(package).await.unwrap_err();
+            assert!(why.is::<crate::InvalidPackageName>(), "{:?}", package);
+        }
+    }
 }
@@ -1,103 +1,408 @@
 // Copyright 2021-2022 System76 <info@system76.com>
 // SPDX-License-Identifier: MPL-2.0
 
+use crate::depends::{
+    parse_showpkg, DependencyGraph, DependencyKind, DependsBlock, DependsParser, PackageProviders,
+    RdependsParser, ReverseDependency,
+};
+use crate::record::{PackageRecord, RecordParser, SourceRecord, SourceRecordParser};
+use crate::utils::{LineReader, DEFAULT_BUFFER_CAPACITY};
 use anyhow::Context;
 use as_result::{IntoResult, MapResult};
 use futures::stream::{Stream, StreamExt};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::io;
+use std::path::Path;
 use std::pin::Pin;
-use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+use thiserror::Error;
+use tokio::io::AsyncReadExt;
 use tokio::process::{Child, ChildStdout, Command};
-use tokio_stream::wrappers::LinesStream;
+
+/// The most bytes of package names passed to one `apt-cache policy`, well below the
+/// `ARG_MAX` of any Linux system, which also counts the environment.
+const POLICY_ARGV_BYTES: usize = 64 * 1024;
 
 pub type PackageStream = Pin<Box<dyn Stream<Item = String>>>;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct Policy {
     pub package: String,
     pub installed: String,
     pub candidate: String,
     pub version_table: HashMap<String, Vec<String>>,
+    /// The percentage of systems to which the candidate is being phased, if it is a
+    /// phased update, as `(phased 10%)` in its version table entry.
+    pub phased_percentage: Option<u8>,
 }
 
-pub type Policies = Pin<Box<dyn Stream<Item = Policy>>>;
+impl Policy {
+    /// Parses captured `apt-cache policy` output, such as from a log, without spawning apt.
+    pub fn parse_str(output: &str) -> Vec<Policy> {
+        let mut parser = PolicyParser::default();
+        let mut policies: Vec<Policy> = output
+            .lines()
+            .filter_map(|line| parser.parse_line(line))
+            .collect();
 
-pub fn policies(lines: impl Stream<Item = io::Result<String>>) -> impl Stream<Item = Policy> {
-    async_stream::stream! {
-        futures::pin_mut!(lines);
+        let last = parser.finish();
+        if !last.package.is_empty() {
+            policies.push(last);
+        }
 
-        let mut policy = Policy {
-            package: String::new(),
-            installed: String::new(),
-            candidate: String::new(),
-            version_table: HashMap::new()
-        };
+        policies
+    }
+}
+
+/// Counts of the structures in apt's package cache, from `apt-cache stats`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct CacheStats {
+    /// Every package name known, whether or not it has any versions.
+    pub package_names: u64,
+    /// Packages with at least one version.
+    pub normal_packages: u64,
+    /// Packages which are only provided by others.
+    pub pure_virtual_packages: u64,
+    /// Virtual packages with exactly one provider.
+    pub single_virtual_packages: u64,
+    /// Packages which have versions, and are also provided by others.
+    pub mixed_virtual_packages: u64,
+    /// Packages which are depended on, but neither available nor provided.
+    pub missing_packages: u64,
+    pub versions: u64,
+    pub descriptions: u64,
+    pub dependencies: u64,
+    pub provides: u64,
+}
+
+impl CacheStats {
+    /// Parses captured `apt-cache stats` output.
+    pub fn parse_str(output: &str) -> Self {
+        let mut stats = CacheStats::default();
+
+        for line in output.lines() {
+            let Some((name, value)) = line.split_once(':') else {
+                continue;
+            };
+
+            // Values are such as `120543 (3,375 k)`, or `572155/135406 (14.2 M)`.
+            let Some(count) = value
+                .split(|c: char| c.is_ascii_whitespace() || c == '/')
+                .find(|word| !word.is_empty())
+                .and_then(|count| count.parse().ok())
+            else {
+                continue;
+            };
+
+            let field = match name.trim() {
+                "Total package names" => &mut stats.package_names,
+                "Normal packages" => &mut stats.normal_packages,
+                "Pure virtual packages" => &mut stats.pure_virtual_packages,
+                "Single virtual packages" => &mut stats.single_virtual_packages,
+                "Mixed virtual packages" => &mut stats.mixed_virtual_packages,
+                "Missing" => &mut stats.missing_packages,
+                "Total distinct versions" => &mut stats.versions,
+                "Total distinct descriptions" => &mut stats.descriptions,
+                "Total dependencies" => &mut stats.dependencies,
+                "Total Provides mappings" => &mut stats.provides,
+                _ => continue,
+            };
+
+            *field = count;
+        }
+
+        stats
+    }
+}
 
-        while let Some(Ok(line)) = lines.next().await {
-            if line.is_empty() {
-                continue
+/// A source of packages and its pin priority, from `apt-cache policy` without packages.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct PackageFile {
+    pub priority: i32,
+    /// The URI of the repository, or the path of a local file such as the dpkg status.
+    pub url: String,
+    /// The suite, as `jammy-updates`, or `now` for the dpkg status.
+    pub suite: String,
+    pub component: String,
+    pub architecture: String,
+    /// The origin of the release, as `Ubuntu`, which may be pinned by `o=`.
+    pub origin: String,
+}
+
+impl PackageFile {
+    /// Parses the package files of captured `apt-cache policy` output, ignoring any
+    /// pinned packages which follow them.
+    pub fn parse_str(output: &str) -> Vec<PackageFile> {
+        let mut files: Vec<PackageFile> = Vec::new();
+
+        for line in output.lines() {
+            if line.starts_with("Pinned packages:") {
+                break;
             }
 
-            if !line.starts_with(' ') {
-                policy.package = line;
-                if policy.package.ends_with(':') {
-                    policy.package.truncate(policy.package.len() - 1);
-                }
-                continue
-            }
-
-            let line = line.trim();
-
-            if line.starts_with('I') {
-                if let Some(v) = line.split_ascii_whitespace().nth(1) {
-                    policy.installed = v.to_owned();
-                }
-            } else if line.starts_with('C') {
-                if let Some(v) = line.split_ascii_whitespace().nth(1) {
-                    policy.candidate = v.to_owned();
-                }
-            } else if line.starts_with('V') {
-                // Start parsing the version table
-                let mut current_version = String::from("unknown");
-                while let Some(Ok(line)) = lines.next().await {
-
-
-                    if let Some(source) = line.strip_prefix("      ") {
-                        policy.version_table.entry(current_version.clone())
-                            .or_insert_with(Vec::new)
-                            .push(source.trim().to_owned());
-                    } else if let Some(version) = line.strip_prefix(" *** ") {
-                        current_version = version.trim().to_owned();
-                    } else if let Some(version) = line.strip_prefix("   ") {
-                        current_version = version.trim().to_owned();
-                    } else {
-                        yield policy.clone();
-                        policy.version_table.clear();
-                        policy.package = line;
-                        if policy.package.ends_with(':') {
-                            policy.package.truncate(policy.package.len() - 1);
-                        }
-                        break
+            let trimmed = line.trim_start();
+
+            if let Some(release) = trimmed.strip_prefix("release ") {
+                let Some(file) = files.last_mut() else {
+                    continue;
+                };
+
+                for (key, value) in release.split(',').filter_map(|kv| kv.split_once('=')) {
+                    match key {
+                        "o" => file.origin = value.to_owned(),
+                        "a" if file.suite.is_empty() => file.suite = value.to_owned(),
+                        _ => (),
                     }
                 }
+            } else if let Some(file) = parse_package_file_line(trimmed) {
+                files.push(file);
             }
         }
 
-        yield policy;
+        files
+    }
+}
+
+pub type Policies = Pin<Box<dyn Stream<Item = Policy> + Send>>;
+
+/// Policies from `AptCache::policy_checked`, with the errors met while parsing them.
+pub type CheckedPolicies = Pin<Box<dyn Stream<Item = Result<Policy, PolicyParseError>> + Send>>;
+
+#[derive(Debug, Error)]
+pub enum PolicyParseError {
+    #[error("unexpected line in `apt-cache policy` output: {0:?}")]
+    UnexpectedLine(String),
+    #[error("failed to read `apt-cache policy` output")]
+    Read(#[source] io::Error),
+    #[error("failed to launch `apt-cache policy`")]
+    Spawn(#[source] io::Error),
+    #[error("`apt-cache policy` exited in error")]
+    Exit(#[source] io::Error),
+}
+
+pub type ReverseDependencies = Pin<Box<dyn Stream<Item = ReverseDependency> + Send>>;
+
+pub type DependsBlocks = Pin<Box<dyn Stream<Item = DependsBlock> + Send>>;
+
+pub type PackageRecords = Pin<Box<dyn Stream<Item = PackageRecord> + Send>>;
+
+pub type SourceRecords = Pin<Box<dyn Stream<Item = SourceRecord> + Send>>;
+
+/// The `(package, version, source)` of each version, from `apt-cache madison`.
+pub type MadisonEntries = Pin<Box<dyn Stream<Item = (String, String, String)> + Send>>;
+
+/// Packages found by `apt-cache search`, with their short descriptions.
+pub type SearchResults = Pin<Box<dyn Stream<Item = (String, String)> + Send>>;
+
+/// The `(package, relation, missing)` of each unmet dependency, from `apt-cache unmet`,
+/// such as `("wine", DependencyKind::Depends, "wine64 (>= 8.0) | wine32 (>= 8.0)")`.
+pub type UnmetDependencies = Pin<Box<dyn Stream<Item = (String, DependencyKind, String)> + Send>>;
+
+/// Parses the output of `apt-cache policy` one line at a time.
+#[derive(Debug, Default)]
+pub struct PolicyParser {
+    policy: Policy,
+    version_table: Option<String>,
+}
+
+impl PolicyParser {
+    /// Returns the previous package's policy once the next package begins.
+    pub fn parse_line(&mut self, line: &str) -> Option<Policy> {
+        if let Some(current_version) = self.version_table.as_mut() {
+            if let Some(source) = line.strip_prefix("      ") {
+                self.policy
+                    .version_table
+                    .entry(current_version.clone())
+                    .or_default()
+                    .push(source.trim().to_owned());
+            } else if let Some(version) = line
+                .strip_prefix(" *** ")
+                .or_else(|| line.strip_prefix("   "))
+            {
+                *current_version = version.trim().to_owned();
+
+                let mut words = current_version.split_ascii_whitespace();
+                if words.next() == Some(self.policy.candidate.as_str()) {
+                    if let Some(percentage) = parse_phased(current_version) {
+                        self.policy.phased_percentage = Some(percentage);
+                    }
+                }
+            } else {
+                let policy = self.policy.clone();
+                self.policy.version_table.clear();
+                self.policy.phased_percentage = None;
+                self.version_table = None;
+                self.set_package(line);
+                return Some(policy);
+            }
+
+            return None;
+        }
+
+        if line.is_empty() {
+            return None;
+        }
+
+        if !line.starts_with(' ') {
+            self.set_package(line);
+            return None;
+        }
+
+        let line = line.trim();
+
+        if line.starts_with('I') {
+            if let Some(v) = line.split_ascii_whitespace().nth(1) {
+                self.policy.installed = v.to_owned();
+            }
+        } else if line.starts_with('C') {
+            if let Some(v) = line.split_ascii_whitespace().nth(1) {
+                self.policy.candidate = v.to_owned();
+            }
+        } else if line.starts_with('V') {
+            // Start parsing the version table
+            self.version_table = Some(String::from("unknown"));
+        }
+
+        None
+    }
+
+    /// As `parse_line`, but lines which do not belong in the output are returned as errors,
+    /// rather than being ignored or misread as a package. These lines are skipped.
+    pub fn try_parse_line(&mut self, line: &str) -> Result<Option<Policy>, PolicyParseError> {
+        let expected = if self.version_table.is_some() {
+            line.starts_with("   ")
+                || line.starts_with(" *** ")
+                || (!line.starts_with(' ') && line.ends_with(':'))
+        } else if let Some(field) = line.strip_prefix("  ") {
+            !self.policy.package.is_empty()
+                && ["Installed:", "Candidate:", "Package pin:", "Version table:"]
+                    .iter()
+                    .any(|name| field.starts_with(name))
+        } else {
+            line.is_empty() || (!line.starts_with(' ') && line.ends_with(':'))
+        };
+
+        if !expected {
+            return Err(PolicyParseError::UnexpectedLine(line.to_owned()));
+        }
+
+        Ok(self.parse_line(line))
+    }
+
+    /// The policy of the last package.
+    pub fn finish(self) -> Policy {
+        self.policy
+    }
+
+    fn set_package(&mut self, line: &str) {
+        self.policy.package.clear();
+        self.policy
+            .package
+            .push_str(line.strip_suffix(':').unwrap_or(line));
     }
 }
 
 #[derive(AsMut, Deref, DerefMut)]
-#[as_mut(forward)]
-pub struct AptCache(Command);
+pub struct AptCache {
+    #[as_mut(forward)]
+    #[deref]
+    #[deref_mut]
+    command: Command,
+    #[as_mut(ignore)]
+    buffer_capacity: usize,
+}
 
 impl AptCache {
     #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
-        let mut cmd = Command::new("apt-cache");
-        cmd.env("LANG", "C");
-        Self(cmd)
+        Self::with_command(crate::utils::command("apt-cache"))
+    }
+
+    pub(crate) fn with_command(command: Command) -> Self {
+        Self {
+            command,
+            buffer_capacity: DEFAULT_BUFFER_CAPACITY,
+        }
+    }
+
+    /// Limits dependencies and reverse dependencies to installed packages.
+    pub fn installed(mut self) -> Self {
+        self.arg("--installed");
+        self
+    }
+
+    /// Limits dependencies and reverse dependencies to Depends and Pre-Depends.
+    pub fn important(mut self) -> Self {
+        self.arg("--important");
+        self
+    }
+
+    /// Follows dependencies recursively, listing the relations of every package reached.
+    pub fn recurse(mut self) -> Self {
+        self.arg("--recurse");
+        self
+    }
+
+    /// Omits Recommends from dependencies and reverse dependencies.
+    pub fn no_recommends(mut self) -> Self {
+        self.arg("--no-recommends");
+        self
+    }
+
+    /// Omits Suggests from dependencies and reverse dependencies.
+    pub fn no_suggests(mut self) -> Self {
+        self.arg("--no-suggests");
+        self
+    }
+
+    /// Omits Suggests, Conflicts, Breaks, Replaces, and Enhances, leaving only the
+    /// relations which cause a package to be installed.
+    pub fn installing_relations(mut self) -> Self {
+        self.args([
+            "--no-suggests",
+            "--no-conflicts",
+            "--no-breaks",
+            "--no-replaces",
+            "--no-enhances",
+        ]);
+        self
+    }
+
+    /// Has `search` match only package names, rather than also their descriptions.
+    pub fn names_only(mut self) -> Self {
+        self.arg("--names-only");
+        self
+    }
+
+    /// Has `search` print the full record of each package, so that the results are
+    /// parsed from those records.
+    pub fn full(mut self) -> Self {
+        self.arg("--full");
+        self
+    }
+
+    /// Limits the command to the sources in the file at `path`, as with
+    /// `AptGet::scoped_sources`.
+    ///
+    /// The package cache is built in memory, so the system's cache is not replaced by
+    /// one of only these sources.
+    pub fn scoped_sources(mut self, path: &Path) -> Self {
+        self.args([
+            "-o",
+            &format!("Dir::Etc::SourceList={}", path.display()),
+            "-o",
+            "Dir::Etc::SourceParts=-",
+            "-o",
+            "Dir::Cache::pkgcache=",
+            "-o",
+            "Dir::Cache::srcpkgcache=",
+        ]);
+        self
+    }
+
+    /// The capacity of the buffer that output is read through, for very large outputs.
+    pub fn buffer_capacity(mut self, bytes: usize) -> Self {
+        self.buffer_capacity = bytes;
+        self
     }
 
     pub async fn depends<I, S>(mut self, packages: I) -> io::Result<(Child, ChildStdout)>
@@ -105,36 +410,484 @@ impl AptCache {
         I: IntoIterator<Item = S>,
         S: AsRef<std::ffi::OsStr>,
     {
-        self.arg("depends");
+        self.args(["depends", "--"]);
         self.args(packages);
         self.spawn_with_stdout().await
     }
 
-    pub async fn rdepends<I, S>(mut self, packages: I) -> io::Result<(Child, PackageStream)>
+    /// Streams the dependencies of each package, one package at a time.
+    pub async fn stream_depends<I, S>(mut self, packages: I) -> io::Result<(Child, DependsBlocks)>
     where
         I: IntoIterator<Item = S>,
         S: AsRef<std::ffi::OsStr>,
     {
-        self.arg("rdepends");
+        self.args(["depends", "--"]);
         self.args(packages);
-        self.stream_packages().await
+
+        let capacity = self.buffer_capacity;
+        let (child, stdout) = self.spawn_with_stdout().await?;
+
+        let mut lines = LineReader::with_capacity(capacity, stdout);
+
+        let stream = async_stream::stream! {
+            let mut parser = DependsParser::default();
+
+            while let Some(line) = lines.next_line().await {
+                if let Some(block) = parser.parse_line(line) {
+                    yield block;
+                }
+            }
+
+            if let Some(block) = parser.finish() {
+                yield block;
+            }
+        };
+
+        Ok((child, Box::pin(stream)))
     }
 
-    pub async fn policy<S: AsRef<std::ffi::OsStr>>(
+    /// The full closure of the packages which would be installed with `packages`,
+    /// following only Depends and Pre-Depends, such as to prefetch every archive for an
+    /// offline install.
+    pub async fn depends_recursive<I, S>(self, packages: I) -> io::Result<DependencyGraph>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<std::ffi::OsStr>,
+    {
+        self.installing_relations()
+            .no_recommends()
+            .recurse()
+            .dependency_graph(packages)
+            .await
+    }
+
+    /// A graph of the relations of `packages`, whose edges are typed by the kind of each
+    /// relation, such as to render with `DependencyGraph::to_dot`.
+    ///
+    /// Only the direct relations of the packages are included, unless with `recurse`.
+    pub async fn dependency_graph<I, S>(self, packages: I) -> io::Result<DependencyGraph>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<std::ffi::OsStr>,
+    {
+        let packages: Vec<S> = packages.into_iter().collect();
+        let roots = packages
+            .iter()
+            .map(|package| package.as_ref().to_string_lossy().into_owned())
+            .collect();
+
+        let (mut child, blocks) = self.stream_depends(packages).await?;
+        let blocks: Vec<DependsBlock> = blocks.collect().await;
+
+        child.wait().await.map_result()?;
+
+        Ok(DependencyGraph::from_blocks(roots, blocks))
+    }
+
+    pub async fn rdepends<I, S>(self, packages: I) -> io::Result<(Child, PackageStream)>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<std::ffi::OsStr>,
+    {
+        let (child, stream) = self.rdepends_tagged(packages).await?;
+        Ok((child, Box::pin(stream.map(|dep| dep.dependent))))
+    }
+
+    /// Streams reverse dependencies, tagged with the queried package they depend on.
+    pub async fn rdepends_tagged<I, S>(
         mut self,
+        packages: I,
+    ) -> io::Result<(Child, ReverseDependencies)>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<std::ffi::OsStr>,
+    {
+        self.args(["rdepends", "--"]);
+        self.args(packages);
+
+        let capacity = self.buffer_capacity;
+        let (child, stdout) = self.spawn_with_stdout().await?;
+
+        let mut lines = LineReader::with_capacity(capacity, stdout);
+
+        let stream = async_stream::stream! {
+            let mut parser = RdependsParser::default();
+
+            while let Some(line) = lines.next_line().await {
+                if let Some(dependency) = parser.parse_line(line) {
+                    yield dependency;
+                }
+            }
+        };
+
+        Ok((child, Box::pin(stream)))
+    }
+
+    /// Streams reverse dependencies, tagged with the queried package and with the kind
+    /// of relation, such that weak relations such as Suggests may be filtered out.
+    pub async fn rdepends_typed<I, S>(
+        mut self,
+        packages: I,
+    ) -> io::Result<(Child, ReverseDependencies)>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<std::ffi::OsStr>,
+    {
+        self.args(["-o", "APT::Cache::ShowDependencyType=true"]);
+        self.rdepends_tagged(packages).await
+    }
+
+    /// Streams the policy of each package.
+    ///
+    /// So that argv never exceeds `ARG_MAX`, the packages are split between as many
+    /// `apt-cache policy` processes as needed, which run one after another. The returned
+    /// child is the first of these; the others are waited on by the stream.
+    ///
+    /// Lines which could not be parsed are skipped, and the stream ends early if the
+    /// output could not be read, or a process could not be launched. Use `policy_checked`
+    /// to see these errors, and those of the processes after the first.
+    pub async fn policy<S: AsRef<std::ffi::OsStr>>(
+        self,
         packages: &[S],
     ) -> anyhow::Result<(Child, Policies)> {
-        self.arg("policy");
+        let (child, policies) = self.policy_checked(packages).await?;
+        let policies = policies.filter_map(|policy| futures::future::ready(policy.ok()));
+        Ok((child, Box::pin(policies)))
+    }
+
+    /// Streams the policy of each package, as `policy`, but with an error for each line of
+    /// output which could not be parsed, and for output which could not be read.
+    ///
+    /// The stream ends after a read error, as the rest of the output of that process is lost.
+    /// Each process after the first which could not be launched, or which exited in error,
+    /// is also reported; the exit status of the first is left to the returned child.
+    pub async fn policy_checked<S: AsRef<std::ffi::OsStr>>(
+        mut self,
+        packages: &[S],
+    ) -> anyhow::Result<(Child, CheckedPolicies)> {
+        let mut chunks = chunk_args(packages, POLICY_ARGV_BYTES).into_iter();
+        let template = crate::utils::clone_command(&self.command);
+
+        self.args(["policy", "--"]);
+        self.args(chunks.next().unwrap_or_default());
+
+        let capacity = self.buffer_capacity;
+        let (child, stdout) = self.spawn_with_stdout().await?;
+
+        let mut lines = LineReader::with_capacity(capacity, stdout);
+
+        let stream = async_stream::stream! {
+            let mut parser = PolicyParser::default();
+
+            loop {
+                match lines.try_next_line().await {
+                    Ok(Some(line)) => match parser.try_parse_line(line) {
+                        Ok(Some(policy)) => yield Ok(policy),
+                        Ok(None) => (),
+                        Err(why) => yield Err(why),
+                    },
+                    Ok(None) => break,
+                    Err(why) => {
+                        yield Err(PolicyParseError::Read(why));
+                        return;
+                    }
+                }
+            }
+
+            for chunk in chunks {
+                let mut command = crate::utils::clone_command(&template);
+                command.args(["policy", "--"]).args(chunk);
+
+                let (mut child, stdout) = match crate::utils::spawn_with_stdout(command).await {
+                    Ok(spawned) => spawned,
+                    Err(why) => {
+                        yield Err(PolicyParseError::Spawn(why));
+                        return;
+                    }
+                };
+
+                let mut lines = LineReader::with_capacity(capacity, stdout);
+
+                loop {
+                    match lines.try_next_line().await {
+                        Ok(Some(line)) => match parser.try_parse_line(line) {
+                            Ok(Some(policy)) => yield Ok(policy),
+                            Ok(None) => (),
+                            Err(why) => yield Err(why),
+                        },
+                        Ok(None) => break,
+                        Err(why) => {
+                            yield Err(PolicyParseError::Read(why));
+                            return;
+                        }
+                    }
+                }
+
+                let status = child.wait().await.and_then(|status| status.into_result());
+                if let Err(why) = status {
+                    yield Err(PolicyParseError::Exit(why));
+                }
+            }
+
+            let last = parser.finish();
+            if !last.package.is_empty() {
+                yield Ok(last);
+            }
+        };
+
+        Ok((child, Box::pin(stream)))
+    }
+
+    /// The candidate version of a package, or `None` if it has no candidate or is unknown.
+    ///
+    /// Only the `Candidate:` line of `apt-cache policy` is parsed.
+    pub async fn candidate_version(mut self, package: &str) -> io::Result<Option<String>> {
+        self.args(["policy", "--", package]);
+
+        let capacity = self.buffer_capacity;
+        let (mut child, stdout) = self.spawn_with_stdout().await?;
+        let mut lines = LineReader::with_capacity(capacity, stdout);
+
+        // The output is read to the end, so that apt-cache is not cut off before it exits.
+        let mut candidate = None;
+        while let Some(line) = lines.next_line().await {
+            if candidate.is_none() {
+                if let Some(version) = parse_candidate_line(line) {
+                    candidate = Some(version.to_owned()).filter(|version| version != "(none)");
+                }
+            }
+        }
+
+        child.wait().await.map_result()?;
+
+        Ok(candidate)
+    }
+
+    /// The packages which provide `package`, if it is virtual, and the packages which
+    /// its versions provide, from `apt-cache showpkg`.
+    ///
+    /// Returns `None` if the package is unknown.
+    pub async fn providers(mut self, package: &str) -> io::Result<Option<PackageProviders>> {
+        self.args(["showpkg", "--", package]);
+
+        let (mut child, mut stdout) = self.spawn_with_stdout().await?;
+
+        let mut output = String::new();
+        stdout.read_to_string(&mut output).await?;
+        child.wait().await.map_result()?;
+
+        Ok(parse_showpkg(&output))
+    }
+
+    /// Streams the record of each version of each package, from `apt-cache show`.
+    pub async fn show<I, S>(mut self, packages: I) -> io::Result<(Child, PackageRecords)>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<std::ffi::OsStr>,
+    {
+        self.args(["show", "--"]);
         self.args(packages);
-        self.env("LANG", "C");
 
+        let capacity = self.buffer_capacity;
         let (child, stdout) = self.spawn_with_stdout().await?;
 
-        let lines = LinesStream::new(BufReader::new(stdout).lines());
+        let mut lines = LineReader::with_capacity(capacity, stdout);
 
-        let stream = Box::pin(policies(lines));
+        let stream = async_stream::stream! {
+            let mut parser = RecordParser::default();
 
-        Ok((child, stream))
+            while let Some(line) = lines.next_line().await {
+                if let Some(record) = parser.parse_line(line) {
+                    yield record;
+                }
+            }
+
+            if let Some(record) = parser.finish() {
+                yield record;
+            }
+        };
+
+        Ok((child, Box::pin(stream)))
+    }
+
+    /// Streams the name of every package known to apt, or only those beginning with
+    /// `prefix`, such as for tab completion.
+    pub async fn pkgnames(mut self, prefix: Option<&str>) -> io::Result<(Child, PackageStream)> {
+        self.arg("pkgnames");
+        if let Some(prefix) = prefix {
+            self.args(["--", prefix]);
+        }
+
+        let capacity = self.buffer_capacity;
+        let (child, stdout) = self.spawn_with_stdout().await?;
+
+        let mut lines = LineReader::with_capacity(capacity, stdout);
+
+        let stream = async_stream::stream! {
+            while let Some(line) = lines.next_line().await {
+                let name = line.trim();
+                if !name.is_empty() {
+                    yield name.to_owned();
+                }
+            }
+        };
+
+        Ok((child, Box::pin(stream)))
+    }
+
+    /// Streams the source package records of each package, from `apt-cache showsrc`.
+    ///
+    /// A binary package name is resolved to the source package which builds it. This
+    /// requires `deb-src` entries in the sources.
+    pub async fn showsrc<I, S>(mut self, packages: I) -> io::Result<(Child, SourceRecords)>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<std::ffi::OsStr>,
+    {
+        self.args(["showsrc", "--"]);
+        self.args(packages);
+
+        let capacity = self.buffer_capacity;
+        let (child, stdout) = self.spawn_with_stdout().await?;
+
+        let mut lines = LineReader::with_capacity(capacity, stdout);
+
+        let stream = async_stream::stream! {
+            let mut parser = SourceRecordParser::default();
+
+            while let Some(line) = lines.next_line().await {
+                if let Some(record) = parser.parse_line(line) {
+                    yield record;
+                }
+            }
+
+            if let Some(record) = parser.finish() {
+                yield record;
+            }
+        };
+
+        Ok((child, Box::pin(stream)))
+    }
+
+    /// Streams the record of every version available from the package lists.
+    pub async fn dumpavail(mut self) -> io::Result<(Child, PackageRecords)> {
+        self.arg("dumpavail");
+
+        let capacity = self.buffer_capacity;
+        let (child, stdout) = self.spawn_with_stdout().await?;
+
+        let mut lines = LineReader::with_capacity(capacity, stdout);
+
+        let stream = async_stream::stream! {
+            let mut parser = RecordParser::default();
+
+            while let Some(line) = lines.next_line().await {
+                if let Some(record) = parser.parse_line(line) {
+                    yield record;
+                }
+            }
+
+            if let Some(record) = parser.finish() {
+                yield record;
+            }
+        };
+
+        Ok((child, Box::pin(stream)))
+    }
+
+    /// Streams each available version of each package, with the source it is from, such
+    /// as `http://apt.pop-os.org/release jammy/main amd64 Packages`.
+    ///
+    /// Far cheaper than `policy` for enumerating the versions of many packages, though
+    /// it says nothing of which are installed, pinned, or candidates.
+    pub async fn madison<I, S>(mut self, packages: I) -> io::Result<(Child, MadisonEntries)>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<std::ffi::OsStr>,
+    {
+        self.args(["madison", "--"]);
+        self.args(packages);
+
+        let capacity = self.buffer_capacity;
+        let (child, stdout) = self.spawn_with_stdout().await?;
+
+        let mut lines = LineReader::with_capacity(capacity, stdout);
+
+        let stream = async_stream::stream! {
+            while let Some(line) = lines.next_line().await {
+                if let Some(entry) = parse_madison_line(line) {
+                    yield entry;
+                }
+            }
+        };
+
+        Ok((child, Box::pin(stream)))
+    }
+
+    /// Streams the dependencies of `packages` which no available version satisfies, or of
+    /// every package in the cache if `packages` is empty.
+    pub async fn unmet<I, S>(mut self, packages: I) -> io::Result<(Child, UnmetDependencies)>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<std::ffi::OsStr>,
+    {
+        self.args(["unmet", "--"]);
+        self.args(packages);
+
+        let capacity = self.buffer_capacity;
+        let (child, stdout) = self.spawn_with_stdout().await?;
+
+        let mut lines = LineReader::with_capacity(capacity, stdout);
+
+        let stream = async_stream::stream! {
+            let mut package = None;
+
+            while let Some(line) = lines.next_line().await {
+                if let Some(entry) = parse_unmet_line(&mut package, line) {
+                    yield entry;
+                }
+            }
+        };
+
+        Ok((child, Box::pin(stream)))
+    }
+
+    /// Streams the packages which match all of the regex `patterns`, with their short
+    /// descriptions.
+    pub async fn search<I, S>(mut self, patterns: I) -> io::Result<(Child, SearchResults)>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<std::ffi::OsStr>,
+    {
+        let full = self.as_std().get_args().any(|arg| arg == "--full");
+
+        self.args(["search", "--"]);
+        self.args(patterns);
+
+        let capacity = self.buffer_capacity;
+        let (child, stdout) = self.spawn_with_stdout().await?;
+
+        let mut lines = LineReader::with_capacity(capacity, stdout);
+
+        let stream = async_stream::stream! {
+            let mut parser = RecordParser::default();
+
+            while let Some(line) = lines.next_line().await {
+                if !full {
+                    if let Some(result) = parse_search_line(line) {
+                        yield result;
+                    }
+                } else if let Some(record) = parser.parse_line(line) {
+                    yield (record.package.clone(), record.summary().to_owned());
+                }
+            }
+
+            if let Some(record) = parser.finish() {
+                yield (record.package.clone(), record.summary().to_owned());
+            }
+        };
+
+        Ok((child, Box::pin(stream)))
     }
 
     pub async fn predepends_of<'a>(
@@ -172,28 +925,275 @@ impl AptCache {
         Ok(PreDependsIter::new(out.as_str(), package)?.collect::<Vec<_>>())
     }
 
-    async fn stream_packages(self) -> io::Result<(Child, PackageStream)> {
-        let (child, stdout) = self.spawn_with_stdout().await?;
+    /// The packages which pre-depend on each of `packages`.
+    ///
+    /// Unlike [`AptCache::predepends_of`], all packages are queried together, with a
+    /// single `apt-cache rdepends` and a single `apt-cache depends` process.
+    pub async fn predepends_of_many<S: AsRef<str>>(
+        packages: &[S],
+    ) -> anyhow::Result<HashMap<String, Vec<String>>> {
+        let packages: Vec<&str> = packages.iter().map(AsRef::as_ref).collect();
 
-        let mut lines = LinesStream::new(BufReader::new(stdout).lines()).skip(2);
+        if packages.is_empty() {
+            return Ok(HashMap::new());
+        }
 
-        let stream = async_stream::stream! {
-            while let Some(Ok(package)) = lines.next().await {
-                yield package.trim_start().to_owned();
+        let (mut child, mut stream) = AptCache::new()
+            .rdepends_tagged(&packages)
+            .await
+            .context("failed to launch `apt-cache rdepends`")?;
+
+        let mut dependents = Vec::new();
+        let mut seen = HashSet::new();
+        while let Some(dependency) = stream.next().await {
+            if seen.insert(dependency.dependent.clone()) {
+                dependents.push(dependency.dependent);
             }
-        };
+        }
 
-        Ok((child, Box::pin(stream)))
+        child
+            .wait()
+            .await
+            .map_result()
+            .context("bad status from `apt-cache rdepends`")?;
+
+        if dependents.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let (mut child, stream) = AptCache::new()
+            .stream_depends(&dependents)
+            .await
+            .context("failed to launch `apt-cache depends`")?;
+
+        let blocks: Vec<DependsBlock> = stream.collect().await;
+
+        child
+            .wait()
+            .await
+            .map_result()
+            .context("bad status from `apt-cache depends`")?;
+
+        Ok(predepends_map(&packages, &blocks))
+    }
+
+    /// Every installed package which depends on `package`, directly or transitively,
+    /// such as to warn that removing a library would also remove them.
+    ///
+    /// Only Depends and PreDepends are followed, as they alone cause a package to be
+    /// removed with its dependency. Each level is queried with one `apt-cache rdepends`.
+    pub async fn reverse_closure(package: &str) -> anyhow::Result<HashSet<String>> {
+        let mut closure = HashSet::new();
+        let mut frontier = vec![package.to_owned()];
+        closure.insert(package.to_owned());
+
+        while !frontier.is_empty() {
+            let (mut child, stream) = AptCache::new()
+                .installed()
+                .installing_relations()
+                .no_recommends()
+                .rdepends_tagged(&frontier)
+                .await
+                .context("failed to launch `apt-cache rdepends`")?;
+
+            let dependencies: Vec<ReverseDependency> = stream.collect().await;
+
+            child
+                .wait()
+                .await
+                .map_result()
+                .context("bad status from `apt-cache rdepends`")?;
+
+            frontier = extend_closure(&mut closure, dependencies);
+        }
+
+        closure.remove(package);
+        Ok(closure)
+    }
+
+    /// Counts of the packages, versions, and relations in the package cache.
+    pub async fn stats(mut self) -> io::Result<CacheStats> {
+        self.arg("stats");
+
+        let (mut child, mut stdout) = self.spawn_with_stdout().await?;
+
+        let mut output = String::new();
+        stdout.read_to_string(&mut output).await?;
+        child.wait().await.map_result()?;
+
+        Ok(CacheStats::parse_str(&output))
+    }
+
+    /// The configured package files and their pin priorities.
+    pub async fn package_files(mut self) -> io::Result<Vec<PackageFile>> {
+        self.arg("policy");
+
+        let (mut child, mut stdout) = self.spawn_with_stdout().await?;
+
+        let mut output = String::new();
+        stdout.read_to_string(&mut output).await?;
+        child.wait().await.map_result()?;
+
+        Ok(PackageFile::parse_str(&output))
     }
 
     pub async fn status(mut self) -> io::Result<()> {
-        self.0.status().await?.into_result()
+        self.command.status().await?.into_result()
     }
 
     pub async fn spawn_with_stdout(self) -> io::Result<(Child, ChildStdout)> {
-        crate::utils::spawn_with_stdout(self.0).await
+        crate::utils::spawn_with_stdout(self.command).await
     }
 }
+
+/// Adds the dependents to the closure, returning those which were not already in it.
+fn extend_closure(
+    closure: &mut HashSet<String>,
+    dependencies: Vec<ReverseDependency>,
+) -> Vec<String> {
+    dependencies
+        .into_iter()
+        .filter_map(|dependency| {
+            if closure.insert(dependency.dependent.clone()) {
+                Some(dependency.dependent)
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+/// Splits arguments into chunks of at most `max_bytes`, counting their nul terminators.
+/// An argument larger than `max_bytes` is in a chunk of its own.
+fn chunk_args<S: AsRef<std::ffi::OsStr>>(
+    args: &[S],
+    max_bytes: usize,
+) -> Vec<Vec<std::ffi::OsString>> {
+    let mut chunks = Vec::new();
+    let mut chunk = Vec::new();
+    let mut bytes = 0;
+
+    for arg in args {
+        let arg = arg.as_ref();
+        let len = arg.len() + 1;
+
+        if !chunk.is_empty() && bytes + len > max_bytes {
+            chunks.push(std::mem::take(&mut chunk));
+            bytes = 0;
+        }
+
+        chunk.push(arg.to_owned());
+        bytes += len;
+    }
+
+    if !chunk.is_empty() {
+        chunks.push(chunk);
+    }
+
+    chunks
+}
+
+/// The version of a `  Candidate: 2.10-2ubuntu4` line of `apt-cache policy`.
+fn parse_candidate_line(line: &str) -> Option<&str> {
+    line.trim_start().strip_prefix("Candidate:").map(str::trim)
+}
+
+/// Parses a package file, such as `500 http://apt.pop-os.org/release jammy/main amd64 Packages`.
+fn parse_package_file_line(line: &str) -> Option<PackageFile> {
+    let mut words = line.split_ascii_whitespace();
+    let priority = words.next()?.parse::<i32>().ok()?;
+    let url = words.next()?.to_owned();
+
+    let mut file = PackageFile {
+        priority,
+        url,
+        ..PackageFile::default()
+    };
+
+    let rest: Vec<&str> = words.collect();
+
+    match rest.as_slice() {
+        [] => (),
+        // A flat repository has a path in place of its suite and component.
+        [suite, "Packages"] => file.suite = (*suite).to_owned(),
+        [distribution, architecture, "Packages"] => {
+            let (suite, component) = distribution.rsplit_once('/').unwrap_or((distribution, ""));
+            file.suite = suite.to_owned();
+            file.component = component.to_owned();
+            file.architecture = (*architecture).to_owned();
+        }
+        _ => return None,
+    }
+
+    Some(file)
+}
+
+/// Parses a line of `apt-cache madison`, such as
+/// `   hello | 2.10-2ubuntu4 | http://us.archive.ubuntu.com/ubuntu jammy/main amd64 Packages`.
+fn parse_madison_line(line: &str) -> Option<(String, String, String)> {
+    let mut fields = line.split(" | ").map(str::trim);
+    let package = fields.next().filter(|package| !package.is_empty())?;
+    let version = fields.next()?;
+    let source = fields.next()?;
+
+    Some((package.to_owned(), version.to_owned(), source.to_owned()))
+}
+
+/// Parses a line of `apt-cache unmet`, where each package is introduced by a line such
+/// as `Package wine version 8.0 has an unmet dep:`, followed by lines of its relations.
+fn parse_unmet_line(
+    package: &mut Option<String>,
+    line: &str,
+) -> Option<(String, DependencyKind, String)> {
+    if let Some(rest) = line.strip_prefix("Package ") {
+        *package = rest.split_whitespace().next().map(String::from);
+        return None;
+    }
+
+    let (kind, missing) = line.trim_start().split_once(": ")?;
+    let kind = kind.parse::<DependencyKind>().ok()?;
+
+    Some((package.clone()?, kind, missing.trim().to_owned()))
+}
+
+/// Parses the percentage of a version table entry such as `2.0-1 1 (phased 10%)`.
+fn parse_phased(entry: &str) -> Option<u8> {
+    let (_, phased) = entry.split_once("(phased ")?;
+    let (percentage, _) = phased.split_once("%)")?;
+    percentage
+        .trim()
+        .parse()
+        .ok()
+        .filter(|&percentage| percentage <= 100)
+}
+
+/// Parses a line of `apt-cache search`, such as `hello - example package based on GNU hello`.
+fn parse_search_line(line: &str) -> Option<(String, String)> {
+    let (package, description) = line.split_once(" - ")?;
+    Some((package.to_owned(), description.to_owned()))
+}
+
+/// Maps each of `packages` to the blocks which pre-depend on it.
+fn predepends_map(packages: &[&str], blocks: &[DependsBlock]) -> HashMap<String, Vec<String>> {
+    let mut map: HashMap<String, Vec<String>> = HashMap::new();
+
+    for block in blocks {
+        for dependency in &block.dependencies {
+            if dependency.kind != DependencyKind::PreDepends
+                || !packages.contains(&dependency.package.as_str())
+            {
+                continue;
+            }
+
+            let dependents = map.entry(dependency.package.clone()).or_default();
+            if !dependents.contains(&block.package) {
+                dependents.push(block.package.clone());
+            }
+        }
+    }
+
+    map
+}
+
 pub struct PreDependsIter<'a> {
     lines: std::str::Lines<'a>,
     predepend: &'a str,
@@ -240,3 +1240,422 @@ impl<'a> Iterator for PreDependsIter<'a> {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::PolicyParser;
+    use crate::depends::{DependencyKind, DependsParser};
+
+    const POLICY: &str = "hello:
+  Installed: (none)
+  Candidate: 2.10-2ubuntu4
+  Version table:
+     2.10-2ubuntu4 500
+        500 http://us.archive.ubuntu.com/ubuntu jammy/main amd64 Packages
+firefox:
+  Installed: 119.0+build2-0ubuntu0.22.04.1~mt1
+  Candidate: 119.0+build2-0ubuntu0.22.04.1~mt1
+  Version table:
+ *** 119.0+build2-0ubuntu0.22.04.1~mt1 1001
+       1001 http://apt.pop-os.org/release jammy/main amd64 Packages
+        100 /var/lib/dpkg/status
+";
+
+    #[test]
+    fn policy_parser() {
+        let mut parser = PolicyParser::default();
+        let mut policies: Vec<_> = POLICY
+            .lines()
+            .filter_map(|line| parser.parse_line(line))
+            .collect();
+        policies.push(parser.finish());
+
+        assert_eq!(2, policies.len());
+
+        assert_eq!("hello", policies[0].package);
+        assert_eq!("(none)", policies[0].installed);
+        assert_eq!(
+            Some(&vec![
+                "500 http://us.archive.ubuntu.com/ubuntu jammy/main amd64 Packages".to_owned()
+            ]),
+            policies[0].version_table.get("2.10-2ubuntu4 500")
+        );
+
+        assert_eq!("firefox", policies[1].package);
+        assert_eq!(
+            2,
+            policies[1].version_table["119.0+build2-0ubuntu0.22.04.1~mt1 1001"].len()
+        );
+    }
+
+    #[test]
+    fn policy_parser_diagnostics() {
+        let output = "  Installed: 1.0
+hello:
+  Installed: (none)
+  Candidate: 2.10-2ubuntu4
+  Package pin: 2.10-2ubuntu4
+  Unknown: field
+  Version table:
+     2.10-2ubuntu4 500
+        500 http://us.archive.ubuntu.com/ubuntu jammy/main amd64 Packages
+ garbage
+firefox:
+  Installed: (none)
+";
+
+        let mut parser = PolicyParser::default();
+        let mut policies = Vec::new();
+        let mut unexpected = Vec::new();
+
+        for line in output.lines() {
+            match parser.try_parse_line(line) {
+                Ok(Some(policy)) => policies.push(policy),
+                Ok(None) => (),
+                Err(super::PolicyParseError::UnexpectedLine(line)) => unexpected.push(line),
+                Err(why) => panic!("{}", why),
+            }
+        }
+
+        policies.push(parser.finish());
+
+        assert_eq!(
+            vec!["  Installed: 1.0", "  Unknown: field", " garbage"],
+            unexpected
+        );
+
+        assert_eq!(2, policies.len());
+        assert_eq!("hello", policies[0].package);
+        assert_eq!("(none)", policies[0].installed);
+        assert_eq!(1, policies[0].version_table.len());
+        assert_eq!("firefox", policies[1].package);
+
+        // The well-formed output has no diagnostics.
+        let mut parser = PolicyParser::default();
+        assert!(POLICY
+            .lines()
+            .all(|line| parser.try_parse_line(line).is_ok()));
+    }
+
+    #[test]
+    fn policy_phased_percentage() {
+        let output = "gnome-shell:
+  Installed: 42.5-0ubuntu1
+  Candidate: 42.9-0ubuntu2
+  Version table:
+     42.9-0ubuntu2 1 (phased 20%)
+        500 http://us.archive.ubuntu.com/ubuntu jammy-updates/main amd64 Packages
+ *** 42.5-0ubuntu1 100
+        100 /var/lib/dpkg/status
+hello:
+  Installed: (none)
+  Candidate: 2.10-2ubuntu4
+  Version table:
+     2.10-2ubuntu5 1 (phased 0%)
+        500 http://us.archive.ubuntu.com/ubuntu jammy-proposed/main amd64 Packages
+     2.10-2ubuntu4 500
+        500 http://us.archive.ubuntu.com/ubuntu jammy/main amd64 Packages
+";
+
+        let policies = super::Policy::parse_str(output);
+        assert_eq!(Some(20), policies[0].phased_percentage);
+        assert!(policies[0]
+            .version_table
+            .contains_key("42.9-0ubuntu2 1 (phased 20%)"));
+
+        // Only the phasing of the candidate is recorded.
+        assert_eq!(None, policies[1].phased_percentage);
+
+        assert_eq!(Some(5), super::parse_phased("1.0 500 (phased 5%)"));
+        assert_eq!(None, super::parse_phased("1.0 500"));
+        assert_eq!(None, super::parse_phased("1.0 500 (phased 120%)"));
+    }
+
+    #[test]
+    fn policy_parse_str() {
+        let policies = super::Policy::parse_str(POLICY);
+        assert_eq!(2, policies.len());
+        assert_eq!("2.10-2ubuntu4", policies[0].candidate);
+        assert_eq!("firefox", policies[1].package);
+
+        assert!(super::Policy::parse_str("").is_empty());
+    }
+
+    #[test]
+    fn policy_argv_chunks() {
+        let packages = ["firefox", "hello", "libc6", "bash"];
+
+        assert_eq!(
+            vec![vec!["firefox", "hello"], vec!["libc6", "bash"]],
+            super::chunk_args(&packages, 14)
+        );
+
+        assert_eq!(vec![packages.to_vec()], super::chunk_args(&packages, 1024));
+        assert_eq!(4, super::chunk_args(&packages, 1).len());
+        assert!(super::chunk_args::<&str>(&[], 1024).is_empty());
+    }
+
+    #[test]
+    fn reverse_closure_levels() {
+        let dependency = |package: &str, dependent: &str| crate::ReverseDependency {
+            package: package.into(),
+            dependent: dependent.into(),
+            kind: None,
+        };
+
+        let mut closure: std::collections::HashSet<String> =
+            vec!["libfoo1".to_owned()].into_iter().collect();
+
+        let frontier = super::extend_closure(
+            &mut closure,
+            vec![dependency("libfoo1", "foo"), dependency("libfoo1", "bar")],
+        );
+        assert_eq!(vec!["foo", "bar"], frontier);
+
+        // Cycles, and packages reached from more than one dependency, are not revisited.
+        let frontier = super::extend_closure(
+            &mut closure,
+            vec![
+                dependency("foo", "bar"),
+                dependency("foo", "libfoo1"),
+                dependency("bar", "baz"),
+            ],
+        );
+        assert_eq!(vec!["baz"], frontier);
+        assert_eq!(4, closure.len());
+    }
+
+    #[test]
+    fn candidate_lines() {
+        let candidates: Vec<_> = POLICY
+            .lines()
+            .filter_map(super::parse_candidate_line)
+            .collect();
+
+        assert_eq!(
+            vec!["2.10-2ubuntu4", "119.0+build2-0ubuntu0.22.04.1~mt1"],
+            candidates
+        );
+    }
+
+    #[test]
+    fn predepends_map() {
+        let output = "dpkg
+  PreDepends: libc6
+  Depends: tar
+perl-base
+  PreDepends: libc6
+  PreDepends: dpkg
+tar
+  PreDepends: libc6
+";
+
+        let mut parser = DependsParser::default();
+        let mut blocks: Vec<_> = output
+            .lines()
+            .filter_map(|line| parser.parse_line(line))
+            .collect();
+        blocks.extend(parser.finish());
+
+        let map = super::predepends_map(&["libc6", "dpkg", "tar"], &blocks);
+
+        assert_eq!(
+            vec!["dpkg".to_owned(), "perl-base".into(), "tar".into()],
+            map["libc6"]
+        );
+        assert_eq!(vec!["perl-base".to_owned()], map["dpkg"]);
+        assert!(!map.contains_key("tar"));
+    }
+
+    #[tokio::test]
+    async fn packages_follow_separator() {
+        use tokio::io::AsyncReadExt;
+
+        let command = tokio::process::Command::new("echo");
+        let (mut child, mut stdout) = super::AptCache::with_command(command)
+            .depends(&["-f"])
+            .await
+            .unwrap();
+
+        let mut output = String::new();
+        stdout.read_to_string(&mut output).await.unwrap();
+        child.wait().await.unwrap();
+
+        assert_eq!("depends -- -f\n", output);
+    }
+
+    #[tokio::test]
+    async fn pkgnames_prefix_follows_separator() {
+        use futures::StreamExt;
+
+        let command = tokio::process::Command::new("echo");
+        let (mut child, names) = super::AptCache::with_command(command)
+            .pkgnames(Some("-f"))
+            .await
+            .unwrap();
+
+        let names: Vec<String> = names.collect().await;
+        child.wait().await.unwrap();
+
+        assert_eq!(vec!["pkgnames -- -f"], names);
+    }
+
+    #[tokio::test]
+    async fn candidate_version_fails_with_apt_cache() {
+        let script = "echo firefox:; echo '  Candidate: 120.0'; exit ${CODE}";
+        let candidate = |code: &str| {
+            let mut command = tokio::process::Command::new("sh");
+            command.args(["-c", script]).env("CODE", code);
+            super::AptCache::with_command(command).candidate_version("firefox")
+        };
+
+        assert_eq!(Some("120.0".to_owned()), candidate("0").await.unwrap());
+        assert!(candidate("100").await.is_err());
+    }
+
+    #[test]
+    fn search_lines() {
+        assert_eq!(
+            Some((
+                "hello".to_owned(),
+                "example package based on GNU hello - with dashes".to_owned()
+            )),
+            super::parse_search_line("hello - example package based on GNU hello - with dashes")
+        );
+
+        assert_eq!(None, super::parse_search_line("hello"));
+    }
+
+    #[test]
+    fn cache_stats() {
+        let output = "Total package names: 120543 (3,375 k)
+Total package structures: 120543 (5,786 k)
+  Normal packages: 88903
+  Pure virtual packages: 1588
+  Single virtual packages: 13154
+  Mixed virtual packages: 2130
+  Missing: 14768
+Total distinct versions: 101520 (8,121 k)
+Total distinct descriptions: 216234 (5,190 k)
+Total dependencies: 572155/135406 (14.2 M)
+Total ver/file relations: 35154 (844 k)
+Total Provides mappings: 21787 (523 k)
+Total space accounted for: 65.6 M
+";
+
+        assert_eq!(
+            super::CacheStats {
+                package_names: 120543,
+                normal_packages: 88903,
+                pure_virtual_packages: 1588,
+                single_virtual_packages: 13154,
+                mixed_virtual_packages: 2130,
+                missing_packages: 14768,
+                versions: 101520,
+                descriptions: 216234,
+                dependencies: 572155,
+                provides: 21787,
+            },
+            super::CacheStats::parse_str(output)
+        );
+    }
+
+    #[test]
+    fn package_files() {
+        let output = "Package files:
+ 100 /var/lib/dpkg/status
+     release a=now
+ 1001 http://apt.pop-os.org/release jammy/main amd64 Packages
+     release o=pop-os-release,a=jammy,n=jammy,l=pop-os-release,c=main,b=amd64
+     origin apt.pop-os.org
+ 500 http://us.archive.ubuntu.com/ubuntu jammy-updates/restricted i386 Packages
+     release v=22.04,o=Ubuntu,a=jammy-updates,n=jammy,l=Ubuntu,c=restricted,b=i386
+     origin us.archive.ubuntu.com
+ -10 file:/srv/repo ./ Packages
+Pinned packages:
+     firefox -> 119.0 with priority 1001
+";
+
+        let files = super::PackageFile::parse_str(output);
+        assert_eq!(4, files.len());
+
+        assert_eq!("/var/lib/dpkg/status", files[0].url);
+        assert_eq!("now", files[0].suite);
+        assert_eq!(100, files[0].priority);
+
+        assert_eq!(
+            super::PackageFile {
+                priority: 500,
+                url: "http://us.archive.ubuntu.com/ubuntu".into(),
+                suite: "jammy-updates".into(),
+                component: "restricted".into(),
+                architecture: "i386".into(),
+                origin: "Ubuntu".into(),
+            },
+            files[2]
+        );
+
+        assert_eq!(1001, files[1].priority);
+        assert_eq!("pop-os-release", files[1].origin);
+
+        assert_eq!(-10, files[3].priority);
+        assert_eq!("./", files[3].suite);
+        assert!(files[3].component.is_empty());
+    }
+
+    #[test]
+    fn unmet_lines() {
+        let output = "Package wine version 8.0 has an unmet dep:
+ Depends: wine64 (>= 8.0) | wine32 (>= 8.0)
+ Recommends: fonts-wine
+Package libfoo1 version 1.0 has an unmet dep:
+ Pre-Depends: libbar1 (= 1.0)
+";
+
+        let mut package = None;
+        let entries: Vec<_> = output
+            .lines()
+            .filter_map(|line| super::parse_unmet_line(&mut package, line))
+            .collect();
+
+        assert_eq!(
+            vec![
+                (
+                    "wine".to_owned(),
+                    DependencyKind::Depends,
+                    "wine64 (>= 8.0) | wine32 (>= 8.0)".to_owned()
+                ),
+                (
+                    "wine".to_owned(),
+                    DependencyKind::Recommends,
+                    "fonts-wine".to_owned()
+                ),
+                (
+                    "libfoo1".to_owned(),
+                    DependencyKind::PreDepends,
+                    "libbar1 (= 1.0)".to_owned()
+                ),
+            ],
+            entries
+        );
+    }
+
+    #[test]
+    fn madison_lines() {
+        assert_eq!(
+            Some((
+                "hello".to_owned(),
+                "2.10-2ubuntu4".to_owned(),
+                "http://us.archive.ubuntu.com/ubuntu jammy/main amd64 Packages".to_owned()
+            )),
+            super::parse_madison_line(
+                "     hello | 2.10-2ubuntu4 | http://us.archive.ubuntu.com/ubuntu jammy/main amd64 Packages"
+            )
+        );
+
+        assert_eq!(
+            None,
+            super::parse_madison_line("N: Unable to locate package nothing")
+        );
+    }
+}
@@ -0,0 +1,110 @@
+// Copyright 2021-2022 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+use anyhow::Context;
+use as_result::IntoResult;
+use std::io;
+use std::path::Path;
+use tokio::process::Command;
+
+/// The identity of a disc, as reported by `apt-cdrom ident`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct CdromIdent {
+    pub id: String,
+    pub label: Option<String>,
+}
+
+#[derive(AsMut, Deref, DerefMut)]
+#[as_mut(forward)]
+pub struct AptCdrom(pub(crate) Command);
+
+impl AptCdrom {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self(crate::utils::command("apt-cdrom"))
+    }
+
+    /// Uses the given directory as the mount point of the media.
+    pub fn mount_point(mut self, path: &Path) -> Self {
+        self.arg("-d");
+        self.arg(path);
+        self
+    }
+
+    /// Skips mounting and unmounting, for media which is already mounted.
+    pub fn no_mount(mut self) -> Self {
+        self.arg("-m");
+        self
+    }
+
+    /// Adds the media to the sources list.
+    pub async fn add(mut self) -> io::Result<()> {
+        self.arg("add");
+        self.status().await
+    }
+
+    /// Reports the identity and label of the media.
+    pub async fn ident(mut self) -> anyhow::Result<CdromIdent> {
+        self.arg("ident");
+
+        let output = self
+            .0
+            .output()
+            .await
+            .context("failed to launch `apt-cdrom ident`")?;
+
+        if !output.status.success() {
+            return Err(anyhow::anyhow!("`apt-cdrom ident` exited in error"));
+        }
+
+        parse_ident(&String::from_utf8_lossy(&output.stdout))
+            .context("disc identity not found in `apt-cdrom ident` output")
+    }
+
+    pub async fn status(mut self) -> io::Result<()> {
+        self.0.status().await?.into_result()
+    }
+}
+
+fn parse_ident(output: &str) -> Option<CdromIdent> {
+    let mut ident: Option<CdromIdent> = None;
+    let mut label = None;
+
+    for line in output.lines() {
+        if let Some(id) = line.strip_prefix("Identifying... [") {
+            ident = Some(CdromIdent {
+                id: id.trim_end_matches(']').to_owned(),
+                label: None,
+            });
+        } else if let Some(value) = line.strip_prefix("Stored label: ") {
+            label = Some(value.to_owned());
+        }
+    }
+
+    ident.map(|ident| CdromIdent { label, ..ident })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CdromIdent;
+
+    const OUTPUT: &str = "Using CD-ROM mount point /media/cdrom/
+Mounting CD-ROM...
+Identifying... [0eabc03d10414e59dfa1622326e20da7-2]
+Stored label: Pop_OS 22.04 _Jammy Jellyfish_ - Release amd64
+Unmounting CD-ROM...
+";
+
+    #[test]
+    fn parse_ident() {
+        assert_eq!(
+            Some(CdromIdent {
+                id: "0eabc03d10414e59dfa1622326e20da7-2".into(),
+                label: Some("Pop_OS 22.04 _Jammy Jellyfish_ - Release amd64".into()),
+            }),
+            super::parse_ident(OUTPUT)
+        );
+
+        assert_eq!(None, super::parse_ident("Mounting CD-ROM...\n"));
+    }
+}
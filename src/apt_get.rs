@@ -1,19 +1,84 @@
 // Copyright 2021-2022 System76 <info@system76.com>
 // SPDX-License-Identifier: MPL-2.0
 
+use crate::apt::SimulationPlan;
+use crate::hooks::{HookError, HookEvent, HookKind, Hooks, Operation};
 use crate::request::{Request, RequestError};
-use crate::AptUpgradeEvent;
+use crate::update::{FetchSummary, IndexEvent, IndexRequest, IndexStatus, IndexTargets};
+use crate::utils::{LineReader, RawLine, DEFAULT_BUFFER_CAPACITY};
+use crate::warnings::Warning;
+use crate::{AptUpgradeEvent, Dpkg, UpgradeParser, UpgradeSummary};
 use as_result::*;
 use async_stream::stream;
 use futures::prelude::*;
-use std::process::ExitStatus;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::time::Instant;
 use std::{collections::HashSet, io, pin::Pin};
-use tokio::io::{AsyncBufReadExt, BufReader};
+use thiserror::Error;
 use tokio::process::{Child, ChildStdout, Command};
 
+/// The subcommands of apt-get which take the dpkg or apt locks.
+const MUTATING: &[&str] = &[
+    "autoclean",
+    "autopurge",
+    "autoremove",
+    "build-dep",
+    "clean",
+    "dist-upgrade",
+    "dselect-upgrade",
+    "full-upgrade",
+    "install",
+    "purge",
+    "reinstall",
+    "remove",
+    "satisfy",
+    "update",
+    "upgrade",
+];
+
+/// The options of apt-get whose value is the next argument.
+const VALUED_OPTIONS: &[&str] = &[
+    "-a",
+    "--host-architecture",
+    "-c",
+    "--config-file",
+    "-o",
+    "--option",
+    "-t",
+    "--target-release",
+    "--default-release",
+];
+
+/// The spellings of the option which simulates an operation, rather than performing it.
+const SIMULATE: &[&str] = &[
+    "-s",
+    "--simulate",
+    "--just-print",
+    "--dry-run",
+    "--recon",
+    "--no-act",
+];
+
 #[derive(Debug)]
 pub enum UpdateEvent {
     BadPPA(BadPPA),
+    /// An index was hit, fetched, or ignored.
+    Index(IndexEvent),
+    /// The amount of data fetched by the update, and how long it took.
+    Fetched(FetchSummary),
+    /// An index failed to download because its hash did not match, as when a mirror
+    /// is caught in the middle of a sync.
+    HashSumMismatch {
+        uri: String,
+    },
+    /// The update is being retried after a hash sum mismatch.
+    Retrying {
+        attempt: u32,
+    },
+    Warning(Warning),
+    /// A line of output, when enabled by `AptGet::verbose_events`.
+    RawLine(RawLine),
     ExitStatus(io::Result<ExitStatus>),
 }
 
@@ -23,18 +88,319 @@ pub struct BadPPA {
     pub pocket: String,
 }
 
+/// An error from [`AptGet::install`].
+#[derive(Debug, Error)]
+pub enum InstallError {
+    /// Packages which apt could not locate, so nothing was installed.
+    #[error("unable to locate packages: {}", .0.join(", "))]
+    NotFound(Vec<String>),
+
+    /// A virtual package was requested, which must be installed by choosing one of the
+    /// packages that provide it.
+    #[error("{} is a virtual package provided by: {}", package, providers.join(", "))]
+    VirtualPackageNeedsProvider {
+        package: String,
+        providers: Vec<String>,
+    },
+
+    #[error("apt-get install failed")]
+    Command(#[from] io::Error),
+}
+
+impl From<InstallError> for io::Error {
+    fn from(why: InstallError) -> Self {
+        match why {
+            InstallError::Command(why) => why,
+            why @ InstallError::NotFound(_) => io::Error::new(io::ErrorKind::NotFound, why),
+            why @ InstallError::VirtualPackageNeedsProvider { .. } => {
+                io::Error::new(io::ErrorKind::InvalidInput, why)
+            }
+        }
+    }
+}
+
+/// An error from [`AptGet::remove`].
+#[derive(Debug, Error)]
+pub enum RemoveError {
+    /// Installed packages which are essential or required were to be removed, so
+    /// nothing was removed.
+    #[error("refusing to remove essential packages: {}", .0.join(", "))]
+    Essential(Vec<String>),
+
+    #[error("apt-get remove failed")]
+    Command(#[from] io::Error),
+}
+
+impl From<RemoveError> for io::Error {
+    fn from(why: RemoveError) -> Self {
+        match why {
+            RemoveError::Command(why) => why,
+            why @ RemoveError::Essential(_) => io::Error::new(io::ErrorKind::PermissionDenied, why),
+        }
+    }
+}
+
+/// Whether the `-o` option sets `APT::Get::Simulate` to true.
+fn sets_simulate(option: &str) -> bool {
+    let Some((key, value)) = option.split_once('=') else {
+        return false;
+    };
+
+    key.eq_ignore_ascii_case("APT::Get::Simulate")
+        && ["1", "yes", "true", "with", "on", "enable"]
+            .iter()
+            .any(|truth| value.eq_ignore_ascii_case(truth))
+}
+
+/// The packages of `names` which are installed, and essential or required.
+///
+/// Names are those printed by apt, which qualifies packages with their architecture,
+/// unless it is `native`.
+fn essential_packages<I, S>(
+    stanzas: &[crate::dpkg::status::Stanza],
+    native: &str,
+    names: I,
+) -> Vec<String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    names
+        .into_iter()
+        .filter(|name| {
+            let (package, arch) = match name.as_ref().split_once(':') {
+                Some((package, arch)) => (package, arch),
+                None => (name.as_ref(), native),
+            };
+
+            stanzas.iter().any(|stanza| {
+                let stanza_arch = stanza.get("Architecture").unwrap_or(arch);
+
+                stanza.package() == Some(package)
+                    && (stanza_arch == arch || stanza_arch == "all")
+                    && stanza.is_installed()
+                    && stanza.is_essential()
+            })
+        })
+        .map(|name| name.as_ref().to_owned())
+        .collect()
+}
+
+pub type UpdateEvents = Pin<Box<dyn Stream<Item = UpdateEvent> + Send>>;
+
 pub type UpgradeEvents = Pin<Box<dyn Stream<Item = AptUpgradeEvent> + Send>>;
 
 #[derive(AsMut, Deref, DerefMut)]
-#[as_mut(forward)]
-pub struct AptGet(Command);
+pub struct AptGet {
+    #[as_mut(forward)]
+    #[deref]
+    #[deref_mut]
+    command: Command,
+    #[as_mut(ignore)]
+    verbose: bool,
+    #[as_mut(ignore)]
+    privilege_check: bool,
+    #[as_mut(ignore)]
+    allow_remove_essential: bool,
+    /// Where the dpkg status database is read from, to guard essential packages.
+    #[as_mut(ignore)]
+    admindir: Option<PathBuf>,
+    /// Qualifies packages without an architecture, as by `host_architecture`.
+    #[as_mut(ignore)]
+    architecture: Option<String>,
+    #[as_mut(ignore)]
+    hooks: Hooks,
+}
 
 impl AptGet {
     #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
-        let mut cmd = Command::new("apt-get");
-        cmd.env("LANG", "C");
-        Self(cmd)
+        Self::with_command(crate::utils::command("apt-get"))
+    }
+
+    pub(crate) fn with_command(command: Command) -> Self {
+        Self {
+            command,
+            verbose: false,
+            privilege_check: true,
+            allow_remove_essential: false,
+            admindir: Some(PathBuf::from(crate::dpkg::status::ADMINDIR)),
+            architecture: None,
+            hooks: Hooks::default(),
+        }
+    }
+
+    /// A copy of this command, with its backend, options, and hooks.
+    fn duplicate(&self) -> Self {
+        Self {
+            command: crate::utils::clone_command(&self.command),
+            verbose: self.verbose,
+            privilege_check: self.privilege_check,
+            allow_remove_essential: self.allow_remove_essential,
+            admindir: self.admindir.clone(),
+            architecture: self.architecture.clone(),
+            hooks: self.hooks.clone(),
+        }
+    }
+
+    pub(crate) fn admindir(mut self, admindir: Option<PathBuf>) -> Self {
+        self.admindir = admindir;
+        self
+    }
+
+    /// Permits `remove` to remove essential and required packages.
+    ///
+    /// Without this, `remove` refuses with `RemoveError::Essential`. The guard is not
+    /// applied within a `Backend::Ssh`, whose dpkg status cannot be read locally.
+    pub fn allow_remove_essential(mut self) -> Self {
+        self.allow_remove_essential = true;
+        self.arg("--allow-remove-essential");
+        self
+    }
+
+    /// Calls these hooks around each operation which updates the indexes, or changes
+    /// packages. The `*_with_summary` operations record the IDs of any snapshots.
+    ///
+    /// Package hooks receive only the events of the streaming and `*_with_summary`
+    /// operations, as `install`, `upgrade`, `remove`, and `satisfy` leave their output
+    /// to the terminal.
+    pub fn hooks(mut self, hooks: Hooks) -> Self {
+        self.hooks = hooks;
+        self
+    }
+
+    /// Targets packages of an architecture other than the native one, such as `i386`
+    /// libraries on an `amd64` system.
+    ///
+    /// Packages given without an architecture qualifier are qualified with `arch`, and
+    /// it is passed as `--host-architecture` for `build-dep` and `source`. The
+    /// architecture must have been enabled with `Dpkg::add_architecture`.
+    pub fn host_architecture(mut self, arch: &str) -> Self {
+        self.args(["--host-architecture", arch]);
+        self.architecture = Some(arch.to_owned());
+        self
+    }
+
+    /// Validates the packages, and qualifies them with the host architecture.
+    ///
+    /// Paths of local `.deb` archives are passed as they are.
+    ///
+    /// Fails with `InvalidInput` if a package is qualified with an architecture which
+    /// the backend's dpkg database is not configured to install. The check is not
+    /// applied within a `Backend::Ssh`, whose database cannot be read locally.
+    async fn target_packages<I, S>(&self, packages: I) -> io::Result<Vec<String>>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<std::ffi::OsStr>,
+    {
+        let packages: Vec<String> = crate::package::validate_packages(packages)?
+            .iter()
+            .map(|package| {
+                let package = package.as_ref().to_string_lossy();
+                match self.architecture {
+                    Some(ref arch) if !crate::package::is_deb_path(&package) => {
+                        crate::package::qualify_architecture(&package, arch)
+                    }
+                    _ => package.into_owned(),
+                }
+            })
+            .collect();
+
+        let mut requested: Vec<&str> = packages
+            .iter()
+            .filter(|package| !crate::package::is_deb_path(package))
+            .filter_map(|package| crate::package::package_architecture(package))
+            .filter(|arch| *arch != "all" && *arch != "any" && *arch != "native")
+            .collect();
+
+        requested.sort_unstable();
+        requested.dedup();
+
+        let admindir = match self.admindir {
+            Some(ref admindir) if !requested.is_empty() => admindir,
+            _ => return Ok(packages),
+        };
+
+        let mut enabled = Dpkg::new()
+            .admindir(admindir)
+            .foreign_architectures()
+            .await?;
+        enabled.push(Dpkg::new().admindir(admindir).architecture().await?);
+
+        if let Some(arch) = requested
+            .into_iter()
+            .find(|arch| !enabled.iter().any(|enabled| enabled == arch))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("architecture {} is not enabled in dpkg", arch),
+            ));
+        }
+
+        Ok(packages)
+    }
+
+    /// Spawns commands which modify the system without first checking that the process
+    /// is privileged, for callers which escalate privileges themselves.
+    pub fn skip_privilege_check(mut self) -> Self {
+        self.privilege_check = false;
+        self
+    }
+
+    /// Fails with `privilege::Error::NotPrivileged` if this command would modify the
+    /// system, but the process may not.
+    ///
+    /// The lock of the backend's dpkg database is checked, so the check is not applied
+    /// within a `Backend::Ssh`, whose database cannot be read locally.
+    fn preflight(&self) -> io::Result<()> {
+        let admindir = match self.admindir {
+            Some(ref admindir) if self.privilege_check => admindir,
+            _ => return Ok(()),
+        };
+
+        let Some(subcommand) = self.mutating_subcommand() else {
+            return Ok(());
+        };
+
+        let required_by = ["apt-get ", &subcommand].concat();
+        Ok(crate::privilege::ensure_privileged_for(
+            &required_by,
+            admindir,
+        )?)
+    }
+
+    /// The subcommand, if it will modify the system. Others, such as `download` and
+    /// `changelog`, and simulations, may be run by any user.
+    fn mutating_subcommand(&self) -> Option<String> {
+        let mut args = self.as_std().get_args().map(|arg| arg.to_string_lossy());
+        let mut subcommand = None;
+
+        // The subcommand is the first argument which is neither an option, nor its value.
+        // Options may also follow it, up to the `--` before the packages.
+        while let Some(arg) = args.next() {
+            if arg == "--" {
+                break;
+            } else if SIMULATE.contains(&arg.as_ref()) {
+                return None;
+            } else if arg == "-o" || arg == "--option" {
+                if matches!(args.next(), Some(option) if sets_simulate(&option)) {
+                    return None;
+                }
+            } else if VALUED_OPTIONS.contains(&arg.as_ref()) {
+                args.next();
+            } else if subcommand.is_none() && !arg.starts_with('-') {
+                subcommand = Some(arg.into_owned());
+            }
+        }
+
+        subcommand.filter(|subcommand| MUTATING.contains(&subcommand.as_str()))
+    }
+
+    /// Has the streaming APIs also yield each line of output as a `RawLine` event,
+    /// before the events parsed from it, for frontends which show the full output.
+    pub fn verbose_events(mut self) -> Self {
+        self.verbose = true;
+        self
     }
 
     pub fn allow_downgrades(mut self) -> Self {
@@ -47,6 +413,26 @@ impl AptGet {
         self
     }
 
+    /// Defers dpkg triggers until all packages have been configured, so that
+    /// triggers such as `update-initramfs` run once rather than once per package.
+    pub fn defer_triggers(mut self) -> Self {
+        self.args([
+            "-o",
+            "DPkg::NoTriggers=true",
+            "-o",
+            "DPkg::ConfigurePending=true",
+            "-o",
+            "DPkg::TriggersPending=true",
+        ]);
+        self
+    }
+
+    /// Has dpkg write its machine-readable status to stdout, so that
+    /// `stream_upgrade` can report trigger processing.
+    pub fn dpkg_status_events(self) -> Self {
+        self.dpkg_option("--status-fd=1")
+    }
+
     pub fn dpkg_option(mut self, option: &str) -> Self {
         self.args(["-o", &["Dpkg::Options::=", option].concat()]);
         self
@@ -57,6 +443,12 @@ impl AptGet {
         self
     }
 
+    /// Proceed with the archives which could be downloaded, rather than aborting.
+    pub fn fix_missing(mut self) -> Self {
+        self.arg("--fix-missing");
+        self
+    }
+
     pub fn force(mut self) -> Self {
         self.arg("-y");
         self
@@ -90,15 +482,125 @@ impl AptGet {
         self.dpkg_option("--force-overwrite")
     }
 
-    pub async fn install<I, S>(mut self, packages: I) -> io::Result<()>
+    /// Installs packages, reporting which of them apt could not find.
+    pub async fn install<I, S>(mut self, packages: I) -> Result<(), InstallError>
     where
         I: IntoIterator<Item = S>,
         S: AsRef<std::ffi::OsStr>,
     {
-        self.arg("install");
-        self.args(packages);
+        let packages = self.target_packages(packages).await?;
+        let template = crate::utils::clone_command(&self.command);
+        self.args(["install", "--"]);
+        self.args(&packages);
+
+        // Errors are parsed from stderr, and reported in the error of a failed install.
+        let mut failure = InstallFailure::default();
+        let mut on_stderr = |line: &str| failure.parse_line(line);
+        let (status, hook_error) = self
+            .run_operation(Operation::Install, packages, Some(&mut on_stderr))
+            .await?;
+
+        if !status.success() {
+            let errors = std::mem::take(&mut failure.errors);
+            if let Some(why) = failure.into_error(template).await {
+                return Err(why);
+            }
 
-        self.status().await
+            if !errors.is_empty() {
+                let why = io::Error::other(errors.join("\n"));
+                return Err(why.into());
+            }
+        }
+
+        status.into_result()?;
+        hook_error.map_or(Ok(()), |why| Err(io::Error::from(why).into()))
+    }
+
+    /// Runs a command which changes packages, between its pre- and post-operation hooks.
+    ///
+    /// The command inherits the terminal, as for its prompts. If `on_stderr` is given,
+    /// stderr is piped instead, and each of its lines is passed to it.
+    ///
+    /// The IDs of any snapshots are passed to the post-operation hooks. Returns the
+    /// error of a post-operation hook, if any.
+    async fn run_operation(
+        mut self,
+        operation: Operation,
+        packages: Vec<String>,
+        on_stderr: Option<&mut dyn FnMut(&str)>,
+    ) -> io::Result<(ExitStatus, Option<HookError>)> {
+        self.preflight()?;
+
+        let hooks = std::mem::take(&mut self.hooks);
+        let snapshots = hooks.run_pre_install(operation, &packages).await?;
+
+        let status = match on_stderr {
+            Some(on_stderr) => {
+                self.stderr(Stdio::piped());
+                let mut child = self.command.spawn()?;
+                let stderr = child.stderr.take().unwrap();
+                let mut lines = LineReader::with_capacity(DEFAULT_BUFFER_CAPACITY, stderr);
+
+                while let Some(line) = lines.next_line().await {
+                    on_stderr(line);
+                }
+
+                child.wait().await?
+            }
+            None => self.command.status().await?,
+        };
+
+        let post = HookEvent::PostInstall {
+            operation,
+            packages,
+            snapshots,
+            success: status.success(),
+        };
+
+        Ok((status, hooks.run(post).await.err()))
+    }
+
+    /// Satisfies dependency relations, such as `libgtk-3-dev (>= 3.24)` or
+    /// `Conflicts: foo`, installing and removing packages as needed.
+    pub async fn satisfy<I, S>(mut self, relations: I) -> io::Result<()>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.args(["satisfy", "--"]);
+        let mut requested = Vec::new();
+
+        for relation in relations {
+            let relation = relation.as_ref().trim();
+            if relation.is_empty() || relation.starts_with('-') {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid relation: {:?}", relation),
+                ));
+            }
+
+            self.arg(relation);
+            requested.push(relation.to_owned());
+        }
+
+        let (status, hook_error) = self
+            .run_operation(Operation::Install, requested, None)
+            .await?;
+
+        status.into_result()?;
+        hook_error.map_or(Ok(()), |why| Err(why.into()))
+    }
+
+    /// Does not install the packages recommended by the packages being installed.
+    pub fn no_install_recommends(mut self) -> Self {
+        self.arg("--no-install-recommends");
+        self
+    }
+
+    /// Also installs the packages suggested by the packages being installed.
+    pub fn install_suggests(mut self) -> Self {
+        self.arg("--install-suggests");
+        self
     }
 
     pub fn mark_auto(mut self) -> Self {
@@ -106,6 +608,12 @@ impl AptGet {
         self
     }
 
+    /// Removes configuration files along with removed packages.
+    pub fn purge(mut self) -> Self {
+        self.arg("--purge");
+        self
+    }
+
     pub fn noninteractive(mut self) -> Self {
         self.env("DEBIAN_FRONTEND", "noninteractive");
         self
@@ -113,7 +621,50 @@ impl AptGet {
 
     pub async fn update(mut self) -> io::Result<()> {
         self.arg("update");
-        self.status().await
+        self.preflight()?;
+
+        let hooks = std::mem::take(&mut self.hooks);
+        hooks.run(HookEvent::PreUpdate).await?;
+
+        let status = self.command.status().await?;
+
+        hooks
+            .run(HookEvent::PostUpdate {
+                success: status.success(),
+            })
+            .await?;
+
+        status.into_result()
+    }
+
+    /// Reads sources from `path` instead of `/etc/apt/sources.list`.
+    pub fn source_list(mut self, path: &Path) -> Self {
+        self.args(["-o", &format!("Dir::Etc::SourceList={}", path.display())]);
+        self
+    }
+
+    /// Reads source parts from `directory` instead of `/etc/apt/sources.list.d`.
+    pub fn source_parts(mut self, directory: &Path) -> Self {
+        self.args([
+            "-o",
+            &format!("Dir::Etc::SourceParts={}", directory.display()),
+        ]);
+        self
+    }
+
+    /// Limits the command to the sources in the file at `path`, such as to update the
+    /// indexes of a single newly-added repository without fetching all of the others.
+    ///
+    /// The lists of the other sources are kept, rather than cleaned up as unused.
+    pub fn scoped_sources(self, path: &Path) -> Self {
+        let mut apt_get = self.source_list(path);
+        apt_get.args([
+            "-o",
+            "Dir::Etc::SourceParts=-",
+            "-o",
+            "APT::Get::List-Cleanup=0",
+        ]);
+        apt_get
     }
 
     pub fn simulate(mut self) -> Self {
@@ -121,38 +672,235 @@ impl AptGet {
         self
     }
 
+    /// Simulates `apt-get install` with the options of this command, such as
+    /// `no_install_recommends`, so that the plan is exactly what the install would do.
+    pub async fn simulate_install<I, S>(mut self, packages: I) -> io::Result<SimulationPlan>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<std::ffi::OsStr>,
+    {
+        let packages = self.target_packages(packages).await?;
+        self.args(["-s", "install", "--"]);
+        self.args(packages);
+
+        let output = self.output().await?;
+        output.status.into_result()?;
+
+        Ok(SimulationPlan::parse(&String::from_utf8_lossy(
+            &output.stdout,
+        )))
+    }
+
     pub async fn upgrade(mut self) -> io::Result<()> {
         self.arg("full-upgrade");
-        self.status().await
+
+        let (status, hook_error) = self
+            .run_operation(Operation::Upgrade, Vec::new(), None)
+            .await?;
+
+        status.into_result()?;
+        hook_error.map_or(Ok(()), |why| Err(why.into()))
     }
 
+    /// Streams events from `apt-get full-upgrade`.
+    ///
+    /// Both stdout and stderr are parsed, so that errors and warnings from apt
+    /// and dpkg are delivered as events rather than printed to the terminal.
     pub async fn stream_upgrade(mut self) -> io::Result<(Child, UpgradeEvents)> {
         self.args(["--show-progress", "full-upgrade"]);
+        self.stream_operation(Operation::Upgrade, Vec::new()).await
+    }
+
+    /// Streams events from `apt-get install`, as with [`AptGet::stream_upgrade`].
+    pub async fn stream_install<I, S>(mut self, packages: I) -> io::Result<(Child, UpgradeEvents)>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<std::ffi::OsStr>,
+    {
+        let packages = self.target_packages(packages).await?;
+        self.args(["--show-progress", "install", "--"]);
+        self.args(&packages);
+        self.stream_operation(Operation::Install, packages).await
+    }
+
+    /// Streams the events of a command which changes packages, between its pre- and
+    /// post-operation hooks, passing each event to the package hooks.
+    ///
+    /// As the exit status is not known to the stream, the post-operation hooks are told
+    /// that the operation succeeded if apt reported no errors. Errors of the hooks after
+    /// apt is spawned are yielded as `AptUpgradeEvent::Error`.
+    async fn stream_operation(
+        mut self,
+        operation: Operation,
+        packages: Vec<String>,
+    ) -> io::Result<(Child, UpgradeEvents)> {
+        self.preflight()?;
+
+        let hooks = std::mem::take(&mut self.hooks);
+        let snapshots = hooks.run_pre_install(operation, &packages).await?;
+
+        let post = HookEvent::PostInstall {
+            operation,
+            packages,
+            snapshots,
+            success: true,
+        };
+
+        let (child, events) = self.stream_events().await?;
+        Ok((child, hooked_events(hooks, events, Some(post))))
+    }
 
-        let (child, stdout) = self.spawn_with_stdout().await?;
+    async fn stream_events(self) -> io::Result<(Child, UpgradeEvents)> {
+        let verbose = self.verbose;
+        let (child, lines) = crate::utils::spawn_with_output(self.command).await?;
 
         let stream = stream! {
-            let mut stdout = BufReader::new(stdout).lines();
+            futures::pin_mut!(lines);
+            let mut parser = UpgradeParser::default();
 
-            while let Ok(Some(line)) = stdout.next_line().await {
-                if let Ok(event) = line.parse::<AptUpgradeEvent>() {
+            while let Some((stream, line)) = lines.next().await {
+                if verbose {
+                    yield AptUpgradeEvent::RawLine(RawLine::new(stream, &line));
+                }
+
+                for event in parser.parse_line(&line) {
                     yield event;
                 }
             }
+
+            if let Some(event) = parser.finish() {
+                yield event;
+            }
         };
 
         Ok((child, Box::pin(stream)))
     }
 
-    pub async fn remove<I, S>(mut self, packages: I) -> io::Result<()>
+    /// Runs `stream_upgrade` to completion, accumulating a summary of the upgrade.
+    ///
+    /// Each event is also passed to `on_event` as it is received.
+    pub async fn upgrade_with_summary<F: FnMut(&AptUpgradeEvent)>(
+        mut self,
+        on_event: F,
+    ) -> io::Result<(ExitStatus, UpgradeSummary)> {
+        let start = Instant::now();
+        self.args(["--show-progress", "full-upgrade"]);
+        self.preflight()?;
+
+        let hooks = std::mem::take(&mut self.hooks);
+        let snapshots = hooks.run_pre_install(Operation::Upgrade, &[]).await?;
+
+        let (child, events) = self.stream_events().await?;
+        let events = hooked_events(hooks.clone(), events, None);
+        let (status, mut summary) = summarize(start, child, events, on_event).await?;
+        summary.snapshots = snapshots.clone();
+
+        hooks
+            .run(HookEvent::PostInstall {
+                operation: Operation::Upgrade,
+                packages: Vec::new(),
+                snapshots,
+                success: status.success(),
+            })
+            .await?;
+
+        #[cfg(feature = "metrics")]
+        crate::metrics::record_upgrade(&summary);
+
+        Ok((status, summary))
+    }
+
+    /// Runs `stream_install` to completion, accumulating a summary of the install.
+    ///
+    /// If some archives could not be downloaded, [`UpgradeSummary::is_partial`] is set,
+    /// and the install may be retried with [`AptGet::fix_missing`].
+    pub async fn install_with_summary<I, S, F>(
+        mut self,
+        packages: I,
+        on_event: F,
+    ) -> io::Result<(ExitStatus, UpgradeSummary)>
     where
         I: IntoIterator<Item = S>,
         S: AsRef<std::ffi::OsStr>,
+        F: FnMut(&AptUpgradeEvent),
     {
-        self.arg("remove");
-        self.args(packages);
+        let start = Instant::now();
+        let packages = self.target_packages(packages).await?;
+        self.args(["--show-progress", "install", "--"]);
+        self.args(&packages);
+        self.preflight()?;
 
-        self.status().await
+        let hooks = std::mem::take(&mut self.hooks);
+        let snapshots = hooks.run_pre_install(Operation::Install, &packages).await?;
+
+        let (child, events) = self.stream_events().await?;
+        let events = hooked_events(hooks.clone(), events, None);
+        let (status, mut summary) = summarize(start, child, events, on_event).await?;
+        summary.snapshots = snapshots.clone();
+
+        hooks
+            .run(HookEvent::PostInstall {
+                operation: Operation::Install,
+                packages,
+                snapshots,
+                success: status.success(),
+            })
+            .await?;
+
+        Ok((status, summary))
+    }
+
+    /// Removes packages, refusing to remove those which are essential or required,
+    /// unless permitted by `allow_remove_essential`.
+    ///
+    /// The guard checks every package which a simulation of the removal would remove,
+    /// including the reverse dependencies of the given packages.
+    pub async fn remove<I, S>(mut self, packages: I) -> Result<(), RemoveError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<std::ffi::OsStr>,
+    {
+        let packages = self.target_packages(packages).await?;
+
+        if !self.allow_remove_essential {
+            if let Some(admindir) = self.admindir.clone() {
+                let removals = self.simulate_removal(&packages).await?;
+                let stanzas = crate::dpkg::status::read(&admindir).await?;
+                let native = Dpkg::new().admindir(&admindir).architecture().await?;
+                let essential = essential_packages(&stanzas, &native, &removals);
+
+                if !essential.is_empty() {
+                    return Err(RemoveError::Essential(essential));
+                }
+            }
+        }
+
+        self.args(["remove", "--"]);
+        self.args(&packages);
+
+        let (status, hook_error) = self
+            .run_operation(Operation::Remove, packages, None)
+            .await?;
+
+        status.into_result()?;
+        hook_error.map_or(Ok(()), |why| Err(io::Error::from(why).into()))
+    }
+
+    /// The packages which `apt-get remove` would remove, from the `Remv` lines of a
+    /// simulation.
+    ///
+    /// If the simulation fails, so would the removal, which is left to report why.
+    async fn simulate_removal(&self, packages: &[String]) -> io::Result<Vec<String>> {
+        let mut command = crate::utils::clone_command(&self.command);
+        command.args(["-s", "remove", "--"]).args(packages);
+
+        let output = command.stderr(Stdio::null()).output().await?;
+
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(crate::apt::parse_simulated_removal)
+            .map(String::from)
+            .collect())
     }
 
     pub async fn fetch_uris(
@@ -160,15 +908,32 @@ impl AptGet {
         command: &[&str],
     ) -> io::Result<Result<HashSet<Request>, RequestError>> {
         self.arg("--print-uris");
-        self.args(command);
+
+        // Packages follow the subcommand, and are qualified by the host architecture.
+        match (command.split_first(), self.architecture.clone()) {
+            (Some((subcommand, args)), Some(arch)) => {
+                self.arg(subcommand);
+
+                for arg in args {
+                    if arg.starts_with('-') {
+                        self.arg(arg);
+                    } else {
+                        self.arg(crate::package::qualify_architecture(arg, &arch));
+                    }
+                }
+            }
+            _ => {
+                self.args(command);
+            }
+        }
 
         let (mut child, stdout) = self.spawn_with_stdout().await?;
 
-        let mut stdout = BufReader::new(stdout).lines();
+        let mut stdout = LineReader::with_capacity(DEFAULT_BUFFER_CAPACITY, stdout);
 
         let mut packages = HashSet::new();
 
-        while let Ok(Some(line)) = stdout.next_line().await {
+        while let Some(line) = stdout.next_line().await {
             if !line.starts_with('\'') {
                 continue;
             }
@@ -186,39 +951,634 @@ impl AptGet {
         Ok(Ok(packages))
     }
 
-    pub async fn stream_update(mut self) -> io::Result<Pin<Box<dyn Stream<Item = UpdateEvent> + Send>>> {
-        self.arg("update");
+    /// The indexes which `apt-get update` would fetch, such as to mirror them for offline
+    /// use. Translations and DEP-11 metadata are included or excluded by `targets`.
+    pub async fn fetch_index_uris(
+        mut self,
+        targets: &IndexTargets,
+    ) -> io::Result<Vec<IndexRequest>> {
+        for option in targets.options() {
+            self.args(["-o", &option]);
+        }
+
+        self.args(["--print-uris", "update"]);
 
         let (mut child, stdout) = self.spawn_with_stdout().await?;
 
-        let mut stdout = BufReader::new(stdout).lines();
+        let mut stdout = LineReader::with_capacity(DEFAULT_BUFFER_CAPACITY, stdout);
+
+        let mut indexes = Vec::new();
+
+        while let Some(line) = stdout.try_next_line().await? {
+            if let Some(index) = crate::update::parse_index_request(line) {
+                if targets.includes(&index) {
+                    indexes.push(index);
+                }
+            }
+        }
+
+        child.wait().await.map_result()?;
+
+        Ok(indexes)
+    }
+
+    /// Streams events from `apt-get update`, between the pre- and post-update hooks.
+    ///
+    /// If a post-update hook fails, its error is yielded as the exit status.
+    pub async fn stream_update(mut self) -> io::Result<UpdateEvents> {
+        self.arg("update");
+        self.preflight()?;
+
+        let hooks = std::mem::take(&mut self.hooks);
+        hooks.run(HookEvent::PreUpdate).await?;
+
+        let verbose = self.verbose;
+        let (mut child, output) = crate::utils::spawn_with_output(self.command).await?;
+        let mut output = Box::pin(output);
 
         let stream = stream! {
-            while let Ok(Some(line)) = stdout.next_line().await {
-                if line.starts_with("Err") {
-                    let mut fields = line.split_ascii_whitespace();
-                    let _ = fields.next();
-                    let url = fields.next().unwrap();
-                    let pocket = fields.next().unwrap();
-
-                    yield UpdateEvent::BadPPA(BadPPA {
-                        url: url.into(),
-                        pocket: pocket.into(),
-                    });
+            while let Some((stream, line)) = output.next().await {
+                if verbose {
+                    yield UpdateEvent::RawLine(RawLine::new(stream, &line));
+                }
+
+                if let Some(event) = parse_update_line(&line) {
+                    yield event;
                 }
             }
 
-            yield UpdateEvent::ExitStatus(child.wait().await);
+            let status = child.wait().await;
+            let post = HookEvent::PostUpdate {
+                success: status.as_ref().is_ok_and(ExitStatus::success),
+            };
+
+            match hooks.run(post).await {
+                Ok(()) => yield UpdateEvent::ExitStatus(status),
+                Err(why) => yield UpdateEvent::ExitStatus(Err(why.into())),
+            }
         };
 
         Ok(Box::pin(stream))
     }
 
+    /// Runs `apt-get update` with this command, retrying up to `attempts` times in total
+    /// when an index fails with a hash sum mismatch. Retries require indexes to be
+    /// fetched by hash.
+    pub fn stream_update_with_retries(self, attempts: u32) -> UpdateEvents {
+        let template = self.noninteractive();
+
+        let stream = stream! {
+            let mut attempt = 1;
+
+            loop {
+                let mut apt_get = template.duplicate();
+                if attempt > 1 {
+                    apt_get.args(["-o", "Acquire::By-Hash=yes"]);
+                }
+
+                let mut events = match apt_get.stream_update().await {
+                    Ok(events) => events,
+                    Err(why) => {
+                        yield UpdateEvent::ExitStatus(Err(why));
+                        return;
+                    }
+                };
+
+                let mut mismatched = false;
+                let mut status = None;
+
+                while let Some(event) = events.next().await {
+                    match event {
+                        UpdateEvent::ExitStatus(result) => status = Some(result),
+                        event => {
+                            mismatched |= matches!(event, UpdateEvent::HashSumMismatch { .. });
+                            yield event;
+                        }
+                    }
+                }
+
+                if mismatched && attempt < attempts {
+                    attempt += 1;
+                    yield UpdateEvent::Retrying { attempt };
+                    continue;
+                }
+
+                if let Some(status) = status {
+                    yield UpdateEvent::ExitStatus(status);
+                }
+
+                return;
+            }
+        };
+
+        Box::pin(stream)
+    }
+
     pub async fn spawn_with_stdout(self) -> io::Result<(Child, ChildStdout)> {
-        crate::utils::spawn_with_stdout(self.0).await
+        crate::utils::spawn_with_stdout(self.command).await
     }
 
     pub async fn status(mut self) -> io::Result<()> {
-        self.0.status().await?.into_result()
+        self.preflight()?;
+        self.command.status().await?.into_result()
+    }
+}
+
+/// Passes each event to the package hooks, and then runs the post-operation hooks with
+/// `post`, if given, once the events end, whose `success` is whether apt reported no
+/// errors. Errors of the hooks are yielded as errors.
+fn hooked_events(
+    hooks: Hooks,
+    mut events: UpgradeEvents,
+    post: Option<HookEvent>,
+) -> UpgradeEvents {
+    if hooks.is_empty() {
+        return events;
+    }
+
+    let hook_error = |why: HookError| AptUpgradeEvent::Error {
+        package: None,
+        message: format!("{}: {:#}", why, why.source).into(),
+    };
+
+    Box::pin(stream! {
+        let mut success = true;
+
+        while let Some(event) = events.next().await {
+            success &= !matches!(event, AptUpgradeEvent::Error { .. });
+
+            if hooks.contains(HookKind::Package) {
+                if let Err(why) = hooks.run(HookEvent::Package(event.clone())).await {
+                    yield hook_error(why);
+                }
+            }
+
+            yield event;
+        }
+
+        if let Some(mut post) = post {
+            if let HookEvent::PostInstall { success: ref mut post_success, .. } = post {
+                *post_success = success;
+            }
+
+            if let Err(why) = hooks.run(post).await {
+                yield hook_error(why);
+            }
+        }
+    })
+}
+
+async fn summarize<F: FnMut(&AptUpgradeEvent)>(
+    start: Instant,
+    mut child: Child,
+    mut events: UpgradeEvents,
+    mut on_event: F,
+) -> io::Result<(ExitStatus, UpgradeSummary)> {
+    let mut summary = UpgradeSummary::default();
+    while let Some(event) = events.next().await {
+        summary.update(&event);
+        on_event(&event);
+    }
+
+    let status = child.wait().await?;
+    summary.duration = start.elapsed();
+
+    if !status.success() {
+        summary
+            .errors
+            .push(format!("apt-get exited with {}", status));
+    }
+
+    Ok((status, summary))
+}
+
+/// Parses a line of `apt-get update` output into an event.
+pub(crate) fn parse_update_line(line: &str) -> Option<UpdateEvent> {
+    if let Some(uri) = parse_hash_mismatch(line) {
+        return Some(UpdateEvent::HashSumMismatch { uri: uri.into() });
+    }
+
+    if let Some(warning) = Warning::parse(line) {
+        return Some(UpdateEvent::Warning(warning));
+    }
+
+    if let Some(event) = crate::update::parse_index_line(line) {
+        if let IndexStatus::Err = event.status {
+            let pocket = event.target.split(' ').next().unwrap_or_default();
+
+            return Some(UpdateEvent::BadPPA(BadPPA {
+                pocket: pocket.into(),
+                url: event.uri,
+            }));
+        }
+
+        return Some(UpdateEvent::Index(event));
+    }
+
+    crate::update::parse_fetch_summary(line).map(UpdateEvent::Fetched)
+}
+
+/// The reasons apt gave for refusing to install the requested packages.
+#[derive(Debug, Default)]
+struct InstallFailure {
+    not_found: Vec<String>,
+    no_candidate: Vec<String>,
+    errors: Vec<String>,
+}
+
+impl InstallFailure {
+    /// Parses a line of stderr.
+    fn parse_line(&mut self, line: &str) {
+        if let Some(package) = parse_not_found(line) {
+            self.not_found.push(package.to_owned());
+        } else if let Some(package) = parse_no_candidate(line) {
+            self.no_candidate.push(package.to_owned());
+        } else if let Some(message) = line.strip_prefix("E: ") {
+            self.errors.push(message.to_owned());
+        }
+    }
+
+    /// The error, where `apt_get` is the command before `install` was added, which is
+    /// used to find the providers of a virtual package without an installation candidate.
+    async fn into_error(self, apt_get: Command) -> Option<InstallError> {
+        if !self.not_found.is_empty() {
+            return Some(InstallError::NotFound(self.not_found));
+        }
+
+        // The providers are listed on stdout, which was left to the terminal, so they
+        // are read from a simulation instead. Only the first is reported.
+        for package in self.no_candidate {
+            let mut command = crate::utils::clone_command(&apt_get);
+            command.args(["-s", "install", "--", &package]);
+
+            let Ok(output) = command.output().await else {
+                break;
+            };
+
+            let output = String::from_utf8_lossy(&output.stdout);
+            if let Some(providers) = parse_providers(&output, &package) {
+                return Some(InstallError::VirtualPackageNeedsProvider { package, providers });
+            }
+        }
+
+        None
+    }
+}
+
+/// The package of an `E: Package 'foo' has no installation candidate` line.
+fn parse_no_candidate(line: &str) -> Option<&str> {
+    line.strip_prefix("E: Package '")?
+        .strip_suffix("' has no installation candidate")
+}
+
+/// The providers listed after `Package foo is a virtual package provided by:`.
+fn parse_providers(output: &str, package: &str) -> Option<Vec<String>> {
+    let header = format!("Package {} is a virtual package provided by:", package);
+    let mut lines = output.lines().skip_while(|line| *line != header);
+    lines.next()?;
+
+    let providers = lines
+        .map_while(|line| line.strip_prefix("  "))
+        .filter_map(|provider| provider.split_ascii_whitespace().next())
+        .map(String::from)
+        .collect();
+
+    Some(providers)
+}
+
+/// The package of an `E: Unable to locate package foo` line, or the pattern of an
+/// `E: Couldn't find any package by glob 'foo*'` line.
+fn parse_not_found(line: &str) -> Option<&str> {
+    let message = line.strip_prefix("E: ")?;
+
+    if let Some(package) = message.strip_prefix("Unable to locate package ") {
+        return Some(package.trim());
+    }
+
+    let pattern = message
+        .strip_prefix("Couldn't find any package by glob ")
+        .or_else(|| message.strip_prefix("Couldn't find any package by regex "))?;
+
+    Some(pattern.trim().trim_matches('\''))
+}
+
+/// The URI of a `Failed to fetch <uri>  Hash Sum mismatch` line.
+fn parse_hash_mismatch(line: &str) -> Option<&str> {
+    let message = line
+        .strip_prefix("E: ")
+        .or_else(|| line.strip_prefix("W: "))?;
+    let failed = message.strip_prefix("Failed to fetch ")?;
+
+    if failed.contains("Hash Sum mismatch") {
+        failed.split_ascii_whitespace().next()
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[tokio::test]
+    async fn hooked_events_run_package_and_post_hooks() {
+        use crate::hooks::{HookEvent, HookKind, Hooks, Operation};
+        use crate::AptUpgradeEvent;
+        use futures::StreamExt;
+        use std::sync::{Arc, Mutex};
+
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let mut hooks = Hooks::default();
+
+        for kind in [HookKind::Package, HookKind::PostInstall] {
+            let calls = calls.clone();
+            hooks.register(kind, move |event| {
+                calls.lock().unwrap().push(event);
+                async { Ok(()) }
+            });
+        }
+
+        hooks.register(HookKind::PostInstall, |_| async {
+            Err(anyhow::anyhow!("notification failed"))
+        });
+
+        let events = futures::stream::iter(vec![
+            AptUpgradeEvent::SettingUp {
+                package: "hello".into(),
+            },
+            AptUpgradeEvent::Error {
+                package: Some("hello".into()),
+                message: "subprocess returned error exit status 1".into(),
+            },
+        ]);
+
+        let post = Some(HookEvent::PostInstall {
+            operation: Operation::Install,
+            packages: vec!["hello".to_owned()],
+            snapshots: vec!["apt-install-1700000000-1-0".to_owned()],
+            success: true,
+        });
+        let events = super::hooked_events(hooks, Box::pin(events), post)
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(3, events.len());
+        assert!(matches!(
+            &events[2],
+            AptUpgradeEvent::Error { package: None, message } if message.contains("notification failed")
+        ));
+
+        let calls = calls.lock().unwrap();
+        assert_eq!(3, calls.len());
+        assert!(matches!(
+            calls[0],
+            HookEvent::Package(AptUpgradeEvent::SettingUp { .. })
+        ));
+        assert_eq!(
+            HookEvent::PostInstall {
+                operation: Operation::Install,
+                packages: vec!["hello".into()],
+                snapshots: vec!["apt-install-1700000000-1-0".into()],
+                success: false,
+            },
+            calls[2]
+        );
+    }
+
+    #[test]
+    fn mutating_subcommands() {
+        let subcommand = |args: &[&str]| {
+            let mut apt_get = super::AptGet::new();
+            apt_get.args(args);
+            apt_get.mutating_subcommand()
+        };
+
+        assert_eq!(
+            Some("install".to_owned()),
+            subcommand(&["-o", "Debug::NoLocking=1", "-y", "install", "--", "firefox"])
+        );
+        assert_eq!(Some("update".to_owned()), subcommand(&["update"]));
+        assert_eq!(None, subcommand(&["-s", "install", "firefox"]));
+        assert_eq!(
+            Some("install".to_owned()),
+            subcommand(&["-t", "jammy-backports", "install", "--", "firefox"])
+        );
+        assert_eq!(
+            Some("remove".to_owned()),
+            subcommand(&["--default-release", "jammy", "remove", "--", "firefox"])
+        );
+        assert_eq!(
+            Some("upgrade".to_owned()),
+            subcommand(&["-c", "/etc/apt/custom.conf", "upgrade"])
+        );
+        assert_eq!(
+            None,
+            subcommand(&["--option", "Foo=install", "download", "firefox"])
+        );
+        assert_eq!(None, subcommand(&["download", "firefox"]));
+        assert_eq!(None, subcommand(&["changelog", "firefox"]));
+    }
+
+    #[test]
+    fn duplicates_keep_options() {
+        let apt_get = super::AptGet::new()
+            .host_architecture("arm64")
+            .noninteractive();
+        let duplicate = apt_get.duplicate();
+
+        assert_eq!(
+            apt_get.as_std().get_args().collect::<Vec<_>>(),
+            duplicate.as_std().get_args().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            apt_get.as_std().get_envs().collect::<Vec<_>>(),
+            duplicate.as_std().get_envs().collect::<Vec<_>>()
+        );
+        assert_eq!(Some("arm64"), duplicate.architecture.as_deref());
+    }
+
+    #[test]
+    fn foreign_architecture_operations_are_mutating() {
+        let mut apt_get = super::AptGet::new().host_architecture("arm64");
+        apt_get.args(["install", "--", "libc6:arm64"]);
+
+        assert_eq!(Some("install".to_owned()), apt_get.mutating_subcommand());
+    }
+
+    #[test]
+    fn simulations_are_not_mutating() {
+        let subcommand = |args: &[&str]| {
+            let mut apt_get = super::AptGet::new();
+            apt_get.args(args);
+            apt_get.mutating_subcommand()
+        };
+
+        for simulate in [
+            "-s",
+            "--simulate",
+            "--just-print",
+            "--dry-run",
+            "--recon",
+            "--no-act",
+        ] {
+            assert_eq!(None, subcommand(&[simulate, "install", "--", "firefox"]));
+            assert_eq!(None, subcommand(&["install", simulate, "--", "firefox"]));
+        }
+
+        assert_eq!(
+            None,
+            subcommand(&["-o", "APT::Get::Simulate=true", "remove", "--", "vim"])
+        );
+        assert_eq!(
+            Some("remove".to_owned()),
+            subcommand(&["-o", "APT::Get::Simulate=false", "remove", "--", "vim"])
+        );
+    }
+
+    #[test]
+    fn parse_hash_mismatch() {
+        assert_eq!(
+            Some("http://us.archive.ubuntu.com/ubuntu/dists/jammy-updates/main/binary-amd64/by-hash/SHA256/0f3b2c"),
+            super::parse_hash_mismatch("E: Failed to fetch http://us.archive.ubuntu.com/ubuntu/dists/jammy-updates/main/binary-amd64/by-hash/SHA256/0f3b2c  Hash Sum mismatch")
+        );
+
+        assert_eq!(
+            None,
+            super::parse_hash_mismatch(
+                "E: Failed to fetch http://example.com/InRelease  404  Not Found"
+            )
+        );
+    }
+
+    #[test]
+    fn option_like_packages_are_rejected() {
+        let command = tokio::process::Command::new("false");
+        let result =
+            futures::executor::block_on(super::AptGet::with_command(command).install(&["-f"]));
+
+        assert!(matches!(
+            result,
+            Err(super::InstallError::Command(why)) if why.kind() == std::io::ErrorKind::InvalidInput
+        ));
+    }
+
+    #[test]
+    fn virtual_package_needs_provider() {
+        let output = "Reading package lists...
+Building dependency tree...
+Package mail-transport-agent is a virtual package provided by:
+  postfix 3.6.4-1ubuntu1.3
+  exim4-daemon-light 4.95-4ubuntu2.5
+  dma 0.13-1build3 [Not candidate version]
+You should explicitly select one to install.
+
+";
+
+        assert_eq!(
+            Some(vec![
+                "postfix".to_owned(),
+                "exim4-daemon-light".to_owned(),
+                "dma".to_owned()
+            ]),
+            super::parse_providers(output, "mail-transport-agent")
+        );
+
+        assert_eq!(None, super::parse_providers(output, "postfix"));
+
+        let mut failure = super::InstallFailure::default();
+        failure.parse_line("E: Package 'mail-transport-agent' has no installation candidate");
+        assert_eq!(vec!["mail-transport-agent"], failure.no_candidate);
+
+        failure.parse_line("E: Unable to correct problems, you have held broken packages.");
+        assert_eq!(
+            vec!["Unable to correct problems, you have held broken packages."],
+            failure.errors
+        );
+    }
+
+    #[test]
+    fn parse_not_found() {
+        assert_eq!(
+            Some("firefx"),
+            super::parse_not_found("E: Unable to locate package firefx")
+        );
+
+        assert_eq!(
+            Some("linux-image-9*"),
+            super::parse_not_found("E: Couldn't find any package by glob 'linux-image-9*'")
+        );
+
+        assert_eq!(
+            None,
+            super::parse_not_found("E: Package 'foo' has no installation candidate")
+        );
+    }
+
+    #[test]
+    fn scoped_sources() {
+        let apt_get = super::AptGet::new()
+            .scoped_sources(std::path::Path::new("/etc/apt/sources.list.d/ppa.list"));
+        let args: Vec<_> = apt_get.as_std().get_args().collect();
+
+        assert_eq!(
+            vec![
+                "-o",
+                "Dir::Etc::SourceList=/etc/apt/sources.list.d/ppa.list",
+                "-o",
+                "Dir::Etc::SourceParts=-",
+                "-o",
+                "APT::Get::List-Cleanup=0",
+            ],
+            args
+        );
+    }
+
+    #[test]
+    fn essential_packages() {
+        let status = "Package: bash
+Essential: yes
+Status: install ok installed
+Priority: required
+Architecture: amd64
+
+Package: libc6
+Status: install ok installed
+Priority: required
+Architecture: amd64
+
+Package: tzdata
+Status: install ok installed
+Priority: required
+Architecture: all
+
+Package: libc6
+Status: install ok installed
+Priority: optional
+Architecture: i386
+
+Package: firefox
+Status: install ok installed
+Priority: optional
+
+Package: removed-essential
+Essential: yes
+Status: deinstall ok config-files
+";
+
+        let stanzas: Vec<_> = crate::dpkg::status::parse(status).collect();
+
+        assert_eq!(
+            vec!["bash".to_owned(), "libc6:amd64".into(), "tzdata".into()],
+            super::essential_packages(
+                &stanzas,
+                "amd64",
+                [
+                    "bash",
+                    "libc6:amd64",
+                    "libc6:i386",
+                    "tzdata",
+                    "firefox",
+                    "removed-essential"
+                ]
+            )
+        );
     }
 }
@@ -0,0 +1,52 @@
+// Copyright 2021-2022 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+use as_result::IntoResult;
+use std::io;
+use std::path::Path;
+use std::process::Stdio;
+use tokio::process::{Child, ChildStdout, Command};
+
+/// Location of `apt-helper`, which is not installed to the `PATH`.
+pub const APT_HELPER: &str = "/usr/lib/apt/apt-helper";
+
+#[derive(AsMut, Deref, DerefMut)]
+#[as_mut(forward)]
+pub struct AptHelper(pub(crate) Command);
+
+impl AptHelper {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self(crate::utils::command(APT_HELPER))
+    }
+
+    /// Downloads a file through apt's own transports, honoring its proxy configuration.
+    ///
+    /// If given, `hash` is verified by apt, in the form of `SHA256:<hex>`.
+    pub async fn download_file(
+        mut self,
+        uri: &str,
+        destination: &Path,
+        hash: Option<&str>,
+    ) -> io::Result<()> {
+        self.args(["download-file", "--", uri]).arg(destination);
+
+        if let Some(hash) = hash {
+            self.arg(hash);
+        }
+
+        self.stdout(Stdio::null());
+        self.status().await
+    }
+
+    /// Writes a file to stdout, decompressed by whichever of apt's compressors its
+    /// extension names, such as `.lz4` or `.zst`.
+    pub async fn cat_file(mut self, path: &Path) -> io::Result<(Child, ChildStdout)> {
+        self.arg("cat-file").arg(path);
+        crate::utils::spawn_with_stdout(self.0).await
+    }
+
+    pub async fn status(mut self) -> io::Result<()> {
+        self.0.status().await?.into_result()
+    }
+}
@@ -1,8 +1,11 @@
 // Copyright 2021-2022 System76 <info@system76.com>
 // SPDX-License-Identifier: MPL-2.0
 
+use crate::dpkg::Selection;
+use crate::Backend;
 use anyhow::Context;
 use as_result::IntoResult;
+use std::collections::HashSet;
 use std::io;
 use std::process::Stdio;
 use tokio::io::{AsyncBufReadExt, BufReader};
@@ -10,14 +13,12 @@ use tokio::process::Command;
 
 #[derive(AsMut, Deref, DerefMut)]
 #[as_mut(forward)]
-pub struct AptMark(Command);
+pub struct AptMark(pub(crate) Command);
 
 impl AptMark {
     #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
-        let mut cmd = Command::new("apt-mark");
-        cmd.env("LANG", "C");
-        Self(cmd)
+        Self(crate::utils::command("apt-mark"))
     }
 
     pub async fn hold<I, S>(mut self, packages: I) -> io::Result<()>
@@ -25,7 +26,8 @@ impl AptMark {
         I: IntoIterator<Item = S>,
         S: AsRef<std::ffi::OsStr>,
     {
-        self.arg("hold");
+        let packages = crate::package::validate_packages(packages)?;
+        self.args(["hold", "--"]);
         self.args(packages);
         self.status().await
     }
@@ -35,7 +37,8 @@ impl AptMark {
         I: IntoIterator<Item = S>,
         S: AsRef<std::ffi::OsStr>,
     {
-        self.arg("unhold");
+        let packages = crate::package::validate_packages(packages)?;
+        self.args(["unhold", "--"]);
         self.args(packages);
         self.status().await
     }
@@ -65,12 +68,202 @@ impl AptMark {
         Ok(auto)
     }
 
+    /// Compares the packages held according to `apt-mark showhold` with those held
+    /// according to `dpkg --get-selections`.
+    ///
+    /// Packages of the native architecture are the same with or without its qualifier,
+    /// such that `libc6:amd64` is not a discrepancy of `libc6` on amd64.
+    pub async fn hold_discrepancies() -> anyhow::Result<HoldDiscrepancies> {
+        let native = async {
+            crate::Dpkg::new()
+                .architecture()
+                .await
+                .context("failed to read `dpkg --print-architecture`")
+        };
+
+        let (apt, dpkg, native) =
+            futures::future::try_join3(AptMark::held(), dpkg_held(), native).await?;
+
+        Ok(HoldDiscrepancies::new(&apt, &dpkg, &native))
+    }
+
+    /// Holds each package which either source reports as held in the other, so that
+    /// both agree. Returns the discrepancies which were reconciled.
+    pub async fn sync_holds() -> anyhow::Result<HoldDiscrepancies> {
+        let discrepancies = AptMark::hold_discrepancies().await?;
+
+        if !discrepancies.apt_only.is_empty() {
+            let selections: Vec<(&str, Selection)> = discrepancies
+                .apt_only
+                .iter()
+                .map(|package| (package.as_str(), Selection::Hold))
+                .collect();
+
+            crate::Dpkg::new()
+                .set_selections(&selections)
+                .await
+                .context("failed to hold packages with `dpkg --set-selections`")?;
+        }
+
+        if !discrepancies.dpkg_only.is_empty() {
+            AptMark::new()
+                .hold(&discrepancies.dpkg_only)
+                .await
+                .context("failed to hold packages with `apt-mark hold`")?;
+        }
+
+        Ok(discrepancies)
+    }
+
     pub async fn status(mut self) -> io::Result<()> {
         self.0.status().await?.into_result()
     }
 }
 
-async fn scrape_packages(command: &mut tokio::process::Command) -> anyhow::Result<Vec<String>> {
+/// Packages whose hold state differs between apt and dpkg.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct HoldDiscrepancies {
+    /// Held according to `apt-mark showhold`, but not dpkg's selections.
+    pub apt_only: Vec<String>,
+    /// Held in dpkg's selections, but not according to `apt-mark showhold`.
+    pub dpkg_only: Vec<String>,
+}
+
+impl HoldDiscrepancies {
+    fn new(apt: &[String], dpkg: &[String], native: &str) -> Self {
+        let native = [":", native].concat();
+        let unqualified = |package: &'_ String| -> String {
+            package.strip_suffix(&native).unwrap_or(package).to_owned()
+        };
+
+        let missing_from = |packages: &[String], other: &[String]| -> Vec<String> {
+            let other: HashSet<String> = other.iter().map(unqualified).collect();
+            packages
+                .iter()
+                .filter(|package| !other.contains(&unqualified(package)))
+                .cloned()
+                .collect()
+        };
+
+        Self {
+            apt_only: missing_from(apt, dpkg),
+            dpkg_only: missing_from(dpkg, apt),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.apt_only.is_empty() && self.dpkg_only.is_empty()
+    }
+}
+
+async fn dpkg_held() -> anyhow::Result<Vec<String>> {
+    let selections = crate::Dpkg::new()
+        .get_selections()
+        .await
+        .context("failed to read `dpkg --get-selections`")?;
+
+    Ok(selections
+        .into_iter()
+        .filter(|(_, selection)| *selection == Selection::Hold)
+        .map(|(package, _)| package)
+        .collect())
+}
+
+/// Holds packages until the guard is released or dropped.
+///
+/// Packages which were already held are left held when the guard is released.
+#[derive(Debug)]
+pub struct HoldGuard {
+    backend: Backend,
+    packages: Vec<String>,
+}
+
+impl HoldGuard {
+    pub async fn hold<S: AsRef<str>>(packages: &[S]) -> anyhow::Result<Self> {
+        HoldGuard::hold_in(&Backend::Host, packages).await
+    }
+
+    /// Holds packages of the system which `backend` runs commands in.
+    pub async fn hold_in<S: AsRef<str>>(backend: &Backend, packages: &[S]) -> anyhow::Result<Self> {
+        let apt_mark = || AptMark(backend.command("apt-mark"));
+        let held = scrape_packages(apt_mark().arg("showhold")).await?;
+
+        let packages: Vec<String> = packages
+            .iter()
+            .map(|package| package.as_ref())
+            .filter(|package| !held.iter().any(|held| held == package))
+            .map(String::from)
+            .collect();
+
+        if !packages.is_empty() {
+            apt_mark()
+                .hold(&packages)
+                .await
+                .context("failed to hold packages")?;
+        }
+
+        Ok(Self {
+            backend: backend.clone(),
+            packages,
+        })
+    }
+
+    /// The packages which this guard holds.
+    pub fn packages(&self) -> &[String] {
+        &self.packages
+    }
+
+    /// Unholds the packages which this guard held.
+    pub async fn release(mut self) -> anyhow::Result<()> {
+        let packages = std::mem::take(&mut self.packages);
+
+        if !packages.is_empty() {
+            AptMark(self.backend.command("apt-mark"))
+                .unhold(&packages)
+                .await
+                .context("failed to unhold packages")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for HoldGuard {
+    fn drop(&mut self) {
+        if self.packages.is_empty() {
+            return;
+        }
+
+        // The guard was not released, so the packages are unheld by a task on the
+        // current runtime, which is not awaited, or else by a runtime of its own.
+
+        let packages = std::mem::take(&mut self.packages);
+        let apt_mark = AptMark(self.backend.command("apt-mark"));
+        let unhold = async move {
+            let _ = apt_mark.unhold(&packages).await;
+        };
+
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                runtime.spawn(unhold);
+            }
+
+            Err(_) => {
+                let runtime = tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build();
+
+                if let Ok(runtime) = runtime {
+                    runtime.block_on(unhold);
+                }
+            }
+        }
+    }
+}
+
+pub(crate) async fn scrape_packages(
+    command: &mut tokio::process::Command,
+) -> anyhow::Result<Vec<String>> {
     let mut child = command
         .stdout(Stdio::piped())
         .stderr(Stdio::inherit())
@@ -103,3 +296,27 @@ async fn scrape_packages(command: &mut tokio::process::Command) -> anyhow::Resul
 
     Ok(packages)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::HoldDiscrepancies;
+
+    #[test]
+    fn hold_discrepancies() {
+        let apt = vec!["firefox".to_owned(), "linux-generic".to_owned()];
+        let dpkg = vec!["linux-generic".to_owned(), "nvidia-driver-535".to_owned()];
+
+        let discrepancies = HoldDiscrepancies::new(&apt, &dpkg, "amd64");
+        assert_eq!(vec!["firefox"], discrepancies.apt_only);
+        assert_eq!(vec!["nvidia-driver-535"], discrepancies.dpkg_only);
+        assert!(!discrepancies.is_empty());
+
+        assert!(HoldDiscrepancies::new(&apt, &apt, "amd64").is_empty());
+
+        let apt = vec!["libc6".to_owned(), "libc6:i386".to_owned()];
+        let dpkg = vec!["libc6:amd64".to_owned(), "libc6".to_owned()];
+        let discrepancies = HoldDiscrepancies::new(&apt, &dpkg, "amd64");
+        assert_eq!(vec!["libc6:i386"], discrepancies.apt_only);
+        assert!(discrepancies.dpkg_only.is_empty());
+    }
+}
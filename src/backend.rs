@@ -0,0 +1,228 @@
+// Copyright 2021-2022 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+use std::path::PathBuf;
+use tokio::process::Command;
+
+/// Where the commands of an [`AptClient`](crate::AptClient) are executed.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub enum Backend {
+    /// Run commands directly on the host.
+    #[default]
+    Host,
+
+    /// Run commands with `chroot` inside the given root filesystem.
+    Chroot(PathBuf),
+
+    /// Run commands with `systemd-nspawn -D` inside the given root filesystem.
+    ///
+    /// `systemd-nspawn` does not forward the environment, so the locale and the
+    /// noninteractive debconf frontend are passed to the container explicitly.
+    Nspawn(PathBuf),
+
+    /// Run commands with `nsenter` inside the namespaces of a running process.
+    Namespace { pid: u32 },
+
+    /// Run commands on a remote host with `ssh`, such as `root@192.168.1.10`.
+    ///
+    /// Authentication must not be interactive. As ssh passes the command to the remote
+    /// shell as one line, every argument is quoted for that shell by a local `sh`.
+    Ssh { destination: String },
+}
+
+/// Runs `ssh` with the destination in `$1`, and the remaining arguments single-quoted,
+/// so that the remote shell receives each of them verbatim.
+const SSH_SCRIPT: &str = r#"q="'" e="'\''" destination=$1
+shift
+remote=
+for arg; do
+    quoted=
+    while :; do
+        case $arg in
+            *"$q"*)
+                head=${arg%%"$q"*}
+                quoted=$quoted$head$e
+                arg=${arg#*"$q"}
+                ;;
+            *) break ;;
+        esac
+    done
+    remote="$remote $q$quoted$arg$q"
+done
+exec ssh -o BatchMode=yes -- "$destination" "$remote""#;
+
+impl Backend {
+    /// The dpkg administrative directory of the system, as seen from the host, if it
+    /// can be read locally.
+    pub(crate) fn admindir(&self) -> Option<PathBuf> {
+        let admindir = crate::dpkg::status::ADMINDIR.trim_start_matches('/');
+
+        match self {
+            Backend::Host => Some(PathBuf::from(crate::dpkg::status::ADMINDIR)),
+            Backend::Chroot(root) | Backend::Nspawn(root) => Some(root.join(admindir)),
+            Backend::Namespace { pid } => {
+                Some(PathBuf::from(format!("/proc/{}/root", pid)).join(admindir))
+            }
+            Backend::Ssh { .. } => None,
+        }
+    }
+
+    /// Creates a command which runs `program` within this backend.
+    pub(crate) fn command(&self, program: &str) -> Command {
+        let mut command = match self {
+            Backend::Host => return crate::utils::command(program),
+
+            Backend::Chroot(root) => {
+                let mut command = Command::new("chroot");
+                command.arg(root).arg(program);
+                command
+            }
+
+            Backend::Nspawn(root) => {
+                let mut command = Command::new("systemd-nspawn");
+                command
+                    .args(["--quiet", "--pipe", "-D"])
+                    .arg(root)
+                    .args([
+                        "--setenv=LANG=C",
+                        "--setenv=LC_ALL=C",
+                        "--setenv=DEBIAN_FRONTEND=noninteractive",
+                        "--",
+                    ])
+                    .arg(program);
+                command
+            }
+
+            Backend::Namespace { pid } => {
+                let mut command = Command::new("nsenter");
+                command
+                    .args(["--target", &pid.to_string()])
+                    .args(["--mount", "--uts", "--ipc", "--net", "--pid", "--"])
+                    .arg(program);
+                command
+            }
+
+            Backend::Ssh { destination } => {
+                let mut command = Command::new("sh");
+                command
+                    .args(["-c", SSH_SCRIPT, "sh"])
+                    .arg(destination)
+                    .args([
+                        "env",
+                        "LANG=C",
+                        "LC_ALL=C",
+                        "DEBIAN_FRONTEND=noninteractive",
+                    ])
+                    .arg(program);
+                command
+            }
+        };
+
+        crate::utils::c_locale(&mut command);
+        command
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Backend;
+    use std::ffi::OsStr;
+
+    fn argv(backend: Backend) -> Vec<String> {
+        let command = backend.command("apt-get");
+        let command = command.as_std();
+        std::iter::once(command.get_program())
+            .chain(command.get_args())
+            .map(OsStr::to_string_lossy)
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn backend_commands() {
+        assert_eq!(vec!["apt-get"], argv(Backend::Host));
+
+        assert_eq!(
+            vec!["chroot", "/mnt/target", "apt-get"],
+            argv(Backend::Chroot("/mnt/target".into()))
+        );
+
+        assert_eq!(
+            vec![
+                "nsenter", "--target", "42", "--mount", "--uts", "--ipc", "--net", "--pid", "--",
+                "apt-get"
+            ],
+            argv(Backend::Namespace { pid: 42 })
+        );
+
+        assert_eq!(
+            vec![
+                "sh",
+                "-c",
+                super::SSH_SCRIPT,
+                "sh",
+                "root@host",
+                "env",
+                "LANG=C",
+                "LC_ALL=C",
+                "DEBIAN_FRONTEND=noninteractive",
+                "apt-get"
+            ],
+            argv(Backend::Ssh {
+                destination: "root@host".into()
+            })
+        );
+
+        let nspawn = argv(Backend::Nspawn("/mnt/target".into()));
+        assert_eq!(
+            ["systemd-nspawn", "--quiet", "--pipe", "-D", "/mnt/target"],
+            nspawn[..5]
+        );
+        assert_eq!(Some(&String::from("apt-get")), nspawn.last());
+    }
+
+    #[tokio::test]
+    async fn ssh_arguments_are_quoted() {
+        // Stands in for ssh, running the remote command line with a shell.
+        let dir = crate::utils::TempDir::new("apt-cmd-backend").unwrap();
+        let ssh = dir.path().join("ssh");
+        std::fs::write(&ssh, "#!/bin/sh\nexec sh -c \"$5\"\n").unwrap();
+        std::fs::set_permissions(&ssh, std::os::unix::fs::PermissionsExt::from_mode(0o755))
+            .unwrap();
+
+        let path = format!("{}:/usr/bin:/bin", dir.path().display());
+
+        let arguments = [
+            "firefox",
+            "two words",
+            "it's",
+            "''",
+            "foo;touch injected",
+            "$(id)",
+            "`id`",
+            "a\\b\"c",
+        ];
+
+        let output = Backend::Ssh {
+            destination: "root@host".into(),
+        }
+        .command("printf")
+        .env("PATH", path)
+        .current_dir(dir.path())
+        .arg("%s\\n")
+        .args(arguments)
+        .output()
+        .await
+        .unwrap();
+
+        assert!(output.status.success());
+        assert_eq!(
+            arguments
+                .iter()
+                .map(|arg| [arg, "\n"].concat())
+                .collect::<String>(),
+            String::from_utf8(output.stdout).unwrap()
+        );
+        assert!(!dir.path().join("injected").exists());
+    }
+}
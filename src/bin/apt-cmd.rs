@@ -0,0 +1,118 @@
+// Copyright 2021-2022 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+use anyhow::Context;
+use apt_cmd::lock::{apt_lock_watch, AptLockEvent};
+use apt_cmd::AptGet;
+use futures::StreamExt;
+use std::process::exit;
+
+const USAGE: &str = "Usage: apt-cmd <command> [args...]
+
+Commands:
+    fetch-uris [apt-get command...]    URIs of the packages a command would fetch (default: full-upgrade)
+    downgradable                       Packages which can be downgraded to a repository version
+    remoteless                         Installed packages which no repository provides
+    watch-lock                         Report when the apt and dpkg locks are held and released
+    stream-upgrade [--json]            Run `apt-get full-upgrade`, printing each event";
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let result = match args.first().map(String::as_str) {
+        Some("fetch-uris") => fetch_uris(&args[1..]).await,
+        Some("downgradable") => downgradable().await,
+        Some("remoteless") => remoteless().await,
+        Some("watch-lock") => watch_lock().await,
+        Some("stream-upgrade") => stream_upgrade(args[1..].iter().any(|arg| arg == "--json")).await,
+        Some("-h" | "--help") => {
+            println!("{}", USAGE);
+            return;
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            exit(2);
+        }
+    };
+
+    if let Err(why) = result {
+        eprintln!("apt-cmd: {:?}", why);
+        exit(1);
+    }
+}
+
+async fn fetch_uris(command: &[String]) -> anyhow::Result<()> {
+    let command: Vec<&str> = if command.is_empty() {
+        vec!["full-upgrade"]
+    } else {
+        command.iter().map(String::as_str).collect()
+    };
+
+    let requests = AptGet::new()
+        .noninteractive()
+        .fetch_uris(&command)
+        .await
+        .context("failed to launch `apt-get --print-uris`")?
+        .context("failed to parse `apt-get --print-uris` output")?;
+
+    for request in requests {
+        println!("{} {} {}", request.uri, request.name, request.size);
+    }
+
+    Ok(())
+}
+
+async fn downgradable() -> anyhow::Result<()> {
+    for (package, version) in apt_cmd::apt::downgradable_packages().await? {
+        println!("{} {}", package, version);
+    }
+
+    Ok(())
+}
+
+async fn remoteless() -> anyhow::Result<()> {
+    for package in apt_cmd::apt::remoteless_packages().await? {
+        println!("{}", package);
+    }
+
+    Ok(())
+}
+
+async fn watch_lock() -> anyhow::Result<()> {
+    let events = apt_lock_watch();
+    futures::pin_mut!(events);
+
+    while let Some(event) = events.next().await {
+        match event {
+            AptLockEvent::Locked => println!("locked"),
+            AptLockEvent::Unlocked => println!("unlocked"),
+        }
+    }
+
+    Ok(())
+}
+
+async fn stream_upgrade(json: bool) -> anyhow::Result<()> {
+    let (mut child, mut events) = AptGet::new()
+        .noninteractive()
+        .stream_upgrade()
+        .await
+        .context("failed to launch `apt-get full-upgrade`")?;
+
+    while let Some(event) = events.next().await {
+        if json {
+            let map = event.into_dbus_map();
+            println!("{}", serde_json::to_string(&map)?);
+        } else {
+            println!("{}", event);
+        }
+    }
+
+    let status = child.wait().await?;
+    if !status.success() {
+        return Err(anyhow::anyhow!("apt-get exited with {}", status));
+    }
+
+    Ok(())
+}
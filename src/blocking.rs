@@ -0,0 +1,109 @@
+// Copyright 2021-2022 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+//! A blocking facade over the main APIs, for consumers without a tokio runtime.
+//!
+//! Each function creates a single-threaded runtime to drive the underlying process.
+//! Streams are returned as iterators, which drive the stream as they are iterated.
+//! These functions panic if called from within an async runtime.
+//!
+//! ```no_run
+//! # fn example() -> anyhow::Result<()> {
+//! apt_cmd::blocking::update()?;
+//!
+//! let mut packages = apt_cmd::blocking::upgradable_packages()?;
+//! for package in &mut packages {
+//!     println!("{}", package);
+//! }
+//!
+//! packages.wait()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::apt::Packages;
+use crate::{AptGet, InstallError, Policies, Policy};
+use futures::stream::{Stream, StreamExt};
+use std::ffi::OsStr;
+use std::io;
+use std::pin::Pin;
+use std::process::ExitStatus;
+use tokio::process::Child;
+use tokio::runtime::Runtime;
+
+fn runtime() -> io::Result<Runtime> {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+}
+
+/// An iterator over the output of a child process.
+pub struct Iter<T> {
+    // Dropped before the runtime which drives them.
+    stream: Pin<Box<dyn Stream<Item = T> + Send>>,
+    child: Child,
+    runtime: Runtime,
+}
+
+impl<T> Iter<T> {
+    /// Discards any remaining items and waits for the process to exit.
+    pub fn wait(mut self) -> io::Result<ExitStatus> {
+        let Self {
+            runtime,
+            child,
+            stream,
+        } = &mut self;
+
+        runtime.block_on(async move {
+            while stream.next().await.is_some() {}
+            child.wait().await
+        })
+    }
+}
+
+impl<T> Iterator for Iter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.runtime.block_on(self.stream.next())
+    }
+}
+
+/// Installs packages with `apt-get install`, noninteractively.
+pub fn install<I, S>(packages: I) -> Result<(), InstallError>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    runtime()?.block_on(AptGet::new().noninteractive().install(packages))
+}
+
+/// Updates the package lists with `apt-get update`, noninteractively.
+pub fn update() -> io::Result<()> {
+    runtime()?.block_on(AptGet::new().noninteractive().update())
+}
+
+/// The policy of each package, from `apt-cache policy`.
+pub fn policy<S: AsRef<OsStr>>(packages: &[S]) -> anyhow::Result<Iter<Policy>> {
+    let runtime = runtime()?;
+    let (child, stream): (Child, Policies) =
+        runtime.block_on(crate::AptCache::new().policy(packages))?;
+
+    Ok(Iter {
+        runtime,
+        child,
+        stream,
+    })
+}
+
+/// Packages which are upgradable, from `apt list --upgradable`.
+pub fn upgradable_packages() -> anyhow::Result<Iter<String>> {
+    let runtime = runtime()?;
+    let (child, stream): (Child, Packages) = runtime.block_on(crate::apt::upgradable_packages())?;
+
+    Ok(Iter {
+        runtime,
+        child,
+        stream,
+    })
+}
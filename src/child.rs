@@ -0,0 +1,70 @@
+// Copyright 2021-2022 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::process::ExitStatus;
+
+pub type ChildFuture<'a, T> = Pin<Box<dyn Future<Output = io::Result<T>> + Send + 'a>>;
+
+/// A child process spawned by this crate, so that code which supervises them may be
+/// written once for every API, or over a `Box<dyn AptChild>`.
+pub trait AptChild: Send {
+    /// The process ID, until the process has been waited on.
+    fn id(&self) -> Option<u32>;
+
+    /// Forcibly kills the process, and waits for it to exit.
+    fn kill(&mut self) -> ChildFuture<'_, ()>;
+
+    /// Waits for the process to exit.
+    fn wait(&mut self) -> ChildFuture<'_, ExitStatus>;
+}
+
+impl AptChild for tokio::process::Child {
+    fn id(&self) -> Option<u32> {
+        tokio::process::Child::id(self)
+    }
+
+    fn kill(&mut self) -> ChildFuture<'_, ()> {
+        Box::pin(tokio::process::Child::kill(self))
+    }
+
+    fn wait(&mut self) -> ChildFuture<'_, ExitStatus> {
+        Box::pin(tokio::process::Child::wait(self))
+    }
+}
+
+impl<C: AptChild + ?Sized> AptChild for Box<C> {
+    fn id(&self) -> Option<u32> {
+        (**self).id()
+    }
+
+    fn kill(&mut self) -> ChildFuture<'_, ()> {
+        (**self).kill()
+    }
+
+    fn wait(&mut self) -> ChildFuture<'_, ExitStatus> {
+        (**self).wait()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AptChild;
+
+    #[tokio::test]
+    async fn boxed_child() {
+        let child = tokio::process::Command::new("sleep")
+            .arg("10")
+            .spawn()
+            .unwrap();
+
+        let mut child: Box<dyn AptChild> = Box::new(child);
+        assert!(child.id().is_some());
+
+        child.kill().await.unwrap();
+        assert!(child.id().is_none());
+        assert!(!child.wait().await.unwrap().success());
+    }
+}
@@ -0,0 +1,73 @@
+// Copyright 2021-2022 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+use crate::hooks::Hooks;
+use crate::{AptCache, AptCdrom, AptGet, AptHelper, AptMark, Backend, Dpkg, DpkgQuery};
+
+/// Creates apt and dpkg commands which execute within a [`Backend`].
+///
+/// ```no_run
+/// use apt_cmd::{AptClient, Backend};
+///
+/// # async fn example() -> std::io::Result<()> {
+/// let client = AptClient::new(Backend::Chroot("/mnt/target".into()));
+/// client.apt_get().noninteractive().update().await
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct AptClient {
+    backend: Backend,
+    hooks: Hooks,
+}
+
+impl AptClient {
+    pub fn new(backend: Backend) -> Self {
+        Self {
+            backend,
+            hooks: Hooks::default(),
+        }
+    }
+
+    pub fn backend(&self) -> &Backend {
+        &self.backend
+    }
+
+    /// The hooks which are given to each `AptGet` created by this client.
+    pub fn hooks(&self) -> &Hooks {
+        &self.hooks
+    }
+
+    pub fn hooks_mut(&mut self) -> &mut Hooks {
+        &mut self.hooks
+    }
+
+    pub fn apt_cache(&self) -> AptCache {
+        AptCache::with_command(self.backend.command("apt-cache"))
+    }
+
+    pub fn apt_cdrom(&self) -> AptCdrom {
+        AptCdrom(self.backend.command("apt-cdrom"))
+    }
+
+    pub fn apt_get(&self) -> AptGet {
+        AptGet::with_command(self.backend.command("apt-get"))
+            .admindir(self.backend.admindir())
+            .hooks(self.hooks.clone())
+    }
+
+    pub fn apt_helper(&self) -> AptHelper {
+        AptHelper(self.backend.command(crate::apt_helper::APT_HELPER))
+    }
+
+    pub fn apt_mark(&self) -> AptMark {
+        AptMark(self.backend.command("apt-mark"))
+    }
+
+    pub fn dpkg(&self) -> Dpkg {
+        Dpkg(self.backend.command("dpkg"))
+    }
+
+    pub fn dpkg_query(&self) -> DpkgQuery {
+        DpkgQuery::with_command(self.backend.command("dpkg-query"))
+    }
+}
@@ -0,0 +1,145 @@
+// Copyright 2021-2022 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+//! Reads apt's data files, such as package lists and rotated logs, whether or not they
+//! are compressed.
+//!
+//! Compressed files are decompressed by `apt-helper cat-file`, so that every format
+//! which apt is able to write may be read.
+//!
+//! ```no_run
+//! # async fn run() -> std::io::Result<()> {
+//! use std::path::Path;
+//!
+//! let history = apt_cmd::compression::read_to_string(Path::new(
+//!     "/var/log/apt/history.log.1.gz",
+//! ))
+//! .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::AptHelper;
+use as_result::IntoResult;
+use std::io;
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncReadExt, ReadBuf};
+use tokio::process::Child;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Xz,
+    Lz4,
+    Zstd,
+}
+
+impl Compression {
+    /// The compression of a file, by its extension.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("gz") => Compression::Gzip,
+            Some("xz") => Compression::Xz,
+            Some("lz4") => Compression::Lz4,
+            Some("zst") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+}
+
+/// An open data file, which reads its decompressed contents.
+pub struct DataFile {
+    reader: Pin<Box<dyn AsyncRead + Send>>,
+    child: Option<Child>,
+}
+
+impl DataFile {
+    /// Opens a file, decompressing it if its extension is that of a compressor.
+    pub async fn open(path: &Path) -> io::Result<Self> {
+        if Compression::from_path(path) == Compression::None {
+            let file = tokio::fs::File::open(path).await?;
+
+            return Ok(Self {
+                reader: Box::pin(file),
+                child: None,
+            });
+        }
+
+        let (child, stdout) = AptHelper::new().cat_file(path).await?;
+
+        Ok(Self {
+            reader: Box::pin(stdout),
+            child: Some(child),
+        })
+    }
+
+    /// Waits for the decompressor to exit, and checks that it succeeded.
+    pub async fn finish(self) -> io::Result<()> {
+        let Self { reader, child } = self;
+        drop(reader);
+
+        match child {
+            Some(mut child) => child.wait().await?.into_result(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl AsyncRead for DataFile {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        self.reader.as_mut().poll_read(cx, buf)
+    }
+}
+
+/// Reads the decompressed contents of a file.
+///
+/// Bytes which are not valid UTF-8 are replaced with `U+FFFD`.
+pub async fn read_to_string(path: &Path) -> io::Result<String> {
+    let mut file = DataFile::open(path).await?;
+
+    let mut contents = Vec::new();
+    file.read_to_end(&mut contents).await?;
+    file.finish().await?;
+
+    Ok(match String::from_utf8(contents) {
+        Ok(contents) => contents,
+        Err(why) => String::from_utf8_lossy(why.as_bytes()).into_owned(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compression_by_extension() {
+        for (path, compression) in &[
+            ("/var/log/apt/history.log", Compression::None),
+            ("/var/log/apt/history.log.1.gz", Compression::Gzip),
+            ("Packages.xz", Compression::Xz),
+            ("jammy_main_binary-amd64_Packages.lz4", Compression::Lz4),
+            ("Contents-amd64.zst", Compression::Zstd),
+        ] {
+            assert_eq!(*compression, Compression::from_path(Path::new(path)));
+        }
+    }
+
+    #[tokio::test]
+    async fn read_plain_file() {
+        let temp = crate::utils::test_dir("data");
+        let path = temp.path().join("data");
+        std::fs::write(&path, b"Package: hello\n\xff\n").unwrap();
+
+        assert_eq!(
+            "Package: hello\n\u{FFFD}\n",
+            read_to_string(&path).await.unwrap()
+        );
+    }
+}
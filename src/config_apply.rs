@@ -0,0 +1,412 @@
+// Copyright 2021-2022 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+//! Converges the package configuration of a system to a declarative [`Document`].
+//!
+//! Repositories and pins are written to files which this module manages exclusively,
+//! so that configuration written by hand or by other tools is left untouched. Held
+//! packages and foreign architectures are system-wide, and are converged exactly.
+//!
+//! ```no_run
+//! use apt_cmd::config_apply::{self, Document};
+//!
+//! # async fn example(document: Document) -> anyhow::Result<()> {
+//! let diff = config_apply::diff(&document).await?;
+//! if !diff.is_empty() {
+//!     println!("{}", diff);
+//!     config_apply::apply(&diff).await?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use anyhow::Context;
+use std::collections::BTreeSet;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+use std::path::Path;
+
+/// The sources file which contains the document's repositories.
+pub const SOURCES_PATH: &str = "/etc/apt/sources.list.d/apt-cmd-managed.sources";
+
+/// The preferences file which contains the document's pins.
+pub const PREFERENCES_PATH: &str = "/etc/apt/preferences.d/apt-cmd-managed";
+
+/// The desired package configuration of a system.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Document {
+    pub repositories: Vec<Repository>,
+    pub pins: Vec<Pin>,
+    /// Every package which should be held. All other packages are unheld.
+    pub held: Vec<String>,
+    /// Every foreign architecture which should be enabled. All others are removed.
+    pub architectures: Vec<String>,
+}
+
+/// A repository, written as a deb822 stanza.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Repository {
+    /// Also fetch source packages from the repository.
+    pub sources: bool,
+    pub uris: Vec<String>,
+    pub suites: Vec<String>,
+    pub components: Vec<String>,
+    pub signed_by: Option<String>,
+}
+
+/// Pins matching versions of a package to a priority.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Pin {
+    pub package: String,
+    /// The `Pin` expression, such as `version 1.2*` or `release o=pop-os`.
+    pub pin: String,
+    pub priority: i32,
+}
+
+/// The package configuration of the system, as it relates to a [`Document`].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Current {
+    pub sources: Option<String>,
+    pub preferences: Option<String>,
+    pub held: Vec<String>,
+    pub architectures: Vec<String>,
+}
+
+/// Changes required to converge the system to a [`Document`].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Diff {
+    /// Contents to write to [`SOURCES_PATH`], or an empty string to remove it.
+    pub sources: Option<String>,
+    /// Contents to write to [`PREFERENCES_PATH`], or an empty string to remove it.
+    pub preferences: Option<String>,
+    pub hold: Vec<String>,
+    pub unhold: Vec<String>,
+    pub add_architectures: Vec<String>,
+    pub remove_architectures: Vec<String>,
+}
+
+impl Diff {
+    pub fn is_empty(&self) -> bool {
+        self == &Diff::default()
+    }
+}
+
+impl Display for Diff {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if let Some(sources) = &self.sources {
+            writeln!(f, "write {}:\n{}", SOURCES_PATH, sources)?;
+        }
+
+        if let Some(preferences) = &self.preferences {
+            writeln!(f, "write {}:\n{}", PREFERENCES_PATH, preferences)?;
+        }
+
+        for package in &self.hold {
+            writeln!(f, "hold {}", package)?;
+        }
+
+        for package in &self.unhold {
+            writeln!(f, "unhold {}", package)?;
+        }
+
+        for arch in &self.add_architectures {
+            writeln!(f, "add architecture {}", arch)?;
+        }
+
+        for arch in &self.remove_architectures {
+            writeln!(f, "remove architecture {}", arch)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads the current configuration of the system.
+pub async fn current() -> anyhow::Result<Current> {
+    let (sources, preferences, held, architectures) = futures::future::try_join4(
+        read_optional(Path::new(SOURCES_PATH)),
+        read_optional(Path::new(PREFERENCES_PATH)),
+        crate::AptMark::held(),
+        async {
+            crate::Dpkg::new()
+                .foreign_architectures()
+                .await
+                .context("failed to list foreign architectures")
+        },
+    )
+    .await?;
+
+    Ok(Current {
+        sources,
+        preferences,
+        held,
+        architectures,
+    })
+}
+
+/// Computes the changes required to converge the system to the document.
+pub async fn diff(document: &Document) -> anyhow::Result<Diff> {
+    Ok(plan(document, &current().await?))
+}
+
+/// Computes the changes required to converge `current` to the document.
+pub fn plan(document: &Document, current: &Current) -> Diff {
+    let file_change = |desired: String, current: &Option<String>| {
+        let current = current.as_deref().unwrap_or("");
+        if desired == current {
+            None
+        } else {
+            Some(desired)
+        }
+    };
+
+    let (hold, unhold) = set_difference(&document.held, &current.held);
+    let (add_architectures, remove_architectures) =
+        set_difference(&document.architectures, &current.architectures);
+
+    Diff {
+        sources: file_change(render_sources(&document.repositories), &current.sources),
+        preferences: file_change(render_preferences(&document.pins), &current.preferences),
+        hold,
+        unhold,
+        add_architectures,
+        remove_architectures,
+    }
+}
+
+/// Applies the changes of a diff.
+///
+/// Files are replaced atomically, but the diff as a whole is not. If a step fails, the
+/// steps before it are reverted before the error is returned: the files are restored to
+/// their previous contents, holds are reversed, and architectures are added or removed
+/// again. Reverting is best-effort, so the system may still be left partially converged.
+pub async fn apply(diff: &Diff) -> anyhow::Result<()> {
+    let previous_sources = read_optional(Path::new(SOURCES_PATH)).await?;
+    let previous_preferences = read_optional(Path::new(PREFERENCES_PATH)).await?;
+
+    let mut applied = Applied::default();
+    let result = apply_changes(diff, &mut applied).await;
+
+    if result.is_err() {
+        revert(diff, &applied).await;
+        let _ = write_optional(Path::new(SOURCES_PATH), previous_sources.as_deref()).await;
+        let _ = write_optional(Path::new(PREFERENCES_PATH), previous_preferences.as_deref()).await;
+    }
+
+    result
+}
+
+/// The system-wide changes of a diff which were applied, so that they may be reverted.
+#[derive(Default)]
+struct Applied {
+    added_architectures: Vec<String>,
+    removed_architectures: Vec<String>,
+    held: bool,
+    unheld: bool,
+}
+
+async fn apply_changes(diff: &Diff, applied: &mut Applied) -> anyhow::Result<()> {
+    if let Some(sources) = &diff.sources {
+        write_optional(Path::new(SOURCES_PATH), Some(sources))
+            .await
+            .with_context(|| format!("failed to write {}", SOURCES_PATH))?;
+    }
+
+    if let Some(preferences) = &diff.preferences {
+        write_optional(Path::new(PREFERENCES_PATH), Some(preferences))
+            .await
+            .with_context(|| format!("failed to write {}", PREFERENCES_PATH))?;
+    }
+
+    for arch in &diff.add_architectures {
+        crate::Dpkg::new()
+            .add_architecture(arch)
+            .await
+            .with_context(|| format!("failed to add architecture {}", arch))?;
+
+        applied.added_architectures.push(arch.clone());
+    }
+
+    for arch in &diff.remove_architectures {
+        crate::Dpkg::new()
+            .remove_architecture(arch)
+            .await
+            .with_context(|| format!("failed to remove architecture {}", arch))?;
+
+        applied.removed_architectures.push(arch.clone());
+    }
+
+    if !diff.hold.is_empty() {
+        crate::AptMark::new()
+            .hold(&diff.hold)
+            .await
+            .context("failed to hold packages")?;
+
+        applied.held = true;
+    }
+
+    if !diff.unhold.is_empty() {
+        crate::AptMark::new()
+            .unhold(&diff.unhold)
+            .await
+            .context("failed to unhold packages")?;
+
+        applied.unheld = true;
+    }
+
+    Ok(())
+}
+
+/// Reverses the applied system-wide changes, in the reverse of their order, ignoring
+/// any which fail.
+async fn revert(diff: &Diff, applied: &Applied) {
+    if applied.unheld {
+        let _ = crate::AptMark::new().hold(&diff.unhold).await;
+    }
+
+    if applied.held {
+        let _ = crate::AptMark::new().unhold(&diff.hold).await;
+    }
+
+    for arch in applied.removed_architectures.iter().rev() {
+        let _ = crate::Dpkg::new().add_architecture(arch).await;
+    }
+
+    for arch in applied.added_architectures.iter().rev() {
+        let _ = crate::Dpkg::new().remove_architecture(arch).await;
+    }
+}
+
+fn render_sources(repositories: &[Repository]) -> String {
+    let stanzas: Vec<String> = repositories
+        .iter()
+        .map(|repo| {
+            let mut stanza = format!(
+                "Types: {}\nURIs: {}\nSuites: {}\n",
+                if repo.sources { "deb deb-src" } else { "deb" },
+                repo.uris.join(" "),
+                repo.suites.join(" "),
+            );
+
+            if !repo.components.is_empty() {
+                stanza.push_str(&format!("Components: {}\n", repo.components.join(" ")));
+            }
+
+            if let Some(signed_by) = &repo.signed_by {
+                stanza.push_str(&format!("Signed-By: {}\n", signed_by));
+            }
+
+            stanza
+        })
+        .collect();
+
+    stanzas.join("\n")
+}
+
+fn render_preferences(pins: &[Pin]) -> String {
+    let stanzas: Vec<String> = pins
+        .iter()
+        .map(|pin| {
+            format!(
+                "Package: {}\nPin: {}\nPin-Priority: {}\n",
+                pin.package, pin.pin, pin.priority
+            )
+        })
+        .collect();
+
+    stanzas.join("\n")
+}
+
+/// Items to add to, and remove from, `current` to make it equal to `desired`.
+fn set_difference(desired: &[String], current: &[String]) -> (Vec<String>, Vec<String>) {
+    let desired: BTreeSet<&String> = desired.iter().collect();
+    let current: BTreeSet<&String> = current.iter().collect();
+
+    (
+        desired.difference(&current).map(|s| (*s).clone()).collect(),
+        current.difference(&desired).map(|s| (*s).clone()).collect(),
+    )
+}
+
+async fn read_optional(path: &Path) -> anyhow::Result<Option<String>> {
+    match tokio::fs::read_to_string(path).await {
+        Ok(contents) => Ok(Some(contents)),
+        Err(why) if why.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(why) => Err(why).with_context(|| format!("failed to read {}", path.display())),
+    }
+}
+
+/// Atomically replaces the file with `contents`, or removes it if there are none.
+async fn write_optional(path: &Path, contents: Option<&str>) -> io::Result<()> {
+    match contents {
+        Some(contents) if !contents.is_empty() => {
+            let temporary = path.with_extension("apt-cmd-tmp");
+            tokio::fs::write(&temporary, contents).await?;
+            tokio::fs::rename(&temporary, path).await
+        }
+
+        _ => match tokio::fs::remove_file(path).await {
+            Err(why) if why.kind() != io::ErrorKind::NotFound => Err(why),
+            _ => Ok(()),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plan() {
+        let document = Document {
+            repositories: vec![Repository {
+                sources: false,
+                uris: vec!["http://apt.pop-os.org/release".into()],
+                suites: vec!["jammy".into()],
+                components: vec!["main".into()],
+                signed_by: Some("/etc/apt/keyrings/pop-keyring-2017-archive.gpg".into()),
+            }],
+            pins: vec![Pin {
+                package: "firefox".into(),
+                pin: "release o=pop-os".into(),
+                priority: 1001,
+            }],
+            held: vec!["linux-generic".into(), "nvidia-driver-535".into()],
+            architectures: vec!["i386".into()],
+        };
+
+        let current = Current {
+            sources: None,
+            preferences: Some(render_preferences(&document.pins)),
+            held: vec!["nvidia-driver-535".into(), "mesa".into()],
+            architectures: vec!["i386".into()],
+        };
+
+        let diff = super::plan(&document, &current);
+
+        assert_eq!(
+            Some(
+                "Types: deb\n\
+                URIs: http://apt.pop-os.org/release\n\
+                Suites: jammy\n\
+                Components: main\n\
+                Signed-By: /etc/apt/keyrings/pop-keyring-2017-archive.gpg\n"
+            ),
+            diff.sources.as_deref()
+        );
+
+        assert_eq!(None, diff.preferences);
+        assert_eq!(vec!["linux-generic"], diff.hold);
+        assert_eq!(vec!["mesa"], diff.unhold);
+        assert!(diff.add_architectures.is_empty());
+        assert!(diff.remove_architectures.is_empty());
+
+        let converged = Current {
+            sources: diff.sources.clone(),
+            held: document.held.clone(),
+            ..current
+        };
+
+        assert!(super::plan(&document, &converged).is_empty());
+    }
+}
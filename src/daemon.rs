@@ -0,0 +1,241 @@
+// Copyright 2021-2022 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+//! Keeps package state in memory for long-running services, such as update indicators.
+//!
+//! The installed packages, their policies, and the source files are loaded once, and
+//! reloaded only when the dpkg status database, the package lists, or the sources have
+//! been modified since. Queries are otherwise answered without spawning a process.
+//!
+//! ```no_run
+//! use apt_cmd::daemon::AptDaemon;
+//!
+//! # async fn run() -> anyhow::Result<()> {
+//! let daemon = AptDaemon::new();
+//!
+//! loop {
+//!     let upgradable = daemon.upgradable().await?;
+//!     println!("{} packages can be upgraded", upgradable.len());
+//!     tokio::time::sleep(std::time::Duration::from_secs(60)).await;
+//! }
+//! # }
+//! ```
+
+use crate::dpkg::status;
+use crate::sources::{SOURCES_DIR, SOURCES_LIST};
+use crate::{AptCache, Policy};
+use anyhow::Context;
+use as_result::MapResult;
+use futures::StreamExt;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tokio::sync::Mutex;
+
+const DPKG_STATUS: &str = "/var/lib/dpkg/status";
+const LISTS_DIR: &str = "/var/lib/apt/lists";
+
+/// The paths whose modification invalidates the cached state.
+const WATCHED: &[&str] = &[DPKG_STATUS, LISTS_DIR, SOURCES_LIST, SOURCES_DIR];
+
+/// Package state which is reloaded when the system's package state changes.
+#[derive(Default)]
+pub struct AptDaemon {
+    cache: Mutex<Option<Cache>>,
+}
+
+struct Cache {
+    stamps: Stamps,
+    /// The version of each installed package.
+    installed: HashMap<String, String>,
+    /// The policy of each installed package.
+    policies: HashMap<String, Policy>,
+    sources: Vec<PathBuf>,
+}
+
+impl AptDaemon {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The installed version of a package, if it is installed.
+    pub async fn installed_version(&self, package: &str) -> anyhow::Result<Option<String>> {
+        self.with_cache(|cache| cache.installed.get(package).cloned())
+            .await
+    }
+
+    /// Every installed package, and its version.
+    pub async fn installed(&self) -> anyhow::Result<HashMap<String, String>> {
+        self.with_cache(|cache| cache.installed.clone()).await
+    }
+
+    /// The policy of an installed package.
+    pub async fn policy(&self, package: &str) -> anyhow::Result<Option<Policy>> {
+        self.with_cache(|cache| cache.policies.get(package).cloned())
+            .await
+    }
+
+    /// Installed packages whose candidate differs from the installed version, sorted.
+    pub async fn upgradable(&self) -> anyhow::Result<Vec<String>> {
+        self.with_cache(|cache| {
+            let mut packages: Vec<String> = cache
+                .policies
+                .values()
+                .filter(|policy| is_upgradable(policy))
+                .map(|policy| policy.package.clone())
+                .collect();
+
+            packages.sort_unstable();
+            packages
+        })
+        .await
+    }
+
+    /// The source files, as from `sources::source_files`.
+    pub async fn sources(&self) -> anyhow::Result<Vec<PathBuf>> {
+        self.with_cache(|cache| cache.sources.clone()).await
+    }
+
+    /// Discards the cached state, so that the next query reloads it.
+    pub async fn invalidate(&self) {
+        *self.cache.lock().await = None;
+    }
+
+    async fn with_cache<T, F: FnOnce(&Cache) -> T>(&self, query: F) -> anyhow::Result<T> {
+        let mut cache = self.cache.lock().await;
+
+        let stamps = Stamps::read(WATCHED);
+        let stale = match cache.as_ref() {
+            Some(cache) => cache.stamps != stamps,
+            None => true,
+        };
+
+        if stale {
+            *cache = Some(Cache::load(stamps).await?);
+        }
+
+        Ok(query(cache.as_ref().expect("cache was loaded")))
+    }
+}
+
+impl Cache {
+    async fn load(stamps: Stamps) -> anyhow::Result<Self> {
+        let native_arch = crate::Dpkg::new()
+            .architecture()
+            .await
+            .context("failed to get the architecture from `dpkg --print-architecture`")?;
+
+        let installed: HashMap<String, String> = status::read(Path::new(status::ADMINDIR))
+            .await
+            .context("failed to read the dpkg status database")?
+            .into_iter()
+            .filter(status::Stanza::is_installed)
+            .filter_map(|stanza| {
+                let package = stanza.package()?;
+                let package = match stanza.get("Architecture") {
+                    Some(arch) if arch != "all" && arch != native_arch => {
+                        [package, ":", arch].concat()
+                    }
+                    _ => package.to_owned(),
+                };
+
+                Some((package, stanza.version()?.to_owned()))
+            })
+            .collect();
+
+        let mut policies = HashMap::with_capacity(installed.len());
+
+        if !installed.is_empty() {
+            let packages: Vec<&str> = installed.keys().map(String::as_str).collect();
+            let (mut child, mut stream) = AptCache::new()
+                .policy(&packages)
+                .await
+                .context("failed to launch `apt-cache policy`")?;
+
+            while let Some(policy) = stream.next().await {
+                policies.insert(policy.package.clone(), policy);
+            }
+
+            child
+                .wait()
+                .await
+                .map_result()
+                .context("bad status from `apt-cache policy`")?;
+        }
+
+        let sources = crate::sources::source_files().await?;
+
+        Ok(Self {
+            stamps,
+            installed,
+            policies,
+            sources,
+        })
+    }
+}
+
+fn is_upgradable(policy: &Policy) -> bool {
+    policy.installed != "(none)"
+        && policy.candidate != "(none)"
+        && !policy.candidate.is_empty()
+        && policy.installed != policy.candidate
+}
+
+/// The modification times of the watched paths, or `None` for those which do not exist.
+#[derive(Debug, Eq, PartialEq)]
+struct Stamps(Vec<Option<SystemTime>>);
+
+impl Stamps {
+    fn read<P: AsRef<Path>>(paths: &[P]) -> Self {
+        Self(
+            paths
+                .iter()
+                .map(|path| {
+                    std::fs::metadata(path)
+                        .and_then(|meta| meta.modified())
+                        .ok()
+                })
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stamps_change_with_modification() {
+        let temp = crate::utils::test_dir("daemon");
+        let dir = temp.path();
+
+        let status = dir.join("status");
+        let paths = [status.clone(), dir.join("missing")];
+
+        std::fs::write(&status, "Package: hello\n").unwrap();
+        let before = Stamps::read(&paths);
+        assert!(before.0[0].is_some());
+        assert!(before.0[1].is_none());
+        assert_eq!(before, Stamps::read(&paths));
+
+        let file = std::fs::File::options().write(true).open(&status).unwrap();
+        file.set_modified(SystemTime::now() + std::time::Duration::from_secs(60))
+            .unwrap();
+        assert_ne!(before, Stamps::read(&paths));
+    }
+
+    #[test]
+    fn upgradable_policies() {
+        let policy = |installed: &str, candidate: &str| Policy {
+            package: "hello".into(),
+            installed: installed.into(),
+            candidate: candidate.into(),
+            ..Policy::default()
+        };
+
+        assert!(is_upgradable(&policy("1.0", "2.0")));
+        assert!(!is_upgradable(&policy("2.0", "2.0")));
+        assert!(!is_upgradable(&policy("(none)", "2.0")));
+        assert!(!is_upgradable(&policy("1.0", "(none)")));
+    }
+}
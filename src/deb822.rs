@@ -0,0 +1,249 @@
+// Copyright 2021-2022 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+//! Parses the deb822 format shared by the dpkg status database, package indexes,
+//! `Release` files, `.dsc` files, `.sources` files, and the output of `apt-cache show`.
+//!
+//! Stanzas are separated by blank lines. Lines beginning with `#` are comments, and a
+//! document signed inline, as an `InRelease` or `.dsc` file, is read without its armor.
+//! The signature is not verified.
+//!
+//! ```
+//! let release = "Origin: Ubuntu
+//! Suite: jammy
+//! Architectures: amd64 arm64 armhf i386
+//! SHA256:
+//!  0b4e4d8d4d2b8e9c4a1f2b1f1bbd6c2d6e1f27f85c8b7e7b94c3ac1cd7c1d0b8 1234 main/binary-amd64/Packages
+//! ";
+//!
+//! let stanza = apt_cmd::deb822::parse(release).next().unwrap();
+//! assert_eq!(Some("jammy"), stanza.get("Suite"));
+//! assert_eq!(vec!["amd64", "arm64", "armhf", "i386"], stanza.get_list("Architectures"));
+//! assert_eq!(1, stanza.get_lines("SHA256").len());
+//! ```
+
+/// A stanza, also known as a paragraph, of fields.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Stanza {
+    /// Fields in the order they appear. Multi-line values keep their continuation
+    /// lines, joined by newlines.
+    pub fields: Vec<(String, String)>,
+}
+
+impl Stanza {
+    /// The value of a field, matched case-insensitively.
+    pub fn get(&self, field: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(field))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The value of a folded field, such as `Depends` or `Uploaders`, with its
+    /// continuation lines joined by spaces.
+    pub fn get_folded(&self, field: &str) -> Option<String> {
+        let value = self.get(field)?;
+        Some(value.split_whitespace().collect::<Vec<_>>().join(" "))
+    }
+
+    /// Each line of a multi-line field, such as `Files`, `SHA256`, or `Conffiles`,
+    /// without its indentation. Empty lines are skipped.
+    pub fn get_lines(&self, field: &str) -> Vec<&str> {
+        self.get(field)
+            .unwrap_or_default()
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect()
+    }
+
+    /// Each value of a whitespace-separated field, such as `Architectures` or `Suites`.
+    pub fn get_list(&self, field: &str) -> Vec<&str> {
+        self.get(field)
+            .unwrap_or_default()
+            .split_ascii_whitespace()
+            .collect()
+    }
+
+    /// Each relation of a comma-separated field, such as `libc6 (>= 2.34)` or
+    /// `awk | mawk`, folded onto a single line.
+    pub fn get_relations(&self, field: &str) -> Vec<String> {
+        self.get(field)
+            .unwrap_or_default()
+            .split(',')
+            .map(|relation| relation.split_whitespace().collect::<Vec<_>>().join(" "))
+            .filter(|relation| !relation.is_empty())
+            .collect()
+    }
+}
+
+/// Where a reader is in an inline-signed document.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+enum Armor {
+    #[default]
+    None,
+    /// The `Hash:` headers, which end at the first blank line.
+    Header,
+    /// The signed text, whose lines beginning with `-` are escaped as `- -`.
+    Signed,
+    /// Everything after `-----BEGIN PGP SIGNATURE-----`.
+    Signature,
+}
+
+/// Parses stanzas one line at a time, as they are read from a file or process.
+#[derive(Debug, Default)]
+pub struct Reader {
+    stanza: Stanza,
+    armor: Armor,
+}
+
+impl Reader {
+    /// Returns a stanza once the blank line which ends it is read.
+    pub fn parse_line(&mut self, line: &str) -> Option<Stanza> {
+        let mut line = line.strip_suffix('\r').unwrap_or(line);
+
+        match self.armor {
+            Armor::Header => {
+                if line.trim().is_empty() {
+                    self.armor = Armor::Signed;
+                }
+
+                return None;
+            }
+            Armor::Signature => return None,
+            Armor::Signed => {
+                if line.starts_with("-----BEGIN PGP SIGNATURE-----") {
+                    self.armor = Armor::Signature;
+                    return self.take();
+                }
+
+                line = line.strip_prefix("- ").unwrap_or(line);
+            }
+            Armor::None => {
+                if line.starts_with("-----BEGIN PGP SIGNED MESSAGE-----") {
+                    self.armor = Armor::Header;
+                    return self.take();
+                }
+            }
+        }
+
+        if line.trim().is_empty() {
+            return self.take();
+        }
+
+        if line.starts_with('#') {
+            return None;
+        }
+
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = self.stanza.fields.last_mut() {
+                value.push('\n');
+                value.push_str(line);
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            self.stanza
+                .fields
+                .push((name.trim().to_owned(), value.trim().to_owned()));
+        }
+
+        None
+    }
+
+    /// The last stanza, if the input did not end with a blank line.
+    pub fn finish(mut self) -> Option<Stanza> {
+        self.take()
+    }
+
+    fn take(&mut self) -> Option<Stanza> {
+        if self.stanza.fields.is_empty() {
+            None
+        } else {
+            Some(std::mem::take(&mut self.stanza))
+        }
+    }
+}
+
+/// Parses every stanza of a document.
+pub fn parse(input: &str) -> impl Iterator<Item = Stanza> + '_ {
+    let mut reader = Some(Reader::default());
+    let mut lines = input.lines();
+
+    std::iter::from_fn(move || loop {
+        match lines.next() {
+            Some(line) => {
+                if let Some(stanza) = reader.as_mut()?.parse_line(line) {
+                    return Some(stanza);
+                }
+            }
+            None => return reader.take()?.finish(),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn folded_and_multiline_fields() {
+        let input = "# A comment before the stanza.
+Source: hello
+Build-Depends: debhelper-compat (= 13),
+ libc6-dev,
+   gettext
+Files:
+ d41d8cd98f00b204e9800998ecf8427e 0 hello_2.10.orig.tar.gz
+ 9e107d9d372bb6826bd81d3542a419d6 1 hello_2.10-2.debian.tar.xz
+\r
+\t
+Package: hello
+";
+
+        let stanzas: Vec<_> = parse(input).collect();
+        assert_eq!(2, stanzas.len());
+
+        let source = &stanzas[0];
+        assert_eq!(
+            vec!["debhelper-compat (= 13)", "libc6-dev", "gettext"],
+            source.get_relations("build-depends")
+        );
+        assert_eq!(
+            Some("debhelper-compat (= 13), libc6-dev, gettext".to_owned()),
+            source.get_folded("Build-Depends")
+        );
+        assert_eq!(
+            vec![
+                "d41d8cd98f00b204e9800998ecf8427e 0 hello_2.10.orig.tar.gz",
+                "9e107d9d372bb6826bd81d3542a419d6 1 hello_2.10-2.debian.tar.xz"
+            ],
+            source.get_lines("Files")
+        );
+
+        assert_eq!(Some("hello"), stanzas[1].get("Package"));
+    }
+
+    #[test]
+    fn signed_document() {
+        let input = "-----BEGIN PGP SIGNED MESSAGE-----
+Hash: SHA512
+
+Origin: Pop!_OS
+Description: Pop!_OS Release
+- -- not a signature
+
+Suite: jammy
+-----BEGIN PGP SIGNATURE-----
+
+iQIzBAEBCgAdFiEE
+Fake: field
+-----END PGP SIGNATURE-----
+";
+
+        let stanzas: Vec<_> = parse(input).collect();
+        assert_eq!(2, stanzas.len());
+        assert_eq!(Some("Pop!_OS"), stanzas[0].get("Origin"));
+        assert_eq!(None, stanzas[0].get("Hash"));
+        assert_eq!(Some("jammy"), stanzas[1].get("Suite"));
+        assert_eq!(None, stanzas[1].get("Fake"));
+    }
+}
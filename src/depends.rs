@@ -0,0 +1,915 @@
+// Copyright 2021-2022 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::str::FromStr;
+
+/// The relationship of a dependency to the package which declares it.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum DependencyKind {
+    Depends,
+    PreDepends,
+    Recommends,
+    Suggests,
+    Enhances,
+    Breaks,
+    Conflicts,
+    Replaces,
+    Other(Box<str>),
+}
+
+impl FromStr for DependencyKind {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let kind = match input {
+            "Depends" => DependencyKind::Depends,
+            "PreDepends" | "Pre-Depends" => DependencyKind::PreDepends,
+            "Recommends" => DependencyKind::Recommends,
+            "Suggests" => DependencyKind::Suggests,
+            "Enhances" => DependencyKind::Enhances,
+            "Breaks" => DependencyKind::Breaks,
+            "Conflicts" => DependencyKind::Conflicts,
+            "Replaces" => DependencyKind::Replaces,
+            other => DependencyKind::Other(other.into()),
+        };
+
+        Ok(kind)
+    }
+}
+
+impl DependencyKind {
+    /// The name of the relation, as printed by `apt-cache depends`.
+    pub fn as_str(&self) -> &str {
+        match self {
+            DependencyKind::Depends => "Depends",
+            DependencyKind::PreDepends => "PreDepends",
+            DependencyKind::Recommends => "Recommends",
+            DependencyKind::Suggests => "Suggests",
+            DependencyKind::Enhances => "Enhances",
+            DependencyKind::Breaks => "Breaks",
+            DependencyKind::Conflicts => "Conflicts",
+            DependencyKind::Replaces => "Replaces",
+            DependencyKind::Other(kind) => kind,
+        }
+    }
+}
+
+/// A dependency listed by `apt-cache depends`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Dependency {
+    pub kind: DependencyKind,
+    /// The package depended on, such as `libc6` or the virtual package `<mail-transport-agent>`.
+    pub package: String,
+    /// Whether the next dependency is an alternative to this one, as in `a | b`.
+    pub or_next: bool,
+    /// Packages which provide a virtual package.
+    pub providers: Vec<String>,
+}
+
+/// The dependencies of a single package.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct DependsBlock {
+    pub package: String,
+    pub dependencies: Vec<Dependency>,
+}
+
+/// Parses the output of `apt-cache depends` one line at a time.
+#[derive(Debug, Default)]
+pub struct DependsParser {
+    block: Option<DependsBlock>,
+}
+
+impl DependsParser {
+    /// Returns the previous package's block once the next package begins.
+    pub fn parse_line(&mut self, line: &str) -> Option<DependsBlock> {
+        if line.trim().is_empty() {
+            return None;
+        }
+
+        if !line.starts_with(' ') {
+            return self.block.replace(DependsBlock {
+                package: line.trim().to_owned(),
+                dependencies: Vec::new(),
+            });
+        }
+
+        let block = self.block.as_mut()?;
+
+        if let Some(provider) = line.strip_prefix("    ") {
+            if let Some(dependency) = block.dependencies.last_mut() {
+                dependency.providers.push(provider.trim().to_owned());
+            }
+
+            return None;
+        }
+
+        let (or_next, relation) = match line.strip_prefix(" |") {
+            Some(relation) => (true, relation),
+            None => (false, line.trim_start()),
+        };
+
+        if let Some((kind, package)) = relation.split_once(": ") {
+            block.dependencies.push(Dependency {
+                kind: kind.parse().unwrap_or(DependencyKind::Other(kind.into())),
+                package: package.trim().to_owned(),
+                or_next,
+                providers: Vec::new(),
+            });
+        }
+
+        None
+    }
+
+    /// The block of the last package.
+    pub fn finish(self) -> Option<DependsBlock> {
+        self.block
+    }
+}
+
+/// The dependency closure of packages, from `apt-cache depends --recurse`.
+///
+/// Each package reached appears once, however many packages depend on it, and however
+/// many times apt printed it, so that cycles such as `libc6` and `libgcc-s1` depending
+/// on each other are represented without repetition.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct DependencyGraph {
+    /// The packages which were queried.
+    pub roots: Vec<String>,
+    /// The dependencies of each package reached, including virtual packages, which are
+    /// named within angle brackets, as `<mail-transport-agent>`.
+    pub nodes: BTreeMap<String, Vec<Dependency>>,
+}
+
+impl DependencyGraph {
+    pub fn from_blocks<I>(roots: Vec<String>, blocks: I) -> Self
+    where
+        I: IntoIterator<Item = DependsBlock>,
+    {
+        let mut nodes: BTreeMap<String, Vec<Dependency>> = BTreeMap::new();
+
+        for block in blocks {
+            let dependencies = nodes.entry(block.package).or_default();
+            for dependency in block.dependencies {
+                if !dependencies.contains(&dependency) {
+                    dependencies.push(dependency);
+                }
+            }
+        }
+
+        Self { roots, nodes }
+    }
+
+    /// The direct dependencies of a package.
+    pub fn dependencies(&self, package: &str) -> &[Dependency] {
+        self.nodes.get(package).map_or(&[], Vec::as_slice)
+    }
+
+    /// Every package reached which is not virtual, such as to fetch for an offline install.
+    pub fn packages(&self) -> impl Iterator<Item = &str> {
+        self.nodes
+            .keys()
+            .map(String::as_str)
+            .filter(|package| !is_virtual(package))
+    }
+
+    /// The packages which `package` depends on, directly or indirectly, including the
+    /// providers of virtual packages, but not the virtual packages themselves.
+    pub fn closure(&self, package: &str) -> BTreeSet<&str> {
+        let mut visited: BTreeSet<&str> = BTreeSet::new();
+        let mut frontier: Vec<&str> = vec![package];
+
+        while let Some(next) = frontier.pop() {
+            for dependency in self.dependencies(next) {
+                let targets = std::iter::once(&dependency.package).chain(&dependency.providers);
+
+                for target in targets {
+                    if visited.insert(target) {
+                        frontier.push(target);
+                    }
+                }
+            }
+        }
+
+        visited.remove(package);
+        visited.retain(|package| !is_virtual(package));
+        visited
+    }
+}
+
+/// What an edge of a `DependencyGraph` represents.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum EdgeKind<'a> {
+    /// The source package declares a relation on the target.
+    Relation(&'a DependencyKind),
+    /// The source, a virtual package, is provided by the target.
+    ProvidedBy,
+}
+
+/// An edge between two packages of a `DependencyGraph`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct DependencyEdge<'a> {
+    pub from: &'a str,
+    pub to: &'a str,
+    pub kind: EdgeKind<'a>,
+}
+
+impl DependencyGraph {
+    /// Every package of the graph, both those whose dependencies are known, and those
+    /// which are only depended on.
+    pub fn nodes(&self) -> BTreeSet<&str> {
+        let mut nodes: BTreeSet<&str> = self.nodes.keys().map(String::as_str).collect();
+        nodes.extend(self.edges().map(|edge| edge.to));
+        nodes
+    }
+
+    /// Each relation of each package, followed by edges from the virtual packages it
+    /// depends on to their providers.
+    pub fn edges(&self) -> impl Iterator<Item = DependencyEdge<'_>> {
+        self.nodes.iter().flat_map(|(from, dependencies)| {
+            dependencies.iter().flat_map(move |dependency| {
+                let relation = DependencyEdge {
+                    from,
+                    to: &dependency.package,
+                    kind: EdgeKind::Relation(&dependency.kind),
+                };
+
+                let providers = dependency
+                    .providers
+                    .iter()
+                    .map(move |provider| DependencyEdge {
+                        from: &dependency.package,
+                        to: provider,
+                        kind: EdgeKind::ProvidedBy,
+                    });
+
+                std::iter::once(relation).chain(providers)
+            })
+        })
+    }
+
+    /// Renders the graph in the DOT language of Graphviz, such as for `dot -Tsvg`.
+    ///
+    /// Queried packages are drawn in bold, virtual packages in boxes, and the edges to
+    /// the providers of virtual packages are dashed.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph dependencies {\n");
+        // A virtual package depended on by several packages is listed with its
+        // providers each time.
+        let mut seen = HashSet::new();
+        let edges = self.edges().filter(|edge| seen.insert(*edge));
+
+        for node in self.nodes() {
+            let mut attributes = Vec::new();
+
+            if self.roots.iter().any(|root| root == node) {
+                attributes.push("style=bold");
+            }
+
+            if is_virtual(node) {
+                attributes.push("shape=box");
+            }
+
+            if attributes.is_empty() {
+                dot.push_str(&format!("    {};\n", dot_id(node)));
+            } else {
+                dot.push_str(&format!(
+                    "    {} [{}];\n",
+                    dot_id(node),
+                    attributes.join(", ")
+                ));
+            }
+        }
+
+        for edge in edges {
+            let attributes = match edge.kind {
+                EdgeKind::Relation(kind) => format!("label={}", dot_id(kind.as_str())),
+                EdgeKind::ProvidedBy => "style=dashed".to_owned(),
+            };
+
+            dot.push_str(&format!(
+                "    {} -> {} [{}];\n",
+                dot_id(edge.from),
+                dot_id(edge.to),
+                attributes
+            ));
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// Quotes an ID of the DOT language.
+fn dot_id(id: &str) -> String {
+    format!("\"{}\"", id.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn is_virtual(package: &str) -> bool {
+    package.starts_with('<')
+}
+
+/// A package which depends on a queried package, from `apt-cache rdepends`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ReverseDependency {
+    /// The package that was queried.
+    pub package: String,
+    /// The package which depends on it.
+    pub dependent: String,
+    /// How it depends on the package, if the output was from `AptCache::rdepends_typed`.
+    pub kind: Option<DependencyKind>,
+}
+
+impl ReverseDependency {
+    /// Whether the relation causes the dependent to be installed with, or removed
+    /// with, the package: a Depends or Pre-Depends.
+    pub fn is_strong(&self) -> bool {
+        matches!(
+            self.kind,
+            Some(DependencyKind::Depends | DependencyKind::PreDepends)
+        )
+    }
+}
+
+/// Parses the output of `apt-cache rdepends`, which may cover several packages.
+#[derive(Debug, Default)]
+pub struct RdependsParser {
+    package: Option<String>,
+}
+
+impl RdependsParser {
+    pub fn parse_line(&mut self, line: &str) -> Option<ReverseDependency> {
+        if line.trim().is_empty() {
+            return None;
+        }
+
+        if !line.starts_with(' ') {
+            if line != "Reverse Depends:" {
+                self.package = Some(line.trim().to_owned());
+            }
+
+            return None;
+        }
+
+        let dependent = line.trim_start().trim_start_matches('|').trim();
+
+        // With `APT::Cache::ShowDependencyType`, as `Depends: foo`.
+        let (kind, dependent) = match dependent.split_once(": ") {
+            Some((kind, dependent)) => (kind.parse::<DependencyKind>().ok(), dependent),
+            None => (None, dependent),
+        };
+
+        Some(ReverseDependency {
+            package: self.package.clone()?,
+            dependent: dependent.to_owned(),
+            kind,
+        })
+    }
+}
+
+/// A package whose dependencies could not be satisfied, from the `The following packages
+/// have unmet dependencies:` section of apt's output.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct UnmetDependency {
+    pub package: String,
+    pub relations: Vec<UnmetRelation>,
+}
+
+/// A relation which could not be satisfied by any of its alternatives.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct UnmetRelation {
+    pub kind: DependencyKind,
+    pub alternatives: Vec<UnmetTarget>,
+}
+
+/// A package which was required by a relation, and why it could not satisfy it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct UnmetTarget {
+    pub package: String,
+    /// The version constraint of the relation, such as `>= 2.0`.
+    pub constraint: Option<String>,
+    pub reason: Option<UnmetReason>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum UnmetReason {
+    /// No version of the package can be installed from the configured repositories.
+    NotInstallable,
+    /// The package can be installed, but another relation prevents it.
+    NotGoingToBeInstalled,
+    /// A virtual package which nothing installable provides.
+    Virtual,
+    NotInstalled,
+    /// This version, which does not satisfy the constraint, is to be installed.
+    ToBeInstalled(String),
+    /// This version, which does not satisfy the constraint, is installed.
+    Installed(String),
+    Other(String),
+}
+
+/// Parses the unmet dependencies printed by a failed `apt-get install`.
+pub(crate) fn parse_unmet_dependencies(output: &str) -> Vec<UnmetDependency> {
+    let mut unmet: Vec<UnmetDependency> = Vec::new();
+    let mut in_section = false;
+    let mut or_next = false;
+
+    for line in output.lines() {
+        if line.starts_with("The following packages have unmet dependencies:") {
+            in_section = true;
+            continue;
+        }
+
+        if !in_section {
+            continue;
+        }
+
+        if !line.starts_with(' ') {
+            in_section = false;
+            continue;
+        }
+
+        let mut rest = line.trim();
+
+        if let Some((package, relation)) = rest.split_once(" : ") {
+            unmet.push(UnmetDependency {
+                package: package.trim().to_owned(),
+                relations: Vec::new(),
+            });
+
+            rest = relation;
+            or_next = false;
+        }
+
+        let dependency = match unmet.last_mut() {
+            Some(dependency) => dependency,
+            None => continue,
+        };
+
+        let (target, or) = match rest.strip_suffix(" or") {
+            Some(target) => (target, true),
+            None => (rest, false),
+        };
+
+        if or_next {
+            if let Some(relation) = dependency.relations.last_mut() {
+                relation.alternatives.push(parse_unmet_target(target));
+            }
+        } else if let Some((kind, target)) = target.split_once(": ") {
+            dependency.relations.push(UnmetRelation {
+                kind: kind.parse().unwrap_or(DependencyKind::Other(kind.into())),
+                alternatives: vec![parse_unmet_target(target)],
+            });
+        }
+
+        or_next = or;
+    }
+
+    unmet
+}
+
+/// Parses a target such as `libbar (>= 2.0) but 1.0-1 is to be installed`.
+fn parse_unmet_target(target: &str) -> UnmetTarget {
+    let (target, reason) = match target.split_once(" but ") {
+        Some((target, reason)) => (target, Some(reason)),
+        None => (target, None),
+    };
+
+    let (package, constraint) = match target.split_once(" (") {
+        Some((package, constraint)) => (package, Some(constraint.trim_end_matches(')'))),
+        None => (target, None),
+    };
+
+    let reason = reason.map(|reason| match reason {
+        "it is not installable" => UnmetReason::NotInstallable,
+        "it is not going to be installed" => UnmetReason::NotGoingToBeInstalled,
+        "it is a virtual package" => UnmetReason::Virtual,
+        "it is not installed" => UnmetReason::NotInstalled,
+        reason => {
+            if let Some(version) = reason.strip_suffix(" is to be installed") {
+                UnmetReason::ToBeInstalled(version.to_owned())
+            } else if let Some(version) = reason.strip_suffix(" is installed") {
+                UnmetReason::Installed(version.to_owned())
+            } else {
+                UnmetReason::Other(reason.to_owned())
+            }
+        }
+    });
+
+    UnmetTarget {
+        package: package.trim().to_owned(),
+        constraint: constraint.map(String::from),
+        reason,
+    }
+}
+
+/// The packages which provide, or are provided by, a package, from `apt-cache showpkg`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct PackageProviders {
+    pub package: String,
+    /// The available versions of the package, which a virtual package has none of.
+    pub versions: Vec<String>,
+    /// The `(version, package)` of each package provided by each version of this one,
+    /// such as `("119.0", "www-browser")`.
+    pub provides: Vec<(String, String)>,
+    /// The `(package, version)` of each package version which provides this one,
+    /// such as `("firefox", "119.0")`.
+    pub provided_by: Vec<(String, String)>,
+}
+
+impl PackageProviders {
+    /// Whether the package is only provided by other packages.
+    pub fn is_virtual(&self) -> bool {
+        self.versions.is_empty()
+    }
+
+    /// The name of each package which provides this one, without duplicates.
+    pub fn provider_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = Vec::new();
+
+        for (package, _) in &self.provided_by {
+            if !names.contains(&package.as_str()) {
+                names.push(package);
+            }
+        }
+
+        names
+    }
+}
+
+const SHOWPKG_SECTIONS: &[&str] = &[
+    "Versions",
+    "Reverse Depends",
+    "Dependencies",
+    "Provides",
+    "Reverse Provides",
+];
+
+/// Parses the `Versions`, `Provides`, and `Reverse Provides` sections of the output of
+/// `apt-cache showpkg` for a single package.
+pub(crate) fn parse_showpkg(output: &str) -> Option<PackageProviders> {
+    let mut providers = PackageProviders::default();
+    let mut section = "";
+
+    for line in output.lines() {
+        if let Some(package) = line.strip_prefix("Package: ") {
+            providers.package = package.trim().to_owned();
+            continue;
+        }
+
+        if let Some(heading) = line.trim_end().strip_suffix(':') {
+            if SHOWPKG_SECTIONS.contains(&heading) {
+                section = heading;
+                continue;
+            }
+        }
+
+        match section {
+            // `119.0 (/var/lib/apt/lists/...) (/var/lib/dpkg/status)`
+            "Versions" if !line.starts_with(' ') => {
+                if let Some(version) = line.split_ascii_whitespace().next() {
+                    providers.versions.push(version.to_owned());
+                }
+            }
+            // `119.0 - gnome-www-browser (= ) www-browser (= ) `
+            "Provides" => {
+                let Some((version, provided)) = line.split_once(" - ") else {
+                    continue;
+                };
+
+                let mut words = provided.split_ascii_whitespace();
+                while let Some(package) = words.next() {
+                    // Skip the version of the provided package, as in `(= )` or `(= 1.0)`.
+                    if words.next().is_some_and(|op| op == "(=") {
+                        words.find(|word| word.ends_with(')'));
+                    }
+
+                    providers
+                        .provides
+                        .push((version.to_owned(), package.to_owned()));
+                }
+            }
+            // `firefox 119.0 (= )`
+            "Reverse Provides" => {
+                let mut words = line.split_ascii_whitespace();
+                if let (Some(package), Some(version)) = (words.next(), words.next()) {
+                    providers
+                        .provided_by
+                        .push((package.to_owned(), version.to_owned()));
+                }
+            }
+            _ => (),
+        }
+    }
+
+    if providers.package.is_empty() {
+        None
+    } else {
+        Some(providers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEPENDS: &str = "firefox
+  PreDepends: debconf
+ |Depends: libgtk-3-0
+  Depends: libgtk-4-1
+  Recommends: <xul-ext-ubufox>
+    xul-ext-ubufox-pop
+hello
+  Depends: libc6
+";
+
+    #[test]
+    fn depends_parser() {
+        let mut parser = DependsParser::default();
+        let mut blocks: Vec<_> = DEPENDS
+            .lines()
+            .filter_map(|line| parser.parse_line(line))
+            .collect();
+        blocks.extend(parser.finish());
+
+        assert_eq!(2, blocks.len());
+        assert_eq!("firefox", blocks[0].package);
+        assert_eq!(4, blocks[0].dependencies.len());
+        assert_eq!(DependencyKind::PreDepends, blocks[0].dependencies[0].kind);
+        assert!(blocks[0].dependencies[1].or_next);
+        assert!(!blocks[0].dependencies[2].or_next);
+        assert_eq!(
+            vec!["xul-ext-ubufox-pop"],
+            blocks[0].dependencies[3].providers
+        );
+
+        assert_eq!(
+            DependsBlock {
+                package: "hello".into(),
+                dependencies: vec![Dependency {
+                    kind: DependencyKind::Depends,
+                    package: "libc6".into(),
+                    or_next: false,
+                    providers: Vec::new(),
+                }],
+            },
+            blocks[1]
+        );
+    }
+
+    #[test]
+    fn unmet_dependencies() {
+        let output = "Some packages could not be installed. This may mean that you have
+requested an impossible situation or if you are using the unstable
+distribution that some required packages have not yet been created
+or been moved out of Incoming.
+The following information may help to resolve the situation:
+
+The following packages have unmet dependencies:
+ foo : Depends: libbar (>= 2.0) but 1.0-1 is to be installed
+       Depends: libbaz but it is not installable or
+                libbaz2 but it is not going to be installed
+       Breaks: qux (< 3) but 2.0 is installed
+ libbar : PreDepends: libc6 (>= 2.38) but 2.35-0ubuntu3 is installed
+E: Unable to correct problems, you have held broken packages.
+";
+
+        let unmet = parse_unmet_dependencies(output);
+
+        assert_eq!(2, unmet.len());
+        assert_eq!("foo", unmet[0].package);
+        assert_eq!(3, unmet[0].relations.len());
+
+        assert_eq!(
+            UnmetRelation {
+                kind: DependencyKind::Depends,
+                alternatives: vec![UnmetTarget {
+                    package: "libbar".into(),
+                    constraint: Some(">= 2.0".into()),
+                    reason: Some(UnmetReason::ToBeInstalled("1.0-1".into())),
+                }],
+            },
+            unmet[0].relations[0]
+        );
+
+        let alternatives = &unmet[0].relations[1].alternatives;
+        assert_eq!(2, alternatives.len());
+        assert_eq!(Some(UnmetReason::NotInstallable), alternatives[0].reason);
+        assert_eq!("libbaz2", alternatives[1].package);
+        assert_eq!(
+            Some(UnmetReason::NotGoingToBeInstalled),
+            alternatives[1].reason
+        );
+
+        assert_eq!(DependencyKind::Breaks, unmet[0].relations[2].kind);
+        assert_eq!(
+            Some(UnmetReason::Installed("2.0".into())),
+            unmet[0].relations[2].alternatives[0].reason
+        );
+
+        assert_eq!(DependencyKind::PreDepends, unmet[1].relations[0].kind);
+        assert!(parse_unmet_dependencies("Reading package lists...").is_empty());
+    }
+
+    #[test]
+    fn dependency_graph_cycles() {
+        let output = "hello
+  Depends: libc6
+libc6
+  Depends: libgcc-s1
+ |Depends: <libcrypt1-dev>
+    libcrypt1
+libgcc-s1
+  Depends: gcc-12-base
+  Depends: libc6
+libcrypt1
+  Depends: libc6
+gcc-12-base
+<libcrypt1-dev>
+libc6
+  Depends: libgcc-s1
+";
+
+        let mut parser = DependsParser::default();
+        let mut blocks: Vec<_> = output
+            .lines()
+            .filter_map(|line| parser.parse_line(line))
+            .collect();
+        blocks.extend(parser.finish());
+
+        let graph = DependencyGraph::from_blocks(vec!["hello".to_owned()], blocks);
+
+        assert_eq!(2, graph.dependencies("libc6").len());
+        assert_eq!(
+            vec!["gcc-12-base", "hello", "libc6", "libcrypt1", "libgcc-s1"],
+            graph.packages().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec!["gcc-12-base", "libc6", "libcrypt1", "libgcc-s1"],
+            graph.closure("hello").into_iter().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec!["gcc-12-base", "libcrypt1", "libgcc-s1"],
+            graph.closure("libc6").into_iter().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn dependency_graph_dot() {
+        let hello = DependsBlock {
+            package: "hello".into(),
+            dependencies: vec![
+                Dependency {
+                    kind: DependencyKind::Depends,
+                    package: "libc6".into(),
+                    or_next: false,
+                    providers: Vec::new(),
+                },
+                Dependency {
+                    kind: DependencyKind::Recommends,
+                    package: "<mail-transport-agent>".into(),
+                    or_next: false,
+                    providers: vec!["postfix".into()],
+                },
+            ],
+        };
+
+        let libc6 = DependsBlock {
+            package: "libc6".into(),
+            dependencies: vec![Dependency {
+                kind: DependencyKind::Suggests,
+                package: "<mail-transport-agent>".into(),
+                or_next: false,
+                providers: vec!["postfix".into()],
+            }],
+        };
+
+        let graph = DependencyGraph::from_blocks(vec!["hello".to_owned()], vec![hello, libc6]);
+
+        assert_eq!(
+            vec!["<mail-transport-agent>", "hello", "libc6", "postfix"],
+            graph.nodes().into_iter().collect::<Vec<_>>()
+        );
+
+        assert_eq!(
+            "digraph dependencies {
+    \"<mail-transport-agent>\" [shape=box];
+    \"hello\" [style=bold];
+    \"libc6\";
+    \"postfix\";
+    \"hello\" -> \"libc6\" [label=\"Depends\"];
+    \"hello\" -> \"<mail-transport-agent>\" [label=\"Recommends\"];
+    \"<mail-transport-agent>\" -> \"postfix\" [style=dashed];
+    \"libc6\" -> \"<mail-transport-agent>\" [label=\"Suggests\"];
+}
+",
+            graph.to_dot()
+        );
+
+        assert_eq!(r#""a\"b""#, dot_id("a\"b"));
+    }
+
+    #[test]
+    fn rdepends_parser() {
+        let output = "libfoo1
+Reverse Depends:
+  foo-utils
+ |bar
+libbaz2
+Reverse Depends:
+  baz
+";
+
+        let mut parser = RdependsParser::default();
+        let dependencies: Vec<_> = output
+            .lines()
+            .filter_map(|line| parser.parse_line(line))
+            .map(|dep| (dep.package, dep.dependent))
+            .collect();
+
+        assert_eq!(
+            vec![
+                ("libfoo1".to_owned(), "foo-utils".to_owned()),
+                ("libfoo1".to_owned(), "bar".to_owned()),
+                ("libbaz2".to_owned(), "baz".to_owned()),
+            ],
+            dependencies
+        );
+    }
+
+    #[test]
+    fn rdepends_typed_parser() {
+        let output = "libfoo1
+Reverse Depends:
+  Depends: foo-utils
+ |Recommends: bar
+  Suggests: baz
+";
+
+        let mut parser = RdependsParser::default();
+        let dependencies: Vec<_> = output
+            .lines()
+            .filter_map(|line| parser.parse_line(line))
+            .collect();
+
+        assert_eq!(3, dependencies.len());
+        assert_eq!("bar", dependencies[1].dependent);
+        assert_eq!(Some(DependencyKind::Recommends), dependencies[1].kind);
+
+        let strong: Vec<_> = dependencies
+            .iter()
+            .filter(|dep| dep.is_strong())
+            .map(|dep| dep.dependent.as_str())
+            .collect();
+        assert_eq!(vec!["foo-utils"], strong);
+    }
+
+    #[test]
+    fn showpkg_providers() {
+        let output = "Package: www-browser
+Versions: 
+
+Reverse Depends: 
+  sensible-utils,www-browser
+Dependencies: 
+Provides: 
+Reverse Provides: 
+lynx 2.9.0dev.10-1 (= )
+firefox 119.0 (= )
+firefox 118.0 (= )
+";
+
+        let virtual_package = parse_showpkg(output).unwrap();
+        assert!(virtual_package.is_virtual());
+        assert_eq!(vec!["lynx", "firefox"], virtual_package.provider_names());
+
+        let output = "Package: firefox
+Versions: 
+119.0 (/var/lib/apt/lists/apt.pop-os.org_release_dists_jammy_main_binary-amd64_Packages) (/var/lib/dpkg/status)
+ Description Language: 
+                 File: /var/lib/apt/lists/apt.pop-os.org_release_dists_jammy_main_binary-amd64_Packages
+                  MD5: 4e5c1c6c6f4ab2b6c3f7d7c2a6dc1b61
+
+
+Reverse Depends: 
+  firefox-locale-en,firefox 119.0
+Dependencies: 
+119.0 - libc6 (2 2.34) lsb-release (0 (null)) 
+Provides: 
+119.0 - gnome-www-browser (= ) www-browser (= ) iceweasel (= 119.0) 
+Reverse Provides: 
+";
+
+        let firefox = parse_showpkg(output).unwrap();
+        assert!(!firefox.is_virtual());
+        assert_eq!(vec!["119.0"], firefox.versions);
+        assert_eq!(
+            vec![
+                ("119.0".to_owned(), "gnome-www-browser".to_owned()),
+                ("119.0".to_owned(), "www-browser".to_owned()),
+                ("119.0".to_owned(), "iceweasel".to_owned()),
+            ],
+            firefox.provides
+        );
+        assert!(firefox.provided_by.is_empty());
+    }
+}
@@ -0,0 +1,187 @@
+// Copyright 2021-2022 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+//! Detects the distribution from os-release, and the origins of its apt repositories.
+
+use anyhow::Context;
+use std::io;
+
+const OS_RELEASE: &[&str] = &["/etc/os-release", "/usr/lib/os-release"];
+
+/// The origin of the Pop!_OS repositories, as shown by `apt-cache policy`.
+const POP_ORIGIN: &str = "o=pop-os";
+
+/// Fields of the os-release file.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct OsRelease {
+    pub id: String,
+    pub id_like: Vec<String>,
+    pub name: String,
+    pub version_id: String,
+    pub version_codename: Option<String>,
+    pub ubuntu_codename: Option<String>,
+}
+
+impl OsRelease {
+    /// Reads the os-release file of the host.
+    pub async fn read() -> io::Result<Self> {
+        let mut last_error = None;
+
+        for path in OS_RELEASE {
+            match tokio::fs::read_to_string(path).await {
+                Ok(contents) => return Ok(Self::parse(&contents)),
+                Err(why) => last_error = Some(why),
+            }
+        }
+
+        Err(last_error.expect("no os-release paths"))
+    }
+
+    pub fn parse(contents: &str) -> Self {
+        let mut release = OsRelease::default();
+
+        for line in contents.lines() {
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), unquote(value.trim())),
+                None => continue,
+            };
+
+            match key {
+                "ID" => release.id = value.into(),
+                "ID_LIKE" => {
+                    release.id_like = value.split_ascii_whitespace().map(String::from).collect()
+                }
+                "NAME" => release.name = value.into(),
+                "VERSION_ID" => release.version_id = value.into(),
+                "VERSION_CODENAME" => release.version_codename = Some(value.into()),
+                "UBUNTU_CODENAME" => release.ubuntu_codename = Some(value.into()),
+                _ => (),
+            }
+        }
+
+        release
+    }
+
+    /// The codename of the release, such as `jammy`.
+    pub fn codename(&self) -> Option<&str> {
+        self.version_codename
+            .as_deref()
+            .or(self.ubuntu_codename.as_deref())
+    }
+
+    pub fn is_pop(&self) -> bool {
+        self.id == "pop"
+    }
+
+    /// Whether this is Ubuntu, or a distribution based on it.
+    pub fn is_ubuntu_like(&self) -> bool {
+        self.id == "ubuntu" || self.id_like.iter().any(|id| id == "ubuntu")
+    }
+}
+
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .or_else(|| {
+            value
+                .strip_prefix('\'')
+                .and_then(|value| value.strip_suffix('\''))
+        })
+        .unwrap_or(value)
+}
+
+/// The distribution of the host, and whether the Pop!_OS repositories are configured.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Distro {
+    pub os_release: OsRelease,
+    pub pop_repositories: bool,
+}
+
+impl Distro {
+    pub fn id(&self) -> &str {
+        &self.os_release.id
+    }
+
+    pub fn codename(&self) -> Option<&str> {
+        self.os_release.codename()
+    }
+}
+
+/// Detects the distribution of the host.
+pub async fn detect() -> anyhow::Result<Distro> {
+    let os_release = OsRelease::read()
+        .await
+        .context("failed to read os-release")?;
+
+    Ok(Distro {
+        os_release,
+        pop_repositories: pop_repositories().await?,
+    })
+}
+
+/// Whether any repository from the Pop!_OS origin is configured.
+pub async fn pop_repositories() -> anyhow::Result<bool> {
+    let output = crate::AptCache::new()
+        .arg("policy")
+        .output()
+        .await
+        .context("failed to launch `apt-cache policy`")?;
+
+    if !output.status.success() {
+        return Err(anyhow::anyhow!("`apt-cache policy` exited in error"));
+    }
+
+    Ok(has_origin(
+        &String::from_utf8_lossy(&output.stdout),
+        POP_ORIGIN,
+    ))
+}
+
+/// Searches the `release` lines of `apt-cache policy` for an origin.
+fn has_origin(policy: &str, origin: &str) -> bool {
+    policy
+        .lines()
+        .filter_map(|line| line.trim_start().strip_prefix("release "))
+        .any(|release| release.split(',').any(|field| field == origin))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OsRelease;
+
+    const POP: &str = r#"NAME="Pop!_OS"
+VERSION="22.04 LTS"
+ID=pop
+ID_LIKE="ubuntu debian"
+PRETTY_NAME="Pop!_OS 22.04 LTS"
+VERSION_ID="22.04"
+VERSION_CODENAME=jammy
+UBUNTU_CODENAME=jammy
+"#;
+
+    #[test]
+    fn parse_os_release() {
+        let release = OsRelease::parse(POP);
+        assert_eq!("pop", release.id);
+        assert_eq!("Pop!_OS", release.name);
+        assert_eq!("22.04", release.version_id);
+        assert_eq!(Some("jammy"), release.codename());
+        assert!(release.is_pop());
+        assert!(release.is_ubuntu_like());
+    }
+
+    #[test]
+    fn has_origin() {
+        let policy = "Package files:
+ 100 /var/lib/dpkg/status
+     release a=now
+ 1001 http://apt.pop-os.org/release jammy/main amd64 Packages
+     release v=22.04,o=pop-os,a=jammy,n=jammy,l=pop-os-release,c=main,b=amd64
+     origin apt.pop-os.org
+";
+
+        assert!(super::has_origin(policy, "o=pop-os"));
+        assert!(!super::has_origin(policy, "o=Ubuntu"));
+    }
+}
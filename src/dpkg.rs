@@ -1,76 +1,402 @@
 // Copyright 2021-2022 System76 <info@system76.com>
 // SPDX-License-Identifier: MPL-2.0
 
+pub mod status;
+
+use crate::utils::{LineReader, DEFAULT_BUFFER_CAPACITY};
 use as_result::*;
 use async_stream::stream;
-use futures::stream::Stream;
-use std::{io, pin::Pin};
-use tokio::io::{AsyncBufReadExt, BufReader};
+use futures::stream::{Stream, StreamExt};
+use std::path::{Path, PathBuf};
+use std::{io, pin::Pin, str::FromStr};
+use thiserror::Error;
 use tokio::process::{Child, ChildStdout, Command};
 
 #[derive(AsMut, Deref, DerefMut)]
 #[as_mut(forward)]
-pub struct Dpkg(Command);
+pub struct Dpkg(pub(crate) Command);
 
 impl Dpkg {
     #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
-        let mut cmd = Command::new("dpkg");
-        cmd.env("LANG", "C");
-        Self(cmd)
+        Self(crate::utils::command("dpkg"))
+    }
+
+    /// Operates on the system installed at `root`, rather than on the host.
+    pub fn root(mut self, root: &Path) -> Self {
+        self.arg(root_option(root));
+        self
     }
 
-    pub fn force_confdef(mut self) -> Self {
-        self.arg("--force-confdef");
+    /// Uses the dpkg database in `admindir`, rather than in `/var/lib/dpkg`.
+    pub fn admindir(mut self, admindir: &Path) -> Self {
+        self.arg(admindir_option(admindir));
         self
     }
 
-    pub fn force_confold(mut self) -> Self {
-        self.arg("--force-confold");
+    /// Passes `--force-<option>`, for options without a method of their own.
+    pub fn force(mut self, option: &str) -> Self {
+        self.arg(["--force-", option].concat());
         self
     }
 
+    pub fn force_breaks(self) -> Self {
+        self.force("breaks")
+    }
+
+    pub fn force_confdef(self) -> Self {
+        self.force("confdef")
+    }
+
+    pub fn force_confold(self) -> Self {
+        self.force("confold")
+    }
+
+    pub fn force_conflicts(self) -> Self {
+        self.force("conflicts")
+    }
+
+    pub fn force_depends(self) -> Self {
+        self.force("depends")
+    }
+
+    pub fn force_depends_version(self) -> Self {
+        self.force("depends-version")
+    }
+
+    pub fn force_overwrite(self) -> Self {
+        self.force("overwrite")
+    }
+
+    /// Removes packages which are marked as requiring reinstallation, as when their
+    /// installation was interrupted.
+    pub fn force_remove_reinstreq(self) -> Self {
+        self.force("remove-reinstreq")
+    }
+
     pub fn configure_all(mut self) -> Self {
         self.args(["--configure", "-a"]);
         self
     }
 
+    /// Enables installing packages of a foreign architecture.
+    pub async fn add_architecture(mut self, arch: &str) -> io::Result<()> {
+        self.args(["--add-architecture", arch]);
+        self.status().await
+    }
+
+    /// Disables installing packages of a foreign architecture.
+    pub async fn remove_architecture(mut self, arch: &str) -> io::Result<()> {
+        self.args(["--remove-architecture", arch]);
+        self.status().await
+    }
+
+    /// The native architecture of the system, such as `amd64`.
+    pub async fn architecture(mut self) -> io::Result<String> {
+        self.arg("--print-architecture");
+
+        let output = self.0.output().await?;
+        output.status.into_result()?;
+
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+    }
+
+    /// Lists the foreign architectures which packages may be installed for.
+    pub async fn foreign_architectures(mut self) -> io::Result<Vec<String>> {
+        self.arg("--print-foreign-architectures");
+
+        let output = self.0.output().await?;
+        output.status.into_result()?;
+
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .split_ascii_whitespace()
+            .map(String::from)
+            .collect())
+    }
+
+    /// The selection of every package which has one, from `dpkg --get-selections`.
+    pub async fn get_selections(mut self) -> io::Result<Vec<(String, Selection)>> {
+        self.arg("--get-selections");
+
+        let output = self.0.output().await?;
+        output.status.into_result()?;
+
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(parse_selection_line)
+            .map(|(package, selection)| (package.to_owned(), selection))
+            .collect())
+    }
+
+    /// Sets the selections of packages with `dpkg --set-selections`.
+    ///
+    /// Fails with `InvalidInput` if a package is not a valid package name, which could
+    /// otherwise inject further selections.
+    pub async fn set_selections<S: AsRef<str>>(
+        mut self,
+        selections: &[(S, Selection)],
+    ) -> io::Result<()> {
+        use tokio::io::AsyncWriteExt;
+
+        let input = selection_input(selections)?;
+
+        self.arg("--set-selections");
+        self.stdin(std::process::Stdio::piped());
+
+        let mut child = self.0.spawn()?;
+        let mut stdin = child.stdin.take().unwrap();
+        stdin.write_all(input.as_bytes()).await?;
+        drop(stdin);
+
+        child.wait().await?.into_result()
+    }
+
     pub async fn status(mut self) -> io::Result<()> {
         self.0.status().await?.into_result()
     }
+
+    pub async fn spawn_with_stdout(self) -> io::Result<(Child, ChildStdout)> {
+        crate::utils::spawn_with_stdout(self.0).await
+    }
+}
+
+/// The input of `dpkg --set-selections`, with a line for each valid package.
+fn selection_input<S: AsRef<str>>(selections: &[(S, Selection)]) -> io::Result<String> {
+    let mut input = String::new();
+
+    for (package, selection) in selections {
+        let package = package.as_ref();
+        crate::package::validate_package_name(package)
+            .map_err(|why| io::Error::new(io::ErrorKind::InvalidInput, why))?;
+
+        input.push_str(&format!("{} {}\n", package, selection));
+    }
+
+    Ok(input)
+}
+
+/// A conffile which differs from the version that dpkg installed, such that an upgrade
+/// changing it will prompt, or keep the local version.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ModifiedConffile {
+    pub package: String,
+    pub path: PathBuf,
+    /// Whether the conffile was deleted, rather than modified.
+    pub deleted: bool,
+}
+
+/// The conffiles of installed packages which were locally modified or deleted, as found
+/// by comparing them with the md5sums recorded in the dpkg status database.
+pub async fn modified_conffiles() -> io::Result<Vec<ModifiedConffile>> {
+    let stanzas = status::read(Path::new(status::ADMINDIR)).await?;
+
+    tokio::task::spawn_blocking(move || modified_conffiles_in(Path::new("/"), &stanzas))
+        .await
+        .map_err(io::Error::other)
+}
+
+fn modified_conffiles_in(root: &Path, stanzas: &[status::Stanza]) -> Vec<ModifiedConffile> {
+    use md5::{Digest, Md5};
+
+    let mut modified = Vec::new();
+
+    for stanza in stanzas.iter().filter(|stanza| stanza.is_installed()) {
+        let Some(package) = stanza.package() else {
+            continue;
+        };
+
+        for (path, md5sum) in stanza.conffiles() {
+            // Conffiles which are new in an unconfigured upgrade have no sum yet.
+            if md5sum == "newconffile" {
+                continue;
+            }
+
+            let path = PathBuf::from(path);
+            let deleted = match std::fs::read(root.join(path.strip_prefix("/").unwrap_or(&path))) {
+                Ok(contents) => {
+                    if hex::encode(Md5::digest(&contents)).eq_ignore_ascii_case(md5sum) {
+                        continue;
+                    }
+
+                    false
+                }
+                Err(why) if why.kind() == io::ErrorKind::NotFound => true,
+                // Unreadable conffiles cannot be compared, and are not reported.
+                Err(_) => continue,
+            };
+
+            modified.push(ModifiedConffile {
+                package: package.to_owned(),
+                path,
+                deleted,
+            });
+        }
+    }
+
+    modified
+}
+
+pub type InstalledEvent = Pin<Box<dyn Stream<Item = String> + Send>>;
+
+pub type PackageStatuses = Pin<Box<dyn Stream<Item = (String, PackageStatus)> + Send>>;
+
+pub type StatusEvents =
+    Pin<Box<dyn Stream<Item = Result<(String, PackageStatus), StatusError>> + Send>>;
+
+/// The `db:Status-Status` of a package.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PackageStatus {
+    NotInstalled,
+    ConfigFiles,
+    HalfInstalled,
+    Unpacked,
+    HalfConfigured,
+    TriggersAwaited,
+    TriggersPending,
+    Installed,
+}
+
+impl FromStr for PackageStatus {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let status = match input {
+            "not-installed" => PackageStatus::NotInstalled,
+            "config-files" => PackageStatus::ConfigFiles,
+            "half-installed" => PackageStatus::HalfInstalled,
+            "unpacked" => PackageStatus::Unpacked,
+            "half-configured" => PackageStatus::HalfConfigured,
+            "triggers-awaited" => PackageStatus::TriggersAwaited,
+            "triggers-pending" => PackageStatus::TriggersPending,
+            "installed" => PackageStatus::Installed,
+            _ => return Err(()),
+        };
+
+        Ok(status)
+    }
+}
+
+/// The selection of a package in dpkg, which determines what apt will do with it.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Selection {
+    Install,
+    /// The package is kept at its installed version.
+    Hold,
+    Deinstall,
+    Purge,
+}
+
+impl FromStr for Selection {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let selection = match input {
+            "install" => Selection::Install,
+            "hold" => Selection::Hold,
+            "deinstall" => Selection::Deinstall,
+            "purge" => Selection::Purge,
+            _ => return Err(()),
+        };
+
+        Ok(selection)
+    }
+}
+
+impl std::fmt::Display for Selection {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fmt.write_str(match self {
+            Selection::Install => "install",
+            Selection::Hold => "hold",
+            Selection::Deinstall => "deinstall",
+            Selection::Purge => "purge",
+        })
+    }
+}
+
+/// Parses a `firefox\t\t\t\t\thold` line of `dpkg --get-selections`.
+fn parse_selection_line(line: &str) -> Option<(&str, Selection)> {
+    let mut fields = line.split_ascii_whitespace();
+    let package = fields.next()?;
+    let selection = fields.next()?.parse().ok()?;
+    Some((package, selection))
+}
+
+#[derive(Debug, Error)]
+pub enum StatusError {
+    #[error("malformed line in `dpkg-query --show` output: {0}")]
+    Malformed(String),
 }
 
-pub type InstalledEvent = Pin<Box<dyn Stream<Item = String>>>;
+/// Parses a `${Package} ${db:Status-Status}` line.
+pub(crate) fn parse_status_line(line: &str) -> Result<(&str, PackageStatus), StatusError> {
+    line.split_once(' ')
+        .and_then(|(package, status)| Some((package, status.parse().ok()?)))
+        .ok_or_else(|| StatusError::Malformed(line.to_owned()))
+}
 
 #[derive(AsMut, Deref, DerefMut)]
-#[as_mut(forward)]
-pub struct DpkgQuery(Command);
+pub struct DpkgQuery {
+    #[as_mut(forward)]
+    #[deref]
+    #[deref_mut]
+    command: Command,
+    #[as_mut(ignore)]
+    buffer_capacity: usize,
+}
 
 impl DpkgQuery {
     #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
-        let mut cmd = Command::new("dpkg-query");
-        cmd.env("LANG", "C");
-        Self(cmd)
+        Self::with_command(crate::utils::command("dpkg-query"))
+    }
+
+    pub(crate) fn with_command(command: Command) -> Self {
+        Self {
+            command,
+            buffer_capacity: DEFAULT_BUFFER_CAPACITY,
+        }
+    }
+
+    /// The capacity of the buffer that output is read through, for very large outputs.
+    pub fn buffer_capacity(mut self, bytes: usize) -> Self {
+        self.buffer_capacity = bytes;
+        self
+    }
+
+    /// Queries the system installed at `root`, rather than the host.
+    pub fn root(mut self, root: &Path) -> Self {
+        self.arg(root_option(root));
+        self
     }
 
+    /// Queries the dpkg database in `admindir`, rather than in `/var/lib/dpkg`.
+    pub fn admindir(mut self, admindir: &Path) -> Self {
+        self.arg(admindir_option(admindir));
+        self
+    }
+
+    /// Streams the installed packages of `packages`, which may be shell-style patterns
+    /// such as `linux-image-*`.
     pub async fn show_installed<I, S>(mut self, packages: I) -> io::Result<(Child, InstalledEvent)>
     where
         I: IntoIterator<Item = S>,
         S: AsRef<std::ffi::OsStr>,
     {
-        self.args(["--show", "--showformat=${Package} ${db:Status-Status}\n"]);
+        self.args([
+            "--show",
+            "--showformat=${Package} ${db:Status-Status}\n",
+            "--",
+        ]);
         self.args(packages);
 
+        let capacity = self.buffer_capacity;
         let (child, stdout) = self.spawn_with_stdout().await?;
 
-        let mut stdout = BufReader::new(stdout).lines();
+        let mut stdout = LineReader::with_capacity(capacity, stdout);
 
         let stream = stream! {
-            while let Ok(Some(line)) = stdout.next_line().await {
-                let mut fields = line.split(' ');
-                let package = fields.next().unwrap();
-                if fields.next().unwrap() == "installed" {
+            while let Some(line) = stdout.next_line().await {
+                if let Ok((package, PackageStatus::Installed)) = parse_status_line(line) {
                     yield package.into();
                 }
             }
@@ -79,11 +405,217 @@ impl DpkgQuery {
         Ok((child, Box::pin(stream)))
     }
 
+    /// Like `show_installed`, but yields the status of every package, including those
+    /// which are not installed, and surfaces lines which could not be parsed.
+    pub async fn show_status<I, S>(mut self, packages: I) -> io::Result<(Child, StatusEvents)>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<std::ffi::OsStr>,
+    {
+        self.args([
+            "--show",
+            "--showformat=${Package} ${db:Status-Status}\n",
+            "--",
+        ]);
+        self.args(packages);
+
+        let capacity = self.buffer_capacity;
+        let (child, stdout) = self.spawn_with_stdout().await?;
+
+        let mut stdout = LineReader::with_capacity(capacity, stdout);
+
+        let stream = stream! {
+            while let Some(line) = stdout.next_line().await {
+                yield parse_status_line(line).map(|(package, status)| (package.to_owned(), status));
+            }
+        };
+
+        Ok((child, Box::pin(stream)))
+    }
+
+    /// Streams the name and status of each package matching the shell-style `patterns`,
+    /// such as `linux-image-*`.
+    ///
+    /// Packages which dpkg knows of, but which were never installed, are skipped. dpkg-query
+    /// exits in error if a pattern matches no packages.
+    pub async fn show_matching<I, S>(self, patterns: I) -> io::Result<(Child, PackageStatuses)>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<std::ffi::OsStr>,
+    {
+        let (child, events) = self.show_status(patterns).await?;
+
+        let stream = events.filter_map(|event| async move {
+            event
+                .ok()
+                .filter(|(_, status)| *status != PackageStatus::NotInstalled)
+        });
+
+        Ok((child, Box::pin(stream)))
+    }
+
     pub async fn status(mut self) -> io::Result<()> {
-        self.0.status().await?.into_result()
+        self.command.status().await?.into_result()
     }
 
     pub async fn spawn_with_stdout(self) -> io::Result<(Child, ChildStdout)> {
-        crate::utils::spawn_with_stdout(self.0).await
+        crate::utils::spawn_with_stdout(self.command).await
+    }
+}
+
+fn root_option(root: &Path) -> std::ffi::OsString {
+    let mut option = std::ffi::OsString::from("--root=");
+    option.push(root);
+    option
+}
+
+fn admindir_option(admindir: &Path) -> std::ffi::OsString {
+    let mut option = std::ffi::OsString::from("--admindir=");
+    option.push(admindir);
+    option
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PackageStatus, Selection};
+
+    #[test]
+    fn force_options() {
+        let dpkg = super::Dpkg::new()
+            .force_confold()
+            .force_remove_reinstreq()
+            .force("unsafe-io");
+
+        let args: Vec<_> = dpkg.as_std().get_args().collect();
+        assert_eq!(
+            vec![
+                "--force-confold",
+                "--force-remove-reinstreq",
+                "--force-unsafe-io"
+            ],
+            args
+        );
+    }
+
+    #[test]
+    fn parse_selection_line() {
+        assert_eq!(
+            Some(("firefox", Selection::Hold)),
+            super::parse_selection_line("firefox\t\t\t\t\thold")
+        );
+
+        assert_eq!(
+            Some(("libfoo1:i386", Selection::Deinstall)),
+            super::parse_selection_line("libfoo1:i386\t\t\t\tdeinstall")
+        );
+
+        assert_eq!(None, super::parse_selection_line("firefox"));
+        assert_eq!(Ok(Selection::Purge), "purge".parse());
+        assert_eq!("hold", Selection::Hold.to_string());
+    }
+
+    #[test]
+    fn selection_input() {
+        assert_eq!(
+            "firefox hold\nlibc6:i386 install\n",
+            super::selection_input(&[
+                ("firefox", Selection::Hold),
+                ("libc6:i386", Selection::Install)
+            ])
+            .unwrap()
+        );
+
+        let injected = super::selection_input(&[("firefox hold\nlibc6", Selection::Purge)]);
+        assert_eq!(
+            std::io::ErrorKind::InvalidInput,
+            injected.unwrap_err().kind()
+        );
+    }
+
+    #[test]
+    fn parse_status_line() {
+        assert_eq!(
+            Some(("firefox", PackageStatus::Installed)),
+            super::parse_status_line("firefox installed").ok()
+        );
+
+        assert_eq!(
+            Some(("libfoo1", PackageStatus::ConfigFiles)),
+            super::parse_status_line("libfoo1 config-files").ok()
+        );
+
+        assert!(super::parse_status_line("dpkg-query: warning: unexpected").is_err());
+        assert!(super::parse_status_line("firefox").is_err());
+    }
+
+    #[test]
+    fn modified_conffiles() {
+        let temp = crate::utils::test_dir("conffiles");
+        let root = temp.path();
+        std::fs::create_dir_all(root.join("etc")).unwrap();
+
+        std::fs::write(root.join("etc/unchanged.conf"), "foo").unwrap();
+        std::fs::write(root.join("etc/modified.conf"), "bar").unwrap();
+
+        let status = "Package: hello
+Status: install ok installed
+Conffiles:
+ /etc/unchanged.conf acbd18db4cc2f85cedef654fccc4a4d8
+ /etc/modified.conf acbd18db4cc2f85cedef654fccc4a4d8
+ /etc/deleted.conf acbd18db4cc2f85cedef654fccc4a4d8
+ /etc/obsolete.conf acbd18db4cc2f85cedef654fccc4a4d8 obsolete
+ /etc/new.conf newconffile
+
+Package: removed
+Status: deinstall ok config-files
+Conffiles:
+ /etc/removed.conf acbd18db4cc2f85cedef654fccc4a4d8
+";
+
+        let stanzas: Vec<_> = super::status::parse(status).collect();
+        let modified = super::modified_conffiles_in(root, &stanzas);
+
+        assert_eq!(
+            vec![
+                super::ModifiedConffile {
+                    package: "hello".into(),
+                    path: "/etc/modified.conf".into(),
+                    deleted: false,
+                },
+                super::ModifiedConffile {
+                    package: "hello".into(),
+                    path: "/etc/deleted.conf".into(),
+                    deleted: true,
+                },
+            ],
+            modified
+        );
+    }
+
+    #[tokio::test]
+    async fn show_matching_skips_unknown() {
+        use futures::StreamExt;
+
+        let mut command = tokio::process::Command::new("sh");
+        command.args([
+            "-c",
+            "printf 'linux-image-6.2 installed\\nlinux-image-5.0 not-installed\\nlinux-image-6.1 config-files\\n'",
+        ]);
+
+        let (mut child, packages) = super::DpkgQuery::with_command(command)
+            .show_matching(&["linux-image-*"])
+            .await
+            .unwrap();
+
+        let packages: Vec<_> = packages.collect().await;
+        child.wait().await.unwrap();
+
+        assert_eq!(
+            vec![
+                ("linux-image-6.2".to_owned(), PackageStatus::Installed),
+                ("linux-image-6.1".to_owned(), PackageStatus::ConfigFiles),
+            ],
+            packages
+        );
     }
 }
@@ -0,0 +1,303 @@
+// Copyright 2021-2022 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+//! Parses the dpkg status database, found at `<admindir>/status`.
+
+pub use crate::deb822::Stanza;
+
+use std::io;
+use std::path::Path;
+
+/// The default dpkg administrative directory.
+pub const ADMINDIR: &str = "/var/lib/dpkg";
+
+/// Fields of the status database.
+impl Stanza {
+    pub fn package(&self) -> Option<&str> {
+        self.get("Package")
+    }
+
+    pub fn version(&self) -> Option<&str> {
+        self.get("Version")
+    }
+
+    /// The `Status` field, such as `install ok installed`.
+    pub fn status(&self) -> Option<&str> {
+        self.get("Status")
+    }
+
+    /// Whether the package is `Essential: yes`, or of `required` priority, such that
+    /// removing it may leave the system unable to boot or to install packages.
+    pub fn is_essential(&self) -> bool {
+        self.get("Essential")
+            .is_some_and(|essential| essential.eq_ignore_ascii_case("yes"))
+            || self
+                .get("Priority")
+                .is_some_and(|priority| priority.eq_ignore_ascii_case("required"))
+    }
+
+    /// The `(path, md5sum)` of each conffile, excluding those marked obsolete.
+    pub fn conffiles(&self) -> Vec<(&str, &str)> {
+        self.get_lines("Conffiles")
+            .into_iter()
+            .filter_map(|line| {
+                let mut fields = line.split_ascii_whitespace();
+                let path = fields.next()?;
+                let md5sum = fields.next()?;
+
+                if fields.any(|flag| flag == "obsolete") {
+                    return None;
+                }
+
+                Some((path, md5sum))
+            })
+            .collect()
+    }
+
+    pub fn is_installed(&self) -> bool {
+        self.status()
+            .is_some_and(|status| status.ends_with(" installed"))
+    }
+}
+
+/// Parses every stanza in the contents of a status file.
+pub fn parse(input: &str) -> impl Iterator<Item = Stanza> + '_ {
+    crate::deb822::parse(input)
+}
+
+/// Reads the status database from a dpkg administrative directory.
+pub async fn read(admindir: &Path) -> io::Result<Vec<Stanza>> {
+    let contents = tokio::fs::read_to_string(admindir.join("status")).await?;
+    Ok(parse(&contents).collect())
+}
+
+/// Reads the status database of a system mounted at `root`.
+pub async fn read_root(root: &Path) -> io::Result<Vec<Stanza>> {
+    read(&root.join(ADMINDIR.trim_start_matches('/'))).await
+}
+
+/// How an installed package differs between two snapshots of the status database.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ChangeKind {
+    Added { version: String },
+    Removed { version: String },
+    Upgraded { from: String, to: String },
+    Downgraded { from: String, to: String },
+}
+
+/// An installed package which differs between two snapshots of the status database.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StatusChange {
+    pub package: String,
+    pub architecture: String,
+    pub kind: ChangeKind,
+}
+
+/// The installed packages which were added, removed, upgraded, or downgraded between the
+/// `old` and `new` snapshots, in order of their names.
+pub fn diff<'a>(old: &'a [Stanza], new: &'a [Stanza]) -> impl Iterator<Item = StatusChange> + 'a {
+    use std::collections::BTreeMap;
+
+    let installed = |stanzas: &'a [Stanza]| -> BTreeMap<(&'a str, &'a str), &'a str> {
+        stanzas
+            .iter()
+            .filter(|stanza| stanza.is_installed())
+            .filter_map(|stanza| {
+                let package = stanza.package()?;
+                let architecture = stanza.get("Architecture").unwrap_or_default();
+                Some(((package, architecture), stanza.version()?))
+            })
+            .collect()
+    };
+
+    let old = installed(old);
+    let mut new = installed(new);
+
+    let mut changes = Vec::new();
+
+    for (key, from) in old {
+        let kind = match new.remove(&key) {
+            None => ChangeKind::Removed {
+                version: from.to_owned(),
+            },
+            Some(to) => match deb_version::compare_versions(from, to) {
+                std::cmp::Ordering::Equal => continue,
+                std::cmp::Ordering::Less => ChangeKind::Upgraded {
+                    from: from.to_owned(),
+                    to: to.to_owned(),
+                },
+                std::cmp::Ordering::Greater => ChangeKind::Downgraded {
+                    from: from.to_owned(),
+                    to: to.to_owned(),
+                },
+            },
+        };
+
+        changes.push((key, kind));
+    }
+
+    for (key, version) in new {
+        changes.push((
+            key,
+            ChangeKind::Added {
+                version: version.to_owned(),
+            },
+        ));
+    }
+
+    changes.sort_by_key(|(key, _)| *key);
+
+    changes
+        .into_iter()
+        .map(|((package, architecture), kind)| StatusChange {
+            package: package.to_owned(),
+            architecture: architecture.to_owned(),
+            kind,
+        })
+}
+
+/// The changes from a snapshot of the status file at `snapshot`, such as one copied
+/// yesterday, to the live status database.
+pub async fn diff_from_snapshot(snapshot: &Path) -> io::Result<Vec<StatusChange>> {
+    let old = tokio::fs::read_to_string(snapshot).await?;
+    let old: Vec<Stanza> = parse(&old).collect();
+    let new = read(Path::new(ADMINDIR)).await?;
+
+    Ok(diff(&old, &new).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    const STATUS: &str = "Package: hello
+Status: install ok installed
+Priority: optional
+Version: 2.10-2ubuntu4
+Description: example package based on GNU hello
+ The GNU hello program produces a familiar, friendly greeting.
+ .
+ It allows non-programmers to use a classic computer science tool.
+
+Package: removed-package
+Status: deinstall ok config-files
+Version: 1.0-1
+";
+
+    #[test]
+    fn parse_status() {
+        let stanzas: Vec<_> = super::parse(STATUS).collect();
+        assert_eq!(2, stanzas.len());
+
+        assert_eq!(Some("hello"), stanzas[0].package());
+        assert_eq!(Some("2.10-2ubuntu4"), stanzas[0].version());
+        assert!(stanzas[0].is_installed());
+        assert_eq!(
+            Some(
+                "example package based on GNU hello\n \
+                The GNU hello program produces a familiar, friendly greeting.\n \
+                .\n \
+                It allows non-programmers to use a classic computer science tool."
+            ),
+            stanzas[0].get("description")
+        );
+
+        assert_eq!(Some("removed-package"), stanzas[1].package());
+        assert!(!stanzas[1].is_installed());
+    }
+
+    #[test]
+    fn diff_snapshots() {
+        use super::{ChangeKind, StatusChange};
+
+        let old = "Package: hello
+Status: install ok installed
+Architecture: amd64
+Version: 1.0
+
+Package: firefox
+Status: install ok installed
+Architecture: amd64
+Version: 2.0
+
+Package: libfoo1
+Status: install ok installed
+Architecture: i386
+Version: 1.0
+
+Package: unchanged
+Status: install ok installed
+Architecture: all
+Version: 1.0
+";
+
+        let new = "Package: hello
+Status: install ok installed
+Architecture: amd64
+Version: 2.0
+
+Package: firefox
+Status: install ok installed
+Architecture: amd64
+Version: 1.0
+
+Package: libfoo1
+Status: deinstall ok config-files
+Architecture: i386
+Version: 1.0
+
+Package: added
+Status: install ok installed
+Architecture: amd64
+Version: 1.0
+
+Package: unchanged
+Status: install ok installed
+Architecture: all
+Version: 1.0
+";
+
+        let old: Vec<_> = super::parse(old).collect();
+        let new: Vec<_> = super::parse(new).collect();
+
+        let change = |package: &str, architecture: &str, kind| StatusChange {
+            package: package.into(),
+            architecture: architecture.into(),
+            kind,
+        };
+
+        assert_eq!(
+            vec![
+                change(
+                    "added",
+                    "amd64",
+                    ChangeKind::Added {
+                        version: "1.0".into()
+                    }
+                ),
+                change(
+                    "firefox",
+                    "amd64",
+                    ChangeKind::Downgraded {
+                        from: "2.0".into(),
+                        to: "1.0".into()
+                    }
+                ),
+                change(
+                    "hello",
+                    "amd64",
+                    ChangeKind::Upgraded {
+                        from: "1.0".into(),
+                        to: "2.0".into()
+                    }
+                ),
+                change(
+                    "libfoo1",
+                    "i386",
+                    ChangeKind::Removed {
+                        version: "1.0".into()
+                    }
+                ),
+            ],
+            super::diff(&old, &new).collect::<Vec<_>>()
+        );
+    }
+}
@@ -3,12 +3,22 @@
 
 pub use async_fetcher::Fetcher;
 
-use crate::request::Request as AptRequest;
+use crate::hash::ChecksumError;
+use crate::request::{ChecksumAlgorithm, Request as AptRequest};
+use crate::signature::{SignatureError, SignatureVerifier};
+use crate::store::Store;
+use crate::timestamp::Timestamp;
 
 use futures::stream::{Stream, StreamExt};
-use std::{path::Path, pin::Pin, sync::Arc};
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use std::{pin::Pin, sync::Arc};
 use thiserror::Error;
-use tokio::sync::mpsc;
+use tokio::sync::mpsc::{self, error::TrySendError};
 
 pub type FetchEvents = Pin<Box<dyn Stream<Item = FetchEvent>>>;
 
@@ -16,11 +26,16 @@ pub type FetchEvents = Pin<Box<dyn Stream<Item = FetchEvent>>>;
 pub struct FetchEvent {
     pub package: Arc<AptRequest>,
     pub kind: EventKind,
+    pub timestamp: Timestamp,
 }
 
 impl FetchEvent {
     pub fn new(package: Arc<AptRequest>, kind: EventKind) -> Self {
-        Self { package, kind }
+        Self {
+            package,
+            kind,
+            timestamp: Timestamp::now(),
+        }
     }
 }
 
@@ -32,12 +47,21 @@ pub enum EventKind {
     /// Package was downloaded successfully
     Fetched,
 
+    /// Timing of a package's download, sent after it was fetched
+    Timing(PackageTiming),
+
     /// An error occurred fetching package
     Error(FetchError),
 
     /// The package has been validated
     Validated,
 
+    /// The package's embedded signature was verified
+    SignatureVerified,
+
+    /// The package's embedded signature could not be verified
+    SignatureFailed(SignatureError),
+
     // Package is being retried
     Retrying,
 }
@@ -47,7 +71,7 @@ pub enum FetchError {
     #[error("{}: fetched package had checksum error", package)]
     Checksum {
         package: String,
-        source: crate::hash::ChecksumError,
+        source: ChecksumError,
     },
 
     #[error("{}: download failed", package)]
@@ -55,6 +79,369 @@ pub enum FetchError {
         package: String,
         source: async_fetcher::Error,
     },
+
+    #[error("{}: apt-helper download failed", package)]
+    AptHelper {
+        package: String,
+        source: std::io::Error,
+    },
+
+    #[error("{}: signature verification did not complete", package)]
+    Verification {
+        package: String,
+        source: tokio::task::JoinError,
+    },
+
+    #[cfg(feature = "networkmanager")]
+    #[error("{}: refusing to download on a metered connection", package)]
+    Metered { package: String },
+}
+
+/// The program which packages are downloaded with.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum DownloadBackend {
+    /// Download with `async-fetcher`, using multiple connections per file.
+    #[default]
+    AsyncFetcher,
+
+    /// Download with `apt-helper download-file`, which behaves exactly as apt does,
+    /// including its transports and proxy configuration.
+    AptHelper,
+}
+
+/// What to do with an event when the receiver has fallen behind.
+#[derive(Clone, Debug, Default)]
+pub enum OverflowPolicy {
+    /// Wait for the receiver to make room for the event.
+    #[default]
+    Block,
+
+    /// Discard `Fetching` and `Retrying` events, and wait for room for all others.
+    Coalesce,
+
+    /// Discard the event, and count it.
+    Drop(Arc<AtomicUsize>),
+}
+
+#[derive(Clone)]
+struct EventSender {
+    tx: mpsc::Sender<FetchEvent>,
+    overflow: OverflowPolicy,
+}
+
+impl EventSender {
+    async fn send(&self, event: FetchEvent) {
+        if let Some(event) = self.try_send(event) {
+            let _ = self.tx.send(event).await;
+        }
+    }
+
+    /// Sends from outside of the async runtime, such as from a rayon thread.
+    fn blocking_send(&self, event: FetchEvent) {
+        if let Some(event) = self.try_send(event) {
+            let _ = self.tx.blocking_send(event);
+        }
+    }
+
+    /// Returns the event if the overflow policy requires waiting for room to send it.
+    fn try_send(&self, event: FetchEvent) -> Option<FetchEvent> {
+        #[cfg(feature = "metrics")]
+        crate::metrics::record_fetch_event(&event);
+
+        match self.overflow {
+            OverflowPolicy::Block => return Some(event),
+            OverflowPolicy::Coalesce => {
+                if !matches!(event.kind, EventKind::Fetching | EventKind::Retrying) {
+                    return Some(event);
+                }
+            }
+            OverflowPolicy::Drop(_) => (),
+        }
+
+        if let Err(TrySendError::Full(_)) = self.tx.try_send(event) {
+            if let OverflowPolicy::Drop(ref dropped) = self.overflow {
+                dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        None
+    }
+}
+
+/// When a package was queued, started, and finished downloading.
+#[derive(Clone, Copy, Debug)]
+pub struct PackageTiming {
+    pub queued: Instant,
+    pub started: Option<Instant>,
+    pub finished: Option<Instant>,
+    pub bytes: u64,
+}
+
+impl PackageTiming {
+    fn new(bytes: u64) -> Self {
+        Self {
+            queued: Instant::now(),
+            started: None,
+            finished: None,
+            bytes,
+        }
+    }
+
+    /// Time spent waiting for a free download slot.
+    pub fn wait(&self) -> Option<Duration> {
+        self.started.map(|started| started - self.queued)
+    }
+
+    /// Time spent downloading.
+    pub fn duration(&self) -> Option<Duration> {
+        Some(self.finished? - self.started?)
+    }
+
+    /// Average download speed, in bytes per second.
+    pub fn throughput(&self) -> Option<f64> {
+        throughput(self.bytes, self.duration()?)
+    }
+}
+
+/// Aggregate metrics of a completed [`PackageFetcher::fetch`].
+#[derive(Clone, Debug, Default)]
+pub struct FetchMetrics {
+    /// Timing of each package which was downloaded.
+    pub fetched: Vec<(Arc<AptRequest>, PackageTiming)>,
+
+    /// Number of packages which failed to download, or whose checksum did not match.
+    pub failed: usize,
+
+    /// Time from the start of the fetch until all packages were processed.
+    pub elapsed: Duration,
+}
+
+impl FetchMetrics {
+    /// Total bytes of all downloaded packages.
+    pub fn bytes(&self) -> u64 {
+        self.fetched.iter().map(|(_, timing)| timing.bytes).sum()
+    }
+
+    /// Average download speed across the whole fetch, in bytes per second.
+    pub fn throughput(&self) -> Option<f64> {
+        throughput(self.bytes(), self.elapsed)
+    }
+
+    /// Whether some packages failed to download, such that an install may only proceed
+    /// with what is available, as with `apt-get --fix-missing`.
+    pub fn is_partial(&self) -> bool {
+        self.failed != 0
+    }
+}
+
+fn throughput(bytes: u64, duration: Duration) -> Option<f64> {
+    let seconds = duration.as_secs_f64();
+    if seconds > 0.0 {
+        Some(bytes as f64 / seconds)
+    } else {
+        None
+    }
+}
+
+type Timings = Arc<Mutex<HashMap<Arc<AptRequest>, PackageTiming>>>;
+
+/// The file in the destination which records packages that have been validated.
+pub const MANIFEST: &str = ".apt-cmd-manifest";
+
+/// Packages validated by previous and current fetches to the same destination.
+///
+/// Each line is `<uri> <size> <checksum>`, appended as each package is validated, and
+/// its signature verified when a verifier is set. A line cut short by a crash fails to
+/// parse, and its package is fetched again.
+struct Manifest {
+    validated: HashSet<String>,
+    file: Option<Mutex<std::fs::File>>,
+}
+
+impl Manifest {
+    fn open(destination: &Path) -> Self {
+        let path = destination.join(MANIFEST);
+
+        let validated = std::fs::read_to_string(&path)
+            .map(|contents| parse_manifest(&contents))
+            .unwrap_or_default();
+
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .ok()
+            .map(Mutex::new);
+
+        Self { validated, file }
+    }
+
+    /// Whether the package was validated, and is still in place.
+    fn is_validated(&self, package: &AptRequest, dest: &Path) -> bool {
+        self.validated.contains(&manifest_entry(package))
+            && std::fs::metadata(dest).is_ok_and(|meta| meta.len() == package.size)
+    }
+
+    fn record(&self, package: &AptRequest) {
+        use std::io::Write;
+
+        if let Some(file) = &self.file {
+            let line = [manifest_entry(package), "\n".into()].concat();
+            let _ = file.lock().unwrap().write_all(line.as_bytes());
+        }
+    }
+}
+
+/// Verifies the signature of a fetched package.
+fn signature_event(verifier: SignatureVerifier, path: &Path) -> EventKind {
+    match verifier.verify(path) {
+        Ok(()) => EventKind::SignatureVerified,
+        Err(why) => EventKind::SignatureFailed(why),
+    }
+}
+
+fn manifest_entry(package: &AptRequest) -> String {
+    let checksum = &package.checksum;
+
+    format!(
+        "{} {} {}:{}",
+        package.uri,
+        package.size,
+        checksum.algorithm(),
+        checksum.sum()
+    )
+}
+
+/// The entries of a manifest, without those which are malformed, or cut short.
+fn parse_manifest(contents: &str) -> HashSet<String> {
+    // A line is only complete once its newline has been written.
+    let complete = contents.rfind('\n').map_or("", |end| &contents[..end]);
+
+    complete
+        .lines()
+        .filter(|line| is_manifest_entry(line))
+        .map(String::from)
+        .collect()
+}
+
+/// Whether the line is `<uri> <size> <algorithm>:<digest>`, with a hex digest of the
+/// length which the algorithm produces.
+fn is_manifest_entry(line: &str) -> bool {
+    let mut fields = line.split(' ');
+    let (Some(uri), Some(size), Some(checksum), None) =
+        (fields.next(), fields.next(), fields.next(), fields.next())
+    else {
+        return false;
+    };
+
+    let valid_uri = matches!(
+        uri.split_once(':'),
+        Some((scheme, rest)) if !rest.is_empty()
+            && !scheme.is_empty()
+            && scheme.bytes().all(|b| b.is_ascii_alphanumeric() || b"+-.".contains(&b))
+    );
+
+    let Some((algorithm, digest)) = checksum.split_once(':') else {
+        return false;
+    };
+
+    let length = match algorithm {
+        "MD5Sum" => 32,
+        "SHA1" => 40,
+        "SHA256" => 64,
+        "SHA512" => 128,
+        _ => return false,
+    };
+
+    valid_uri
+        && size.parse::<u64>().is_ok()
+        && digest.len() == length
+        && digest.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// The outcome of [`verify_directory`].
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    /// Packages which were found intact.
+    pub verified: Vec<AptRequest>,
+    /// Packages which are not in the directory.
+    pub missing: Vec<AptRequest>,
+    /// Packages whose file does not match the size or checksum of the request.
+    pub corrupt: Vec<(AptRequest, ChecksumError)>,
+    /// Files in the directory which no request accounts for.
+    pub extra: Vec<PathBuf>,
+}
+
+impl VerifyReport {
+    /// Whether every package is present and intact, regardless of extra files.
+    pub fn is_complete(&self) -> bool {
+        self.missing.is_empty() && self.corrupt.is_empty()
+    }
+}
+
+/// Checks a directory of fetched packages, such as an offline bundle, against the
+/// requests it is expected to satisfy before they are installed.
+///
+/// Hidden files, such as the [`MANIFEST`], and directories are not reported as extra.
+/// Packages are validated with [`crate::hash::verify_batch`].
+pub fn verify_directory<I>(directory: &Path, requests: I) -> io::Result<VerifyReport>
+where
+    I: IntoIterator<Item = AptRequest>,
+{
+    // Requests for the same file are checked once.
+    let mut names = HashSet::new();
+    let requests: Vec<AptRequest> = requests
+        .into_iter()
+        .filter(|request| names.insert(request.name.clone()))
+        .collect();
+
+    let expected: HashSet<&str> = requests
+        .iter()
+        .map(|request| request.name.as_str())
+        .collect();
+
+    let mut extra = Vec::new();
+
+    for entry in std::fs::read_dir(directory)? {
+        let entry = entry?;
+
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+
+        if !name.starts_with('.') && !expected.contains(&*name) {
+            extra.push(entry.path());
+        }
+    }
+
+    extra.sort();
+
+    let mut results = crate::hash::verify_batch(&requests, directory);
+
+    let mut report = VerifyReport {
+        extra,
+        ..VerifyReport::default()
+    };
+
+    for request in requests {
+        let result = match results.remove(&request.name) {
+            Some(result) => result,
+            None => continue,
+        };
+
+        match result {
+            Ok(()) => report.verified.push(request),
+            Err(ChecksumError::FileOpen(why)) if why.kind() == io::ErrorKind::NotFound => {
+                report.missing.push(request)
+            }
+            Err(why) => report.corrupt.push((request, why)),
+        }
+    }
+
+    Ok(report)
 }
 
 pub struct FetchRequest {
@@ -62,10 +449,71 @@ pub struct FetchRequest {
     pub attempt: usize,
 }
 
-#[derive(Default)]
+/// The number of packages fetched concurrently by default.
+const DEFAULT_CONCURRENT: usize = 1;
+
+/// The number of packages validated concurrently by default.
+const DEFAULT_VALIDATION_THREADS: usize = 2;
+
+/// The number of events which may be buffered for the receiver by default.
+const DEFAULT_CHANNEL_CAPACITY: usize = 256;
+
+/// The order in which packages are fetched.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Schedule {
+    /// In the order they were received, fetching each as soon as it is received.
+    #[default]
+    Fifo,
+
+    /// The largest first, which tends to shorten large fetches, as the small packages
+    /// fill in the connections left idle once the large ones are done.
+    ///
+    /// Every package must be received before the first is fetched.
+    LargestFirst,
+
+    /// The smallest first, so that as many packages as possible are fetched early.
+    ///
+    /// Every package must be received before the first is fetched.
+    SmallestFirst,
+}
+
+impl Schedule {
+    fn sort(self, packages: &mut [Arc<AptRequest>]) {
+        match self {
+            Schedule::Fifo => (),
+            Schedule::LargestFirst => {
+                packages.sort_by_key(|package| std::cmp::Reverse(package.size))
+            }
+            Schedule::SmallestFirst => packages.sort_by_key(|package| package.size),
+        }
+    }
+}
+
+/// Fetches packages from apt repositories and validates them after they are downloaded.
+///
+/// Construct one with [`PackageFetcher::builder`], or from an existing
+/// `Fetcher` through [`FetcherExt::into_package_fetcher`].
 pub struct PackageFetcher {
     fetcher: Fetcher<AptRequest>,
+    backend: DownloadBackend,
     concurrent: usize,
+    verifier: Option<SignatureVerifier>,
+    capacity: usize,
+    overflow: OverflowPolicy,
+    store: Option<Arc<Store>>,
+    validation_threads: usize,
+    checkpoint: bool,
+    schedule: Schedule,
+    minimum_checksum: ChecksumAlgorithm,
+    #[cfg(feature = "networkmanager")]
+    on_metered: crate::network::MeteredPolicy,
+}
+
+/// Fetches with a default `Fetcher`, as `PackageFetcher::new`.
+impl Default for PackageFetcher {
+    fn default() -> Self {
+        PackageFetcher::builder().build()
+    }
 }
 
 pub trait FetcherExt {
@@ -84,12 +532,118 @@ impl From<Fetcher<AptRequest>> for PackageFetcher {
     }
 }
 
+/// Configures a [`PackageFetcher`], starting from sane defaults.
+pub struct PackageFetcherBuilder(PackageFetcher);
+
+impl PackageFetcherBuilder {
+    /// The fetcher to download packages with.
+    pub fn fetcher(mut self, fetcher: Fetcher<AptRequest>) -> Self {
+        self.0.fetcher = fetcher;
+        self
+    }
+
+    /// The program to download packages with. The `fetcher` is unused by `AptHelper`.
+    pub fn backend(mut self, backend: DownloadBackend) -> Self {
+        self.0.backend = backend;
+        self
+    }
+
+    /// How many packages may be fetched at the same time.
+    pub fn concurrent(mut self, concurrent: usize) -> Self {
+        self.0.concurrent = concurrent;
+        self
+    }
+
+    /// How many packages may be validated at the same time.
+    ///
+    /// Validation reads each package in full, so a high value may saturate slow disks.
+    pub fn validation_threads(mut self, threads: usize) -> Self {
+        self.0.validation_threads = threads;
+        self
+    }
+
+    /// Record each validated package in a [`MANIFEST`] file in the destination, so that
+    /// an interrupted fetch may be resumed without fetching or validating them again.
+    pub fn checkpoint(mut self, checkpoint: bool) -> Self {
+        self.0.checkpoint = checkpoint;
+        self
+    }
+
+    /// The order in which packages are fetched.
+    pub fn schedule(mut self, schedule: Schedule) -> Self {
+        self.0.schedule = schedule;
+        self
+    }
+
+    /// Fail the validation of packages whose checksum is weaker than `algorithm`, as
+    /// when an old repository only offers MD5 sums.
+    pub fn minimum_checksum(mut self, algorithm: ChecksumAlgorithm) -> Self {
+        self.0.minimum_checksum = algorithm;
+        self
+    }
+
+    /// Verify the signature of each package after its checksum has been validated.
+    pub fn verify_signatures(mut self, verifier: SignatureVerifier) -> Self {
+        self.0.verifier = Some(verifier);
+        self
+    }
+
+    /// How many events may be buffered before the overflow policy applies.
+    ///
+    /// With [`OverflowPolicy::Block`], the receiver must be drained while the fetch is
+    /// awaited, or the fetch stops once the buffer is full. See [`PackageFetcher::fetch`].
+    pub fn channel_capacity(mut self, capacity: usize) -> Self {
+        self.0.capacity = capacity;
+        self
+    }
+
+    /// What to do with events when the receiver falls behind. Defaults to
+    /// [`OverflowPolicy::Block`], which requires the receiver to be drained.
+    pub fn overflow(mut self, policy: OverflowPolicy) -> Self {
+        self.0.overflow = policy;
+        self
+    }
+
+    /// Reuse packages from a content-addressed store, and add validated packages to it.
+    pub fn store(mut self, store: Store) -> Self {
+        self.0.store = Some(Arc::new(store));
+        self
+    }
+
+    /// Refuse or throttle downloads when the active connection is metered.
+    #[cfg(feature = "networkmanager")]
+    pub fn on_metered(mut self, policy: crate::network::MeteredPolicy) -> Self {
+        self.0.on_metered = policy;
+        self
+    }
+
+    pub fn build(self) -> PackageFetcher {
+        self.0
+    }
+}
+
 impl PackageFetcher {
+    /// Creates a builder which fetches with a default `Fetcher`, one package at a time.
+    pub fn builder() -> PackageFetcherBuilder {
+        PackageFetcherBuilder(PackageFetcher {
+            fetcher: Fetcher::default(),
+            backend: DownloadBackend::AsyncFetcher,
+            concurrent: DEFAULT_CONCURRENT,
+            verifier: None,
+            capacity: DEFAULT_CHANNEL_CAPACITY,
+            overflow: OverflowPolicy::Block,
+            store: None,
+            validation_threads: DEFAULT_VALIDATION_THREADS,
+            checkpoint: false,
+            schedule: Schedule::Fifo,
+            minimum_checksum: ChecksumAlgorithm::Md5,
+            #[cfg(feature = "networkmanager")]
+            on_metered: crate::network::MeteredPolicy::Allow,
+        })
+    }
+
     pub fn new(fetcher: Fetcher<AptRequest>) -> Self {
-        Self {
-            fetcher,
-            concurrent: 1,
-        }
+        PackageFetcher::builder().fetcher(fetcher).build()
     }
 
     pub fn concurrent(mut self, concurrent: usize) -> Self {
@@ -97,54 +651,192 @@ impl PackageFetcher {
         self
     }
 
+    /// Fetches the packages into `destination`, returning a future which fetches and
+    /// validates them, and the receiver of their events.
+    ///
+    /// The future resolves once every fetched package has also been validated. Unless
+    /// the overflow policy discards events, the receiver must be drained while it is
+    /// awaited, as from another task: once the channel is full, both the downloads and
+    /// the validation threads wait for room in it.
     pub fn fetch(
         self,
         packages: impl Stream<Item = Arc<AptRequest>> + Send + Unpin + 'static,
         destination: Arc<Path>,
     ) -> (
-        impl std::future::Future<Output = ()> + Send + 'static,
-        mpsc::UnboundedReceiver<FetchEvent>,
+        impl std::future::Future<Output = FetchMetrics> + Send + 'static,
+        mpsc::Receiver<FetchEvent>,
     ) {
-        let (tx, rx) = mpsc::unbounded_channel::<FetchEvent>();
-        let (events_tx, mut events_rx) = mpsc::unbounded_channel();
-
-        let input_stream = packages.map(move |package| {
-            (
-                async_fetcher::Source::new(
-                    Arc::from(vec![Box::from(&*package.uri)].into_boxed_slice()),
-                    Arc::from(destination.join(&package.name)),
+        let (tx, rx) = mpsc::channel::<FetchEvent>(self.capacity.max(1));
+        let tx = EventSender {
+            tx,
+            overflow: self.overflow,
+        };
+
+        // async-fetcher requires an unbounded sender for its own events.
+        let (events_tx, mut events_rx) =
+            mpsc::unbounded_channel::<(Arc<Path>, Arc<AptRequest>, async_fetcher::FetchEvent)>();
+        let verifier = self.verifier;
+        let store = self.store;
+        let minimum_checksum = self.minimum_checksum;
+        let timings = Timings::default();
+        let invalid = Arc::new(AtomicUsize::new(0));
+
+        // Each validation holds a sender, so that the end of all of them is awaited by
+        // waiting for the channel to close.
+        let (validating, mut validations) = mpsc::unbounded_channel::<()>();
+
+        let manifest = if self.checkpoint {
+            Some(Arc::new(Manifest::open(&destination)))
+        } else {
+            None
+        };
+
+        // Packages are validated on a dedicated pool, rather than the global rayon pool.
+        let validators = rayon::ThreadPoolBuilder::new()
+            .num_threads(self.validation_threads.max(1))
+            .thread_name(|id| format!("apt-cmd-validate-{}", id))
+            .build()
+            .ok()
+            .map(Arc::new);
+
+        let packages: Pin<Box<dyn Stream<Item = Arc<AptRequest>> + Send>> = match self.schedule {
+            Schedule::Fifo => Box::pin(packages),
+            schedule => Box::pin(
+                futures::stream::once(packages.collect::<Vec<_>>()).flat_map(
+                    move |mut packages| {
+                        schedule.sort(&mut packages);
+                        futures::stream::iter(packages)
+                    },
                 ),
-                package,
-            )
+            ),
+        };
+
+        let input_stream = packages.map({
+            let timings = timings.clone();
+            move |package| {
+                timings
+                    .lock()
+                    .unwrap()
+                    .insert(package.clone(), PackageTiming::new(package.size));
+
+                (Arc::from(destination.join(&package.name)), package)
+            }
         });
 
-        let mut fetch_results = self
-            .fetcher
-            .events(events_tx)
-            .build()
-            .stream_from(input_stream, self.concurrent.min(1));
+        // Packages validated by a previous run are skipped. Packages found in the store
+        // are linked into place, and validated as if fetched.
+        let input_stream = input_stream.filter_map({
+            let tx = tx.clone();
+            let events_tx = events_tx.clone();
+            let store = store.clone();
+            let manifest = manifest.clone();
+            move |(dest, package): (Arc<Path>, Arc<AptRequest>)| {
+                let tx = tx.clone();
+                let events_tx = events_tx.clone();
+                let store = store.clone();
+                let manifest = manifest.clone();
+
+                Box::pin(async move {
+                    if package.checksum.algorithm() >= minimum_checksum
+                        && manifest
+                            .as_ref()
+                            .is_some_and(|manifest| manifest.is_validated(&package, &dest))
+                    {
+                        tx.send(FetchEvent::new(package.clone(), EventKind::Validated))
+                            .await;
+
+                        // The signature is verified again, as the policy may have changed.
+                        if let Some(verifier) = verifier {
+                            let event = tokio::task::spawn_blocking(move || {
+                                signature_event(verifier, &dest)
+                            })
+                            .await
+                            .unwrap_or_else(|source| {
+                                EventKind::Error(FetchError::Verification {
+                                    package: package.uri.clone(),
+                                    source,
+                                })
+                            });
+
+                            tx.send(FetchEvent::new(package, event)).await;
+                        }
+
+                        return None;
+                    }
+
+                    let stored = store
+                        .as_ref()
+                        .is_some_and(|store| store.link_into(&package, &dest).unwrap_or(false));
+
+                    if stored {
+                        use async_fetcher::FetchEvent::{Fetched, Fetching};
+                        let _ = events_tx.send((dest.clone(), package.clone(), Fetching));
+                        let _ = events_tx.send((dest, package, Fetched));
+                        return None;
+                    }
+
+                    Some((dest, package))
+                })
+            }
+        });
 
         let event_handler = {
             let tx = tx.clone();
+            let timings = timings.clone();
+            let invalid = invalid.clone();
             async move {
                 while let Some((dest, package, event)) = events_rx.recv().await {
                     match event {
                         async_fetcher::FetchEvent::Fetching => {
-                            let _ = tx.send(FetchEvent::new(package, EventKind::Fetching));
+                            if let Some(timing) = timings.lock().unwrap().get_mut(&package) {
+                                timing.started.get_or_insert_with(Instant::now);
+                            }
+
+                            tx.send(FetchEvent::new(package, EventKind::Fetching)).await;
                         }
 
                         async_fetcher::FetchEvent::Fetched => {
-                            let _ = tx.send(FetchEvent::new(package.clone(), EventKind::Fetched));
+                            tx.send(FetchEvent::new(package.clone(), EventKind::Fetched))
+                                .await;
+
+                            let timing = timings.lock().unwrap().get_mut(&package).map(|timing| {
+                                timing.finished = Some(Instant::now());
+                                *timing
+                            });
+
+                            if let Some(timing) = timing {
+                                tx.send(FetchEvent::new(
+                                    package.clone(),
+                                    EventKind::Timing(timing),
+                                ))
+                                .await;
+                            }
                             let tx = tx.clone();
+                            let store = store.clone();
+                            let manifest = manifest.clone();
+                            let invalid = invalid.clone();
+                            let validating = validating.clone();
+
+                            // Sent after the `Fetched` event, which has already been queued.
+                            let validate = move || {
+                                let _validating = validating;
 
-                            rayon::spawn(move || {
-                                let event = match crate::hash::compare_hash(
-                                    &dest,
-                                    package.size,
+                                let result = crate::hash::check_algorithm(
                                     &package.checksum,
-                                ) {
+                                    minimum_checksum,
+                                )
+                                .and_then(|()| {
+                                    crate::hash::compare_hash(
+                                        &dest,
+                                        package.size,
+                                        &package.checksum,
+                                    )
+                                });
+
+                                let event = match result {
                                     Ok(()) => EventKind::Validated,
                                     Err(source) => {
+                                        invalid.fetch_add(1, Ordering::Relaxed);
                                         let _ = std::fs::remove_file(&dest);
                                         EventKind::Error(FetchError::Checksum {
                                             package: package.uri.clone(),
@@ -153,12 +845,42 @@ impl PackageFetcher {
                                     }
                                 };
 
-                                let _ = tx.send(FetchEvent::new(package, event));
-                            });
+                                let validated = matches!(event, EventKind::Validated);
+                                tx.blocking_send(FetchEvent::new(package.clone(), event));
+
+                                if !validated {
+                                    return;
+                                }
+
+                                // Packages are only kept for later runs once their
+                                // signatures have also been verified, if required.
+                                if let Some(verifier) = verifier {
+                                    let event = signature_event(verifier, &dest);
+                                    let verified = matches!(event, EventKind::SignatureVerified);
+                                    tx.blocking_send(FetchEvent::new(package.clone(), event));
+
+                                    if !verified {
+                                        return;
+                                    }
+                                }
+
+                                if let Some(store) = &store {
+                                    let _ = store.insert(&package, &dest);
+                                }
+
+                                if let Some(manifest) = &manifest {
+                                    manifest.record(&package);
+                                }
+                            };
+
+                            match &validators {
+                                Some(pool) => pool.spawn(validate),
+                                None => rayon::spawn(validate),
+                            }
                         }
 
                         async_fetcher::FetchEvent::Retrying => {
-                            let _ = tx.send(FetchEvent::new(package, EventKind::Retrying));
+                            tx.send(FetchEvent::new(package, EventKind::Retrying)).await;
                         }
 
                         _ => (),
@@ -167,26 +889,325 @@ impl PackageFetcher {
             }
         };
 
+        let fetcher = self.fetcher;
+        let backend = self.backend;
+        let concurrent = self.concurrent;
+        #[cfg(feature = "networkmanager")]
+        let on_metered = self.on_metered;
+
         let fetcher = async move {
-            while let Some((dest, package, result)) = fetch_results.next().await {
-                if let Err(source) = result {
-                    let _ = tx.send(FetchEvent::new(
-                        package.clone(),
-                        EventKind::Error(FetchError::Fetch {
-                            package: package.uri.clone(),
-                            source,
-                        }),
-                    ));
-
-                    let _ = tokio::fs::remove_file(&dest).await;
+            let mut failed = 0;
+
+            #[cfg(feature = "networkmanager")]
+            let concurrent = match on_metered.concurrency(concurrent).await {
+                Some(concurrent) => concurrent,
+                None => {
+                    let mut input_stream = input_stream;
+                    while let Some((_, package)) = input_stream.next().await {
+                        failed += 1;
+                        tx.send(FetchEvent::new(
+                            package.clone(),
+                            EventKind::Error(FetchError::Metered {
+                                package: package.uri.clone(),
+                            }),
+                        ))
+                        .await;
+                    }
+
+                    return failed;
+                }
+            };
+
+            match backend {
+                DownloadBackend::AsyncFetcher => {
+                    let input_stream = input_stream.map(|(dest, package)| {
+                        let uris = Arc::from(vec![Box::from(&*package.uri)].into_boxed_slice());
+                        (async_fetcher::Source::new(uris, dest), package)
+                    });
+
+                    let mut fetch_results = fetcher
+                        .events(events_tx)
+                        .build()
+                        .stream_from(input_stream, concurrent.max(1));
+
+                    while let Some((dest, package, result)) = fetch_results.next().await {
+                        if let Err(source) = result {
+                            failed += 1;
+                            tx.send(FetchEvent::new(
+                                package.clone(),
+                                EventKind::Error(FetchError::Fetch {
+                                    package: package.uri.clone(),
+                                    source,
+                                }),
+                            ))
+                            .await;
+
+                            let _ = tokio::fs::remove_file(&dest).await;
+                        }
+                    }
+                }
+
+                DownloadBackend::AptHelper => {
+                    // Events are routed through the same handler as async-fetcher's.
+                    let mut fetch_results = input_stream
+                        .map(move |(dest, package)| {
+                            let events_tx = events_tx.clone();
+                            async move {
+                                use async_fetcher::FetchEvent::{Fetched, Fetching};
+
+                                let _ = events_tx.send((dest.clone(), package.clone(), Fetching));
+
+                                let result = crate::AptHelper::new()
+                                    .download_file(&package.uri, &dest, None)
+                                    .await;
+
+                                if result.is_ok() {
+                                    let _ =
+                                        events_tx.send((dest.clone(), package.clone(), Fetched));
+                                }
+
+                                (dest, package, result)
+                            }
+                        })
+                        .buffer_unordered(concurrent.max(1));
+
+                    while let Some((dest, package, result)) = fetch_results.next().await {
+                        if let Err(source) = result {
+                            failed += 1;
+                            tx.send(FetchEvent::new(
+                                package.clone(),
+                                EventKind::Error(FetchError::AptHelper {
+                                    package: package.uri.clone(),
+                                    source,
+                                }),
+                            ))
+                            .await;
+
+                            let _ = tokio::fs::remove_file(&dest).await;
+                        }
+                    }
                 }
             }
+
+            failed
         };
 
         let future = async move {
-            let _ = futures::future::join(event_handler, fetcher).await;
+            let start = Instant::now();
+            let (_, failed) = futures::future::join(event_handler, fetcher).await;
+            while validations.recv().await.is_some() {}
+
+            let fetched = timings
+                .lock()
+                .unwrap()
+                .drain()
+                .filter(|(_, timing)| timing.finished.is_some())
+                .collect();
+
+            FetchMetrics {
+                fetched,
+                failed: failed + invalid.load(Ordering::Relaxed),
+                elapsed: start.elapsed(),
+            }
         };
 
         (future, rx)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::RequestChecksum;
+
+    fn event(kind: EventKind) -> FetchEvent {
+        let package = AptRequest {
+            uri: "http://archive.ubuntu.com/ubuntu/pool/main/f/foo/foo_1.0_amd64.deb".into(),
+            name: "foo_1.0_amd64.deb".into(),
+            size: 0,
+            checksum: RequestChecksum::Md5(String::new()),
+        };
+
+        FetchEvent::new(Arc::new(package), kind)
+    }
+
+    #[test]
+    fn overflow_drop_counts_discarded_events() {
+        let dropped = Arc::new(AtomicUsize::new(0));
+        let (tx, mut rx) = mpsc::channel(1);
+        let tx = EventSender {
+            tx,
+            overflow: OverflowPolicy::Drop(dropped.clone()),
+        };
+
+        tx.blocking_send(event(EventKind::Fetching));
+        tx.blocking_send(event(EventKind::Fetched));
+        tx.blocking_send(event(EventKind::Validated));
+
+        assert_eq!(2, dropped.load(Ordering::Relaxed));
+        assert!(matches!(rx.try_recv().unwrap().kind, EventKind::Fetching));
+    }
+
+    #[test]
+    fn overflow_coalesce_keeps_significant_events() {
+        let (tx, _rx) = mpsc::channel(1);
+        let tx = EventSender {
+            tx,
+            overflow: OverflowPolicy::Coalesce,
+        };
+
+        tx.blocking_send(event(EventKind::Fetching));
+        assert!(tx.try_send(event(EventKind::Retrying)).is_none());
+        assert!(tx.try_send(event(EventKind::Validated)).is_some());
+    }
+
+    #[test]
+    fn package_timing_throughput() {
+        let mut timing = PackageTiming::new(2_000_000);
+        assert_eq!(None, timing.throughput());
+
+        let started = timing.queued + Duration::from_millis(500);
+        timing.started = Some(started);
+        timing.finished = Some(started + Duration::from_secs(2));
+
+        assert_eq!(Some(Duration::from_millis(500)), timing.wait());
+        assert_eq!(Some(1_000_000.0), timing.throughput());
+    }
+
+    #[test]
+    fn schedule_orders_by_size() {
+        let package = |size| {
+            let mut package = (*event(EventKind::Fetching).package).clone();
+            package.size = size;
+            Arc::new(package)
+        };
+
+        let mut packages = vec![package(2), package(30), package(1)];
+        let sizes = |packages: &[Arc<AptRequest>]| -> Vec<u64> {
+            packages.iter().map(|package| package.size).collect()
+        };
+
+        Schedule::Fifo.sort(&mut packages);
+        assert_eq!(vec![2, 30, 1], sizes(&packages));
+
+        Schedule::LargestFirst.sort(&mut packages);
+        assert_eq!(vec![30, 2, 1], sizes(&packages));
+
+        Schedule::SmallestFirst.sort(&mut packages);
+        assert_eq!(vec![1, 2, 30], sizes(&packages));
+    }
+
+    #[test]
+    fn manifest_skips_truncated_entries() {
+        let package = AptRequest {
+            uri: "http://archive.ubuntu.com/ubuntu/pool/main/f/foo/foo_1.0_amd64.deb".into(),
+            name: "foo_1.0_amd64.deb".into(),
+            size: 3,
+            checksum: RequestChecksum::Md5("acbd18db4cc2f85cedef654fccc4a4d8".into()),
+        };
+
+        let entry = manifest_entry(&package);
+        assert_eq!(
+            "http://archive.ubuntu.com/ubuntu/pool/main/f/foo/foo_1.0_amd64.deb 3 \
+             MD5Sum:acbd18db4cc2f85cedef654fccc4a4d8",
+            entry
+        );
+
+        let malformed = [
+            "http://archive.ubuntu.com/ubuntu/pool/main/b/bar/bar_1.0_amd64.deb 1x MD5Sum:37b51d194a7513e45b56f6524f2d51f2",
+            "http://archive.ubuntu.com/ubuntu/pool/main/b/bar/bar_1.0_amd64.deb 3 MD5Sum:37b51d194a7513e45b56f6524f2d51",
+            "http://archive.ubuntu.com/ubuntu/pool/main/b/bar/bar_1.0_amd64.deb 3 MD5Sum:zzb51d194a7513e45b56f6524f2d51f2",
+            "http://archive.ubuntu.com/ubuntu/pool/main/b/bar/bar_1.0_amd64.deb 3 CRC32:37b51d19",
+            "bar_1.0_amd64.deb 3 MD5Sum:37b51d194a7513e45b56f6524f2d51f2",
+        ];
+
+        let mut contents = [entry.as_str(), "\n"].concat();
+        for line in malformed {
+            contents.push_str(line);
+            contents.push('\n');
+        }
+
+        // The last entry is whole, but its line was not finished.
+        let truncated = "http://archive.ubuntu.com/ubuntu/pool/main/b/baz/baz_1.0_amd64.deb 3 \
+                         MD5Sum:73feffa4b7f6bb68e44cf984c85f6e88";
+        contents.push_str(truncated);
+
+        let validated = parse_manifest(&contents);
+        assert!(validated.contains(&entry));
+        assert_eq!(1, validated.len());
+
+        assert!(parse_manifest(truncated).is_empty());
+    }
+
+    #[test]
+    fn verify_directory_report() {
+        let temp = crate::utils::test_dir("verify");
+        let dir = temp.path();
+
+        let request = |name: &str, sum: &str| AptRequest {
+            uri: ["http://apt.pop-os.org/release/pool/main/", name].concat(),
+            name: name.into(),
+            size: 3,
+            checksum: RequestChecksum::Md5(sum.into()),
+        };
+
+        let intact = request("foo_1.0_amd64.deb", "acbd18db4cc2f85cedef654fccc4a4d8");
+        let corrupt = request("bar_1.0_amd64.deb", "37b51d194a7513e45b56f6524f2d51f2");
+        let missing = request("baz_1.0_amd64.deb", "73feffa4b7f6bb68e44cf984c85f6e88");
+
+        std::fs::write(dir.join(&intact.name), "foo").unwrap();
+        std::fs::write(dir.join(&corrupt.name), "foo").unwrap();
+        std::fs::write(dir.join("qux_1.0_amd64.deb"), "qux").unwrap();
+        std::fs::write(dir.join(MANIFEST), "").unwrap();
+
+        let report =
+            verify_directory(dir, vec![intact.clone(), corrupt.clone(), missing.clone()]).unwrap();
+
+        assert!(!report.is_complete());
+        assert_eq!(vec![intact], report.verified);
+        assert_eq!(vec![missing], report.missing);
+        assert_eq!(corrupt, report.corrupt[0].0);
+        assert!(matches!(report.corrupt[0].1, ChecksumError::Mismatch));
+        assert_eq!(vec![dir.join("qux_1.0_amd64.deb")], report.extra);
+    }
+
+    #[tokio::test]
+    async fn metrics_await_validation() {
+        let temp = crate::utils::test_dir("fetch-metrics");
+        let dir = temp.path();
+
+        // The store holds the wrong contents for the checksum, so the validation fails.
+        let store = Store::new(dir.join("store"));
+        let request = AptRequest {
+            uri: "http://apt.pop-os.org/release/pool/main/f/foo/foo_1.0_amd64.deb".into(),
+            name: "foo_1.0_amd64.deb".into(),
+            size: 3,
+            checksum: RequestChecksum::Md5("acbd18db4cc2f85cedef654fccc4a4d8".into()),
+        };
+
+        let stored = store.path(&request.checksum);
+        std::fs::create_dir_all(stored.parent().unwrap()).unwrap();
+        std::fs::write(&stored, "bar").unwrap();
+
+        let destination = dir.join("fetched");
+        std::fs::create_dir(&destination).unwrap();
+
+        let (future, mut rx) = PackageFetcher::builder().store(store).build().fetch(
+            futures::stream::iter(vec![Arc::new(request)]),
+            Arc::from(destination.as_path()),
+        );
+
+        let metrics = future.await;
+        assert_eq!(1, metrics.failed);
+
+        let mut kinds = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            kinds.push(event.kind);
+        }
+
+        assert!(matches!(
+            kinds.last(),
+            Some(EventKind::Error(FetchError::Checksum { .. }))
+        ));
+    }
+}
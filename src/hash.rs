@@ -1,13 +1,15 @@
 // Copyright 2021-2022 System76 <info@system76.com>
 // SPDX-License-Identifier: MPL-2.0
 
-use hex::FromHex;
-use md5::{digest::generic_array::GenericArray, Digest, Md5};
+use md5::digest::FixedOutputReset;
+use md5::{Digest, Md5};
 use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+use std::collections::HashMap;
 use std::{io, path::Path};
 use thiserror::Error;
 
-use crate::request::RequestChecksum;
+use crate::request::{ChecksumAlgorithm, Request, RequestChecksum};
 
 #[derive(Debug, Error)]
 pub enum ChecksumError {
@@ -29,6 +31,12 @@ pub enum ChecksumError {
 
     #[error("checksum mismatch")]
     Mismatch,
+
+    #[error("{} checksum is weaker than the minimum of {}", found, minimum)]
+    WeakChecksum {
+        found: ChecksumAlgorithm,
+        minimum: ChecksumAlgorithm,
+    },
 }
 
 pub fn compare_hash(
@@ -36,66 +44,229 @@ pub fn compare_hash(
     expected_size: u64,
     expected_hash: &RequestChecksum,
 ) -> Result<(), ChecksumError> {
-    use std::io::Read;
+    Hasher::default().compare(path, expected_size, expected_hash)
+}
+
+/// The number of files which [`verify_batch`] validates at the same time.
+pub const BATCH_THREADS: usize = 4;
 
-    let mut file = std::fs::File::open(path).map_err(ChecksumError::FileOpen)?;
+/// Validates the files of many requests in `directory`, returning the result of each
+/// by the name of its request, as when re-validating an archives directory.
+///
+/// Files are validated on a dedicated pool of [`BATCH_THREADS`], each of which reuses
+/// its digests and read buffer for every file that it validates.
+pub fn verify_batch(
+    requests: &[Request],
+    directory: &Path,
+) -> HashMap<String, Result<(), ChecksumError>> {
+    use rayon::prelude::*;
 
-    let file_size = file.metadata().unwrap().len();
-    if file_size != expected_size {
-        return Err(ChecksumError::InvalidSize {
-            found: file_size / 1024,
-            expected: expected_size / 1024,
-        });
+    let verify = || {
+        requests
+            .par_iter()
+            .map_init(Hasher::default, |hasher, request| {
+                let path = directory.join(&request.name);
+                let result = hasher.compare(&path, request.size, &request.checksum);
+                (request.name.clone(), result)
+            })
+            .collect()
+    };
+
+    match rayon::ThreadPoolBuilder::new()
+        .num_threads(BATCH_THREADS)
+        .thread_name(|id| format!("apt-cmd-verify-{}", id))
+        .build()
+    {
+        Ok(pool) => pool.install(verify),
+        Err(_) => verify(),
     }
+}
 
-    match expected_hash {
-        RequestChecksum::Sha1(sum) => {
-            let expected = <[u8; 20]>::from_hex(sum)
-                .map(GenericArray::from)
-                .map_err(|_| ChecksumError::InvalidInput(format!("SHA1 {}", sum)))?;
-
-            let mut buffer = vec![0u8; 8 * 1024];
-            let mut hasher = Sha1::new();
-
-            loop {
-                match file.read(&mut buffer) {
-                    Ok(0) => break,
-                    Ok(bytes) => hasher.update(&buffer[..bytes]),
-                    Err(why) => return Err(ChecksumError::FileRead(why)),
-                }
-            }
+/// Digests and a read buffer, which are reused for each file that is compared.
+struct Hasher {
+    buffer: Box<[u8]>,
+    md5: Md5,
+    sha1: Sha1,
+    sha256: Sha256,
+    sha512: Sha512,
+}
+
+impl Default for Hasher {
+    fn default() -> Self {
+        Self {
+            buffer: vec![0u8; 64 * 1024].into_boxed_slice(),
+            md5: Md5::new(),
+            sha1: Sha1::new(),
+            sha256: Sha256::new(),
+            sha512: Sha512::new(),
+        }
+    }
+}
 
-            let hash = &*hasher.finalize();
+impl Hasher {
+    fn compare(
+        &mut self,
+        path: &Path,
+        expected_size: u64,
+        expected_hash: &RequestChecksum,
+    ) -> Result<(), ChecksumError> {
+        let mut file = std::fs::File::open(path).map_err(ChecksumError::FileOpen)?;
 
-            if &*expected == hash {
-                Ok(())
-            } else {
-                Err(ChecksumError::Mismatch)
-            }
+        let file_size = file.metadata().map_err(ChecksumError::FileRead)?.len();
+        if file_size != expected_size {
+            return Err(ChecksumError::InvalidSize {
+                found: file_size / 1024,
+                expected: expected_size / 1024,
+            });
         }
-        RequestChecksum::Md5(sum) => {
-            let expected = <[u8; 16]>::from_hex(sum)
-                .map(GenericArray::from)
-                .map_err(|_| ChecksumError::InvalidInput(format!("MD5 {}", sum)))?;
-
-            let mut buffer = vec![0u8; 8 * 1024];
-            let mut hasher = Md5::new();
-
-            loop {
-                match file.read(&mut buffer) {
-                    Ok(0) => break,
-                    Ok(bytes) => hasher.update(&buffer[..bytes]),
-                    Err(why) => return Err(ChecksumError::FileRead(why)),
-                }
-            }
 
-            let hash = &*hasher.finalize();
+        let buffer = &mut self.buffer;
+        let sum = expected_hash.sum();
 
-            if &*expected == hash {
-                Ok(())
-            } else {
-                Err(ChecksumError::Mismatch)
+        match expected_hash {
+            RequestChecksum::Md5(_) => {
+                compare_digest(&mut self.md5, buffer, &mut file, sum, expected_hash)
+            }
+            RequestChecksum::Sha1(_) => {
+                compare_digest(&mut self.sha1, buffer, &mut file, sum, expected_hash)
+            }
+            RequestChecksum::Sha256(_) => {
+                compare_digest(&mut self.sha256, buffer, &mut file, sum, expected_hash)
+            }
+            RequestChecksum::Sha512(_) => {
+                compare_digest(&mut self.sha512, buffer, &mut file, sum, expected_hash)
             }
         }
     }
 }
+
+/// Fails with [`ChecksumError::WeakChecksum`] if the checksum is weaker than `minimum`.
+pub fn check_algorithm(
+    checksum: &RequestChecksum,
+    minimum: ChecksumAlgorithm,
+) -> Result<(), ChecksumError> {
+    let found = checksum.algorithm();
+
+    if found < minimum {
+        Err(ChecksumError::WeakChecksum { found, minimum })
+    } else {
+        Ok(())
+    }
+}
+
+fn compare_digest<D: Digest + FixedOutputReset>(
+    hasher: &mut D,
+    buffer: &mut [u8],
+    file: &mut std::fs::File,
+    sum: &str,
+    checksum: &RequestChecksum,
+) -> Result<(), ChecksumError> {
+    use std::io::Read;
+
+    let expected = hex::decode(sum)
+        .ok()
+        .filter(|expected| expected.len() == <D as Digest>::output_size())
+        .ok_or_else(|| ChecksumError::InvalidInput(format!("{} {}", checksum.algorithm(), sum)))?;
+
+    // A previous comparison may have been interrupted by an error.
+    Digest::reset(hasher);
+
+    loop {
+        match file.read(buffer) {
+            Ok(0) => break,
+            Ok(bytes) => Digest::update(hasher, &buffer[..bytes]),
+            Err(why) => return Err(ChecksumError::FileRead(why)),
+        }
+    }
+
+    if *expected == *Digest::finalize_reset(hasher) {
+        Ok(())
+    } else {
+        Err(ChecksumError::Mismatch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weak_checksums() {
+        let md5 = RequestChecksum::Md5("acbd18db4cc2f85cedef654fccc4a4d8".into());
+        let sha256 = RequestChecksum::Sha256(
+            "2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae".into(),
+        );
+
+        assert!(check_algorithm(&md5, ChecksumAlgorithm::Md5).is_ok());
+        assert!(check_algorithm(&sha256, ChecksumAlgorithm::Sha256).is_ok());
+        assert!(matches!(
+            check_algorithm(&md5, ChecksumAlgorithm::Sha256),
+            Err(ChecksumError::WeakChecksum {
+                found: ChecksumAlgorithm::Md5,
+                minimum: ChecksumAlgorithm::Sha256,
+            })
+        ));
+    }
+
+    #[test]
+    fn compare_sha256() {
+        let temp = crate::utils::test_dir("hash");
+        let path = temp.path().join("hash");
+        std::fs::write(&path, "foo").unwrap();
+
+        let sha256 = RequestChecksum::Sha256(
+            "2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae".into(),
+        );
+        let truncated = RequestChecksum::Sha256("2c26b46b".into());
+
+        assert!(compare_hash(&path, 3, &sha256).is_ok());
+        assert!(matches!(
+            compare_hash(&path, 3, &truncated),
+            Err(ChecksumError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn verify_batch_results() {
+        let temp = crate::utils::test_dir("batch");
+        let dir = temp.path();
+
+        let request = |name: &str, checksum| Request {
+            uri: ["http://apt.pop-os.org/release/pool/main/", name].concat(),
+            name: name.into(),
+            size: 3,
+            checksum,
+        };
+
+        let requests = [
+            request(
+                "foo_1.0_amd64.deb",
+                RequestChecksum::Md5("acbd18db4cc2f85cedef654fccc4a4d8".into()),
+            ),
+            request(
+                "bar_1.0_amd64.deb",
+                RequestChecksum::Sha1("62cdb7020ff920e5aa642c3d4066950dd1f01f4d".into()),
+            ),
+            request(
+                "baz_1.0_amd64.deb",
+                RequestChecksum::Md5("acbd18db4cc2f85cedef654fccc4a4d8".into()),
+            ),
+        ];
+
+        std::fs::write(dir.join("foo_1.0_amd64.deb"), "foo").unwrap();
+        std::fs::write(dir.join("bar_1.0_amd64.deb"), "foo").unwrap();
+
+        let results = verify_batch(&requests, dir);
+
+        assert_eq!(3, results.len());
+        assert!(results["foo_1.0_amd64.deb"].is_ok());
+        assert!(matches!(
+            results["bar_1.0_amd64.deb"],
+            Err(ChecksumError::Mismatch)
+        ));
+        assert!(matches!(
+            results["baz_1.0_amd64.deb"],
+            Err(ChecksumError::FileOpen(_))
+        ));
+    }
+}
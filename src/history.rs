@@ -0,0 +1,345 @@
+// Copyright 2021-2022 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+//! Parses apt's transaction log at `/var/log/apt/history.log`, and correlates it with
+//! the dpkg status database.
+//!
+//! ```no_run
+//! # async fn run() -> anyhow::Result<()> {
+//! let history = apt_cmd::history::find_package_events("firefox").await?;
+//!
+//! if let Some(transaction) = history.installed_by() {
+//!     println!("{:?} was installed on {}", history.installed, transaction.start);
+//!
+//!     for event in &transaction.events {
+//!         println!("  along with {} {:?}", event.package, event.action);
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::deb822::Stanza;
+use crate::dpkg::status;
+use anyhow::Context;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const HISTORY_DIR: &str = "/var/log/apt";
+
+/// What a transaction did to a package.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Action {
+    Install,
+    Reinstall,
+    Upgrade,
+    Downgrade,
+    Remove,
+    Purge,
+}
+
+impl Action {
+    fn from_field(field: &str) -> Option<Self> {
+        let action = match field {
+            "Install" => Action::Install,
+            "Reinstall" => Action::Reinstall,
+            "Upgrade" => Action::Upgrade,
+            "Downgrade" => Action::Downgrade,
+            "Remove" => Action::Remove,
+            "Purge" => Action::Purge,
+            _ => return None,
+        };
+
+        Some(action)
+    }
+}
+
+/// A change to a package in a transaction.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PackageEvent {
+    pub package: String,
+    pub architecture: Option<String>,
+    pub action: Action,
+    /// The version before an upgrade or downgrade.
+    pub from: Option<String>,
+    /// The version installed, or the version which was removed.
+    pub version: String,
+    /// Whether the package was installed automatically, as a dependency.
+    pub automatic: bool,
+}
+
+/// A single run of apt, from `Start-Date` to `End-Date`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Transaction {
+    /// When the transaction started, as `2023-10-05  10:12:33` in local time.
+    pub start: String,
+    pub end: Option<String>,
+    pub commandline: Option<String>,
+    pub requested_by: Option<String>,
+    pub error: Option<String>,
+    pub events: Vec<PackageEvent>,
+}
+
+impl Transaction {
+    /// The event of a package in this transaction, if it was changed by it.
+    pub fn event(&self, package: &str) -> Option<&PackageEvent> {
+        self.events.iter().find(|event| event.package == package)
+    }
+}
+
+/// Parses the transactions in the contents of a history log, oldest first.
+pub fn parse(contents: &str) -> Vec<Transaction> {
+    crate::deb822::parse(contents)
+        .filter_map(|stanza| parse_transaction(&stanza))
+        .collect()
+}
+
+fn parse_transaction(stanza: &Stanza) -> Option<Transaction> {
+    let mut transaction = Transaction::default();
+
+    for (field, value) in &stanza.fields {
+        match field.as_str() {
+            "Start-Date" => transaction.start = value.to_owned(),
+            "End-Date" => transaction.end = Some(value.to_owned()),
+            "Commandline" => transaction.commandline = Some(value.to_owned()),
+            "Requested-By" => transaction.requested_by = Some(value.to_owned()),
+            "Error" => transaction.error = Some(value.to_owned()),
+            field => {
+                if let Some(action) = Action::from_field(field) {
+                    transaction.events.extend(parse_events(action, value));
+                }
+            }
+        }
+    }
+
+    if transaction.start.is_empty() {
+        None
+    } else {
+        Some(transaction)
+    }
+}
+
+/// Parses a list such as `firefox:amd64 (118.0, 119.0), libfoo1:amd64 (1.0, automatic)`.
+fn parse_events(action: Action, mut value: &str) -> Vec<PackageEvent> {
+    let mut events = Vec::new();
+
+    while let Some((name, rest)) = value.split_once(" (") {
+        let Some((versions, rest)) = rest.split_once(')') else {
+            break;
+        };
+
+        value = rest.trim_start_matches(',').trim_start();
+
+        let name = name.trim();
+        let (package, architecture) = match name.split_once(':') {
+            Some((package, arch)) => (package, Some(arch.to_owned())),
+            None => (name, None),
+        };
+
+        let mut versions: Vec<&str> = versions.split(", ").collect();
+        let automatic = versions.last() == Some(&"automatic");
+        if automatic {
+            versions.pop();
+        }
+
+        let (from, version) = match versions.as_slice() {
+            [version] => (None, *version),
+            [from, version] => (Some((*from).to_owned()), *version),
+            _ => continue,
+        };
+
+        events.push(PackageEvent {
+            package: package.to_owned(),
+            architecture,
+            action,
+            from,
+            version: version.to_owned(),
+            automatic,
+        });
+    }
+
+    events
+}
+
+/// The history of a package, with its installed version from the dpkg status database.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct PackageHistory {
+    pub package: String,
+    /// The version currently installed.
+    pub installed: Option<String>,
+    /// Each transaction which changed the package, oldest first.
+    pub transactions: Vec<Transaction>,
+}
+
+impl PackageHistory {
+    /// The most recent transaction which installed `version` of the package.
+    pub fn installed_version_by(&self, version: &str) -> Option<&Transaction> {
+        self.transactions.iter().rev().find(|transaction| {
+            transaction.event(&self.package).is_some_and(|event| {
+                event.version == version && !matches!(event.action, Action::Remove | Action::Purge)
+            })
+        })
+    }
+
+    /// The transaction which installed the currently-installed version, if it was
+    /// installed by apt since the oldest log.
+    pub fn installed_by(&self) -> Option<&Transaction> {
+        self.installed
+            .as_deref()
+            .and_then(|version| self.installed_version_by(version))
+    }
+}
+
+/// Finds every transaction in the history logs which changed `package`, such as to tell
+/// when it was upgraded to its installed version, and what else changed at the time.
+pub async fn find_package_events(package: &str) -> anyhow::Result<PackageHistory> {
+    let mut transactions = Vec::new();
+
+    for path in history_logs(Path::new(HISTORY_DIR)).await? {
+        let contents = crate::compression::read_to_string(&path)
+            .await
+            .with_context(|| format!("failed to read {}", path.display()))?;
+
+        transactions.extend(
+            parse(&contents)
+                .into_iter()
+                .filter(|transaction| transaction.event(package).is_some()),
+        );
+    }
+
+    let installed = status::read(Path::new(status::ADMINDIR))
+        .await
+        .context("failed to read the dpkg status database")?
+        .into_iter()
+        .find(|stanza| stanza.package() == Some(package) && stanza.is_installed())
+        .and_then(|stanza| stanza.version().map(String::from));
+
+    Ok(PackageHistory {
+        package: package.to_owned(),
+        installed,
+        transactions,
+    })
+}
+
+/// The history logs in `dir`, oldest first: `history.log.N.gz`, down to
+/// `history.log.1.gz`, then `history.log`. Rotated logs may also be uncompressed.
+async fn history_logs(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut logs: Vec<(u32, PathBuf)> = Vec::new();
+    let mut entries = tokio::fs::read_dir(dir).await?;
+
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name();
+        let Some(name) = name.to_str() else {
+            continue;
+        };
+
+        let rotation = match name.strip_prefix("history.log") {
+            Some("") => 0,
+            Some(suffix) => match suffix
+                .strip_prefix('.')
+                .map(|n| n.strip_suffix(".gz").unwrap_or(n))
+                .and_then(|n| n.parse().ok())
+            {
+                Some(rotation) => rotation,
+                None => continue,
+            },
+            None => continue,
+        };
+
+        logs.push((rotation, entry.path()));
+    }
+
+    logs.sort_by_key(|(rotation, _)| std::cmp::Reverse(*rotation));
+    Ok(logs.into_iter().map(|(_, path)| path).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HISTORY: &str = "
+Start-Date: 2023-10-05  10:12:33
+Commandline: apt-get install hello
+Requested-By: user (1000)
+Install: hello:amd64 (2.10-2ubuntu4), libfoo1:amd64 (1.0, automatic)
+End-Date: 2023-10-05  10:12:40
+
+Start-Date: 2023-10-06  09:00:00
+Commandline: apt-get full-upgrade
+Upgrade: firefox:amd64 (118.0, 119.0), hello:amd64 (2.10-2ubuntu4, 2.10-3)
+Remove: bar:amd64 (1.0)
+End-Date: 2023-10-06  09:01:00
+";
+
+    #[test]
+    fn parse_history() {
+        let transactions = parse(HISTORY);
+        assert_eq!(2, transactions.len());
+
+        let install = &transactions[0];
+        assert_eq!("2023-10-05  10:12:33", install.start);
+        assert_eq!(Some("user (1000)"), install.requested_by.as_deref());
+        assert_eq!(
+            &PackageEvent {
+                package: "libfoo1".into(),
+                architecture: Some("amd64".into()),
+                action: Action::Install,
+                from: None,
+                version: "1.0".into(),
+                automatic: true,
+            },
+            install.event("libfoo1").unwrap()
+        );
+
+        let upgrade = &transactions[1];
+        assert_eq!(3, upgrade.events.len());
+        assert_eq!(Action::Remove, upgrade.event("bar").unwrap().action);
+
+        let hello = upgrade.event("hello").unwrap();
+        assert_eq!(Action::Upgrade, hello.action);
+        assert_eq!(Some("2.10-2ubuntu4"), hello.from.as_deref());
+        assert_eq!("2.10-3", hello.version);
+
+        let history = PackageHistory {
+            package: "hello".into(),
+            installed: Some("2.10-3".into()),
+            transactions,
+        };
+
+        assert_eq!(
+            "2023-10-06  09:00:00",
+            history.installed_by().unwrap().start
+        );
+        assert_eq!(
+            "2023-10-05  10:12:33",
+            history.installed_version_by("2.10-2ubuntu4").unwrap().start
+        );
+    }
+
+    #[tokio::test]
+    async fn history_logs_oldest_first() {
+        let temp = crate::utils::test_dir("history");
+        let dir = temp.path();
+
+        for name in &[
+            "history.log",
+            "history.log.1",
+            "history.log.10.gz",
+            "term.log",
+            "term.log.1.gz",
+        ] {
+            std::fs::write(dir.join(name), "").unwrap();
+        }
+
+        let logs = history_logs(dir).await.unwrap();
+        let names: Vec<_> = logs
+            .iter()
+            .map(|path| path.file_name().unwrap().to_str().unwrap())
+            .collect();
+
+        assert_eq!(
+            vec!["history.log.10.gz", "history.log.1", "history.log"],
+            names
+        );
+    }
+}
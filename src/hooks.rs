@@ -0,0 +1,298 @@
+// Copyright 2021-2022 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+//! Callbacks which run before and after the operations of `AptGet`, such that
+//! integrations like filesystem snapshots and notifications need not wrap each method.
+//!
+//! An error from a pre-operation hook aborts the operation before apt is spawned. Errors
+//! from the other hooks are returned once the operation has completed, or yielded by
+//! the streams of the streaming operations.
+//!
+//! ```no_run
+//! use apt_cmd::hooks::{HookEvent, HookKind};
+//! use apt_cmd::{AptClient, Backend};
+//!
+//! # async fn run() -> std::io::Result<()> {
+//! let mut client = AptClient::new(Backend::Host);
+//!
+//! client.hooks_mut().register(HookKind::PreInstall, |event| async move {
+//!     if let HookEvent::PreInstall { packages, .. } = event {
+//!         println!("taking a snapshot before installing {:?}", packages);
+//!     }
+//!
+//!     Ok(())
+//! });
+//!
+//! client.apt_get().noninteractive().install(["hello"]).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::AptUpgradeEvent;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use thiserror::Error;
+
+/// When a hook is called.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum HookKind {
+    PreUpdate,
+    PostUpdate,
+    /// Before packages are installed, upgraded, or removed.
+    PreInstall,
+    /// After packages were installed, upgraded, or removed, whether or not it succeeded.
+    PostInstall,
+    /// Each event concerning a package, such as it being unpacked or set up.
+    Package,
+    /// After the pre-install hooks, to snapshot the filesystem, returning the ID of
+    /// the snapshot. Registered with `Hooks::register_snapshot`.
+    Snapshot,
+}
+
+/// The operation which packages are changed by.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Operation {
+    Install,
+    Upgrade,
+    Remove,
+}
+
+impl Operation {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Operation::Install => "install",
+            Operation::Upgrade => "upgrade",
+            Operation::Remove => "remove",
+        }
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(self.as_str())
+    }
+}
+
+/// What a hook is called with.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum HookEvent {
+    PreUpdate,
+    PostUpdate {
+        success: bool,
+    },
+    PreInstall {
+        operation: Operation,
+        /// The packages requested, which are empty for an upgrade.
+        packages: Vec<String>,
+    },
+    PostInstall {
+        operation: Operation,
+        packages: Vec<String>,
+        /// The IDs of the snapshots taken before the operation.
+        snapshots: Vec<String>,
+        success: bool,
+    },
+    Package(AptUpgradeEvent),
+}
+
+impl HookEvent {
+    pub fn kind(&self) -> HookKind {
+        match self {
+            HookEvent::PreUpdate => HookKind::PreUpdate,
+            HookEvent::PostUpdate { .. } => HookKind::PostUpdate,
+            HookEvent::PreInstall { .. } => HookKind::PreInstall,
+            HookEvent::PostInstall { .. } => HookKind::PostInstall,
+            HookEvent::Package(_) => HookKind::Package,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("{kind:?} hook failed")]
+pub struct HookError {
+    pub kind: HookKind,
+    #[source]
+    pub source: anyhow::Error,
+}
+
+impl From<HookError> for io::Error {
+    fn from(why: HookError) -> Self {
+        io::Error::other(why)
+    }
+}
+
+pub type HookFuture = Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send>>;
+
+pub type SnapshotFuture = Pin<Box<dyn Future<Output = anyhow::Result<String>> + Send>>;
+
+type Hook = Arc<dyn Fn(HookEvent) -> HookFuture + Send + Sync>;
+
+type SnapshotHook = Arc<dyn Fn(Operation) -> SnapshotFuture + Send + Sync>;
+
+/// A registry of hooks, which are called in the order they were registered.
+#[derive(Clone, Default)]
+pub struct Hooks {
+    hooks: Vec<(HookKind, Hook)>,
+    snapshots: Vec<SnapshotHook>,
+}
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let snapshots = self.snapshots.iter().map(|_| &HookKind::Snapshot);
+
+        fmt.debug_list()
+            .entries(self.hooks.iter().map(|(kind, _)| kind).chain(snapshots))
+            .finish()
+    }
+}
+
+impl Hooks {
+    pub fn register<F, Fut>(&mut self, kind: HookKind, hook: F) -> &mut Self
+    where
+        F: Fn(HookEvent) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+    {
+        self.hooks
+            .push((kind, Arc::new(move |event| Box::pin(hook(event)))));
+        self
+    }
+
+    /// Registers a hook which snapshots the filesystem before packages are changed,
+    /// returning the ID of the snapshot, such as its path or name.
+    pub fn register_snapshot<F, Fut>(&mut self, hook: F) -> &mut Self
+    where
+        F: Fn(Operation) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = anyhow::Result<String>> + Send + 'static,
+    {
+        self.snapshots
+            .push(Arc::new(move |operation| Box::pin(hook(operation))));
+        self
+    }
+
+    /// Whether any hook is registered for `kind`.
+    pub fn contains(&self, kind: HookKind) -> bool {
+        match kind {
+            HookKind::Snapshot => !self.snapshots.is_empty(),
+            kind => self.hooks.iter().any(|(registered, _)| *registered == kind),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty() && self.snapshots.is_empty()
+    }
+
+    /// Runs the pre-install hooks, followed by the snapshot hooks, returning the ID of
+    /// each snapshot taken.
+    pub async fn run_pre_install(
+        &self,
+        operation: Operation,
+        packages: &[String],
+    ) -> Result<Vec<String>, HookError> {
+        self.run(HookEvent::PreInstall {
+            operation,
+            packages: packages.to_vec(),
+        })
+        .await?;
+
+        let mut snapshots = Vec::with_capacity(self.snapshots.len());
+
+        for hook in &self.snapshots {
+            let id = hook(operation).await.map_err(|source| HookError {
+                kind: HookKind::Snapshot,
+                source,
+            })?;
+
+            snapshots.push(id);
+        }
+
+        Ok(snapshots)
+    }
+
+    /// Calls each hook of the event's kind, stopping at the first which fails.
+    pub async fn run(&self, event: HookEvent) -> Result<(), HookError> {
+        let kind = event.kind();
+
+        for (_, hook) in self
+            .hooks
+            .iter()
+            .filter(|(registered, _)| *registered == kind)
+        {
+            hook(event.clone())
+                .await
+                .map_err(|source| HookError { kind, source })?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[tokio::test]
+    async fn hooks_run_in_order() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let mut hooks = Hooks::default();
+
+        for id in 0..2 {
+            let calls = calls.clone();
+            hooks.register(HookKind::PreUpdate, move |event| {
+                calls.lock().unwrap().push((id, event));
+                async { Ok(()) }
+            });
+        }
+
+        hooks.register(HookKind::PostUpdate, |_| async {
+            Err(anyhow::anyhow!("notification failed"))
+        });
+
+        hooks.run(HookEvent::PreUpdate).await.unwrap();
+        assert_eq!(
+            vec![(0, HookEvent::PreUpdate), (1, HookEvent::PreUpdate)],
+            *calls.lock().unwrap()
+        );
+
+        // Nothing is registered for package events.
+        assert!(!hooks.contains(HookKind::Package));
+        hooks
+            .run(HookEvent::Package(AptUpgradeEvent::Fetched { bytes: 0 }))
+            .await
+            .unwrap();
+
+        let why = hooks
+            .run(HookEvent::PostUpdate { success: true })
+            .await
+            .unwrap_err();
+        assert_eq!(HookKind::PostUpdate, why.kind);
+    }
+
+    #[tokio::test]
+    async fn snapshots_follow_pre_install_hooks() {
+        let mut hooks = Hooks::default();
+        hooks.register_snapshot(|operation| async move { Ok(format!("apt-{}", operation)) });
+
+        assert_eq!(
+            vec!["apt-upgrade"],
+            hooks
+                .run_pre_install(Operation::Upgrade, &[])
+                .await
+                .unwrap()
+        );
+
+        // A failing pre-install hook aborts before any snapshot is taken.
+        hooks.register(HookKind::PreInstall, |_| async {
+            Err(anyhow::anyhow!("not enough space"))
+        });
+
+        let why = hooks
+            .run_pre_install(Operation::Install, &["hello".to_owned()])
+            .await
+            .unwrap_err();
+        assert_eq!(HookKind::PreInstall, why.kind);
+    }
+}
@@ -0,0 +1,165 @@
+// Copyright 2021-2022 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+use std::ffi::{CString, OsString};
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::Path;
+use tokio::io::unix::AsyncFd;
+
+/// The size of the fixed part of `struct inotify_event`.
+const EVENT_HEADER: usize = 16;
+
+/// A non-blocking inotify instance.
+pub(crate) struct Inotify {
+    fd: OwnedFd,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub(crate) struct Event {
+    pub wd: i32,
+    pub mask: u32,
+    /// The name of the file within the watched directory.
+    pub name: Option<OsString>,
+}
+
+impl Inotify {
+    pub fn new() -> io::Result<Self> {
+        // SAFETY: the returned descriptor is checked, and owned by nothing else.
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+        })
+    }
+
+    pub fn add_watch(&self, path: &Path, mask: u32) -> io::Result<i32> {
+        let path = CString::new(path.as_os_str().as_bytes())
+            .map_err(|why| io::Error::new(io::ErrorKind::InvalidInput, why))?;
+
+        // SAFETY: `path` is a valid nul-terminated string for the duration of the call.
+        let wd = unsafe { libc::inotify_add_watch(self.fd.as_raw_fd(), path.as_ptr(), mask) };
+
+        if wd < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(wd)
+        }
+    }
+
+    /// Reads the events which are available, failing with `WouldBlock` if there are none.
+    pub fn read_events(&self, buffer: &mut [u8]) -> io::Result<Vec<Event>> {
+        // SAFETY: the length of the buffer is passed with it.
+        let read = unsafe {
+            libc::read(
+                self.fd.as_raw_fd(),
+                buffer.as_mut_ptr() as *mut libc::c_void,
+                buffer.len(),
+            )
+        };
+
+        if read < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(parse_events(&buffer[..read as usize]))
+    }
+}
+
+impl AsRawFd for Inotify {
+    fn as_raw_fd(&self) -> std::os::fd::RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+/// An inotify instance which is read on the tokio runtime, and closed when dropped.
+pub(crate) struct AsyncInotify(AsyncFd<Inotify>);
+
+impl AsyncInotify {
+    /// Must be called within a tokio runtime.
+    pub fn new(inotify: Inotify) -> io::Result<Self> {
+        AsyncFd::new(inotify).map(Self)
+    }
+
+    /// Waits until events are available, and reads them.
+    pub async fn read_events(&self, buffer: &mut [u8]) -> io::Result<Vec<Event>> {
+        loop {
+            let mut ready = self.0.readable().await?;
+
+            match ready.try_io(|inotify| inotify.get_ref().read_events(buffer)) {
+                Ok(Err(why)) if why.kind() == io::ErrorKind::Interrupted => continue,
+                Ok(result) => return result,
+                Err(_would_block) => continue,
+            }
+        }
+    }
+}
+
+fn parse_events(mut buffer: &[u8]) -> Vec<Event> {
+    let mut events = Vec::new();
+    let field = |bytes: &[u8], at: usize| {
+        u32::from_ne_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+    };
+
+    while buffer.len() >= EVENT_HEADER {
+        let wd = field(buffer, 0) as i32;
+        let mask = field(buffer, 4);
+        let len = field(buffer, 12) as usize;
+
+        let Some(name) = buffer.get(EVENT_HEADER..EVENT_HEADER + len) else {
+            break;
+        };
+
+        // The name is padded with nul bytes.
+        let name = name.split(|&byte| byte == 0).next().unwrap_or_default();
+
+        events.push(Event {
+            wd,
+            mask,
+            name: Some(OsString::from_vec(name.to_vec())).filter(|name| !name.is_empty()),
+        });
+
+        buffer = &buffer[EVENT_HEADER + len..];
+    }
+
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn inotify_events() {
+        let temp = crate::utils::test_dir("inotify");
+        let dir = temp.path();
+
+        let inotify = Inotify::new().unwrap();
+        let wd = inotify.add_watch(dir, libc::IN_CLOSE_WRITE).unwrap();
+
+        let mut buffer = [0; 4096];
+        assert_eq!(
+            io::ErrorKind::WouldBlock,
+            inotify.read_events(&mut buffer).unwrap_err().kind()
+        );
+
+        std::fs::write(dir.join("status"), "Package: hello\n").unwrap();
+
+        let inotify = AsyncInotify::new(inotify).unwrap();
+        let events = inotify.read_events(&mut buffer).await.unwrap();
+
+        assert_eq!(
+            vec![Event {
+                wd,
+                mask: libc::IN_CLOSE_WRITE,
+                name: Some("status".into()),
+            }],
+            events
+        );
+    }
+}
@@ -0,0 +1,199 @@
+// Copyright 2021-2022 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+//! Forwards SIGINT and SIGTERM to apt without interrupting dpkg mid-operation.
+//!
+//! A terminal sends Ctrl-C to the whole foreground process group, so the child
+//! must be spawned in its own group for these signals to be withheld from it:
+//!
+//! ```no_run
+//! # async fn run() -> std::io::Result<()> {
+//! let mut apt_get = apt_cmd::AptGet::new();
+//!
+//! // Spawns apt in a new process group, whose ID is its PID, so that Ctrl-C reaches only
+//! // this process, which then decides whether to forward it to apt.
+//! apt_get.process_group(0);
+//! let (child, events) = apt_get.stream_upgrade().await?;
+//! let events = apt_cmd::interrupt::interruptible_upgrade(child, events)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::apt_get::UpgradeEvents;
+use crate::{AptChild, AptUpgradeEvent};
+use async_stream::stream;
+use futures::stream::{Stream, StreamExt};
+use std::io;
+use std::process::ExitStatus;
+use tokio::signal::unix::{signal, SignalKind};
+
+#[derive(Debug)]
+pub enum InterruptibleEvent {
+    Upgrade(AptUpgradeEvent),
+
+    /// A termination signal was received.
+    ///
+    /// It is forwarded to apt as SIGINT while packages are still being downloaded.
+    /// Once dpkg begins unpacking and configuring packages, it is withheld so that
+    /// the dpkg database is not left in an inconsistent state.
+    Interrupted { forwarded: bool },
+
+    /// The child process exited.
+    Exited(io::Result<ExitStatus>),
+}
+
+/// Watches for termination signals while an upgrade from `AptGet::stream_upgrade` runs.
+///
+/// Signal handlers are installed immediately, so that signals received before
+/// the stream is first polled are not lost.
+pub fn interruptible_upgrade<C: AptChild>(
+    mut child: C,
+    mut events: UpgradeEvents,
+) -> io::Result<impl Stream<Item = InterruptibleEvent>> {
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    let mut terminate = signal(SignalKind::terminate())?;
+
+    Ok(stream! {
+        let mut critical = false;
+
+        loop {
+            let event = tokio::select! {
+                event = events.next() => match event {
+                    Some(event) => event,
+                    None => break,
+                },
+                Some(()) = interrupt.recv() => {
+                    yield InterruptibleEvent::Interrupted { forwarded: forward(&child, critical) };
+                    continue
+                }
+                Some(()) = terminate.recv() => {
+                    yield InterruptibleEvent::Interrupted { forwarded: forward(&child, critical) };
+                    continue
+                }
+            };
+
+            critical = critical || is_critical(&event);
+            yield InterruptibleEvent::Upgrade(event);
+        }
+
+        yield InterruptibleEvent::Exited(child.wait().await);
+    })
+}
+
+/// Whether dpkg has begun modifying the system.
+fn is_critical(event: &AptUpgradeEvent) -> bool {
+    matches!(
+        event,
+        AptUpgradeEvent::Unpacking { .. }
+            | AptUpgradeEvent::Installing { .. }
+            | AptUpgradeEvent::Removing { .. }
+            | AptUpgradeEvent::SettingUp { .. }
+            | AptUpgradeEvent::Processing { .. }
+            | AptUpgradeEvent::ProcessingTriggers { .. }
+    )
+}
+
+/// Sends SIGINT to the child if it is safe to do so.
+fn forward<C: AptChild>(child: &C, critical: bool) -> bool {
+    if critical {
+        return false;
+    }
+
+    match child.id() {
+        // SAFETY: kill has no memory safety requirements.
+        Some(pid) => unsafe { libc::kill(pid as libc::pid_t, libc::SIGINT) == 0 },
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{interruptible_upgrade, InterruptibleEvent};
+    use crate::child::{AptChild, ChildFuture};
+    use crate::AptUpgradeEvent;
+    use futures::channel::mpsc;
+    use futures::StreamExt;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::ExitStatus;
+
+    /// A child whose process is only signalled, and which is never waited on.
+    struct Signalled(u32);
+
+    impl AptChild for Signalled {
+        fn id(&self) -> Option<u32> {
+            Some(self.0)
+        }
+
+        fn kill(&mut self) -> ChildFuture<'_, ()> {
+            Box::pin(async { Ok(()) })
+        }
+
+        fn wait(&mut self) -> ChildFuture<'_, ExitStatus> {
+            Box::pin(async { Ok(ExitStatus::from_raw(0)) })
+        }
+    }
+
+    fn raise_interrupt() {
+        // SAFETY: kill has no memory safety requirements.
+        unsafe { libc::kill(libc::getpid(), libc::SIGINT) };
+    }
+
+    #[tokio::test]
+    async fn interrupts_are_held_once_dpkg_begins() {
+        // Before dpkg begins, the interrupt is forwarded to the child.
+        let mut sleeper = std::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+        let (_tx, rx) = mpsc::unbounded::<AptUpgradeEvent>();
+        let child = Signalled(sleeper.id());
+        let mut events = Box::pin(interruptible_upgrade(child, Box::pin(rx)).unwrap());
+
+        raise_interrupt();
+        assert!(matches!(
+            events.next().await,
+            Some(InterruptibleEvent::Interrupted { forwarded: true })
+        ));
+        assert_eq!(Some(libc::SIGINT), sleeper.wait().unwrap().signal());
+        drop(events);
+
+        // Once a package is unpacked, the interrupt is withheld.
+        let mut sleeper = std::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+        let (tx, rx) = mpsc::unbounded::<AptUpgradeEvent>();
+        let child = Signalled(sleeper.id());
+        let mut events = Box::pin(interruptible_upgrade(child, Box::pin(rx)).unwrap());
+
+        tx.unbounded_send(AptUpgradeEvent::Unpacking {
+            package: "firefox".into(),
+            version: "120.0+build2".into(),
+            over: "119.0".into(),
+        })
+        .unwrap();
+
+        assert!(matches!(
+            events.next().await,
+            Some(InterruptibleEvent::Upgrade(
+                AptUpgradeEvent::Unpacking { .. }
+            ))
+        ));
+
+        raise_interrupt();
+        assert!(matches!(
+            events.next().await,
+            Some(InterruptibleEvent::Interrupted { forwarded: false })
+        ));
+        assert!(sleeper.try_wait().unwrap().is_none());
+
+        drop(tx);
+        assert!(matches!(
+            events.next().await,
+            Some(InterruptibleEvent::Exited(Ok(_)))
+        ));
+
+        sleeper.kill().unwrap();
+        sleeper.wait().unwrap();
+    }
+}
@@ -0,0 +1,157 @@
+// Copyright 2021-2022 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+//! Installs the missing signing keys reported by `apt-get update`.
+
+use crate::apt_get::UpdateEvent;
+use crate::warnings::MissingKey;
+use anyhow::Context;
+use futures::stream::StreamExt;
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+
+pub const DEFAULT_KEYSERVER: &str = "hkps://keyserver.ubuntu.com";
+pub const DEFAULT_KEYRING_DIR: &str = "/etc/apt/keyrings";
+
+/// Fetches missing keys from a keyserver, and configures repositories to be verified by them.
+#[derive(Clone, Debug)]
+pub struct KeyRemediation {
+    keyserver: String,
+    keyring_dir: PathBuf,
+}
+
+impl Default for KeyRemediation {
+    fn default() -> Self {
+        Self {
+            keyserver: DEFAULT_KEYSERVER.into(),
+            keyring_dir: PathBuf::from(DEFAULT_KEYRING_DIR),
+        }
+    }
+}
+
+impl KeyRemediation {
+    pub fn keyserver(mut self, keyserver: impl Into<String>) -> Self {
+        self.keyserver = keyserver.into();
+        self
+    }
+
+    /// The directory which keyrings are written to.
+    pub fn keyring_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.keyring_dir = dir.into();
+        self
+    }
+
+    /// Runs `apt-get update`, installs any keys reported missing, and updates again.
+    ///
+    /// Returns the keys which were installed.
+    pub async fn update(&self) -> anyhow::Result<Vec<MissingKey>> {
+        let missing = missing_keys().await?;
+
+        for key in &missing {
+            self.remediate(key).await?;
+        }
+
+        if !missing.is_empty() {
+            crate::AptGet::new()
+                .noninteractive()
+                .update()
+                .await
+                .context("`apt-get update` failed after installing missing keys")?;
+        }
+
+        Ok(missing)
+    }
+
+    /// Installs the keys of a repository into a keyring, and adds `signed-by` to its entries.
+    pub async fn remediate(&self, key: &MissingKey) -> anyhow::Result<PathBuf> {
+        let name = key.key_ids.join("-");
+        let keyring = self.keyring_dir.join(format!("apt-cmd-{}.gpg", name));
+
+        self.receive_keys(&key.key_ids, &keyring).await?;
+
+        crate::sources::add_signed_by(&key.uri, &keyring)
+            .await?
+            .apply()
+            .await
+            .context("failed to add signed-by to sources")?;
+
+        Ok(keyring)
+    }
+
+    /// Receives keys from the keyserver, and exports them as a binary keyring.
+    async fn receive_keys(&self, key_ids: &[String], keyring: &Path) -> anyhow::Result<()> {
+        // A temporary home keeps the keys out of root's own keyring. It is private, and
+        // never an existing directory, so no other user may plant keys in it.
+        let dir = crate::utils::TempDir::new("apt-cmd-gpg")
+            .context("failed to create temporary GnuPG home")?;
+        let home = dir.path();
+
+        async {
+            let status = gpg(home)
+                .args(["--keyserver", &self.keyserver, "--recv-keys", "--"])
+                .args(key_ids)
+                .status()
+                .await
+                .context("failed to launch `gpg --recv-keys`")?;
+
+            if !status.success() {
+                return Err(anyhow::anyhow!(
+                    "failed to receive keys {} from {}",
+                    key_ids.join(", "),
+                    self.keyserver
+                ));
+            }
+
+            tokio::fs::create_dir_all(&self.keyring_dir)
+                .await
+                .context("failed to create keyring directory")?;
+
+            let status = gpg(home)
+                .arg("--output")
+                .arg(keyring)
+                .args(["--export", "--"])
+                .args(key_ids)
+                .status()
+                .await
+                .context("failed to launch `gpg --export`")?;
+
+            if !status.success() {
+                return Err(anyhow::anyhow!(
+                    "failed to export keys to {}",
+                    keyring.display()
+                ));
+            }
+
+            Ok(())
+        }
+        .await
+    }
+}
+
+fn gpg(home: &Path) -> Command {
+    let mut command = crate::utils::command("gpg");
+    command.args(["--batch", "--yes", "--no-default-keyring", "--homedir"]);
+    command.arg(home);
+    command
+}
+
+/// Runs `apt-get update`, collecting the repositories with missing keys.
+pub async fn missing_keys() -> anyhow::Result<Vec<MissingKey>> {
+    let mut events = crate::AptGet::new()
+        .noninteractive()
+        .stream_update()
+        .await
+        .context("failed to launch `apt-get update`")?;
+
+    let mut missing = Vec::new();
+    while let Some(event) = events.next().await {
+        if let UpdateEvent::Warning(warning) = event {
+            // Each suite of a repository reports the same missing key.
+            if let Some(key) = warning.missing_key().filter(|key| !missing.contains(key)) {
+                missing.push(key);
+            }
+        }
+    }
+
+    Ok(missing)
+}
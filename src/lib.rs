@@ -5,20 +5,86 @@
 extern crate derive_more;
 
 mod apt_cache;
+mod apt_cdrom;
 mod apt_get;
+mod apt_helper;
 mod apt_mark;
-mod dpkg;
+mod backend;
+mod child;
+mod client;
+mod depends;
+mod inotify;
+mod package;
+mod record;
+mod update;
 mod upgrade;
 mod utils;
 
 pub mod apt;
+pub mod blocking;
+pub mod compression;
+pub mod config_apply;
+pub mod daemon;
+pub mod deb822;
+pub mod distro;
+pub mod dpkg;
 pub mod fetch;
 pub mod hash;
+pub mod history;
+pub mod hooks;
+pub mod interrupt;
+pub mod keys;
 pub mod lock;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "networkmanager")]
+pub mod network;
+#[cfg(feature = "notify")]
+pub mod notify;
+pub mod orchestrate;
+pub mod parsers;
+pub mod privilege;
+pub mod progress;
 pub mod request;
+pub mod signature;
+#[cfg(feature = "snapshot")]
+pub mod snapshot;
+pub mod sources;
+pub mod store;
+pub mod timestamp;
+pub mod warnings;
+pub mod watchdog;
 
-pub use self::apt_cache::{AptCache, Policies, Policy};
-pub use self::apt_get::AptGet;
-pub use self::apt_mark::AptMark;
-pub use self::dpkg::{Dpkg, DpkgQuery};
-pub use self::upgrade::AptUpgradeEvent;
+/// The version of the serialized shape of events, such as the D-Bus map of an
+/// [`AptUpgradeEvent`], which is included in each under the `schema` key.
+///
+/// It is incremented whenever a key is renamed, removed, or changes meaning. Maps
+/// without a `schema` key predate versioning, and are read as version `0`.
+pub const SCHEMA_VERSION: u32 = 1;
+
+pub use self::apt_cache::{
+    AptCache, CacheStats, CheckedPolicies, DependsBlocks, MadisonEntries, PackageFile,
+    PackageRecords, Policies, Policy, PolicyParseError, PolicyParser, ReverseDependencies,
+    SearchResults, UnmetDependencies,
+};
+pub use self::apt_cdrom::{AptCdrom, CdromIdent};
+pub use self::apt_get::{AptGet, BadPPA, InstallError, RemoveError, UpdateEvent, UpdateEvents};
+pub use self::apt_helper::AptHelper;
+pub use self::apt_mark::{AptMark, HoldDiscrepancies, HoldGuard};
+pub use self::backend::Backend;
+pub use self::child::{AptChild, ChildFuture};
+pub use self::client::AptClient;
+pub use self::depends::{
+    Dependency, DependencyEdge, DependencyGraph, DependencyKind, DependsBlock, DependsParser,
+    EdgeKind, PackageProviders, RdependsParser, ReverseDependency, UnmetDependency, UnmetReason,
+    UnmetRelation, UnmetTarget,
+};
+pub use self::dpkg::{Dpkg, DpkgQuery, PackageStatus, PackageStatuses};
+pub use self::fetch::{FetcherExt, PackageFetcher, PackageFetcherBuilder};
+pub use self::package::{package_architecture, validate_package_name, InvalidPackageName};
+pub use self::record::{PackageRecord, RecordParser, SourceFile, SourceRecord, SourceRecordParser};
+pub use self::update::{
+    FetchSummary, IndexEvent, IndexKind, IndexRequest, IndexStatus, IndexTargets,
+};
+pub use self::upgrade::{AptUpgradeEvent, PackageChange, UpgradeParser, UpgradeSummary};
+pub use self::utils::{OutputStream, RawLine};
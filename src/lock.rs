@@ -3,12 +3,23 @@
 
 use async_stream::stream;
 use futures::stream::{Stream, StreamExt};
-use std::path::Path;
-use std::time::Duration;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
 use tokio::time::sleep;
 
 const LISTS_LOCK: &str = "/var/lib/apt/lists/lock";
-const DPKG_LOCK: &str = "/var/lib/dpkg/lock";
+const ARCHIVES_LOCK: &str = "/var/cache/apt/archives/lock";
+const FRONTEND_LOCK: &str = "/var/lib/dpkg/lock-frontend";
+pub(crate) const DPKG_LOCK: &str = "/var/lib/dpkg/lock";
+
+/// The locks reported by `status`, in the order that apt takes them.
+const LOCKS: &[&str] = &[FRONTEND_LOCK, DPKG_LOCK, LISTS_LOCK, ARCHIVES_LOCK];
+
+/// When the holders of each lock were first seen by `status`, with their process IDs.
+static HELD_SINCE: Mutex<BTreeMap<PathBuf, (Vec<i32>, SystemTime)>> = Mutex::new(BTreeMap::new());
+
 pub enum AptLockEvent {
     Locked,
     Unlocked,
@@ -49,9 +60,7 @@ pub fn apt_lock_found(paths: &[&Path]) -> bool {
     };
 
     for proc in processes.filter_map(Result::ok) {
-        let Ok(fdinfos) = proc.fd() else {
-            continue
-        };
+        let Ok(fdinfos) = proc.fd() else { continue };
 
         for fdinfo in fdinfos.filter_map(Result::ok) {
             if let FDTarget::Path(path) = fdinfo.target {
@@ -64,3 +73,169 @@ pub fn apt_lock_found(paths: &[&Path]) -> bool {
 
     false
 }
+
+/// A process which has a lock open.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LockHolder {
+    pub pid: i32,
+    /// The name of its command, as `unattended-upgr`.
+    pub command: String,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LockState {
+    pub path: PathBuf,
+    pub exists: bool,
+    pub holders: Vec<LockHolder>,
+    /// When `status` first saw the lock held by any of its current holders.
+    ///
+    /// Locks are not timestamped by apt, so this is only as accurate as the interval
+    /// between calls, and is the time of the first call if the lock was already held.
+    pub since: Option<SystemTime>,
+}
+
+impl LockState {
+    pub fn is_held(&self) -> bool {
+        !self.holders.is_empty()
+    }
+
+    /// How long the lock has been held, as far as is known.
+    pub fn held_for(&self) -> Option<Duration> {
+        self.since.map(|since| since.elapsed().unwrap_or_default())
+    }
+}
+
+/// A snapshot of the apt and dpkg locks.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct LockStatus {
+    pub locks: Vec<LockState>,
+}
+
+impl LockStatus {
+    pub fn is_locked(&self) -> bool {
+        self.locks.iter().any(LockState::is_held)
+    }
+
+    /// The locks which are held.
+    pub fn held(&self) -> impl Iterator<Item = &LockState> {
+        self.locks.iter().filter(|lock| lock.is_held())
+    }
+}
+
+/// Which of the apt and dpkg locks exist, and which processes hold them.
+///
+/// The time at which each lock became held is tracked across calls, so that this may
+/// be polled to report how long apt has been locked.
+pub fn status() -> LockStatus {
+    use procfs::process::{all_processes, FDTarget};
+
+    let mut holders: Vec<Vec<LockHolder>> = vec![Vec::new(); LOCKS.len()];
+
+    if let Ok(processes) = all_processes() {
+        for proc in processes.filter_map(Result::ok) {
+            let Ok(fdinfos) = proc.fd() else { continue };
+
+            for fdinfo in fdinfos.filter_map(Result::ok) {
+                let FDTarget::Path(path) = fdinfo.target else {
+                    continue;
+                };
+
+                let Some(index) = LOCKS.iter().position(|&lock| path == Path::new(lock)) else {
+                    continue;
+                };
+
+                if holders[index].iter().any(|holder| holder.pid == proc.pid()) {
+                    continue;
+                }
+
+                holders[index].push(LockHolder {
+                    pid: proc.pid(),
+                    command: proc.stat().map(|stat| stat.comm).unwrap_or_default(),
+                });
+            }
+        }
+    }
+
+    let now = SystemTime::now();
+    let mut held_since = HELD_SINCE.lock().unwrap_or_else(|why| why.into_inner());
+
+    let locks = LOCKS
+        .iter()
+        .zip(holders)
+        .map(|(&path, holders)| {
+            let path = PathBuf::from(path);
+            let since = track_holders(&mut held_since, &path, &holders, now);
+
+            LockState {
+                exists: path.exists(),
+                path,
+                holders,
+                since,
+            }
+        })
+        .collect();
+
+    LockStatus { locks }
+}
+
+/// Records when a lock became held, which carries over for as long as any of the
+/// processes which held it before still hold it.
+fn track_holders(
+    held_since: &mut BTreeMap<PathBuf, (Vec<i32>, SystemTime)>,
+    path: &Path,
+    holders: &[LockHolder],
+    now: SystemTime,
+) -> Option<SystemTime> {
+    if holders.is_empty() {
+        held_since.remove(path);
+        return None;
+    }
+
+    let pids: Vec<i32> = holders.iter().map(|holder| holder.pid).collect();
+
+    let since = match held_since.get(path) {
+        Some((previous, since)) if previous.iter().any(|pid| pids.contains(pid)) => *since,
+        _ => now,
+    };
+
+    held_since.insert(path.to_owned(), (pids, since));
+    Some(since)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn held_since_tracking() {
+        let mut held_since = BTreeMap::new();
+        let path = Path::new(FRONTEND_LOCK);
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+        let later = start + Duration::from_secs(720);
+
+        let holder = |pid| LockHolder {
+            pid,
+            command: "unattended-upgr".into(),
+        };
+
+        assert_eq!(None, track_holders(&mut held_since, path, &[], start));
+
+        assert_eq!(
+            Some(start),
+            track_holders(&mut held_since, path, &[holder(10)], start)
+        );
+
+        // Still held by the same process, which has been joined by another.
+        assert_eq!(
+            Some(start),
+            track_holders(&mut held_since, path, &[holder(10), holder(11)], later)
+        );
+
+        // Released, and taken again by another process.
+        assert_eq!(None, track_holders(&mut held_since, path, &[], later));
+        assert_eq!(
+            Some(later),
+            track_holders(&mut held_since, path, &[holder(12)], later)
+        );
+    }
+}
@@ -0,0 +1,135 @@
+// Copyright 2021-2022 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+//! Exports counters and gauges of package operations through a pluggable [`Recorder`].
+//!
+//! Nothing is recorded until a recorder is installed with [`set_recorder`].
+
+use crate::fetch::{EventKind, FetchError, FetchEvent};
+use crate::UpgradeSummary;
+use std::sync::OnceLock;
+
+/// Packages which were downloaded and validated.
+pub const PACKAGES_FETCHED: &str = "apt_cmd_packages_fetched_total";
+
+/// Bytes of packages which were downloaded and validated.
+pub const BYTES_DOWNLOADED: &str = "apt_cmd_bytes_downloaded_total";
+
+/// Packages which failed to be fetched, labeled by `kind`.
+pub const FETCH_FAILURES: &str = "apt_cmd_fetch_failures_total";
+
+/// Duration of the most recent upgrade.
+pub const UPGRADE_DURATION: &str = "apt_cmd_upgrade_duration_seconds";
+
+/// Upgrades which reported errors.
+pub const UPGRADE_FAILURES: &str = "apt_cmd_upgrade_failures_total";
+
+/// Receives metrics as they are recorded, such as to forward them to a Prometheus registry.
+pub trait Recorder: Send + Sync {
+    fn increment_counter(&self, name: &'static str, labels: &[(&'static str, &str)], value: u64);
+
+    fn set_gauge(&self, name: &'static str, labels: &[(&'static str, &str)], value: f64);
+}
+
+static RECORDER: OnceLock<Box<dyn Recorder>> = OnceLock::new();
+
+/// Installs the global recorder. Returns the recorder back if one was already installed.
+pub fn set_recorder<R: Recorder + 'static>(recorder: R) -> Result<(), Box<dyn Recorder>> {
+    RECORDER.set(Box::new(recorder))
+}
+
+fn recorder() -> Option<&'static dyn Recorder> {
+    RECORDER.get().map(Box::as_ref)
+}
+
+pub(crate) fn record_fetch_event(event: &FetchEvent) {
+    let Some(recorder) = recorder() else {
+        return;
+    };
+
+    let failure = match &event.kind {
+        EventKind::Validated => {
+            recorder.increment_counter(PACKAGES_FETCHED, &[], 1);
+            recorder.increment_counter(BYTES_DOWNLOADED, &[], event.package.size);
+            return;
+        }
+        EventKind::Error(FetchError::Checksum { .. }) => "checksum",
+        EventKind::Error(FetchError::Fetch { .. } | FetchError::AptHelper { .. }) => "download",
+        #[cfg(feature = "networkmanager")]
+        EventKind::Error(FetchError::Metered { .. }) => "metered",
+        EventKind::Error(FetchError::Verification { .. }) | EventKind::SignatureFailed(_) => {
+            "signature"
+        }
+        _ => return,
+    };
+
+    recorder.increment_counter(FETCH_FAILURES, &[("kind", failure)], 1);
+}
+
+pub(crate) fn record_upgrade(summary: &UpgradeSummary) {
+    let Some(recorder) = recorder() else {
+        return;
+    };
+
+    recorder.set_gauge(UPGRADE_DURATION, &[], summary.duration.as_secs_f64());
+
+    if !summary.errors.is_empty() {
+        recorder.increment_counter(UPGRADE_FAILURES, &[], 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::{Request, RequestChecksum};
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct Counters(Arc<Mutex<HashMap<String, u64>>>);
+
+    impl Recorder for Counters {
+        fn increment_counter(
+            &self,
+            name: &'static str,
+            labels: &[(&'static str, &str)],
+            value: u64,
+        ) {
+            let mut key = name.to_owned();
+            for (label, value) in labels {
+                key.push_str(&format!(",{}={}", label, value));
+            }
+
+            *self.0.lock().unwrap().entry(key).or_default() += value;
+        }
+
+        fn set_gauge(&self, _name: &'static str, _labels: &[(&'static str, &str)], _value: f64) {}
+    }
+
+    #[test]
+    fn records_fetch_events() {
+        let counters = Counters::default();
+        assert!(set_recorder(counters.clone()).is_ok());
+
+        let package = Arc::new(Request {
+            uri: "http://archive.ubuntu.com/ubuntu/pool/main/f/foo/foo_1.0_amd64.deb".into(),
+            name: "foo_1.0_amd64.deb".into(),
+            size: 1024,
+            checksum: RequestChecksum::Md5(String::new()),
+        });
+
+        record_fetch_event(&FetchEvent::new(package.clone(), EventKind::Validated));
+        record_fetch_event(&FetchEvent::new(
+            package,
+            EventKind::SignatureFailed(crate::signature::SignatureError::BadSignature),
+        ));
+
+        let counters = counters.0.lock().unwrap();
+        assert_eq!(Some(&1), counters.get(PACKAGES_FETCHED));
+        assert_eq!(Some(&1024), counters.get(BYTES_DOWNLOADED));
+        assert_eq!(
+            Some(&1),
+            counters.get("apt_cmd_fetch_failures_total,kind=signature")
+        );
+    }
+}
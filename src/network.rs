@@ -0,0 +1,197 @@
+// Copyright 2021-2022 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+//! Queries NetworkManager for connectivity and metered state.
+
+use anyhow::Context;
+use tokio::process::Command;
+
+const NM_DEST: &str = "org.freedesktop.NetworkManager";
+const NM_PATH: &str = "/org/freedesktop/NetworkManager";
+
+/// Connectivity state as reported by NetworkManager.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Connectivity {
+    Unknown,
+    None,
+    Portal,
+    Limited,
+    Full,
+}
+
+impl Connectivity {
+    /// Whether package downloads can be expected to succeed.
+    pub fn is_available(self) -> bool {
+        matches!(self, Connectivity::Full | Connectivity::Unknown)
+    }
+}
+
+/// How a [`PackageFetcher`](crate::fetch::PackageFetcher) behaves on a metered connection.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum MeteredPolicy {
+    /// Fetch as normal.
+    #[default]
+    Allow,
+
+    /// Refuse to fetch any packages.
+    Refuse,
+
+    /// Limit the number of concurrent fetches.
+    Throttle(usize),
+}
+
+impl MeteredPolicy {
+    /// The concurrency to fetch with, or `None` if fetching is refused.
+    pub(crate) async fn concurrency(self, concurrent: usize) -> Option<usize> {
+        if let MeteredPolicy::Allow = self {
+            return Some(concurrent);
+        }
+
+        self.limit(concurrent, metered().await)
+    }
+
+    /// The concurrency on a connection which may be metered. If that could not be
+    /// determined, as when NetworkManager is not running, it is assumed not to be.
+    fn limit(self, concurrent: usize, metered: anyhow::Result<bool>) -> Option<usize> {
+        if !metered.unwrap_or(false) {
+            return Some(concurrent);
+        }
+
+        match self {
+            MeteredPolicy::Allow => Some(concurrent),
+            MeteredPolicy::Refuse => None,
+            MeteredPolicy::Throttle(limit) => Some(concurrent.min(limit)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct NetworkStatus {
+    pub connectivity: Connectivity,
+    pub metered: bool,
+}
+
+/// Fetches the current connectivity and metered state of the active connection.
+pub async fn status() -> anyhow::Result<NetworkStatus> {
+    let (connectivity, metered) = futures::future::try_join(connectivity(), metered()).await?;
+    Ok(NetworkStatus {
+        connectivity,
+        metered,
+    })
+}
+
+/// Fetches the current connectivity state.
+pub async fn connectivity() -> anyhow::Result<Connectivity> {
+    let state = match nm_property("Connectivity").await? {
+        1 => Connectivity::None,
+        2 => Connectivity::Portal,
+        3 => Connectivity::Limited,
+        4 => Connectivity::Full,
+        _ => Connectivity::Unknown,
+    };
+
+    Ok(state)
+}
+
+/// Whether the active connection is known or guessed to be metered.
+///
+/// Fails if `busctl` is missing, or NetworkManager could not be queried.
+pub async fn metered() -> anyhow::Result<bool> {
+    nm_property("Metered").await.map(is_metered)
+}
+
+/// Whether an `NMMetered` state is known or guessed to be metered.
+fn is_metered(state: u32) -> bool {
+    // NM_METERED_YES = 1, NM_METERED_GUESS_YES = 3
+    matches!(state, 1 | 3)
+}
+
+async fn nm_property(property: &str) -> anyhow::Result<u32> {
+    busctl_property(crate::utils::command("busctl"), property).await
+}
+
+/// Reads a NetworkManager property with the `busctl` command.
+async fn busctl_property(mut command: Command, property: &str) -> anyhow::Result<u32> {
+    let output = command
+        .args([
+            "--system",
+            "get-property",
+            NM_DEST,
+            NM_PATH,
+            NM_DEST,
+            property,
+        ])
+        .output()
+        .await
+        .context("failed to launch `busctl`")?;
+
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "`busctl get-property {}` exited in error",
+            property
+        ));
+    }
+
+    parse_property(&String::from_utf8_lossy(&output.stdout))
+        .with_context(|| format!("unexpected output from `busctl` for {}", property))
+}
+
+/// Parses the `u 4` output of `busctl get-property`.
+fn parse_property(output: &str) -> Option<u32> {
+    let mut fields = output.split_ascii_whitespace();
+    if fields.next()? != "u" {
+        return None;
+    }
+
+    fields.next()?.parse::<u32>().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MeteredPolicy;
+
+    #[test]
+    fn parse_property() {
+        assert_eq!(Some(4), super::parse_property("u 4\n"));
+        assert_eq!(None, super::parse_property("s \"full\"\n"));
+    }
+
+    #[test]
+    fn metered_states() {
+        let metered = |output: &str| super::parse_property(output).map(super::is_metered);
+
+        assert_eq!(Some(false), metered("u 0\n"), "unknown");
+        assert_eq!(Some(true), metered("u 1\n"), "yes");
+        assert_eq!(Some(false), metered("u 2\n"), "no");
+        assert_eq!(Some(true), metered("u 3\n"), "guess-yes");
+        assert_eq!(Some(false), metered("u 4\n"), "guess-no");
+    }
+
+    #[tokio::test]
+    async fn busctl_failures_are_errors() {
+        let property = |program: &str, script: &str| {
+            let mut command = tokio::process::Command::new(program);
+            command.args(["-c", script]);
+            super::busctl_property(command, "Metered")
+        };
+
+        assert_eq!(3, property("sh", "echo u 3").await.unwrap());
+        assert!(property("/nonexistent/busctl", "").await.is_err());
+        assert!(
+            property("sh", "echo 'Failed to connect to bus' >&2; exit 1")
+                .await
+                .is_err()
+        );
+        assert!(property("sh", "echo 'b true'").await.is_err());
+    }
+
+    #[test]
+    fn unknown_metering_is_not_metered() {
+        let error = || Err(anyhow::anyhow!("`busctl` not found"));
+
+        assert_eq!(Some(4), MeteredPolicy::Refuse.limit(4, error()));
+        assert_eq!(Some(4), MeteredPolicy::Throttle(1).limit(4, Ok(false)));
+        assert_eq!(Some(1), MeteredPolicy::Throttle(1).limit(4, Ok(true)));
+        assert_eq!(None, MeteredPolicy::Refuse.limit(4, Ok(true)));
+    }
+}
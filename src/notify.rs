@@ -0,0 +1,308 @@
+// Copyright 2021-2022 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+//! Sends freedesktop notifications when updates are available, when an upgrade has
+//! finished, and when a restart is required to complete it.
+//!
+//! Notifications are sent with `busctl` on the session bus of the process, so a
+//! daemon running as root must run the notifier as the desktop user instead.
+//!
+//! ```no_run
+//! use apt_cmd::notify::{notify_upgrade, Notifier};
+//! use futures::StreamExt;
+//!
+//! # async fn run() -> std::io::Result<()> {
+//! let (mut child, events) = apt_cmd::AptGet::new().stream_upgrade().await?;
+//! let events = notify_upgrade(Notifier::new("Pop!_OS Updater"), events);
+//! futures::pin_mut!(events);
+//!
+//! while let Some(event) = events.next().await {
+//!     println!("{:?}", event);
+//! }
+//!
+//! child.wait().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::apt_get::{UpdateEvents, UpgradeEvents};
+use crate::{AptUpgradeEvent, UpdateEvent, UpgradeSummary};
+use anyhow::Context;
+use async_stream::stream;
+use futures::stream::{Stream, StreamExt};
+
+const NOTIFY_DEST: &str = "org.freedesktop.Notifications";
+const NOTIFY_PATH: &str = "/org/freedesktop/Notifications";
+
+/// Created by packages whose upgrade requires the system to be restarted.
+const REBOOT_REQUIRED: &str = "/var/run/reboot-required";
+
+/// The packages which required a restart, one per line.
+const REBOOT_REQUIRED_PKGS: &str = "/var/run/reboot-required.pkgs";
+
+/// An event which the user is notified of.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Milestone {
+    UpdatesAvailable {
+        count: usize,
+    },
+    UpgradeFinished {
+        upgraded: usize,
+        installed: usize,
+        removed: usize,
+        success: bool,
+    },
+    /// The packages which required the restart, if known.
+    RebootRequired {
+        packages: Vec<String>,
+    },
+}
+
+impl Milestone {
+    pub fn upgrade_finished(summary: &UpgradeSummary) -> Self {
+        Milestone::UpgradeFinished {
+            upgraded: summary.upgraded.len(),
+            installed: summary.installed.len(),
+            removed: summary.removed.len(),
+            success: summary.errors.is_empty(),
+        }
+    }
+
+    /// The icon, summary, and body of the notification.
+    pub fn notification(&self) -> (&'static str, String, String) {
+        match self {
+            Milestone::UpdatesAvailable { count } => (
+                "software-update-available",
+                "Updates available".into(),
+                format!("{} {} can be upgraded", count, plural(*count, "package")),
+            ),
+            Milestone::UpgradeFinished {
+                upgraded,
+                installed,
+                removed,
+                success: true,
+            } => (
+                "emblem-default",
+                "Upgrade finished".into(),
+                format!(
+                    "{} upgraded, {} newly installed, {} removed",
+                    upgraded, installed, removed
+                ),
+            ),
+            Milestone::UpgradeFinished { success: false, .. } => (
+                "dialog-error",
+                "Upgrade failed".into(),
+                "Some packages could not be upgraded".into(),
+            ),
+            Milestone::RebootRequired { packages } => (
+                "system-reboot",
+                "Restart required".into(),
+                if packages.is_empty() {
+                    "Restart to finish installing updates".into()
+                } else {
+                    format!("Restart to finish updating {}", packages.join(", "))
+                },
+            ),
+        }
+    }
+}
+
+fn plural(count: usize, noun: &str) -> String {
+    if count == 1 {
+        noun.to_owned()
+    } else {
+        [noun, "s"].concat()
+    }
+}
+
+/// Sends notifications on behalf of an application.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Notifier {
+    app_name: String,
+}
+
+impl Notifier {
+    pub fn new<S: Into<String>>(app_name: S) -> Self {
+        Self {
+            app_name: app_name.into(),
+        }
+    }
+
+    /// Sends a notification of the milestone, returning its ID.
+    pub async fn notify(&self, milestone: &Milestone) -> anyhow::Result<u32> {
+        let output = crate::utils::command("busctl")
+            .args(self.notify_args(milestone))
+            .output()
+            .await
+            .context("failed to launch `busctl`")?;
+
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(
+                "`busctl call {} Notify` exited in error",
+                NOTIFY_DEST
+            ));
+        }
+
+        // The reply is the ID of the notification, as `u 12`.
+        String::from_utf8_lossy(&output.stdout)
+            .trim()
+            .strip_prefix("u ")
+            .and_then(|id| id.parse().ok())
+            .context("unexpected output from `busctl` for Notify")
+    }
+
+    fn notify_args(&self, milestone: &Milestone) -> Vec<String> {
+        let (icon, summary, body) = milestone.notification();
+
+        let mut args: Vec<String> = [
+            "--user",
+            "call",
+            NOTIFY_DEST,
+            NOTIFY_PATH,
+            NOTIFY_DEST,
+            "Notify",
+            "susssasa{sv}i",
+        ]
+        .iter()
+        .map(|arg| String::from(*arg))
+        .collect();
+
+        // No ID to replace, no actions, no hints, and the server's default timeout.
+        args.extend([
+            self.app_name.clone(),
+            "0".into(),
+            icon.into(),
+            summary,
+            body,
+            "0".into(),
+            "0".into(),
+            "-1".into(),
+        ]);
+
+        args
+    }
+}
+
+/// The packages which require a restart, or `None` if no restart is required.
+pub async fn reboot_required() -> Option<Vec<String>> {
+    if tokio::fs::metadata(REBOOT_REQUIRED).await.is_err() {
+        return None;
+    }
+
+    let packages = tokio::fs::read_to_string(REBOOT_REQUIRED_PKGS)
+        .await
+        .unwrap_or_default();
+
+    let mut packages: Vec<String> = packages
+        .lines()
+        .map(str::trim)
+        .filter(|package| !package.is_empty())
+        .map(String::from)
+        .collect();
+
+    packages.dedup();
+    Some(packages)
+}
+
+/// Passes through the events of `AptGet::stream_update`, notifying the user of the
+/// packages which may be upgraded once the update has succeeded.
+///
+/// Failures to notify are ignored, as no notification server may be running.
+pub fn notify_update(
+    notifier: Notifier,
+    mut events: UpdateEvents,
+) -> impl Stream<Item = UpdateEvent> {
+    stream! {
+        let mut success = false;
+
+        while let Some(event) = events.next().await {
+            if let UpdateEvent::ExitStatus(Ok(status)) = &event {
+                success = status.success();
+            }
+
+            yield event;
+        }
+
+        if !success {
+            return;
+        }
+
+        if let Ok((mut child, packages)) = crate::apt::upgradable_packages().await {
+            let count = packages.count().await;
+            let _ = child.wait().await;
+
+            if count > 0 {
+                let _ = notifier.notify(&Milestone::UpdatesAvailable { count }).await;
+            }
+        }
+    }
+}
+
+/// Passes through the events of `AptGet::stream_upgrade` or `AptGet::stream_install`,
+/// notifying the user once the upgrade has finished, and if a restart is required.
+///
+/// Failures to notify are ignored, as no notification server may be running.
+pub fn notify_upgrade(
+    notifier: Notifier,
+    mut events: UpgradeEvents,
+) -> impl Stream<Item = AptUpgradeEvent> {
+    stream! {
+        let mut summary = UpgradeSummary::default();
+
+        while let Some(event) = events.next().await {
+            summary.update(&event);
+            yield event;
+        }
+
+        let _ = notifier.notify(&Milestone::upgrade_finished(&summary)).await;
+
+        if let Some(packages) = reboot_required().await {
+            let _ = notifier.notify(&Milestone::RebootRequired { packages }).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn milestone_notifications() {
+        let (_, summary, body) = Milestone::UpdatesAvailable { count: 1 }.notification();
+        assert_eq!("Updates available", summary);
+        assert_eq!("1 package can be upgraded", body);
+
+        let finished = Milestone::UpgradeFinished {
+            upgraded: 3,
+            installed: 1,
+            removed: 0,
+            success: true,
+        };
+        assert_eq!(
+            "3 upgraded, 1 newly installed, 0 removed",
+            finished.notification().2
+        );
+
+        let failed = Milestone::upgrade_finished(&UpgradeSummary {
+            errors: vec!["dpkg was interrupted".into()],
+            ..UpgradeSummary::default()
+        });
+        assert_eq!("Upgrade failed", failed.notification().1);
+
+        let reboot = Milestone::RebootRequired {
+            packages: vec!["linux-image-6.5.6-76060506-generic".into()],
+        };
+        assert_eq!(
+            "Restart to finish updating linux-image-6.5.6-76060506-generic",
+            reboot.notification().2
+        );
+
+        let args = Notifier::new("Updater").notify_args(&reboot);
+        assert_eq!("susssasa{sv}i", args[6]);
+        assert_eq!(
+            vec!["Updater", "0", "system-reboot", "Restart required"],
+            args[7..11].to_vec()
+        );
+        assert_eq!(vec!["0", "0", "-1"], args[12..].to_vec());
+    }
+}
@@ -0,0 +1,235 @@
+// Copyright 2021-2022 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+//! Runs the same operation across many targets, such as hosts reached with
+//! [`Backend::Ssh`](crate::Backend::Ssh), with bounded concurrency.
+//!
+//! ```no_run
+//! use apt_cmd::orchestrate::{self, Operation, Report, Target};
+//! use apt_cmd::{AptClient, Backend};
+//! use futures::StreamExt;
+//!
+//! # async fn example() {
+//! let targets = ["root@10.0.0.1", "root@10.0.0.2"].iter().map(|host| Target {
+//!     name: (*host).into(),
+//!     client: AptClient::new(Backend::Ssh { destination: (*host).into() }),
+//! });
+//!
+//! let mut events = orchestrate::run(targets, Operation::Update, 4);
+//! let mut report = Report::default();
+//!
+//! while let Some(event) = events.next().await {
+//!     report.update(&event);
+//! }
+//!
+//! println!("{} of {} targets failed", report.failed.len(), report.len());
+//! # }
+//! ```
+
+use crate::{AptClient, AptUpgradeEvent, Policy, UpdateEvent};
+use async_stream::stream;
+use futures::stream::{Stream, StreamExt};
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// A target to run an operation on.
+#[derive(Clone, Debug)]
+pub struct Target {
+    /// Identifies the target in events and reports.
+    pub name: Arc<str>,
+    pub client: AptClient,
+}
+
+/// The operation to run on each target.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Operation {
+    /// `apt-get update`
+    Update,
+    /// `apt-get full-upgrade`
+    Upgrade,
+    /// `apt-cache policy` for the given packages.
+    PolicyScan(Vec<String>),
+}
+
+/// An event from one target.
+#[derive(Debug)]
+pub struct TargetEvent {
+    pub target: Arc<str>,
+    pub event: HostEvent,
+}
+
+#[derive(Debug)]
+pub enum HostEvent {
+    Update(UpdateEvent),
+    Upgrade(AptUpgradeEvent),
+    Policy(Policy),
+    /// The operation completed on this target. This is the last event of the target.
+    Finished(io::Result<()>),
+}
+
+pub type TargetEvents = Pin<Box<dyn Stream<Item = TargetEvent> + Send>>;
+
+/// Runs `operation` on every target, with at most `concurrency` targets at a time.
+///
+/// Events of all targets are interleaved, and each target ends with [`HostEvent::Finished`].
+pub fn run<I>(targets: I, operation: Operation, concurrency: usize) -> TargetEvents
+where
+    I: IntoIterator<Item = Target>,
+{
+    let operation = Arc::new(operation);
+
+    let streams: Vec<_> = targets
+        .into_iter()
+        .map(|target| run_target(target, operation.clone()))
+        .collect();
+
+    Box::pin(futures::stream::iter(streams).flatten_unordered(concurrency.max(1)))
+}
+
+fn run_target(target: Target, operation: Arc<Operation>) -> TargetEvents {
+    let name = target.name;
+    let client = target.client;
+
+    let events = stream! {
+        let result = match &*operation {
+            Operation::Update => match client.apt_get().noninteractive().stream_update().await {
+                Ok(mut events) => {
+                    let mut result = Ok(());
+
+                    while let Some(event) = events.next().await {
+                        match event {
+                            UpdateEvent::ExitStatus(status) => {
+                                result = status.and_then(as_result::IntoResult::into_result);
+                            }
+                            event => yield HostEvent::Update(event),
+                        }
+                    }
+
+                    result
+                }
+                Err(why) => Err(why),
+            },
+
+            Operation::Upgrade => match client.apt_get().noninteractive().stream_upgrade().await {
+                Ok((mut child, mut events)) => {
+                    while let Some(event) = events.next().await {
+                        yield HostEvent::Upgrade(event);
+                    }
+
+                    child.wait().await.and_then(as_result::IntoResult::into_result)
+                }
+                Err(why) => Err(why),
+            },
+
+            Operation::PolicyScan(packages) => match client.apt_cache().policy(packages).await {
+                Ok((mut child, mut policies)) => {
+                    while let Some(policy) = policies.next().await {
+                        yield HostEvent::Policy(policy);
+                    }
+
+                    child.wait().await.and_then(as_result::IntoResult::into_result)
+                }
+                Err(why) => Err(io::Error::other(why)),
+            },
+        };
+
+        yield HostEvent::Finished(result);
+    };
+
+    Box::pin(events.map(move |event| TargetEvent {
+        target: name.clone(),
+        event,
+    }))
+}
+
+/// The aggregated outcome of an operation across every target.
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    pub succeeded: Vec<Arc<str>>,
+    /// Targets which failed, and the error of each.
+    pub failed: Vec<(Arc<str>, String)>,
+    /// Errors and warnings reported by apt on each target.
+    pub warnings: Vec<(Arc<str>, String)>,
+}
+
+impl Report {
+    /// The number of targets which finished.
+    pub fn len(&self) -> usize {
+        self.succeeded.len() + self.failed.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
+
+    /// Accumulates an event into the report.
+    pub fn update(&mut self, event: &TargetEvent) {
+        let target = &event.target;
+
+        match &event.event {
+            HostEvent::Finished(Ok(())) => self.succeeded.push(target.clone()),
+            HostEvent::Finished(Err(why)) => self.failed.push((target.clone(), why.to_string())),
+            HostEvent::Update(UpdateEvent::Warning(warning)) => {
+                self.warnings
+                    .push((target.clone(), warning.message.clone()));
+            }
+            HostEvent::Upgrade(AptUpgradeEvent::Error { message, .. })
+            | HostEvent::Upgrade(AptUpgradeEvent::Warning { message }) => {
+                self.warnings.push((target.clone(), message.to_string()));
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Runs `operation` on every target to completion, passing each event to `on_event`.
+pub async fn run_with_report<I, F>(
+    targets: I,
+    operation: Operation,
+    concurrency: usize,
+    mut on_event: F,
+) -> Report
+where
+    I: IntoIterator<Item = Target>,
+    F: FnMut(&TargetEvent),
+{
+    let mut events = run(targets, operation, concurrency);
+    let mut report = Report::default();
+
+    while let Some(event) = events.next().await {
+        report.update(&event);
+        on_event(&event);
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report() {
+        let event = |target: &str, event| TargetEvent {
+            target: target.into(),
+            event,
+        };
+
+        let mut report = Report::default();
+        report.update(&event("a", HostEvent::Finished(Ok(()))));
+        report.update(&event(
+            "b",
+            HostEvent::Finished(Err(io::Error::other("unreachable"))),
+        ));
+
+        assert_eq!(2, report.len());
+        assert!(!report.is_success());
+        assert_eq!(vec![Arc::<str>::from("a")], report.succeeded);
+        assert_eq!("unreachable", report.failed[0].1);
+    }
+}
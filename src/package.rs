@@ -0,0 +1,188 @@
+// Copyright 2021-2022 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+use std::ffi::OsStr;
+use std::io;
+use thiserror::Error;
+
+/// A package argument which is not a valid package name.
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+#[error("invalid package name: {0:?}")]
+pub struct InvalidPackageName(pub String);
+
+/// Checks that a package argument is a package name, rather than an option or garbage.
+///
+/// Names must follow Debian policy: lowercase letters, digits, `+`, `-`, and `.`, beginning
+/// with a letter or digit. The name may be followed by an architecture (`:amd64`), and
+/// either a version (`=1.0-1`) or a release (`/jammy-updates`), as accepted by apt.
+pub fn validate_package_name(package: &str) -> Result<(), InvalidPackageName> {
+    let (rest, suffix) = match package.find(['=', '/']) {
+        Some(pos) => package.split_at(pos),
+        None => (package, ""),
+    };
+
+    let (name, arch) = match rest.split_once(':') {
+        Some((name, arch)) => (name, Some(arch)),
+        None => (rest, None),
+    };
+
+    let name_valid = name.len() >= 2
+        && name.starts_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "+-.".contains(c));
+
+    let arch_valid = match arch {
+        Some(arch) => {
+            !arch.is_empty()
+                && arch
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        }
+        None => true,
+    };
+
+    let suffix_valid = suffix.len() != 1
+        && suffix
+            .chars()
+            .skip(1)
+            .all(|c| c.is_ascii_alphanumeric() || "+-.:~_".contains(c));
+
+    if name_valid && arch_valid && suffix_valid {
+        Ok(())
+    } else {
+        Err(InvalidPackageName(package.to_owned()))
+    }
+}
+
+/// The architecture qualifier of a package argument, as `i386` of `libc6:i386=2.35`.
+pub fn package_architecture(package: &str) -> Option<&str> {
+    let name = match package.find(['=', '/']) {
+        Some(pos) => &package[..pos],
+        None => package,
+    };
+
+    name.split_once(':').map(|(_, arch)| arch)
+}
+
+/// Qualifies a package argument with an architecture, unless it already has one.
+pub(crate) fn qualify_architecture(package: &str, arch: &str) -> String {
+    if package_architecture(package).is_some() {
+        return package.to_owned();
+    }
+
+    match package.find(['=', '/']) {
+        Some(pos) => [&package[..pos], ":", arch, &package[pos..]].concat(),
+        None => [package, ":", arch].concat(),
+    }
+}
+
+/// Whether a package argument is the path of a local `.deb` archive, as `./foo.deb` or
+/// `/tmp/foo.deb`, which apt installs in place of a package from the archive.
+///
+/// The path must be explicit, beginning with `/`, `./`, or `../`, so that it may never be
+/// mistaken for an option.
+pub(crate) fn is_deb_path(package: &str) -> bool {
+    (package.starts_with('/') || package.starts_with("./") || package.starts_with("../"))
+        && package.ends_with(".deb")
+        && !package.chars().any(char::is_control)
+}
+
+/// Validates each package, as an `InvalidInput` error for the wrappers.
+///
+/// Paths of local `.deb` archives are accepted, as by `is_deb_path`.
+pub(crate) fn validate_packages<I, S>(packages: I) -> io::Result<Vec<S>>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    packages
+        .into_iter()
+        .map(|package| {
+            let name = package.as_ref();
+            name.to_str()
+                .ok_or_else(|| InvalidPackageName(name.to_string_lossy().into_owned()))
+                .and_then(|name| match is_deb_path(name) {
+                    true => Ok(()),
+                    false => validate_package_name(name),
+                })
+                .map_err(|why| io::Error::new(io::ErrorKind::InvalidInput, why))?;
+
+            Ok(package)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        is_deb_path, package_architecture, qualify_architecture, validate_package_name,
+        validate_packages,
+    };
+
+    #[test]
+    fn package_names() {
+        for valid in &[
+            "firefox",
+            "libc6:i386",
+            "g++",
+            "linux-image-6.2.6-76060206-generic",
+            "firefox=119.0+build2-0ubuntu0.22.04.1~mt1",
+            "nvidia-driver-535/jammy-updates",
+            "libfoo1:amd64=1:2.0-1",
+        ] {
+            assert_eq!(Ok(()), validate_package_name(valid), "{}", valid);
+        }
+
+        for invalid in &[
+            "",
+            "a",
+            "-f",
+            "--purge",
+            "Firefox",
+            "foo bar",
+            "foo;rm",
+            "$(reboot)",
+            "foo:",
+            "foo=",
+            "foo/jammy;ls",
+        ] {
+            assert!(validate_package_name(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn deb_paths() {
+        for valid in &["./foo.deb", "/tmp/foo_1.0_amd64.deb", "../debs/foo.deb"] {
+            assert!(is_deb_path(valid), "{}", valid);
+        }
+
+        for invalid in &["foo.deb", "-foo.deb", "/tmp/foo.tar", "./foo\n.deb", ".deb"] {
+            assert!(!is_deb_path(invalid), "{}", invalid);
+        }
+
+        assert!(validate_packages(&["firefox", "./foo.deb"]).is_ok());
+        assert!(validate_packages(&["firefox", "--fix-broken.deb"]).is_err());
+    }
+
+    #[test]
+    fn architecture_qualifiers() {
+        assert_eq!(
+            Some("i386"),
+            package_architecture("libc6:i386=2.35-0ubuntu3")
+        );
+        assert_eq!(None, package_architecture("libfoo1=1:2.0-1"));
+        assert_eq!(None, package_architecture("firefox/jammy-updates"));
+
+        assert_eq!("libc6:i386", qualify_architecture("libc6", "i386"));
+        assert_eq!("libc6:amd64", qualify_architecture("libc6:amd64", "i386"));
+        assert_eq!(
+            "libfoo1:i386=1:2.0-1",
+            qualify_architecture("libfoo1=1:2.0-1", "i386")
+        );
+        assert_eq!(
+            "mesa:i386/jammy-updates",
+            qualify_architecture("mesa/jammy-updates", "i386")
+        );
+    }
+}
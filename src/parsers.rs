@@ -0,0 +1,186 @@
+// Copyright 2021-2022 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+//! Parsers for the output of apt and dpkg, decoupled from the processes which produce it.
+//!
+//! These accept lines from any source, such as a command run over ssh, inside of a
+//! container, or a recorded log, and yield the same events as the process wrappers.
+//! The stream parsers end after yielding the first read error.
+
+use crate::depends::{DependsBlock, DependsParser};
+use crate::dpkg::{PackageStatus, StatusError};
+use crate::{AptUpgradeEvent, Policy, PolicyParser, UpdateEvent, UpgradeParser};
+use async_stream::stream;
+use futures::stream::{Stream, StreamExt};
+use std::io;
+
+/// Parses the output of `apt-cache policy` into the policy of each package.
+pub fn policies<S>(lines: S) -> impl Stream<Item = io::Result<Policy>>
+where
+    S: Stream<Item = io::Result<String>>,
+{
+    stream! {
+        futures::pin_mut!(lines);
+        let mut parser = PolicyParser::default();
+
+        while let Some(line) = lines.next().await {
+            match line {
+                Ok(line) => {
+                    if let Some(policy) = parser.parse_line(&line) {
+                        yield Ok(policy);
+                    }
+                }
+                Err(why) => {
+                    yield Err(why);
+                    return;
+                }
+            }
+        }
+
+        yield Ok(parser.finish());
+    }
+}
+
+/// Parses the output of `apt-cache depends` into the dependencies of each package.
+pub fn depends<S>(lines: S) -> impl Stream<Item = io::Result<DependsBlock>>
+where
+    S: Stream<Item = io::Result<String>>,
+{
+    stream! {
+        futures::pin_mut!(lines);
+        let mut parser = DependsParser::default();
+
+        while let Some(line) = lines.next().await {
+            match line {
+                Ok(line) => {
+                    if let Some(block) = parser.parse_line(&line) {
+                        yield Ok(block);
+                    }
+                }
+                Err(why) => {
+                    yield Err(why);
+                    return;
+                }
+            }
+        }
+
+        if let Some(block) = parser.finish() {
+            yield Ok(block);
+        }
+    }
+}
+
+/// Parses the output of `apt-get --show-progress full-upgrade`, from stdout and stderr.
+pub fn upgrade_events<S>(lines: S) -> impl Stream<Item = io::Result<AptUpgradeEvent>>
+where
+    S: Stream<Item = io::Result<String>>,
+{
+    stream! {
+        futures::pin_mut!(lines);
+        let mut parser = UpgradeParser::default();
+
+        while let Some(line) = lines.next().await {
+            match line {
+                Ok(line) => {
+                    for event in parser.parse_line(&line) {
+                        yield Ok(event);
+                    }
+                }
+                Err(why) => {
+                    yield Err(why);
+                    return;
+                }
+            }
+        }
+
+        if let Some(event) = parser.finish() {
+            yield Ok(event);
+        }
+    }
+}
+
+/// Parses the output of `apt-get update`, from stdout and stderr.
+///
+/// [`UpdateEvent::ExitStatus`] is never yielded, as there is no process to wait on.
+pub fn update_events<S>(lines: S) -> impl Stream<Item = io::Result<UpdateEvent>>
+where
+    S: Stream<Item = io::Result<String>>,
+{
+    stream! {
+        futures::pin_mut!(lines);
+
+        while let Some(line) = lines.next().await {
+            match line {
+                Ok(line) => {
+                    if let Some(event) = update_event(&line) {
+                        yield Ok(event);
+                    }
+                }
+                Err(why) => {
+                    yield Err(why);
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Parses a line of `apt-get update` output.
+pub fn update_event(line: &str) -> Option<UpdateEvent> {
+    crate::apt_get::parse_update_line(line)
+}
+
+/// The package of an `Inst` line of `apt -s dist-upgrade`, if it is a security update.
+pub fn security_update(line: &str) -> Option<&str> {
+    crate::apt::parse_security_update(line)
+}
+
+/// Parses a line of `dpkg-query --show --showformat='${Package} ${db:Status-Status}\n'`.
+pub fn dpkg_status(line: &str) -> Result<(&str, PackageStatus), StatusError> {
+    crate::dpkg::parse_status_line(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    fn lines(input: &'static str) -> impl Stream<Item = io::Result<String>> {
+        futures::stream::iter(input.lines().map(|line| Ok(line.to_owned())))
+    }
+
+    #[test]
+    fn policies() {
+        let output = "hello:
+  Installed: (none)
+  Candidate: 2.10-2ubuntu4
+  Version table:
+     2.10-2ubuntu4 500
+        500 http://us.archive.ubuntu.com/ubuntu jammy/main amd64 Packages
+";
+
+        let policies: Vec<_> = block_on(super::policies(lines(output)).collect());
+
+        assert_eq!(1, policies.len());
+        assert_eq!(
+            "2.10-2ubuntu4",
+            policies[0].as_ref().unwrap().candidate.as_str()
+        );
+    }
+
+    #[test]
+    fn update_events_stop_at_error() {
+        let input = futures::stream::iter(vec![
+            Ok("Hit:1 http://apt.pop-os.org/release jammy InRelease".to_owned()),
+            Ok("Reading package lists...".to_owned()),
+            Err(io::Error::new(io::ErrorKind::UnexpectedEof, "closed")),
+            Ok("Fetched 1,234 kB in 2s (617 kB/s)".to_owned()),
+        ]);
+
+        let events: Vec<_> = block_on(update_events(input).collect());
+
+        assert_eq!(2, events.len());
+        assert!(matches!(events[0], Ok(UpdateEvent::Index(_))));
+        assert!(events[1].is_err());
+    }
+}
@@ -0,0 +1,82 @@
+// Copyright 2021-2022 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+//! Checks that the process may modify the system before a command is spawned.
+//!
+//! Without this, an unprivileged apt-get fails only after it has started, with a lock
+//! error which does not say that root was required. Callers which escalate privileges
+//! themselves, such as by wrapping the command with `pkexec`, may skip the check with
+//! `AptGet::skip_privilege_check`.
+
+use std::ffi::CString;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The process is not root, and cannot write to the dpkg lock.
+    #[error("`{required_by}` must be run as root")]
+    NotPrivileged { required_by: String },
+}
+
+impl From<Error> for io::Error {
+    fn from(why: Error) -> Self {
+        io::Error::new(io::ErrorKind::PermissionDenied, why)
+    }
+}
+
+/// Whether the process is root, or may otherwise write to the dpkg lock.
+pub fn is_privileged() -> bool {
+    is_privileged_for(Path::new(crate::dpkg::status::ADMINDIR))
+}
+
+/// Whether the process is root, or may otherwise write to the lock of the dpkg database
+/// in `admindir`, such as that of a chroot.
+pub fn is_privileged_for(admindir: &Path) -> bool {
+    // SAFETY: `geteuid` is always successful.
+    if unsafe { libc::geteuid() } == 0 {
+        return true;
+    }
+
+    let Ok(lock) = CString::new(admindir.join("lock").as_os_str().as_bytes()) else {
+        return false;
+    };
+
+    // The effective IDs are checked, as they are what dpkg will open the lock with.
+    // SAFETY: `lock` is a valid nul-terminated string for the duration of the call.
+    unsafe { libc::faccessat(libc::AT_FDCWD, lock.as_ptr(), libc::W_OK, libc::AT_EACCESS) == 0 }
+}
+
+/// Fails with [`Error::NotPrivileged`] if the process may not modify the system.
+pub fn ensure_privileged(required_by: &str) -> Result<(), Error> {
+    ensure_privileged_for(required_by, Path::new(crate::dpkg::status::ADMINDIR))
+}
+
+/// Fails with [`Error::NotPrivileged`] if the process may not modify the system whose
+/// dpkg database is in `admindir`.
+pub fn ensure_privileged_for(required_by: &str, admindir: &Path) -> Result<(), Error> {
+    if is_privileged_for(admindir) {
+        Ok(())
+    } else {
+        Err(Error::NotPrivileged {
+            required_by: required_by.to_owned(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn not_privileged_is_permission_denied() {
+        let why = io::Error::from(Error::NotPrivileged {
+            required_by: "apt-get install".into(),
+        });
+
+        assert_eq!(io::ErrorKind::PermissionDenied, why.kind());
+        assert_eq!("`apt-get install` must be run as root", why.to_string());
+    }
+}
@@ -0,0 +1,191 @@
+// Copyright 2021-2022 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+//! Combines the download, unpack, and configure phases of an upgrade into one percentage.
+//!
+//! apt's own `Progress` lines only cover dpkg's work, and jump unevenly as packages
+//! of varying sizes are processed. This model weighs each phase by the share of the
+//! upgrade it is expected to take, and never reports a lower percentage than before.
+
+use crate::AptUpgradeEvent;
+use futures::stream::{Stream, StreamExt};
+
+/// How much of the overall progress each phase accounts for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PhaseWeights {
+    pub download: f64,
+    pub unpack: f64,
+    pub configure: f64,
+}
+
+impl Default for PhaseWeights {
+    fn default() -> Self {
+        Self {
+            download: 0.4,
+            unpack: 0.3,
+            configure: 0.3,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ProgressModel {
+    weights: PhaseWeights,
+    total_bytes: u64,
+    total_packages: usize,
+    downloaded: u64,
+    unpacked: usize,
+    configured: usize,
+    percent: u8,
+}
+
+impl ProgressModel {
+    /// Creates a model for an upgrade which downloads `total_bytes` and changes `total_packages`.
+    pub fn new(total_bytes: u64, total_packages: usize) -> Self {
+        Self {
+            weights: PhaseWeights::default(),
+            total_bytes,
+            total_packages,
+            downloaded: 0,
+            unpacked: 0,
+            configured: 0,
+            percent: 0,
+        }
+    }
+
+    pub fn weights(mut self, weights: PhaseWeights) -> Self {
+        self.weights = weights;
+        self
+    }
+
+    /// The most recently calculated percentage.
+    pub fn percent(&self) -> u8 {
+        self.percent
+    }
+
+    /// Records bytes downloaded by a fetcher, and returns the overall percentage.
+    pub fn downloaded(&mut self, bytes: u64) -> u8 {
+        self.downloaded = self.downloaded.saturating_add(bytes);
+        self.recalculate()
+    }
+
+    /// Records an event from the upgrade, and returns the overall percentage.
+    pub fn update(&mut self, event: &AptUpgradeEvent) -> u8 {
+        match event {
+            // apt reports the total once all downloads have finished.
+            AptUpgradeEvent::Fetched { .. } => self.downloaded = self.total_bytes,
+            AptUpgradeEvent::Unpacking { .. }
+            | AptUpgradeEvent::Installing { .. }
+            | AptUpgradeEvent::Removing { .. } => {
+                self.downloaded = self.total_bytes;
+                self.unpacked += 1;
+            }
+            AptUpgradeEvent::SettingUp { .. } => self.configured += 1,
+            _ => (),
+        }
+
+        self.recalculate()
+    }
+
+    fn recalculate(&mut self) -> u8 {
+        let weights = self.weights;
+        let total_weight = weights.download + weights.unpack + weights.configure;
+        if total_weight <= 0.0 {
+            return self.percent;
+        }
+
+        let download = fraction(self.downloaded as f64, self.total_bytes as f64);
+        let unpack = fraction(self.unpacked as f64, self.total_packages as f64);
+        let configure = fraction(self.configured as f64, self.total_packages as f64);
+
+        let overall =
+            (download * weights.download + unpack * weights.unpack + configure * weights.configure)
+                / total_weight;
+
+        let percent = (overall * 100.0).floor() as u8;
+        self.percent = self.percent.max(percent.min(100));
+        self.percent
+    }
+}
+
+fn fraction(done: f64, total: f64) -> f64 {
+    if total <= 0.0 {
+        1.0
+    } else {
+        (done / total).min(1.0)
+    }
+}
+
+/// Maps a stream of upgrade events into the overall percentage, yielding only when it changes.
+pub fn smoothed(
+    mut model: ProgressModel,
+    events: impl Stream<Item = AptUpgradeEvent>,
+) -> impl Stream<Item = u8> {
+    let mut last = None;
+    events.filter_map(move |event| {
+        let percent = model.update(&event);
+        let changed = last != Some(percent);
+        last = Some(percent);
+        futures::future::ready(if changed { Some(percent) } else { None })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setting_up(package: &str) -> AptUpgradeEvent {
+        AptUpgradeEvent::SettingUp {
+            package: package.into(),
+        }
+    }
+
+    fn installing(package: &str) -> AptUpgradeEvent {
+        AptUpgradeEvent::Installing {
+            package: package.into(),
+            version: "1.0".into(),
+        }
+    }
+
+    #[test]
+    fn weighs_each_phase() {
+        let mut model = ProgressModel::new(1000, 2);
+
+        assert_eq!(20, model.downloaded(500));
+        assert_eq!(40, model.downloaded(500));
+        assert_eq!(55, model.update(&installing("foo")));
+        assert_eq!(70, model.update(&installing("bar")));
+        assert_eq!(85, model.update(&setting_up("foo")));
+        assert_eq!(100, model.update(&setting_up("bar")));
+    }
+
+    #[test]
+    fn never_decreases() {
+        let mut model = ProgressModel::new(0, 1).weights(PhaseWeights {
+            download: 0.0,
+            unpack: 0.0,
+            configure: 1.0,
+        });
+
+        assert_eq!(100, model.update(&setting_up("foo")));
+        assert_eq!(100, model.update(&setting_up("bar")));
+        assert_eq!(100, model.update(&AptUpgradeEvent::WaitingOnLock));
+    }
+
+    #[test]
+    fn smoothed_yields_changes() {
+        let events = futures::stream::iter(vec![
+            AptUpgradeEvent::WaitingOnLock,
+            AptUpgradeEvent::Fetched { bytes: 10 },
+            AptUpgradeEvent::WaitingOnLock,
+            installing("foo"),
+            setting_up("foo"),
+        ]);
+
+        let percentages = futures::executor::block_on(
+            smoothed(ProgressModel::new(10, 1), events).collect::<Vec<_>>(),
+        );
+
+        assert_eq!(vec![0, 40, 70, 100], percentages);
+    }
+}
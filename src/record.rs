@@ -0,0 +1,319 @@
+// Copyright 2021-2022 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+use crate::deb822::{Reader, Stanza};
+use crate::request::RequestChecksum;
+
+/// A package record, as printed by `apt-cache show`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct PackageRecord {
+    pub package: String,
+    pub version: String,
+    pub architecture: String,
+    pub section: Option<String>,
+    pub priority: Option<String>,
+    pub source: Option<String>,
+    pub maintainer: Option<String>,
+    /// The installed size, in kibibytes.
+    pub installed_size: Option<u64>,
+    /// The size of the archive, in bytes.
+    pub size: Option<u64>,
+    /// The path of the archive, relative to the root of its repository.
+    pub filename: Option<String>,
+    pub homepage: Option<String>,
+    /// Each relation of these fields, such as `libc6 (>= 2.34)` or `awk | mawk`.
+    pub depends: Vec<String>,
+    pub pre_depends: Vec<String>,
+    pub recommends: Vec<String>,
+    pub suggests: Vec<String>,
+    pub conflicts: Vec<String>,
+    pub breaks: Vec<String>,
+    pub provides: Vec<String>,
+    /// The full description: the summary, followed by the extended description on
+    /// continuation lines.
+    pub description: String,
+    /// Every field of the record, including those above.
+    pub stanza: Stanza,
+}
+
+impl PackageRecord {
+    /// Creates a record from a stanza, if it names a package.
+    pub fn from_stanza(stanza: Stanza) -> Option<Self> {
+        let field = |name: &str| stanza.get(name).map(String::from);
+        let relations = |name: &str| stanza.get_relations(name);
+        let number = |name: &str| stanza.get(name).and_then(|value| value.parse().ok());
+
+        Some(Self {
+            package: field("Package")?,
+            version: field("Version").unwrap_or_default(),
+            architecture: field("Architecture").unwrap_or_default(),
+            section: field("Section"),
+            priority: field("Priority"),
+            source: field("Source"),
+            maintainer: field("Maintainer"),
+            installed_size: number("Installed-Size"),
+            size: number("Size"),
+            filename: field("Filename"),
+            homepage: field("Homepage"),
+            depends: relations("Depends"),
+            pre_depends: relations("Pre-Depends"),
+            recommends: relations("Recommends"),
+            suggests: relations("Suggests"),
+            conflicts: relations("Conflicts"),
+            breaks: relations("Breaks"),
+            provides: relations("Provides"),
+            // Without translations, apt labels the description by its language.
+            description: field("Description")
+                .or_else(|| field("Description-en"))
+                .unwrap_or_default(),
+            stanza,
+        })
+    }
+
+    /// The first line of the description.
+    pub fn summary(&self) -> &str {
+        self.description.lines().next().unwrap_or("")
+    }
+}
+
+/// Parses the output of `apt-cache show` one line at a time.
+#[derive(Debug, Default)]
+pub struct RecordParser {
+    reader: Reader,
+}
+
+impl RecordParser {
+    /// Returns a record once the blank line which ends it is read.
+    pub fn parse_line(&mut self, line: &str) -> Option<PackageRecord> {
+        self.reader
+            .parse_line(line)
+            .and_then(PackageRecord::from_stanza)
+    }
+
+    /// The last record, if the output did not end with a blank line.
+    pub fn finish(self) -> Option<PackageRecord> {
+        self.reader.finish().and_then(PackageRecord::from_stanza)
+    }
+}
+
+/// A source package record, as printed by `apt-cache showsrc`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SourceRecord {
+    /// The name of the source package.
+    pub package: String,
+    pub version: String,
+    /// The binary packages built from the source package.
+    pub binaries: Vec<String>,
+    pub build_depends: Vec<String>,
+    pub build_depends_indep: Vec<String>,
+    pub build_depends_arch: Vec<String>,
+    /// The directory of the files, relative to the root of the repository.
+    pub directory: Option<String>,
+    pub files: Vec<SourceFile>,
+    /// Every field of the record, including those above.
+    pub stanza: Stanza,
+}
+
+/// A file of a source package, such as its `.dsc` or `.orig.tar.gz`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SourceFile {
+    pub name: String,
+    pub size: u64,
+    /// Each checksum of the file, from the weakest to the strongest.
+    pub checksums: Vec<RequestChecksum>,
+}
+
+impl SourceRecord {
+    /// Creates a record from a stanza, if it names a package.
+    pub fn from_stanza(stanza: Stanza) -> Option<Self> {
+        let field = |name: &str| stanza.get(name).map(String::from);
+        let relations = |name: &str| stanza.get_relations(name);
+
+        let mut files: Vec<SourceFile> = Vec::new();
+
+        for name in &[
+            "Files",
+            "Checksums-Sha1",
+            "Checksums-Sha256",
+            "Checksums-Sha512",
+        ] {
+            for line in stanza.get_lines(name) {
+                let mut columns = line.split_ascii_whitespace();
+                let (Some(sum), Some(size), Some(file)) =
+                    (columns.next(), columns.next(), columns.next())
+                else {
+                    continue;
+                };
+
+                let Ok(size) = size.parse() else {
+                    continue;
+                };
+
+                let sum = sum.to_owned();
+                let checksum = match *name {
+                    "Files" => RequestChecksum::Md5(sum),
+                    "Checksums-Sha1" => RequestChecksum::Sha1(sum),
+                    "Checksums-Sha256" => RequestChecksum::Sha256(sum),
+                    _ => RequestChecksum::Sha512(sum),
+                };
+
+                match files.iter_mut().find(|existing| existing.name == file) {
+                    Some(existing) => existing.checksums.push(checksum),
+                    None => files.push(SourceFile {
+                        name: file.to_owned(),
+                        size,
+                        checksums: vec![checksum],
+                    }),
+                }
+            }
+        }
+
+        Some(Self {
+            package: field("Package")?,
+            version: field("Version").unwrap_or_default(),
+            binaries: relations("Binary"),
+            build_depends: relations("Build-Depends"),
+            build_depends_indep: relations("Build-Depends-Indep"),
+            build_depends_arch: relations("Build-Depends-Arch"),
+            directory: field("Directory"),
+            files,
+            stanza,
+        })
+    }
+}
+
+/// Parses the output of `apt-cache showsrc` one line at a time.
+#[derive(Debug, Default)]
+pub struct SourceRecordParser {
+    reader: Reader,
+}
+
+impl SourceRecordParser {
+    /// Returns a record once the blank line which ends it is read.
+    pub fn parse_line(&mut self, line: &str) -> Option<SourceRecord> {
+        self.reader
+            .parse_line(line)
+            .and_then(SourceRecord::from_stanza)
+    }
+
+    /// The last record, if the output did not end with a blank line.
+    pub fn finish(self) -> Option<SourceRecord> {
+        self.reader.finish().and_then(SourceRecord::from_stanza)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHOW: &str = "Package: hello
+Architecture: amd64
+Version: 2.10-2ubuntu4
+Priority: optional
+Section: devel
+Origin: Ubuntu
+Maintainer: Ubuntu Developers <ubuntu-devel-discuss@lists.ubuntu.com>
+Installed-Size: 109
+Depends: libc6 (>= 2.34)
+Conflicts: hello-traditional
+Breaks: hello-debhelper (<< 2.9)
+Filename: pool/main/h/hello/hello_2.10-2ubuntu4_amd64.deb
+Size: 28196
+Description-en: example package based on GNU hello
+ The GNU hello program produces a familiar, friendly greeting.
+Description-md5: 19f3d3a1e6d2d0a3c3ba6cbd2ce6b2ba
+
+Package: coreutils
+Architecture: amd64
+Version: 8.32-4.1ubuntu1
+Pre-Depends: libacl1 (>= 2.2.23), libattr1 (>= 1:2.4.44), libc6 (>= 2.34)
+Depends: awk | mawk
+Description: GNU core utilities
+";
+
+    #[test]
+    fn show_records() {
+        let mut parser = RecordParser::default();
+        let mut records: Vec<_> = SHOW
+            .lines()
+            .filter_map(|line| parser.parse_line(line))
+            .collect();
+        records.extend(parser.finish());
+
+        assert_eq!(2, records.len());
+
+        let hello = &records[0];
+        assert_eq!("hello", hello.package);
+        assert_eq!("2.10-2ubuntu4", hello.version);
+        assert_eq!("amd64", hello.architecture);
+        assert_eq!(Some("devel"), hello.section.as_deref());
+        assert_eq!(Some(109), hello.installed_size);
+        assert_eq!(Some(28196), hello.size);
+        assert_eq!(vec!["libc6 (>= 2.34)"], hello.depends);
+        assert_eq!(vec!["hello-debhelper (<< 2.9)"], hello.breaks);
+        assert_eq!("example package based on GNU hello", hello.summary());
+        assert_eq!(Some("Ubuntu"), hello.stanza.get("Origin"));
+
+        let coreutils = &records[1];
+        assert_eq!(3, coreutils.pre_depends.len());
+        assert_eq!(vec!["awk | mawk"], coreutils.depends);
+        assert_eq!("GNU core utilities", coreutils.description);
+        assert_eq!(None, coreutils.installed_size);
+    }
+
+    #[test]
+    fn showsrc_records() {
+        let showsrc = "Package: hello
+Format: 3.0 (quilt)
+Binary: hello
+Version: 2.10-2ubuntu4
+Build-Depends: debhelper-compat (= 13),
+ help2man,
+ texinfo
+Directory: pool/main/h/hello
+Files:
+ 6cd0ffea3884a4e79330338dcc2987d6 725946 hello_2.10.orig.tar.gz
+ 2d2c5d7dac3a4b1e7bc8f6f53bd35b6c 12688 hello_2.10-2ubuntu4.debian.tar.xz
+Checksums-Sha256:
+ 31e066137a962676e89f69d1b65382de95a7ef7d914b8cb956f41ea72e0f516b 725946 hello_2.10.orig.tar.gz
+
+Package: hello-traditional
+Binary: hello-traditional
+Version: 2.10-5
+";
+
+        let mut parser = SourceRecordParser::default();
+        let mut records: Vec<_> = showsrc
+            .lines()
+            .filter_map(|line| parser.parse_line(line))
+            .collect();
+        records.extend(parser.finish());
+
+        assert_eq!(2, records.len());
+
+        let hello = &records[0];
+        assert_eq!("hello", hello.package);
+        assert_eq!(vec!["hello"], hello.binaries);
+        assert_eq!(
+            vec!["debhelper-compat (= 13)", "help2man", "texinfo"],
+            hello.build_depends
+        );
+        assert_eq!(Some("pool/main/h/hello"), hello.directory.as_deref());
+
+        assert_eq!(2, hello.files.len());
+        assert_eq!("hello_2.10.orig.tar.gz", hello.files[0].name);
+        assert_eq!(725946, hello.files[0].size);
+        assert_eq!(
+            vec![
+                RequestChecksum::Md5("6cd0ffea3884a4e79330338dcc2987d6".into()),
+                RequestChecksum::Sha256(
+                    "31e066137a962676e89f69d1b65382de95a7ef7d914b8cb956f41ea72e0f516b".into()
+                ),
+            ],
+            hello.files[0].checksums
+        );
+
+        assert_eq!("2.10-5", records[1].version);
+        assert!(records[1].files.is_empty());
+    }
+}
@@ -2,6 +2,7 @@
 // SPDX-License-Identifier: MPL-2.0
 
 use std::{
+    fmt,
     hash::{Hash, Hasher},
     io,
     str::FromStr,
@@ -28,10 +29,62 @@ pub enum RequestError {
     UnknownChecksum(String),
 }
 
+/// The checksum of a requested file. Algorithms may be added as apt adopts them.
 #[derive(Debug, Clone, Eq, PartialEq)]
+#[non_exhaustive]
 pub enum RequestChecksum {
     Md5(String),
     Sha1(String),
+    Sha256(String),
+    Sha512(String),
+}
+
+impl RequestChecksum {
+    pub fn algorithm(&self) -> ChecksumAlgorithm {
+        match self {
+            RequestChecksum::Md5(_) => ChecksumAlgorithm::Md5,
+            RequestChecksum::Sha1(_) => ChecksumAlgorithm::Sha1,
+            RequestChecksum::Sha256(_) => ChecksumAlgorithm::Sha256,
+            RequestChecksum::Sha512(_) => ChecksumAlgorithm::Sha512,
+        }
+    }
+
+    /// The hex digest, without the algorithm.
+    pub fn sum(&self) -> &str {
+        match self {
+            RequestChecksum::Md5(sum)
+            | RequestChecksum::Sha1(sum)
+            | RequestChecksum::Sha256(sum)
+            | RequestChecksum::Sha512(sum) => sum,
+        }
+    }
+}
+
+/// A checksum algorithm, ordered from weakest to strongest.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum ChecksumAlgorithm {
+    Md5,
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl ChecksumAlgorithm {
+    /// The name of the algorithm in `apt-get --print-uris` output, such as `SHA256`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Md5 => "MD5Sum",
+            ChecksumAlgorithm::Sha1 => "SHA1",
+            ChecksumAlgorithm::Sha256 => "SHA256",
+            ChecksumAlgorithm::Sha512 => "SHA512",
+        }
+    }
+}
+
+impl fmt::Display for ChecksumAlgorithm {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(self.as_str())
+    }
 }
 
 #[derive(Debug, Clone, Eq)]
@@ -42,6 +95,26 @@ pub struct Request {
     pub checksum: RequestChecksum,
 }
 
+impl Request {
+    /// Whether the package is provided by media registered with `apt-cdrom`,
+    /// rather than by a network repository.
+    pub fn is_cdrom(&self) -> bool {
+        self.uri.starts_with("cdrom:")
+    }
+
+    /// The architecture of the package, from its file name, as `i386` of
+    /// `libc6_2.35-0ubuntu3_i386.deb`.
+    pub fn architecture(&self) -> Option<&str> {
+        let stem = self
+            .name
+            .strip_suffix(".deb")
+            .or_else(|| self.name.strip_suffix(".udeb"))
+            .or_else(|| self.name.strip_suffix(".ddeb"))?;
+
+        stem.rsplit_once('_').map(|(_, arch)| arch)
+    }
+}
+
 impl PartialEq for Request {
     fn eq(&self, other: &Self) -> bool {
         self.uri == other.uri
@@ -89,6 +162,10 @@ impl FromStr for Request {
             RequestChecksum::Md5(value.to_owned())
         } else if let Some(value) = checksum_string.strip_prefix("SHA1:") {
             RequestChecksum::Sha1(value.to_owned())
+        } else if let Some(value) = checksum_string.strip_prefix("SHA256:") {
+            RequestChecksum::Sha256(value.to_owned())
+        } else if let Some(value) = checksum_string.strip_prefix("SHA512:") {
+            RequestChecksum::Sha512(value.to_owned())
         } else {
             return Err(RequestError::UnknownChecksum(checksum_string.into()));
         };
@@ -101,3 +178,30 @@ impl FromStr for Request {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{ChecksumAlgorithm, Request, RequestChecksum};
+
+    #[test]
+    fn cdrom_request() {
+        let request: Request = "'cdrom:[Pop_OS%2022.04%20_Jammy%20Jellyfish_]/pool/main/h/hello/hello_2.10-2_amd64.deb' hello_2.10-2_amd64.deb 56000 MD5Sum:6b2a2ad3a8e3a0b0e0b57d6e0d7a9c1b"
+            .parse()
+            .unwrap();
+
+        assert!(request.is_cdrom());
+        assert_eq!("hello_2.10-2_amd64.deb", request.name);
+        assert_eq!(Some("amd64"), request.architecture());
+    }
+
+    #[test]
+    fn sha512_request() {
+        let request: Request = "'http://archive.ubuntu.com/ubuntu/pool/main/h/hello/hello_2.10-2_amd64.deb' hello_2.10-2_amd64.deb 56000 SHA512:0f3b"
+            .parse()
+            .unwrap();
+
+        assert_eq!(RequestChecksum::Sha512("0f3b".into()), request.checksum);
+        assert_eq!(ChecksumAlgorithm::Sha512, request.checksum.algorithm());
+        assert!(ChecksumAlgorithm::Sha1 < ChecksumAlgorithm::Sha256);
+    }
+}
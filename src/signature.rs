@@ -0,0 +1,150 @@
+// Copyright 2021-2022 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+#[cfg(feature = "native-signatures")]
+mod native;
+
+use std::io;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use thiserror::Error;
+
+/// The tool used to verify the signature embedded in a fetched `.deb` archive.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SignatureVerifier {
+    /// Verify against the policies in `/etc/debsig/policies` with `debsig-verify`.
+    DebsigVerify,
+
+    /// Verify with `dpkg-sig --verify`.
+    DpkgSig,
+
+    /// Verify the origin signature which debsigs embeds, against the keyrings named by
+    /// the policies in `/etc/debsig/policies`, as `debsig-verify` does, but in-process.
+    #[cfg(feature = "native-signatures")]
+    Native,
+}
+
+#[derive(Debug, Error)]
+pub enum SignatureError {
+    #[error("failed to launch `{0}`")]
+    Spawn(&'static str, #[source] io::Error),
+
+    #[error("failed to read the package or its keyrings")]
+    Read(#[source] io::Error),
+
+    #[error("package is not signed")]
+    NoSignatures,
+
+    #[error("package was signed by an unknown origin")]
+    UnknownOrigin,
+
+    #[error("no debsig policies were found for the package's signer")]
+    NoPolicies,
+
+    #[error("package has a bad signature")]
+    BadSignature,
+
+    #[error("`{0}` exited with an unexpected status: {1:?}")]
+    Status(&'static str, Option<i32>),
+}
+
+impl SignatureVerifier {
+    /// Verifies the signature of the archive at `path`.
+    ///
+    /// This blocks until the verifier exits, and is meant to be called from a worker thread.
+    pub fn verify(self, path: &Path) -> Result<(), SignatureError> {
+        let program = match self {
+            SignatureVerifier::DebsigVerify => "debsig-verify",
+            SignatureVerifier::DpkgSig => "dpkg-sig",
+            #[cfg(feature = "native-signatures")]
+            SignatureVerifier::Native => {
+                let (policies, keyrings) = (native::POLICIES, native::KEYRINGS);
+                return native::verify(path, policies.as_ref(), keyrings.as_ref());
+            }
+        };
+
+        let mut command = Command::new(program);
+
+        if let SignatureVerifier::DpkgSig = self {
+            command.arg("--verify");
+        }
+
+        let output = command
+            .arg(path)
+            .env("LANG", "C")
+            .env("LC_ALL", "C")
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .map_err(|why| SignatureError::Spawn(program, why))?;
+
+        let code = output.status.code();
+
+        // dpkg-sig exits successfully for some unsigned packages and unknown signers,
+        // so its verdict is read from its output instead.
+        if let SignatureVerifier::DpkgSig = self {
+            return match parse_dpkg_sig(&String::from_utf8_lossy(&output.stdout)) {
+                Some(result) => result,
+                None => Err(SignatureError::Status(program, code)),
+            };
+        }
+
+        match code {
+            Some(0) => Ok(()),
+            Some(10) => Err(SignatureError::NoSignatures),
+            Some(11) => Err(SignatureError::UnknownOrigin),
+            Some(12) => Err(SignatureError::NoPolicies),
+            Some(13) => Err(SignatureError::BadSignature),
+            code => Err(SignatureError::Status(program, code)),
+        }
+    }
+}
+
+/// The verdict of `dpkg-sig --verify`, if it gave one. Every signature must be good.
+fn parse_dpkg_sig(output: &str) -> Option<Result<(), SignatureError>> {
+    let mut verdict = None;
+
+    for line in output.lines() {
+        match line.split_ascii_whitespace().next() {
+            Some("BADSIG") => return Some(Err(SignatureError::BadSignature)),
+            Some("UNKNOWNSIG") => return Some(Err(SignatureError::UnknownOrigin)),
+            Some("NOSIG") => return Some(Err(SignatureError::NoSignatures)),
+            Some("GOODSIG") => verdict = Some(Ok(())),
+            _ => (),
+        }
+    }
+
+    verdict
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_dpkg_sig, SignatureError};
+
+    #[test]
+    fn dpkg_sig_verdicts() {
+        let good = "Processing foo_1.0_amd64.deb...\n\
+            GOODSIG _gpgbuilder 63C46DF0140D738961429F4E204DD8AEC33A7AFF 1666666666\n";
+        assert!(matches!(parse_dpkg_sig(good), Some(Ok(()))));
+
+        let bad = [good, "BADSIG _gpgbuilder\n"].concat();
+        assert!(matches!(
+            parse_dpkg_sig(&bad),
+            Some(Err(SignatureError::BadSignature))
+        ));
+
+        let unknown = "Processing foo_1.0_amd64.deb...\nUNKNOWNSIG _gpgbuilder 204DD8AEC33A7AFF\n";
+        assert!(matches!(
+            parse_dpkg_sig(unknown),
+            Some(Err(SignatureError::UnknownOrigin))
+        ));
+
+        let unsigned = "Processing foo_1.0_amd64.deb...\nNOSIG\n";
+        assert!(matches!(
+            parse_dpkg_sig(unsigned),
+            Some(Err(SignatureError::NoSignatures))
+        ));
+
+        assert!(parse_dpkg_sig("Processing foo_1.0_amd64.deb...\n").is_none());
+    }
+}
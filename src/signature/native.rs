@@ -0,0 +1,236 @@
+// Copyright 2021-2022 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+//! Verifies the `_gpgorigin` signature which debsigs embeds in a `.deb` archive, as
+//! debsig-verify does, without spawning it.
+
+use super::SignatureError;
+use pgp::types::{KeyId, PublicKeyTrait};
+use pgp::{Deserializable, SignedPublicKey, StandaloneSignature};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Where debsig-verify reads policies from, in a directory for each signer's key ID.
+pub const POLICIES: &str = "/etc/debsig/policies";
+
+/// Where the keyrings named by the policies are, in a directory for each signer's key ID.
+pub const KEYRINGS: &str = "/usr/share/debsig/keyrings";
+
+/// Verifies the archive at `path` against the keyrings which the policies for its
+/// signer name.
+pub fn verify(path: &Path, policies: &Path, keyrings: &Path) -> Result<(), SignatureError> {
+    let archive = fs::read(path).map_err(SignatureError::Read)?;
+    let members = ar_members(&archive).ok_or(SignatureError::BadSignature)?;
+
+    // The signature is of the concatenation of the other members, in archive order.
+    let mut signature = None;
+    let mut signed = Vec::new();
+
+    for (name, data) in members {
+        if name == "_gpgorigin" {
+            signature = Some(data);
+        } else if !name.starts_with("_gpg") {
+            signed.extend_from_slice(data);
+        }
+    }
+
+    let signature = signature.ok_or(SignatureError::NoSignatures)?;
+    let signature = parse_signature(signature).ok_or(SignatureError::BadSignature)?;
+    let issuer = signature
+        .signature
+        .issuer()
+        .ok_or(SignatureError::UnknownOrigin)?;
+
+    let id = hex::encode_upper(issuer.as_ref());
+    let names = match policy_keyrings(&policies.join(&id)) {
+        Ok(names) if !names.is_empty() => names,
+        Ok(_) => return Err(SignatureError::NoPolicies),
+        Err(why) if why.kind() == io::ErrorKind::NotFound => {
+            return Err(SignatureError::NoPolicies)
+        }
+        Err(why) => return Err(SignatureError::Read(why)),
+    };
+
+    let mut known = false;
+
+    for name in names {
+        let keyring = match fs::read(keyrings.join(&id).join(&name)) {
+            Ok(keyring) => keyring,
+            Err(why) if why.kind() == io::ErrorKind::NotFound => continue,
+            Err(why) => return Err(SignatureError::Read(why)),
+        };
+
+        for key in SignedPublicKey::from_bytes_many(keyring.as_slice()).flatten() {
+            let verdicts = std::iter::once(check(&signature, &key, issuer, &signed))
+                .chain(
+                    key.public_subkeys
+                        .iter()
+                        .map(|subkey| check(&signature, subkey, issuer, &signed)),
+                )
+                .flatten();
+
+            for verified in verdicts {
+                if verified {
+                    return Ok(());
+                }
+
+                known = true;
+            }
+        }
+    }
+
+    Err(if known {
+        SignatureError::BadSignature
+    } else {
+        SignatureError::UnknownOrigin
+    })
+}
+
+/// Whether the signature was made by `key`, or `None` if `key` is not its issuer.
+fn check(
+    signature: &StandaloneSignature,
+    key: &impl PublicKeyTrait,
+    issuer: &KeyId,
+    signed: &[u8],
+) -> Option<bool> {
+    if key.key_id() != *issuer {
+        return None;
+    }
+
+    Some(signature.verify(key, signed).is_ok())
+}
+
+/// Parses a detached signature, which may be armored.
+fn parse_signature(signature: &[u8]) -> Option<StandaloneSignature> {
+    if signature.starts_with(b"-----BEGIN") {
+        let armored = std::str::from_utf8(signature).ok()?;
+        return StandaloneSignature::from_string(armored)
+            .ok()
+            .map(|(signature, _)| signature);
+    }
+
+    StandaloneSignature::from_bytes(signature).ok()
+}
+
+/// The keyrings named by the `File` attributes of the policies in `directory`.
+fn policy_keyrings(directory: &Path) -> io::Result<Vec<String>> {
+    let mut keyrings = Vec::new();
+
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.extension() != Some("pol".as_ref()) {
+            continue;
+        }
+
+        let policy = fs::read_to_string(&path)?;
+        for attribute in policy.split("File=\"").skip(1) {
+            if let Some((name, _)) = attribute.split_once('"') {
+                // Keyrings are only read from the signer's directory.
+                if !name.is_empty() && !name.contains('/') {
+                    keyrings.push(name.to_owned());
+                }
+            }
+        }
+    }
+
+    keyrings.sort_unstable();
+    keyrings.dedup();
+    Ok(keyrings)
+}
+
+/// The members of an ar archive, such as a `.deb`, by name, or `None` if it is malformed.
+fn ar_members(archive: &[u8]) -> Option<Vec<(&str, &[u8])>> {
+    let mut rest = archive.strip_prefix(b"!<arch>\n")?;
+    let mut members = Vec::new();
+
+    while !rest.is_empty() {
+        if rest.len() < 60 || &rest[58..60] != b"`\n" {
+            return None;
+        }
+
+        let (header, body) = rest.split_at(60);
+        let name = std::str::from_utf8(&header[..16]).ok()?.trim_end();
+        let size: usize = std::str::from_utf8(&header[48..58])
+            .ok()?
+            .trim()
+            .parse()
+            .ok()?;
+
+        members.push((name.trim_end_matches('/'), body.get(..size)?));
+
+        // Members are aligned to even offsets.
+        rest = body.get(size + size % 2..).unwrap_or_default();
+    }
+
+    Some(members)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ar_members, policy_keyrings};
+
+    fn member(name: &str, data: &str) -> String {
+        let padding = if data.len() % 2 == 1 { "\n" } else { "" };
+        format!(
+            "{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n{}{}",
+            name,
+            0,
+            0,
+            0,
+            100644,
+            data.len(),
+            data,
+            padding
+        )
+    }
+
+    #[test]
+    fn deb_members() {
+        let archive = [
+            "!<arch>\n",
+            &member("debian-binary", "2.0\n"),
+            &member("control.tar.xz", "control"),
+            &member("data.tar.xz/", "data"),
+            &member("_gpgorigin", "signature"),
+        ]
+        .concat();
+
+        assert_eq!(
+            Some(vec![
+                ("debian-binary", &b"2.0\n"[..]),
+                ("control.tar.xz", &b"control"[..]),
+                ("data.tar.xz", &b"data"[..]),
+                ("_gpgorigin", &b"signature"[..]),
+            ]),
+            ar_members(archive.as_bytes())
+        );
+
+        assert_eq!(None, ar_members(b"!<arch>\ndebian-binary"));
+        assert_eq!(None, ar_members(b"PK\x03\x04"));
+    }
+
+    #[test]
+    fn keyrings_of_policies() {
+        let temp = crate::utils::test_dir("debsig-policies");
+        let dir = temp.path();
+
+        let policy = r#"<?xml version="1.0"?>
+<Policy xmlns="https://www.debian.org/debsig/1.0/">
+  <Origin Name="Pop!_OS" id="204DD8AEC33A7AFF" Description="Pop!_OS packages"/>
+  <Selection>
+    <Required Type="origin" File="pop-os.gpg" id="204DD8AEC33A7AFF"/>
+  </Selection>
+  <Verification MinOptional="0">
+    <Required Type="origin" File="pop-os.gpg" id="204DD8AEC33A7AFF"/>
+    <Optional Type="origin" File="../escape.gpg" id="204DD8AEC33A7AFF"/>
+  </Verification>
+</Policy>
+"#;
+
+        std::fs::write(dir.join("pop-os.pol"), policy).unwrap();
+        std::fs::write(dir.join("README"), r#"File="ignored.gpg""#).unwrap();
+
+        assert_eq!(vec!["pop-os.gpg"], policy_keyrings(dir).unwrap());
+    }
+}
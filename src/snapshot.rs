@@ -0,0 +1,208 @@
+// Copyright 2021-2022 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+//! Snapshots the root filesystem before packages are changed, so that an operation
+//! which breaks the system may be rolled back.
+//!
+//! Snapshots are taken by hooks, whose IDs are passed to the post-install hooks, and
+//! recorded in the `UpgradeSummary` of the `*_with_summary` operations of `AptGet`.
+//!
+//! ```no_run
+//! use apt_cmd::snapshot::Snapshotter;
+//! use apt_cmd::{AptClient, Backend};
+//!
+//! # async fn run() -> std::io::Result<()> {
+//! let mut client = AptClient::new(Backend::Host);
+//! Snapshotter::btrfs("/", "/.snapshots").register(client.hooks_mut());
+//!
+//! let (_status, summary) = client
+//!     .apt_get()
+//!     .noninteractive()
+//!     .upgrade_with_summary(|_| ())
+//!     .await?;
+//!
+//! println!("snapshots taken: {:?}", summary.snapshots);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::hooks::{Hooks, Operation};
+use anyhow::Context;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+
+/// Replaced in the arguments of a snapshot command by the name of the snapshot.
+pub const NAME_PLACEHOLDER: &str = "{name}";
+
+/// Distinguishes the snapshots of this process which are named in the same second.
+static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+/// A command which takes a snapshot, whose arguments may contain `{name}`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Snapshotter {
+    program: String,
+    args: Vec<String>,
+    prefix: String,
+}
+
+impl Snapshotter {
+    /// Takes read-only snapshots of a btrfs `subvolume` into `directory`, each
+    /// identified by its path.
+    pub fn btrfs<P: AsRef<Path>, D: AsRef<Path>>(subvolume: P, directory: D) -> Self {
+        let destination = directory.as_ref().join(NAME_PLACEHOLDER);
+
+        Self::custom(
+            "btrfs",
+            vec![
+                "subvolume".into(),
+                "snapshot".into(),
+                "-r".into(),
+                subvolume.as_ref().to_string_lossy().into_owned(),
+                destination.to_string_lossy().into_owned(),
+            ],
+        )
+    }
+
+    /// Takes snapshots of a ZFS `dataset`, such as `rpool/ROOT/pop`, each identified
+    /// as `dataset@name`.
+    pub fn zfs(dataset: &str) -> Self {
+        Self::custom(
+            "zfs",
+            vec!["snapshot".into(), [dataset, "@", NAME_PLACEHOLDER].concat()],
+        )
+    }
+
+    /// Takes snapshots with any command. The ID of each snapshot is the last argument
+    /// which contains `{name}`, or else the name itself.
+    pub fn custom<S: Into<String>>(program: S, args: Vec<String>) -> Self {
+        Self {
+            program: program.into(),
+            args,
+            prefix: "apt".into(),
+        }
+    }
+
+    /// Begins the name of each snapshot, which is followed by the operation, the time,
+    /// the process ID, and a sequence number, as `apt-upgrade-1700000000-4242-0`.
+    pub fn prefix<S: Into<String>>(mut self, prefix: S) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// The name of a snapshot taken now, before `operation`.
+    ///
+    /// Names are unique, even for operations which begin in the same second.
+    pub fn name(&self, operation: Operation) -> String {
+        let secs = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+
+        let sequence = SEQUENCE.fetch_add(1, Ordering::Relaxed);
+
+        format!(
+            "{}-{}-{}-{}-{}",
+            self.prefix,
+            operation,
+            secs,
+            std::process::id(),
+            sequence
+        )
+    }
+
+    /// The arguments of the command to take the snapshot `name`, and its ID.
+    fn command_args(&self, name: &str) -> (Vec<String>, String) {
+        let args: Vec<String> = self
+            .args
+            .iter()
+            .map(|arg| arg.replace(NAME_PLACEHOLDER, name))
+            .collect();
+
+        let id = self
+            .args
+            .iter()
+            .zip(&args)
+            .rev()
+            .find(|(template, _)| template.contains(NAME_PLACEHOLDER))
+            .map(|(_, arg)| arg.clone())
+            .unwrap_or_else(|| name.to_owned());
+
+        (args, id)
+    }
+
+    /// Takes a snapshot before `operation`, returning its ID.
+    pub async fn snapshot(&self, operation: Operation) -> anyhow::Result<String> {
+        let (args, id) = self.command_args(&self.name(operation));
+
+        let status = crate::utils::command(&self.program)
+            .args(&args)
+            .status()
+            .await
+            .with_context(|| format!("failed to launch `{}`", self.program))?;
+
+        if !status.success() {
+            return Err(anyhow::anyhow!(
+                "`{} {}` exited with {}",
+                self.program,
+                args.join(" "),
+                status
+            ));
+        }
+
+        Ok(id)
+    }
+
+    /// Takes a snapshot before each operation which changes packages.
+    pub fn register(self, hooks: &mut Hooks) {
+        hooks.register_snapshot(move |operation| {
+            let snapshotter = self.clone();
+            async move { snapshotter.snapshot(operation).await }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_commands() {
+        let (args, id) = Snapshotter::btrfs("/", "/.snapshots").command_args("apt-upgrade-1");
+        assert_eq!(
+            vec![
+                "subvolume",
+                "snapshot",
+                "-r",
+                "/",
+                "/.snapshots/apt-upgrade-1"
+            ],
+            args
+        );
+        assert_eq!("/.snapshots/apt-upgrade-1", id);
+
+        let (args, id) = Snapshotter::zfs("rpool/ROOT/pop").command_args("apt-install-1");
+        assert_eq!(vec!["snapshot", "rpool/ROOT/pop@apt-install-1"], args);
+        assert_eq!("rpool/ROOT/pop@apt-install-1", id);
+
+        let snapshotter = Snapshotter::custom("timeshift", vec!["--create".into()]).prefix("pop");
+        assert_eq!("apt-remove-1", snapshotter.command_args("apt-remove-1").1);
+        assert!(snapshotter
+            .name(Operation::Remove)
+            .starts_with("pop-remove-"));
+        assert_ne!(
+            snapshotter.name(Operation::Remove),
+            snapshotter.name(Operation::Remove)
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_snapshot() {
+        let mut hooks = Hooks::default();
+        Snapshotter::custom("false", Vec::new()).register(&mut hooks);
+
+        assert!(hooks
+            .run_pre_install(Operation::Upgrade, &[])
+            .await
+            .is_err());
+    }
+}
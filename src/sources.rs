@@ -0,0 +1,527 @@
+// Copyright 2021-2022 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+//! Rewrites apt source entries, in both the one-line `.list` and deb822 `.sources` formats.
+
+use anyhow::Context;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub const SOURCES_LIST: &str = "/etc/apt/sources.list";
+pub const SOURCES_DIR: &str = "/etc/apt/sources.list.d";
+
+/// Hosts of the Ubuntu and Pop!_OS archives. Entries pointing elsewhere are third-party.
+const FIRST_PARTY: &[&str] = &[
+    "archive.ubuntu.com",
+    "security.ubuntu.com",
+    "ports.ubuntu.com",
+    "apt.pop-os.org",
+    "ppa.launchpad.net/system76/",
+    "ppa.launchpadcontent.net/system76/",
+];
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct RewriteOptions {
+    /// Disable entries which do not belong to the Ubuntu or Pop!_OS archives.
+    pub disable_third_party: bool,
+}
+
+/// The original and rewritten contents of a sources file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FilePatch {
+    pub path: PathBuf,
+    pub original: String,
+    pub rewritten: String,
+}
+
+/// Changes to sources files, which may be applied and later reverted.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Patch {
+    pub files: Vec<FilePatch>,
+}
+
+impl Patch {
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Writes the rewritten contents of each file.
+    pub async fn apply(&self) -> io::Result<()> {
+        for file in &self.files {
+            tokio::fs::write(&file.path, &file.rewritten).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Restores the original contents of each file.
+    pub async fn revert(&self) -> io::Result<()> {
+        for file in &self.files {
+            tokio::fs::write(&file.path, &file.original).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Computes a patch which moves every source entry from one release to another.
+///
+/// Pockets such as `-updates`, `-security`, and `-backports` are carried over.
+/// The patch is not applied until [`Patch::apply`] is called.
+pub async fn rewrite_suites(from: &str, to: &str) -> anyhow::Result<Patch> {
+    rewrite_suites_with(from, to, RewriteOptions::default()).await
+}
+
+/// Same as [`rewrite_suites`], with additional options.
+pub async fn rewrite_suites_with(
+    from: &str,
+    to: &str,
+    options: RewriteOptions,
+) -> anyhow::Result<Patch> {
+    patch_files(|path, contents| {
+        if is_deb822(path) {
+            rewrite_deb822(contents, from, to, options)
+        } else {
+            rewrite_list(contents, from, to, options)
+        }
+    })
+    .await
+}
+
+/// Computes a patch which verifies every entry of the repository at `uri` with `keyring`.
+pub async fn add_signed_by(uri: &str, keyring: &Path) -> anyhow::Result<Patch> {
+    let keyring = keyring.to_string_lossy();
+    patch_files(|path, contents| {
+        if is_deb822(path) {
+            add_signed_by_deb822(contents, uri, &keyring)
+        } else {
+            add_signed_by_list(contents, uri, &keyring)
+        }
+    })
+    .await
+}
+
+/// Collects the changes made by `rewrite` to each sources file.
+async fn patch_files<F: Fn(&Path, &str) -> String>(rewrite: F) -> anyhow::Result<Patch> {
+    let mut patch = Patch::default();
+
+    for path in source_files().await? {
+        let original = tokio::fs::read_to_string(&path)
+            .await
+            .with_context(|| format!("failed to read {}", path.display()))?;
+
+        let rewritten = rewrite(&path, &original);
+
+        if rewritten != original {
+            patch.files.push(FilePatch {
+                path,
+                original,
+                rewritten,
+            });
+        }
+    }
+
+    Ok(patch)
+}
+
+/// `/etc/apt/sources.list`, followed by each `.list` and `.sources` file in `sources.list.d`.
+pub async fn source_files() -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+
+    if Path::new(SOURCES_LIST).exists() {
+        files.push(PathBuf::from(SOURCES_LIST));
+    }
+
+    let mut dir = match tokio::fs::read_dir(SOURCES_DIR).await {
+        Ok(dir) => dir,
+        Err(why) if why.kind() == io::ErrorKind::NotFound => return Ok(files),
+        Err(why) => return Err(why).context("failed to read sources.list.d"),
+    };
+
+    let mut entries = Vec::new();
+    while let Some(entry) = dir
+        .next_entry()
+        .await
+        .context("failed to read sources.list.d")?
+    {
+        let path = entry.path();
+        if matches!(
+            path.extension().and_then(|ext| ext.to_str()),
+            Some("list" | "sources")
+        ) {
+            entries.push(path);
+        }
+    }
+
+    entries.sort();
+    files.extend(entries);
+    Ok(files)
+}
+
+fn is_deb822(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "sources")
+}
+
+/// Whether the URI is a `cdrom:` URI, registered with `apt-cdrom`.
+pub fn is_cdrom_uri(uri: &str) -> bool {
+    uri.starts_with("cdrom:")
+}
+
+fn is_third_party(uri: &str) -> bool {
+    is_cdrom_uri(uri) || !FIRST_PARTY.iter().any(|host| uri.contains(host))
+}
+
+/// The suite moved to the new release, if it belongs to the old release.
+fn rewrite_suite(suite: &str, from: &str, to: &str) -> Option<String> {
+    let pocket = suite.strip_prefix(from)?;
+    if pocket.is_empty() || pocket.starts_with('-') {
+        Some([to, pocket].concat())
+    } else {
+        None
+    }
+}
+
+fn same_uri(a: &str, b: &str) -> bool {
+    a.trim_end_matches('/') == b.trim_end_matches('/')
+}
+
+/// Rewrites a file in the one-line format, such as `deb http://... jammy main`.
+pub fn rewrite_list(contents: &str, from: &str, to: &str, options: RewriteOptions) -> String {
+    map_lines(contents, |line| rewrite_list_line(line, from, to, options))
+}
+
+/// Adds `signed-by` to the options of one-line entries for the repository at `uri`.
+pub fn add_signed_by_list(contents: &str, uri: &str, keyring: &str) -> String {
+    map_lines(contents, |line| {
+        let mut tokens = list_tokens(line);
+
+        if !matches!(tokens.first().map(String::as_str), Some("deb" | "deb-src")) {
+            return None;
+        }
+
+        let signed_by = ["signed-by=", keyring].concat();
+
+        if let Some(options) = tokens[1].strip_prefix('[') {
+            if !same_uri(tokens.get(2)?, uri) {
+                return None;
+            }
+
+            let mut options: Vec<&str> = options
+                .trim_end_matches(']')
+                .split_ascii_whitespace()
+                .filter(|option| !option.starts_with("signed-by="))
+                .collect();
+
+            options.push(&signed_by);
+            tokens[1] = ["[", &options.join(" "), "]"].concat();
+        } else {
+            if !same_uri(tokens.get(1)?, uri) {
+                return None;
+            }
+
+            tokens.insert(1, ["[", &signed_by, "]"].concat());
+        }
+
+        Some(tokens.join(" "))
+    })
+}
+
+/// Replaces each line for which `rewrite` returns a new line.
+fn map_lines<F: Fn(&str) -> Option<String>>(contents: &str, rewrite: F) -> String {
+    let mut output = String::with_capacity(contents.len());
+
+    for line in contents.lines() {
+        output.push_str(&rewrite(line).unwrap_or_else(|| line.into()));
+        output.push('\n');
+    }
+
+    if !contents.ends_with('\n') {
+        output.pop();
+    }
+
+    output
+}
+
+fn rewrite_list_line(line: &str, from: &str, to: &str, options: RewriteOptions) -> Option<String> {
+    let mut tokens = list_tokens(line);
+
+    if !matches!(tokens.first().map(String::as_str), Some("deb" | "deb-src")) {
+        return None;
+    }
+
+    let uri = if tokens.get(1)?.starts_with('[') {
+        2
+    } else {
+        1
+    };
+    let suite = uri + 1;
+    let rewritten = rewrite_suite(tokens.get(suite)?, from, to)?;
+
+    if options.disable_third_party && is_third_party(&tokens[uri]) {
+        return Some(["# ", line].concat());
+    }
+
+    tokens[suite] = rewritten;
+    Some(tokens.join(" "))
+}
+
+/// Splits a line on whitespace, keeping `[...]` options and `cdrom:[...]` labels together.
+fn list_tokens(line: &str) -> Vec<String> {
+    let mut tokens: Vec<String> = Vec::new();
+    let mut open = false;
+
+    for word in line.split_ascii_whitespace() {
+        if open {
+            let token = tokens.last_mut().expect("open bracket without token");
+            token.push(' ');
+            token.push_str(word);
+        } else {
+            tokens.push(word.to_owned());
+        }
+
+        if word.contains('[') {
+            open = true;
+        }
+
+        if word.contains(']') {
+            open = false;
+        }
+    }
+
+    tokens
+}
+
+/// Rewrites a file in the deb822 format, where each stanza has a `Suites` field.
+pub fn rewrite_deb822(contents: &str, from: &str, to: &str, options: RewriteOptions) -> String {
+    let stanzas: Vec<String> = contents
+        .split("\n\n")
+        .map(|stanza| rewrite_deb822_stanza(stanza, from, to, options))
+        .collect();
+
+    stanzas.join("\n\n")
+}
+
+/// The value of a single-line field of a deb822 stanza.
+fn deb822_field(stanza: &str, name: &str) -> Option<String> {
+    crate::deb822::parse(stanza)
+        .next()?
+        .get(name)
+        .map(String::from)
+}
+
+/// Sets the `Signed-By` field of deb822 stanzas for the repository at `uri`.
+///
+/// An existing `Signed-By` field, including an embedded key, is replaced.
+pub fn add_signed_by_deb822(contents: &str, uri: &str, keyring: &str) -> String {
+    let stanzas: Vec<String> = contents
+        .split("\n\n")
+        .map(|stanza| {
+            let matches = deb822_field(stanza, "URIs")
+                .is_some_and(|uris| uris.split_ascii_whitespace().any(|u| same_uri(u, uri)));
+
+            if !matches {
+                return stanza.to_owned();
+            }
+
+            let mut lines: Vec<String> = Vec::new();
+            let mut replacing = false;
+
+            for line in stanza.lines() {
+                if replacing && line.starts_with([' ', '\t']) {
+                    continue;
+                }
+
+                replacing = line
+                    .split_once(':')
+                    .is_some_and(|(key, _)| key.trim().eq_ignore_ascii_case("Signed-By"));
+
+                if !replacing {
+                    lines.push(line.to_owned());
+                }
+            }
+
+            lines.push(["Signed-By: ", keyring].concat());
+
+            let mut output = lines.join("\n");
+            if stanza.ends_with('\n') {
+                output.push('\n');
+            }
+
+            output
+        })
+        .collect();
+
+    stanzas.join("\n\n")
+}
+
+fn rewrite_deb822_stanza(stanza: &str, from: &str, to: &str, options: RewriteOptions) -> String {
+    let field = |name: &str| deb822_field(stanza, name);
+
+    let suites = match field("Suites") {
+        Some(suites) => suites,
+        None => return stanza.to_owned(),
+    };
+
+    let rewritten: Vec<String> = suites
+        .split_ascii_whitespace()
+        .map(|suite| rewrite_suite(suite, from, to).unwrap_or_else(|| suite.to_owned()))
+        .collect();
+
+    let rewritten = rewritten.join(" ");
+    if rewritten == suites {
+        return stanza.to_owned();
+    }
+
+    let third_party = options.disable_third_party
+        && field("URIs").is_some_and(|uris| uris.split_ascii_whitespace().any(is_third_party));
+
+    let mut lines: Vec<String> = Vec::new();
+    let mut disabled = false;
+
+    for line in stanza.lines() {
+        let key = line.split_once(':').map(|(key, _)| key.trim());
+
+        match key {
+            Some(key) if key.eq_ignore_ascii_case("Enabled") && third_party => {
+                lines.push("Enabled: no".into());
+                disabled = true;
+            }
+            Some(key) if key.eq_ignore_ascii_case("Suites") && !third_party => {
+                lines.push(["Suites: ", &rewritten].concat());
+            }
+            _ => lines.push(line.to_owned()),
+        }
+    }
+
+    if third_party && !disabled {
+        lines.push("Enabled: no".into());
+    }
+
+    let mut output = lines.join("\n");
+    if stanza.ends_with('\n') {
+        output.push('\n');
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RewriteOptions;
+
+    const DISABLE: RewriteOptions = RewriteOptions {
+        disable_third_party: true,
+    };
+
+    #[test]
+    fn rewrite_list() {
+        let input = "# comment about jammy\n\
+            deb http://us.archive.ubuntu.com/ubuntu/ jammy main restricted\n\
+            deb [arch=amd64 signed-by=/usr/share/keyrings/pop.gpg] http://apt.pop-os.org/release jammy-updates main\n\
+            deb-src http://security.ubuntu.com/ubuntu jammy-security main\n\
+            deb https://repo.example.com/apt stable main\n\
+            deb https://ppa.example.com/ubuntu jammy main\n\
+            deb cdrom:[Pop_OS 22.04 _Jammy Jellyfish_]/ jammy main\n";
+
+        let expected = "# comment about jammy\n\
+            deb http://us.archive.ubuntu.com/ubuntu/ noble main restricted\n\
+            deb [arch=amd64 signed-by=/usr/share/keyrings/pop.gpg] http://apt.pop-os.org/release noble-updates main\n\
+            deb-src http://security.ubuntu.com/ubuntu noble-security main\n\
+            deb https://repo.example.com/apt stable main\n\
+            # deb https://ppa.example.com/ubuntu jammy main\n\
+            # deb cdrom:[Pop_OS 22.04 _Jammy Jellyfish_]/ jammy main\n";
+
+        assert_eq!(
+            expected,
+            super::rewrite_list(input, "jammy", "noble", DISABLE)
+        );
+    }
+
+    #[test]
+    fn rewrite_deb822() {
+        let input = "Types: deb\n\
+            URIs: http://apt.pop-os.org/ubuntu\n\
+            Suites: jammy jammy-security jammy-updates jammy-backports\n\
+            Components: main restricted universe multiverse\n\
+            \n\
+            Enabled: yes\n\
+            Types: deb\n\
+            URIs: https://repo.example.com/apt\n\
+            Suites: jammy\n\
+            Components: main\n";
+
+        let expected = "Types: deb\n\
+            URIs: http://apt.pop-os.org/ubuntu\n\
+            Suites: noble noble-security noble-updates noble-backports\n\
+            Components: main restricted universe multiverse\n\
+            \n\
+            Enabled: no\n\
+            Types: deb\n\
+            URIs: https://repo.example.com/apt\n\
+            Suites: jammy\n\
+            Components: main\n";
+
+        assert_eq!(
+            expected,
+            super::rewrite_deb822(input, "jammy", "noble", DISABLE)
+        );
+
+        let kept = super::rewrite_deb822(input, "jammy", "noble", RewriteOptions::default());
+        assert!(kept.ends_with(
+            "Enabled: yes\n\
+            Types: deb\n\
+            URIs: https://repo.example.com/apt\n\
+            Suites: noble\n\
+            Components: main\n"
+        ));
+    }
+
+    #[test]
+    fn rewrite_suite() {
+        assert_eq!(
+            Some("noble-proposed".into()),
+            super::rewrite_suite("jammy-proposed", "jammy", "noble")
+        );
+        assert_eq!(None, super::rewrite_suite("jammyish", "jammy", "noble"));
+        assert_eq!(None, super::rewrite_suite("stable", "jammy", "noble"));
+    }
+
+    #[test]
+    fn add_signed_by() {
+        let list = "deb http://ppa.launchpadcontent.net/example/ppa/ubuntu jammy main\n\
+            deb [arch=amd64 signed-by=/old.gpg] http://ppa.launchpadcontent.net/example/ppa/ubuntu/ jammy main\n\
+            deb http://apt.pop-os.org/release jammy main\n";
+
+        assert_eq!(
+            "deb [signed-by=/etc/apt/keyrings/ABCD.gpg] http://ppa.launchpadcontent.net/example/ppa/ubuntu jammy main\n\
+            deb [arch=amd64 signed-by=/etc/apt/keyrings/ABCD.gpg] http://ppa.launchpadcontent.net/example/ppa/ubuntu/ jammy main\n\
+            deb http://apt.pop-os.org/release jammy main\n",
+            super::add_signed_by_list(
+                list,
+                "http://ppa.launchpadcontent.net/example/ppa/ubuntu",
+                "/etc/apt/keyrings/ABCD.gpg"
+            )
+        );
+
+        let deb822 = "Types: deb\n\
+            URIs: https://repo.example.com/apt\n\
+            Suites: stable\n\
+            Signed-By:\n \
+            -----BEGIN PGP PUBLIC KEY BLOCK-----\n \
+            -----END PGP PUBLIC KEY BLOCK-----\n\
+            Components: main\n";
+
+        assert_eq!(
+            "Types: deb\n\
+            URIs: https://repo.example.com/apt\n\
+            Suites: stable\n\
+            Components: main\n\
+            Signed-By: /etc/apt/keyrings/ABCD.gpg\n",
+            super::add_signed_by_deb822(
+                deb822,
+                "https://repo.example.com/apt/",
+                "/etc/apt/keyrings/ABCD.gpg"
+            )
+        );
+    }
+}
@@ -0,0 +1,135 @@
+// Copyright 2021-2022 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+//! A local cache of fetched packages, addressed by their checksums.
+//!
+//! Packages are hardlinked between the store and the destination of each fetch, so
+//! that an archive which was already downloaded by a previous run is never fetched
+//! again. The store should be on the same filesystem as the destinations; otherwise
+//! packages are copied instead.
+//!
+//! ```no_run
+//! use apt_cmd::store::Store;
+//! use apt_cmd::PackageFetcher;
+//!
+//! let fetcher = PackageFetcher::builder()
+//!     .store(Store::new("/var/cache/apt-cmd/store"))
+//!     .build();
+//! ```
+
+use crate::request::{Request, RequestChecksum};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A directory of packages, each stored under `<algorithm>/<checksum>`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Store {
+    root: PathBuf,
+}
+
+impl Store {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Self { root: root.into() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Where the package with this checksum is stored.
+    pub fn path(&self, checksum: &RequestChecksum) -> PathBuf {
+        let algorithm = match checksum {
+            RequestChecksum::Md5(_) => "md5",
+            RequestChecksum::Sha1(_) => "sha1",
+            RequestChecksum::Sha256(_) => "sha256",
+            RequestChecksum::Sha512(_) => "sha512",
+        };
+
+        self.root
+            .join(algorithm)
+            .join(checksum.sum().to_ascii_lowercase())
+    }
+
+    /// Whether the package is in the store.
+    pub fn contains(&self, request: &Request) -> bool {
+        fs::metadata(self.path(&request.checksum)).is_ok_and(|meta| meta.len() == request.size)
+    }
+
+    /// Links the stored package to `destination`, returning `false` if it is not stored.
+    pub fn link_into(&self, request: &Request, destination: &Path) -> io::Result<bool> {
+        if !self.contains(request) {
+            return Ok(false);
+        }
+
+        let source = self.path(&request.checksum);
+
+        match fs::remove_file(destination) {
+            Err(why) if why.kind() != io::ErrorKind::NotFound => return Err(why),
+            _ => (),
+        }
+
+        if fs::hard_link(&source, destination).is_err() {
+            fs::copy(&source, destination)?;
+        }
+
+        Ok(true)
+    }
+
+    /// Adds a package which has been validated against its checksum to the store.
+    pub fn insert(&self, request: &Request, path: &Path) -> io::Result<()> {
+        if self.contains(request) {
+            return Ok(());
+        }
+
+        let stored = self.path(&request.checksum);
+        let parent = stored.parent().expect("store path without parent");
+        fs::create_dir_all(parent)?;
+
+        // Written beside the stored path, then renamed, so it is never seen incomplete.
+        let temporary = stored.with_extension("partial");
+        let _ = fs::remove_file(&temporary);
+
+        if fs::hard_link(path, &temporary).is_err() {
+            fs::copy(path, &temporary)?;
+        }
+
+        fs::rename(&temporary, &stored)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Store;
+    use crate::request::{Request, RequestChecksum};
+
+    #[test]
+    fn store_round_trip() {
+        let temp = crate::utils::test_dir("store");
+        let dir = temp.path();
+
+        let request = Request {
+            uri: "http://apt.pop-os.org/release/pool/main/f/foo/foo_1.0_amd64.deb".into(),
+            name: "foo_1.0_amd64.deb".into(),
+            size: 3,
+            checksum: RequestChecksum::Md5("ACBD18DB4CC2F85CEDEF654FCCC4A4D8".into()),
+        };
+
+        let store = Store::new(dir.join("store"));
+        let fetched = dir.join("fetched.deb");
+        let linked = dir.join("linked.deb");
+        std::fs::write(&fetched, "foo").unwrap();
+
+        assert!(!store.link_into(&request, &linked).unwrap());
+
+        store.insert(&request, &fetched).unwrap();
+        assert!(store.contains(&request));
+        assert_eq!(
+            dir.join("store/md5/acbd18db4cc2f85cedef654fccc4a4d8"),
+            store.path(&request.checksum)
+        );
+
+        assert!(store.link_into(&request, &linked).unwrap());
+        assert_eq!("foo", std::fs::read_to_string(&linked).unwrap());
+    }
+}
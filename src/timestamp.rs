@@ -0,0 +1,82 @@
+// Copyright 2021-2022 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+//! Timestamps for the events of every stream in this crate.
+//!
+//! [`FetchEvent`](crate::fetch::FetchEvent) carries its own timestamp. Other event
+//! streams, such as those of `AptGet::stream_upgrade` and `AptGet::stream_update`,
+//! can be wrapped with [`timestamped`].
+
+use futures::stream::{Stream, StreamExt};
+use std::time::{Duration, Instant, SystemTime};
+
+/// When an event was received.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Timestamp {
+    /// Monotonic time, for computing durations between events.
+    pub instant: Instant,
+
+    /// Wall-clock time, for building timelines.
+    pub wall: Option<SystemTime>,
+}
+
+impl Timestamp {
+    /// Records both the monotonic and wall-clock time.
+    pub fn now() -> Self {
+        Self {
+            instant: Instant::now(),
+            wall: Some(SystemTime::now()),
+        }
+    }
+
+    /// Records only the monotonic time.
+    pub fn monotonic() -> Self {
+        Self::from(Instant::now())
+    }
+
+    /// Time elapsed between an earlier timestamp and this one.
+    pub fn duration_since(&self, earlier: &Timestamp) -> Duration {
+        self.instant.saturating_duration_since(earlier.instant)
+    }
+}
+
+impl From<Instant> for Timestamp {
+    fn from(instant: Instant) -> Self {
+        Self {
+            instant,
+            wall: None,
+        }
+    }
+}
+
+/// An event paired with the time it was received.
+#[derive(Clone, Debug)]
+pub struct Timestamped<T> {
+    pub timestamp: Timestamp,
+    pub event: T,
+}
+
+/// Attaches a timestamp to each event of a stream as it is received.
+pub fn timestamped<S: Stream>(events: S) -> impl Stream<Item = Timestamped<S::Item>> {
+    events.map(|event| Timestamped {
+        timestamp: Timestamp::now(),
+        event,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::stream::StreamExt;
+
+    #[test]
+    fn timestamped() {
+        let events: Vec<_> = futures::executor::block_on(
+            super::timestamped(futures::stream::iter(vec![1, 2])).collect(),
+        );
+
+        assert_eq!(2, events.len());
+        assert_eq!(2, events[1].event);
+        assert!(events[0].timestamp.wall.is_some());
+        assert!(events[1].timestamp.instant >= events[0].timestamp.instant);
+    }
+}
@@ -0,0 +1,392 @@
+// Copyright 2021-2022 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+//! Parses the progress lines of `apt-get update`, in both the legacy and compact formats.
+
+use std::time::Duration;
+
+/// The outcome of fetching an index.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum IndexStatus {
+    /// The index was unchanged.
+    Hit,
+    /// The index was downloaded.
+    Get,
+    /// The index was ignored, such as a missing translation.
+    Ign,
+    /// The index failed to be fetched.
+    Err,
+}
+
+/// A `Hit`, `Get`, `Ign`, or `Err` line, such as
+/// `Get:5 http://apt.pop-os.org/release jammy/main amd64 Packages [15.2 kB]`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct IndexEvent {
+    pub status: IndexStatus,
+    /// The sequence number of the download, which older releases of apt omit.
+    pub id: Option<u32>,
+    pub uri: String,
+    pub suite: String,
+    /// The index fetched, such as `jammy/main amd64 Packages`.
+    pub target: String,
+    pub size: Option<u64>,
+}
+
+/// The final `Fetched 1,234 kB in 2s (617 kB/s)` line.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct FetchSummary {
+    pub bytes: u64,
+    pub duration: Duration,
+    /// Bytes per second.
+    pub rate: Option<u64>,
+}
+
+/// An index which `apt-get update --print-uris` would fetch.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct IndexRequest {
+    pub uri: String,
+    /// The file name of the index in `/var/lib/apt/lists`.
+    pub name: String,
+    /// The size of the index, which is `0` when apt does not know it in advance.
+    pub size: u64,
+    /// The checksum of the index, such as `SHA256:...`, if apt knows it in advance.
+    pub checksum: Option<String>,
+}
+
+/// The kind of data an index contains.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum IndexKind {
+    /// `InRelease`, `Release`, or `Release.gpg`
+    Release,
+    Packages,
+    Sources,
+    /// The translated descriptions of a language, such as `Translation-en`.
+    Translation(String),
+    /// AppStream metadata and icons, used by software centers.
+    Dep11,
+    /// Any other target, such as `Contents`.
+    Other,
+}
+
+impl IndexRequest {
+    pub fn kind(&self) -> IndexKind {
+        // Directories of the index's path are separated by underscores in its name.
+        let name = &self.name;
+
+        if let Some((_, translation)) = name.split_once("_i18n_Translation-") {
+            let language = translation.split('.').next().unwrap_or_default();
+            IndexKind::Translation(language.to_owned())
+        } else if name.contains("_dep11_") {
+            IndexKind::Dep11
+        } else if name.ends_with("_InRelease")
+            || name.ends_with("_Release")
+            || name.ends_with("_Release.gpg")
+        {
+            IndexKind::Release
+        } else if name.contains("_Packages") {
+            IndexKind::Packages
+        } else if name.contains("_Sources") {
+            IndexKind::Sources
+        } else {
+            IndexKind::Other
+        }
+    }
+}
+
+/// Which optional indexes are fetched when mirroring indexes.
+///
+/// The default is a complete mirror, with the translations configured by
+/// `Acquire::Languages` and the DEP-11 metadata.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct IndexTargets {
+    languages: Option<Vec<String>>,
+    dep11: bool,
+}
+
+impl Default for IndexTargets {
+    fn default() -> Self {
+        Self {
+            languages: None,
+            dep11: true,
+        }
+    }
+}
+
+impl IndexTargets {
+    /// Only the release files, and the package and source lists.
+    pub fn minimal() -> Self {
+        Self {
+            languages: Some(Vec::new()),
+            dep11: false,
+        }
+    }
+
+    /// The languages of the translations to fetch, overriding `Acquire::Languages`.
+    /// If empty, no translations are fetched.
+    pub fn languages<I, S>(mut self, languages: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.languages = Some(languages.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Whether the DEP-11 metadata and icons are fetched.
+    pub fn dep11(mut self, dep11: bool) -> Self {
+        self.dep11 = dep11;
+        self
+    }
+
+    /// The configuration options to pass to apt for these targets.
+    pub(crate) fn options(&self) -> Vec<String> {
+        let mut options = Vec::new();
+
+        if let Some(languages) = &self.languages {
+            let languages = if languages.is_empty() {
+                "none".to_owned()
+            } else {
+                languages.join(",")
+            };
+
+            options.push(["Acquire::Languages=", &languages].concat());
+        }
+
+        if !self.dep11 {
+            options.push("Acquire::IndexTargets::deb::DEP-11::DefaultEnabled=false".into());
+        }
+
+        options
+    }
+
+    /// Whether the index is to be fetched, for targets which apt fetches regardless,
+    /// such as DEP-11 icons, which are configured separately from the metadata.
+    pub fn includes(&self, index: &IndexRequest) -> bool {
+        match index.kind() {
+            IndexKind::Translation(language) => self
+                .languages
+                .as_ref()
+                .is_none_or(|languages| languages.contains(&language)),
+            IndexKind::Dep11 => self.dep11,
+            _ => true,
+        }
+    }
+}
+
+/// Parses a line of `apt-get update --print-uris`, such as
+/// `'http://apt.pop-os.org/release/dists/jammy/InRelease' apt.pop-os.org_release_dists_jammy_InRelease 0 `.
+pub(crate) fn parse_index_request(line: &str) -> Option<IndexRequest> {
+    let mut fields = line.split_ascii_whitespace();
+
+    let uri = fields.next()?.strip_prefix('\'')?.strip_suffix('\'')?;
+    let name = fields.next()?;
+    let size = fields.next()?.parse::<u64>().ok()?;
+    let checksum = fields.next().map(String::from);
+
+    Some(IndexRequest {
+        uri: uri.to_owned(),
+        name: name.to_owned(),
+        size,
+        checksum,
+    })
+}
+
+pub(crate) fn parse_index_line(line: &str) -> Option<IndexEvent> {
+    let mut fields = line.split_ascii_whitespace();
+
+    let first = fields.next()?;
+    let (status, id) = match first.split_once(':') {
+        Some((status, id)) => (status, Some(id.parse::<u32>().ok()?)),
+        None => (first, None),
+    };
+
+    let status = match status {
+        "Hit" => IndexStatus::Hit,
+        "Get" => IndexStatus::Get,
+        "Ign" => IndexStatus::Ign,
+        "Err" => IndexStatus::Err,
+        _ => return None,
+    };
+
+    let uri = fields.next()?;
+    let rest: Vec<&str> = fields.collect();
+
+    let (target, size) = match rest.iter().position(|field| field.starts_with('[')) {
+        Some(pos) => {
+            let size = match &rest[pos..] {
+                [number, unit] => crate::utils::parse_size(
+                    number.trim_start_matches('['),
+                    unit.trim_end_matches(']'),
+                ),
+                _ => None,
+            };
+
+            (&rest[..pos], size)
+        }
+        None => (&rest[..], None),
+    };
+
+    Some(IndexEvent {
+        status,
+        id,
+        uri: uri.to_owned(),
+        suite: target.first()?.split('/').next()?.to_owned(),
+        target: target.join(" "),
+        size,
+    })
+}
+
+pub(crate) fn parse_fetch_summary(line: &str) -> Option<FetchSummary> {
+    let line = line.strip_prefix("Fetched ")?;
+    let (size, rest) = line.split_once(" in ")?;
+
+    let (number, unit) = size.split_once(' ')?;
+    let bytes = crate::utils::parse_size(number, unit)?;
+
+    let (duration, rate) = match rest.split_once(" (") {
+        Some((duration, rate)) => (duration, Some(rate.trim_end_matches(')'))),
+        None => (rest, None),
+    };
+
+    let rate = rate.and_then(|rate| {
+        let (number, unit) = rate.split_once(' ')?;
+        crate::utils::parse_size(number, unit.strip_suffix("/s")?)
+    });
+
+    Some(FetchSummary {
+        bytes,
+        duration: parse_duration(duration)?,
+        rate,
+    })
+}
+
+/// Parses a duration as apt prints it, such as `1h 2min 3s`.
+fn parse_duration(input: &str) -> Option<Duration> {
+    let mut seconds = 0;
+
+    for part in input.split_ascii_whitespace() {
+        let split = part.find(|c: char| !c.is_ascii_digit())?;
+        let (value, unit) = part.split_at(split);
+        let value = value.parse::<u64>().ok()?;
+
+        seconds += value
+            * match unit {
+                "s" => 1,
+                "min" => 60,
+                "h" => 3600,
+                "d" => 86400,
+                _ => return None,
+            };
+    }
+
+    Some(Duration::from_secs(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_index_lines() {
+        assert_eq!(
+            Some(IndexEvent {
+                status: IndexStatus::Get,
+                id: Some(5),
+                uri: "http://apt.pop-os.org/release".into(),
+                suite: "jammy".into(),
+                target: "jammy/main amd64 Packages".into(),
+                size: Some(15_200),
+            }),
+            parse_index_line(
+                "Get:5 http://apt.pop-os.org/release jammy/main amd64 Packages [15.2 kB]"
+            )
+        );
+
+        assert_eq!(
+            Some(IndexEvent {
+                status: IndexStatus::Hit,
+                id: None,
+                uri: "http://archive.ubuntu.com".into(),
+                suite: "trusty".into(),
+                target: "trusty Release.gpg".into(),
+                size: None,
+            }),
+            parse_index_line("Hit http://archive.ubuntu.com trusty Release.gpg")
+        );
+
+        assert_eq!(
+            Some(IndexStatus::Ign),
+            parse_index_line("Ign:3 http://example.com/apt stable InRelease")
+                .map(|event| event.status)
+        );
+
+        assert_eq!(None, parse_index_line("Reading package lists..."));
+    }
+
+    #[test]
+    fn parse_fetch_summaries() {
+        assert_eq!(
+            Some(FetchSummary {
+                bytes: 1_234_000,
+                duration: Duration::from_secs(2),
+                rate: Some(617_000),
+            }),
+            parse_fetch_summary("Fetched 1,234 kB in 2s (617 kB/s)")
+        );
+
+        assert_eq!(
+            Some(Duration::from_secs(62)),
+            parse_fetch_summary("Fetched 119 kB in 1min 2s (1,922 B/s)")
+                .map(|summary| summary.duration)
+        );
+
+        assert_eq!(None, parse_fetch_summary("Fetched 0 B"));
+    }
+
+    #[test]
+    fn index_requests() {
+        let release = parse_index_request(
+            "'http://apt.pop-os.org/release/dists/jammy/InRelease' apt.pop-os.org_release_dists_jammy_InRelease 0 ",
+        )
+        .unwrap();
+
+        assert_eq!(None, release.checksum);
+        assert_eq!(IndexKind::Release, release.kind());
+
+        let translation = parse_index_request(
+            "'http://us.archive.ubuntu.com/ubuntu/dists/jammy/main/i18n/by-hash/SHA256/2b2c' us.archive.ubuntu.com_ubuntu_dists_jammy_main_i18n_Translation-de.xz 51234 SHA256:2b2c",
+        )
+        .unwrap();
+
+        assert_eq!(Some("SHA256:2b2c".into()), translation.checksum);
+        assert_eq!(IndexKind::Translation("de".into()), translation.kind());
+
+        let icons = parse_index_request(
+            "'http://us.archive.ubuntu.com/ubuntu/dists/jammy/main/dep11/icons-64x64.tar.gz' us.archive.ubuntu.com_ubuntu_dists_jammy_main_dep11_icons-64x64.tar.gz 0",
+        )
+        .unwrap();
+
+        assert_eq!(IndexKind::Dep11, icons.kind());
+
+        let complete = IndexTargets::default();
+        assert!(complete.options().is_empty());
+        assert!(complete.includes(&translation) && complete.includes(&icons));
+
+        let minimal = IndexTargets::minimal();
+        assert_eq!(
+            vec![
+                "Acquire::Languages=none",
+                "Acquire::IndexTargets::deb::DEP-11::DefaultEnabled=false"
+            ],
+            minimal.options()
+        );
+        assert!(!minimal.includes(&translation) && !minimal.includes(&icons));
+        assert!(minimal.includes(&release));
+
+        let english = IndexTargets::default().languages(vec!["en"]);
+        assert_eq!(vec!["Acquire::Languages=en"], english.options());
+        assert!(!english.includes(&translation));
+
+        assert_eq!(None, parse_index_request("Reading package lists..."));
+    }
+}
@@ -1,43 +1,117 @@
 // Copyright 2021-2022 System76 <info@system76.com>
 // SPDX-License-Identifier: MPL-2.0
 
+use crate::{OutputStream, RawLine};
 use std::collections::HashMap;
 use std::fmt::{self, Display, Formatter};
 use std::str::FromStr;
+use std::time::Duration;
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum AptUpgradeEvent {
+    /// Packages which were automatically installed and are no longer required.
+    AutoremovableSuggested {
+        packages: Box<[Box<str>]>,
+    },
+    /// An error reported by apt or dpkg, and the package it concerns, if known.
+    Error {
+        package: Option<Box<str>>,
+        message: Box<str>,
+    },
+    /// The total size of the archives which were downloaded.
+    Fetched {
+        bytes: u64,
+    },
+    /// A package which was not previously installed is being unpacked.
+    Installing {
+        package: Box<str>,
+        version: Box<str>,
+    },
     Processing {
         package: Box<str>,
     },
+    /// dpkg has begun running the triggers of a package.
+    ///
+    /// Requires dpkg's status output, enabled by `AptGet::dpkg_status_events`.
+    ProcessingTriggers {
+        package: Box<str>,
+    },
     Progress {
         percent: u8,
     },
+    /// A line of output, when enabled by `AptGet::verbose_events`.
+    RawLine(RawLine),
+    Removing {
+        package: Box<str>,
+        version: Box<str>,
+    },
     SettingUp {
         package: Box<str>,
     },
+    /// A package has triggers awaiting processing, which may be deferred until
+    /// the end of the upgrade by `AptGet::defer_triggers`.
+    ///
+    /// Requires dpkg's status output, enabled by `AptGet::dpkg_status_events`.
+    TriggersPending {
+        package: Box<str>,
+    },
     Unpacking {
         package: Box<str>,
         version: Box<str>,
         over: Box<str>,
     },
     WaitingOnLock,
+    Warning {
+        message: Box<str>,
+    },
 }
 
 impl AptUpgradeEvent {
+    /// Serializes the event as a map of strings, including the [`SCHEMA_VERSION`](crate::SCHEMA_VERSION).
     pub fn into_dbus_map(self) -> HashMap<&'static str, String> {
         let mut map = HashMap::new();
+        map.insert("schema", crate::SCHEMA_VERSION.to_string());
 
         match self {
+            AptUpgradeEvent::AutoremovableSuggested { packages } => {
+                map.insert("autoremovable", packages.join(" "));
+            }
+            AptUpgradeEvent::Error { package, message } => {
+                if let Some(package) = package {
+                    map.insert("package", package.into());
+                }
+                map.insert("error", message.into());
+            }
+            AptUpgradeEvent::Fetched { bytes } => {
+                map.insert("fetched_bytes", bytes.to_string());
+            }
+            AptUpgradeEvent::Installing { package, version } => {
+                map.insert("installing", package.into());
+                map.insert("version", version.into());
+            }
             AptUpgradeEvent::Processing { package } => {
                 map.insert("processing_package", package.into());
             }
+            AptUpgradeEvent::ProcessingTriggers { package } => {
+                map.insert("processing_triggers", package.into());
+            }
             AptUpgradeEvent::Progress { percent } => {
                 map.insert("percent", percent.to_string());
             }
+            AptUpgradeEvent::RawLine(raw) => {
+                map.insert("raw_line", raw.line.into());
+                map.insert("stream", raw.stream.as_str().into());
+            }
+            AptUpgradeEvent::Removing { package, version } => {
+                map.insert("removing", package.into());
+                map.insert("version", version.into());
+            }
             AptUpgradeEvent::SettingUp { package } => {
                 map.insert("setting_up", package.into());
             }
+            AptUpgradeEvent::TriggersPending { package } => {
+                map.insert("triggers_pending", package.into());
+            }
             AptUpgradeEvent::Unpacking {
                 package,
                 version,
@@ -50,88 +124,127 @@ impl AptUpgradeEvent {
             AptUpgradeEvent::WaitingOnLock => {
                 map.insert("waiting", "".into());
             }
+            AptUpgradeEvent::Warning { message } => {
+                map.insert("warning", message.into());
+            }
         }
 
         map
     }
 
+    /// Deserializes an event from a map of any schema version.
+    ///
+    /// Keys which this version does not know of, as those added by a newer schema, are
+    /// ignored, so that an older client may still read the events of a newer service.
     #[allow(clippy::result_unit_err)]
     pub fn from_dbus_map<K: AsRef<str>, V: AsRef<str> + Into<Box<str>>>(
-        mut map: impl Iterator<Item = (K, V)>,
+        map: impl Iterator<Item = (K, V)>,
     ) -> Result<Self, ()> {
         use self::AptUpgradeEvent::*;
 
-        let (key, value) = match map.next() {
-            Some(value) => value,
-            None => return Err(()),
-        };
+        let mut fields = map
+            .map(|(key, value)| (key.as_ref().to_owned(), value.into()))
+            .collect::<HashMap<String, Box<str>>>();
 
-        let event = match key.as_ref() {
-            "waiting" => WaitingOnLock,
-            "processing_package" => Processing {
-                package: value.into(),
-            },
-            "percent" => {
-                let percent = value.as_ref().parse::<u8>().map_err(|_| ())?;
-                Progress { percent }
+        fields.remove("schema");
+
+        // Version 0 maps share the keys of version 1, which only added `schema`.
+        let mut take = |key: &str| fields.remove(key);
+
+        let event = if take("waiting").is_some() {
+            WaitingOnLock
+        } else if let Some(packages) = take("autoremovable") {
+            AutoremovableSuggested {
+                packages: packages.split_whitespace().map(Box::from).collect(),
             }
-            "setting_up" => SettingUp {
-                package: value.into(),
-            },
-            key => match (map.next(), map.next()) {
-                (Some((key1, value1)), Some((key2, value2))) => {
-                    let over = &mut None;
-                    let version = &mut None;
-                    let package = &mut None;
-
-                    match_field(over, version, package, key, value.into())?;
-                    match_field(over, version, package, key1.as_ref(), value1.into())?;
-                    match_field(over, version, package, key2.as_ref(), value2.into())?;
-
-                    match (over.take(), version.take(), package.take()) {
-                        (Some(over), Some(version), Some(package)) => Unpacking {
-                            package,
-                            version,
-                            over,
-                        },
-                        _ => return Err(()),
-                    }
-                }
+        } else if let Some(message) = take("error") {
+            Error {
+                package: take("package"),
+                message,
+            }
+        } else if let Some(message) = take("warning") {
+            Warning { message }
+        } else if let Some(bytes) = take("fetched_bytes") {
+            Fetched {
+                bytes: bytes.parse::<u64>().map_err(|_| ())?,
+            }
+        } else if let Some(package) = take("installing") {
+            Installing {
+                package,
+                version: take("version").ok_or(())?,
+            }
+        } else if let Some(package) = take("processing_package") {
+            Processing { package }
+        } else if let Some(package) = take("processing_triggers") {
+            ProcessingTriggers { package }
+        } else if let Some(percent) = take("percent") {
+            Progress {
+                percent: percent.parse::<u8>().map_err(|_| ())?,
+            }
+        } else if let Some(line) = take("raw_line") {
+            let stream = match take("stream").as_deref() {
+                Some("stdout") => OutputStream::Stdout,
+                Some("stderr") => OutputStream::Stderr,
                 _ => return Err(()),
-            },
+            };
+
+            RawLine(crate::RawLine { stream, line })
+        } else if let Some(package) = take("removing") {
+            Removing {
+                package,
+                version: take("version").ok_or(())?,
+            }
+        } else if let Some(package) = take("setting_up") {
+            SettingUp { package }
+        } else if let Some(package) = take("triggers_pending") {
+            TriggersPending { package }
+        } else if let Some(package) = take("unpacking") {
+            Unpacking {
+                package,
+                version: take("version").ok_or(())?,
+                over: take("over").ok_or(())?,
+            }
+        } else {
+            return Err(());
         };
 
         Ok(event)
     }
 }
 
-fn match_field<'a>(
-    over: &'a mut Option<Box<str>>,
-    version: &'a mut Option<Box<str>>,
-    package: &'a mut Option<Box<str>>,
-    key: &str,
-    value: Box<str>,
-) -> Result<(), ()> {
-    let field = match key {
-        "over" => over,
-        "version" => version,
-        "unpacking" => package,
-        _ => return Err(()),
-    };
-
-    *field = Some(value);
-
-    Ok(())
-}
-
 impl Display for AptUpgradeEvent {
     fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
         match self {
+            AptUpgradeEvent::AutoremovableSuggested { packages } => {
+                write!(fmt, "no longer required: {}", packages.join(" "))
+            }
+            AptUpgradeEvent::Error {
+                package: Some(package),
+                message,
+            } => write!(fmt, "error: {}: {}", package, message),
+            AptUpgradeEvent::Error {
+                package: None,
+                message,
+            } => write!(fmt, "error: {}", message),
+            AptUpgradeEvent::Fetched { bytes } => write!(fmt, "fetched {} bytes", bytes),
+            AptUpgradeEvent::Installing { package, version } => {
+                write!(fmt, "installing {} ({})", package, version)
+            }
             AptUpgradeEvent::Processing { package } => {
                 write!(fmt, "processing triggers for {}", package)
             }
+            AptUpgradeEvent::ProcessingTriggers { package } => {
+                write!(fmt, "running triggers for {}", package)
+            }
             AptUpgradeEvent::Progress { percent } => write!(fmt, "progress: [{:>3}%]", percent),
+            AptUpgradeEvent::RawLine(raw) => write!(fmt, "{}", raw.line),
+            AptUpgradeEvent::Removing { package, version } => {
+                write!(fmt, "removing {} ({})", package, version)
+            }
             AptUpgradeEvent::SettingUp { package } => write!(fmt, "setting up {}", package),
+            AptUpgradeEvent::TriggersPending { package } => {
+                write!(fmt, "triggers pending for {}", package)
+            }
             AptUpgradeEvent::Unpacking {
                 package,
                 version,
@@ -140,15 +253,234 @@ impl Display for AptUpgradeEvent {
             AptUpgradeEvent::WaitingOnLock => {
                 write!(fmt, "waiting on a process holding the apt lock files")
             }
+            AptUpgradeEvent::Warning { message } => write!(fmt, "warning: {}", message),
+        }
+    }
+}
+
+/// Parses the output of `apt-get full-upgrade`, including events which span multiple lines.
+#[derive(Debug, Default)]
+pub struct UpgradeParser {
+    pending: Option<Pending>,
+}
+
+#[derive(Debug)]
+enum Pending {
+    Autoremovable(Vec<Box<str>>),
+    DpkgError(Box<str>),
+}
+
+impl UpgradeParser {
+    /// Parses the next line of output, returning any events that it completes.
+    pub fn parse_line(&mut self, line: &str) -> impl Iterator<Item = AptUpgradeEvent> {
+        match &mut self.pending {
+            Some(Pending::Autoremovable(packages)) => {
+                if let Some(line) = line.strip_prefix("  ") {
+                    packages.extend(line.split_ascii_whitespace().map(Box::from));
+                    return None.into_iter().chain(None);
+                }
+            }
+            Some(Pending::DpkgError(_)) => {
+                if let Some(message) = line.strip_prefix(' ') {
+                    if let Some(Pending::DpkgError(package)) = self.pending.take() {
+                        let event = AptUpgradeEvent::Error {
+                            package: Some(package),
+                            message: message.trim().into(),
+                        };
+
+                        return Some(event).into_iter().chain(None);
+                    }
+                }
+            }
+            None => (),
+        }
+
+        let finished = self.finish();
+
+        if line.starts_with("The following package") && line.contains("automatically installed and")
+        {
+            self.pending = Some(Pending::Autoremovable(Vec::new()));
+            return finished.into_iter().chain(None);
+        }
+
+        if let Some(package) = parse_dpkg_error(line) {
+            self.pending = Some(Pending::DpkgError(package.into()));
+            return finished.into_iter().chain(None);
+        }
+
+        finished
+            .into_iter()
+            .chain(line.parse::<AptUpgradeEvent>().ok())
+    }
+
+    /// Returns the event still being parsed once the output has ended.
+    pub fn finish(&mut self) -> Option<AptUpgradeEvent> {
+        self.pending.take().map(|pending| match pending {
+            Pending::Autoremovable(packages) => AptUpgradeEvent::AutoremovableSuggested {
+                packages: packages.into(),
+            },
+            Pending::DpkgError(package) => AptUpgradeEvent::Error {
+                package: Some(package),
+                message: "error processing package".into(),
+            },
+        })
+    }
+}
+
+/// The package named by `dpkg: error processing package foo (--configure):`, or the
+/// archive named by `dpkg: error processing archive /path/foo_1.0_amd64.deb (--unpack):`.
+fn parse_dpkg_error(line: &str) -> Option<&str> {
+    let line = line.strip_prefix("dpkg: error processing ")?;
+
+    if let Some(package) = line.strip_prefix("package ") {
+        package.split(" (").next()
+    } else if let Some(archive) = line.strip_prefix("archive ") {
+        let archive = archive.split(" (").next()?;
+        let file = archive.rsplit('/').next()?;
+        file.split('_').next()
+    } else {
+        None
+    }
+}
+
+/// A package which was changed by an upgrade.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PackageChange {
+    pub package: String,
+    pub version: String,
+    /// The version which was replaced, if the package was already installed.
+    pub previous: Option<String>,
+}
+
+/// Information accumulated over the course of an upgrade.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct UpgradeSummary {
+    /// Packages which replaced an installed version.
+    pub upgraded: Vec<PackageChange>,
+    /// Packages which were newly installed.
+    pub installed: Vec<PackageChange>,
+    /// Packages which were removed.
+    pub removed: Vec<PackageChange>,
+    /// Packages which may now be removed with `apt-get autoremove`.
+    pub autoremovable: Vec<String>,
+    /// The total size of the archives which were downloaded.
+    pub bytes_downloaded: u64,
+    /// How long the upgrade took to complete.
+    pub duration: Duration,
+    /// Errors which were encountered.
+    pub errors: Vec<String>,
+    /// Warnings which were encountered.
+    pub warnings: Vec<String>,
+    /// URIs of archives which failed to download.
+    pub missing_archives: Vec<String>,
+    /// Whether apt aborted because some archives could not be fetched, suggesting
+    /// that the operation be retried with `AptGet::fix_missing`.
+    pub fix_missing_suggested: bool,
+    /// The IDs of the filesystem snapshots taken before the operation, by the hooks
+    /// registered with `Hooks::register_snapshot`.
+    pub snapshots: Vec<String>,
+}
+
+impl UpgradeSummary {
+    pub fn update(&mut self, event: &AptUpgradeEvent) {
+        match event {
+            AptUpgradeEvent::AutoremovableSuggested { packages } => {
+                self.autoremovable = packages.iter().map(|p| p.to_string()).collect();
+            }
+            AptUpgradeEvent::Error { package, message } => {
+                if let Some(uri) = parse_missing_archive(message) {
+                    self.missing_archives.push(uri.to_owned());
+                }
+
+                self.fix_missing_suggested |= message.contains("--fix-missing");
+
+                self.errors.push(match package {
+                    Some(package) => format!("{}: {}", package, message),
+                    None => message.to_string(),
+                })
+            }
+            AptUpgradeEvent::Fetched { bytes } => self.bytes_downloaded += bytes,
+            AptUpgradeEvent::Warning { message } => self.warnings.push(message.to_string()),
+            AptUpgradeEvent::Installing { package, version } => {
+                self.installed.push(PackageChange {
+                    package: package.to_string(),
+                    version: version.to_string(),
+                    previous: None,
+                });
+            }
+            AptUpgradeEvent::Removing { package, version } => {
+                self.removed.push(PackageChange {
+                    package: package.to_string(),
+                    version: version.to_string(),
+                    previous: None,
+                });
+            }
+            AptUpgradeEvent::Unpacking {
+                package,
+                version,
+                over,
+            } => {
+                self.upgraded.push(PackageChange {
+                    package: package.to_string(),
+                    version: version.to_string(),
+                    previous: Some(over.to_string()),
+                });
+            }
+            _ => (),
         }
     }
 }
 
+impl UpgradeSummary {
+    /// Whether some archives could not be downloaded.
+    pub fn is_partial(&self) -> bool {
+        self.fix_missing_suggested || !self.missing_archives.is_empty()
+    }
+}
+
+/// The URI of a `Failed to fetch http://.../foo_1.0_amd64.deb  404  Not Found` error.
+fn parse_missing_archive(message: &str) -> Option<&str> {
+    let uri = message
+        .strip_prefix("Failed to fetch ")?
+        .split_ascii_whitespace()
+        .next()?;
+
+    if uri.ends_with(".deb") {
+        Some(uri)
+    } else {
+        None
+    }
+}
+
 // TODO: Unit test this
 impl FromStr for AptUpgradeEvent {
     type Err = ();
 
     fn from_str(input: &str) -> Result<Self, Self::Err> {
+        if let Some(message) = input.strip_prefix("E: ") {
+            return Ok(AptUpgradeEvent::Error {
+                package: None,
+                message: message.into(),
+            });
+        } else if let Some(message) = crate::warnings::strip_prefix(input) {
+            return Ok(AptUpgradeEvent::Warning {
+                message: message.into(),
+            });
+        }
+
+        // Lines written by `dpkg --status-fd`.
+        if let Some(status) = input.strip_prefix("status: ") {
+            if let Some(package) = status.strip_suffix(": triggers-pending") {
+                return Ok(AptUpgradeEvent::TriggersPending {
+                    package: package.into(),
+                });
+            }
+        } else if let Some(package) = input.strip_prefix("processing: trigproc: ") {
+            return Ok(AptUpgradeEvent::ProcessingTriggers {
+                package: package.trim().into(),
+            });
+        }
+
         if let Some(mut progress) = input.strip_prefix("Progress: [") {
             progress = progress.trim();
             if let Some(pos) = progress.find('%') {
@@ -170,16 +502,40 @@ impl FromStr for AptUpgradeEvent {
             }
         } else if let Some(input) = input.strip_prefix("Unpacking ") {
             let mut fields = input.split_whitespace();
-            if let (Some(package), Some(version), Some(over)) =
-                (fields.next(), fields.next(), fields.nth(1))
-            {
-                if version.len() > 2 && over.len() > 2 {
+            match (fields.next(), fields.next(), fields.next(), fields.next()) {
+                (Some(package), Some(version), Some("over"), Some(over))
+                    if version.len() > 2 && over.len() > 2 =>
+                {
                     return Ok(AptUpgradeEvent::Unpacking {
                         package: package.into(),
                         version: version[1..version.len() - 1].into(),
                         over: over[1..over.len() - 1].into(),
                     });
                 }
+                (Some(package), Some(version), _, _) if version.len() > 2 => {
+                    return Ok(AptUpgradeEvent::Installing {
+                        package: package.into(),
+                        version: version[1..version.len() - 1].into(),
+                    });
+                }
+                _ => (),
+            }
+        } else if let Some(input) = input.strip_prefix("Removing ") {
+            let mut fields = input.split_whitespace();
+            if let (Some(package), Some(version)) = (fields.next(), fields.next()) {
+                if version.len() > 2 {
+                    return Ok(AptUpgradeEvent::Removing {
+                        package: package.into(),
+                        version: version[1..version.len() - 1].into(),
+                    });
+                }
+            }
+        } else if let Some(input) = input.strip_prefix("Fetched ") {
+            let mut fields = input.split_whitespace();
+            if let (Some(size), Some(unit)) = (fields.next(), fields.next()) {
+                if let Some(bytes) = crate::utils::parse_size(size, unit) {
+                    return Ok(AptUpgradeEvent::Fetched { bytes });
+                }
             }
         }
 
@@ -208,4 +564,332 @@ mod tests {
             "Progress: [100%]".parse::<AptUpgradeEvent>().unwrap()
         );
     }
+
+    /// The output of `apt-get install hello`, upgrading it from 2.10-2ubuntu2.
+    const UPGRADE_C: &str = "\
+Reading package lists...
+Building dependency tree...
+Reading state information...
+The following packages will be upgraded:
+  hello
+1 upgraded, 0 newly installed, 0 to remove and 0 not upgraded.
+Need to get 27.9 kB of archives.
+After this operation, 0 B of additional disk space will be used.
+Get:1 http://us.archive.ubuntu.com/ubuntu jammy/main amd64 hello amd64 2.10-2ubuntu4 [27.9 kB]
+Fetched 27.9 kB in 0s (95.2 kB/s)
+(Reading database ... 215360 files and directories currently installed.)
+Preparing to unpack .../hello_2.10-2ubuntu4_amd64.deb ...
+Unpacking hello (2.10-2ubuntu4) over (2.10-2ubuntu2) ...
+Setting up hello (2.10-2ubuntu4) ...
+Processing triggers for man-db (2.10.2-1) ...
+Processing triggers for install-info (6.8-4build1) ...
+";
+
+    /// The same upgrade with `LANG=de_DE.UTF-8`.
+    const UPGRADE_DE: &str = "\
+Paketlisten werden gelesen…
+Abhängigkeitsbaum wird aufgebaut…
+Statusinformationen werden eingelesen…
+Die folgenden Pakete werden aktualisiert (Upgrade):
+  hello
+1 aktualisiert, 0 neu installiert, 0 zu entfernen und 0 nicht aktualisiert.
+Es müssen 27,9 kB an Archiven heruntergeladen werden.
+Nach dieser Operation werden 0 B Plattenplatz zusätzlich benutzt.
+Holen:1 http://de.archive.ubuntu.com/ubuntu jammy/main amd64 hello amd64 2.10-2ubuntu4 [27,9 kB]
+Es wurden 27,9 kB in 0 s geholt (95,2 kB/s).
+(Lese Datenbank ... 215360 Dateien und Verzeichnisse sind derzeit installiert.)
+Vorbereitung zum Entpacken von .../hello_2.10-2ubuntu4_amd64.deb ...
+Entpacken von hello (2.10-2ubuntu4) über (2.10-2ubuntu2) ...
+hello (2.10-2ubuntu4) wird eingerichtet ...
+Trigger für man-db (2.10.2-1) werden verarbeitet ...
+Trigger für install-info (6.8-4build1) werden verarbeitet ...
+";
+
+    /// The same upgrade with `LANG=fr_FR.UTF-8`.
+    const UPGRADE_FR: &str = "\
+Lecture des listes de paquets... Fait
+Construction de l'arbre des dépendances... Fait
+Lecture des informations d'état... Fait
+Les paquets suivants seront mis à jour :
+  hello
+1 mis à jour, 0 nouvellement installés, 0 à enlever et 0 non mis à jour.
+Il est nécessaire de prendre 27,9 ko dans les archives.
+Après cette opération, 0 o d'espace disque supplémentaires seront utilisés.
+Réception de :1 http://fr.archive.ubuntu.com/ubuntu jammy/main amd64 hello amd64 2.10-2ubuntu4 [27,9 kB]
+27,9 ko réceptionnés en 0s (95,2 ko/s)
+(Lecture de la base de données... 215360 fichiers et répertoires déjà installés.)
+Préparation du dépaquetage de .../hello_2.10-2ubuntu4_amd64.deb ...
+Dépaquetage de hello (2.10-2ubuntu4) sur (2.10-2ubuntu2) ...
+Paramétrage de hello (2.10-2ubuntu4) ...
+Traitement des actions différées (« triggers ») pour man-db (2.10.2-1) ...
+Traitement des actions différées (« triggers ») pour install-info (6.8-4build1) ...
+";
+
+    fn upgrade_events(output: &str) -> Vec<AptUpgradeEvent> {
+        let mut parser = UpgradeParser::default();
+        output
+            .lines()
+            .flat_map(|line| parser.parse_line(line).collect::<Vec<_>>())
+            .collect()
+    }
+
+    #[test]
+    fn apt_upgrade_event_localized_output_is_not_parsed() {
+        // The parsers only understand C-locale output, which is why every
+        // command is spawned with `LANG=C` and `LC_ALL=C`.
+        let events = upgrade_events(UPGRADE_C);
+
+        assert!(events.contains(&AptUpgradeEvent::Unpacking {
+            package: "hello".into(),
+            version: "2.10-2ubuntu4".into(),
+            over: "2.10-2ubuntu2".into(),
+        }));
+        assert!(events.contains(&AptUpgradeEvent::SettingUp {
+            package: "hello".into(),
+        }));
+        assert!(events.contains(&AptUpgradeEvent::Processing {
+            package: "man-db".into(),
+        }));
+
+        assert!(upgrade_events(UPGRADE_DE).is_empty());
+        assert!(upgrade_events(UPGRADE_FR).is_empty());
+    }
+
+    #[tokio::test]
+    async fn commands_are_spawned_with_c_locale() {
+        use crate::utils::is_c_locale;
+        use futures::StreamExt;
+
+        assert!(is_c_locale(&crate::AptGet::new()));
+        assert!(is_c_locale(&crate::AptCache::new()));
+        assert!(is_c_locale(&crate::apt::apt_command(&[
+            "list",
+            "--upgradable"
+        ])));
+
+        // The locale is forced again as the output is piped, over the caller's own.
+        let command = || {
+            let mut command = tokio::process::Command::new("sh");
+            command
+                .args(["-c", "echo $LANG $LC_ALL"])
+                .env("LANG", "de_DE.UTF-8")
+                .env("LC_ALL", "de_DE.UTF-8");
+            command
+        };
+
+        let (mut child, lines) = crate::utils::spawn_with_output(command()).await.unwrap();
+        let lines: Vec<String> = lines.map(|(_, line)| line).collect().await;
+        child.wait().await.unwrap();
+        assert_eq!(vec!["C C"], lines);
+
+        let (mut child, stdout) = crate::utils::spawn_with_stdout(command()).await.unwrap();
+        let lines: Vec<String> = crate::utils::lossy_lines(stdout).collect().await;
+        child.wait().await.unwrap();
+        assert_eq!(vec!["C C"], lines);
+    }
+
+    #[test]
+    fn upgrade_parser_autoremovable() {
+        let output = [
+            "Reading package lists...",
+            "The following packages were automatically installed and are no longer required:",
+            "  libfoo1 libbar2",
+            "  libbaz3",
+            "Use 'sudo apt autoremove' to remove them.",
+            "Progress: [ 50%]",
+        ];
+
+        let mut parser = UpgradeParser::default();
+        let mut summary = UpgradeSummary::default();
+        let events = output
+            .iter()
+            .flat_map(|line| parser.parse_line(line).collect::<Vec<_>>())
+            .inspect(|event| summary.update(event))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            vec![
+                AptUpgradeEvent::AutoremovableSuggested {
+                    packages: vec!["libfoo1".into(), "libbar2".into(), "libbaz3".into()].into()
+                },
+                AptUpgradeEvent::Progress { percent: 50 },
+            ],
+            events
+        );
+
+        assert_eq!(vec!["libfoo1", "libbar2", "libbaz3"], summary.autoremovable);
+        assert_eq!(None, parser.finish());
+    }
+
+    #[test]
+    fn upgrade_summary() {
+        let output = [
+            "Fetched 1,234 kB in 2s (617 kB/s)",
+            "Unpacking firefox (100.0) over (99.0) ...",
+            "Selecting previously unselected package libnew1:amd64.",
+            "Unpacking libnew1:amd64 (1.0-1) ...",
+            "Removing libold0:amd64 (0.9-2) ...",
+        ];
+
+        let mut summary = UpgradeSummary::default();
+        for line in output.iter() {
+            if let Ok(event) = line.parse::<AptUpgradeEvent>() {
+                summary.update(&event);
+            }
+        }
+
+        assert_eq!(1_234_000, summary.bytes_downloaded);
+        assert_eq!(
+            vec![PackageChange {
+                package: "firefox".into(),
+                version: "100.0".into(),
+                previous: Some("99.0".into()),
+            }],
+            summary.upgraded
+        );
+        assert_eq!("libnew1:amd64", summary.installed[0].package);
+        assert_eq!("0.9-2", summary.removed[0].version);
+        assert!(!summary.is_partial());
+    }
+
+    #[test]
+    fn upgrade_summary_missing_archives() {
+        let output = [
+            "E: Failed to fetch http://apt.pop-os.org/release/pool/main/f/firefox/firefox_120.0_amd64.deb  404  Not Found [IP: 10.0.0.1 80]",
+            "E: Unable to fetch some archives, maybe run apt-get update or try with --fix-missing?",
+        ];
+
+        let mut summary = UpgradeSummary::default();
+        for line in output.iter() {
+            if let Ok(event) = line.parse::<AptUpgradeEvent>() {
+                summary.update(&event);
+            }
+        }
+
+        assert!(summary.is_partial());
+        assert!(summary.fix_missing_suggested);
+        assert_eq!(
+            vec!["http://apt.pop-os.org/release/pool/main/f/firefox/firefox_120.0_amd64.deb"],
+            summary.missing_archives
+        );
+    }
+
+    #[test]
+    fn apt_upgrade_event_dbus_round_trip() {
+        let events = [
+            AptUpgradeEvent::Unpacking {
+                package: "firefox".into(),
+                version: "100.0".into(),
+                over: "99.0".into(),
+            },
+            AptUpgradeEvent::Installing {
+                package: "libnew1".into(),
+                version: "1.0-1".into(),
+            },
+            AptUpgradeEvent::Fetched { bytes: 42 },
+            AptUpgradeEvent::WaitingOnLock,
+            AptUpgradeEvent::RawLine(RawLine::new(
+                OutputStream::Stderr,
+                "debconf: unable to initialize frontend: Dialog",
+            )),
+        ];
+
+        for event in events.iter() {
+            let map = event.clone().into_dbus_map();
+            assert_eq!(
+                Ok(event.clone()),
+                AptUpgradeEvent::from_dbus_map(map.into_iter())
+            );
+        }
+    }
+
+    #[test]
+    fn apt_upgrade_event_dbus_schema() {
+        let map = AptUpgradeEvent::Progress { percent: 5 }.into_dbus_map();
+        assert_eq!(Some(&crate::SCHEMA_VERSION.to_string()), map.get("schema"));
+
+        assert_eq!(
+            Ok(AptUpgradeEvent::Progress { percent: 5 }),
+            AptUpgradeEvent::from_dbus_map(vec![("percent", "5")].into_iter())
+        );
+
+        let future = (crate::SCHEMA_VERSION + 1).to_string();
+        assert_eq!(
+            Ok(AptUpgradeEvent::Progress { percent: 5 }),
+            AptUpgradeEvent::from_dbus_map(
+                vec![
+                    ("schema", future.as_str()),
+                    ("percent", "5"),
+                    ("eta_seconds", "30")
+                ]
+                .into_iter()
+            )
+        );
+    }
+
+    #[test]
+    fn upgrade_parser_errors() {
+        let output = [
+            "Setting up foo (1.0) ...",
+            "dpkg: error processing package foo (--configure):",
+            " installed foo package post-installation script subprocess returned error exit status 1",
+            "dpkg: error processing archive /var/cache/apt/archives/bar_2.0_amd64.deb (--unpack):",
+            " trying to overwrite '/usr/bin/bar', which is also in package baz 1.0",
+            "W: Possible missing firmware /lib/firmware/foo.bin for module foo",
+            "E: Sub-process /usr/bin/dpkg returned an error code (1)",
+        ];
+
+        let mut parser = UpgradeParser::default();
+        let mut summary = UpgradeSummary::default();
+        let events = output
+            .iter()
+            .flat_map(|line| parser.parse_line(line).collect::<Vec<_>>())
+            .inspect(|event| summary.update(event))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            AptUpgradeEvent::Error {
+                package: Some("foo".into()),
+                message: "installed foo package post-installation script subprocess returned error exit status 1".into(),
+            },
+            events[1]
+        );
+
+        assert_eq!(
+            AptUpgradeEvent::Error {
+                package: Some("bar".into()),
+                message: "trying to overwrite '/usr/bin/bar', which is also in package baz 1.0"
+                    .into(),
+            },
+            events[2]
+        );
+
+        assert_eq!(5, events.len());
+        assert_eq!(3, summary.errors.len());
+        assert_eq!(
+            vec!["Possible missing firmware /lib/firmware/foo.bin for module foo"],
+            summary.warnings
+        );
+    }
+
+    #[test]
+    fn apt_upgrade_event_triggers() {
+        assert_eq!(
+            Ok(AptUpgradeEvent::TriggersPending {
+                package: "man-db:amd64".into()
+            }),
+            "status: man-db:amd64: triggers-pending".parse::<AptUpgradeEvent>()
+        );
+
+        assert_eq!(
+            Ok(AptUpgradeEvent::ProcessingTriggers {
+                package: "initramfs-tools:all".into()
+            }),
+            "processing: trigproc: initramfs-tools:all".parse::<AptUpgradeEvent>()
+        );
+
+        assert!("status: man-db:amd64: installed"
+            .parse::<AptUpgradeEvent>()
+            .is_err());
+    }
 }
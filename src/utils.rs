@@ -1,11 +1,158 @@
 // Copyright 2021-2022 System76 <info@system76.com>
 // SPDX-License-Identifier: MPL-2.0
 
+use futures::stream::{self, Stream, StreamExt};
+use std::ffi::{CString, OsString};
 use std::io;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, BufReader};
 use tokio::process::{Child, ChildStdout, Command};
 
+/// The default capacity of the buffer that command output is read through.
+pub const DEFAULT_BUFFER_CAPACITY: usize = 8 * 1024;
+
+/// Reads lines into a single reused buffer, rather than allocating a string per line.
+///
+/// Bytes which are not valid UTF-8, such as from a maintainer's name or a file path, are
+/// replaced with `U+FFFD`, rather than ending the output at that line.
+pub struct LineReader<R> {
+    reader: R,
+    bytes: Vec<u8>,
+    line: String,
+}
+
+impl<R: AsyncRead + Unpin> LineReader<BufReader<R>> {
+    pub fn with_capacity(capacity: usize, reader: R) -> Self {
+        Self::new(BufReader::with_capacity(capacity, reader))
+    }
+}
+
+impl<R: AsyncBufRead + Unpin> LineReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            bytes: Vec::new(),
+            line: String::new(),
+        }
+    }
+
+    /// The next line, without its line ending. Stops at the end of input, or on an error.
+    pub async fn next_line(&mut self) -> Option<&str> {
+        self.try_next_line().await.ok().flatten()
+    }
+
+    /// The next line, without its line ending, or the error which stopped the reader.
+    pub async fn try_next_line(&mut self) -> io::Result<Option<&str>> {
+        self.bytes.clear();
+        self.line.clear();
+
+        if self.reader.read_until(b'\n', &mut self.bytes).await? == 0 {
+            return Ok(None);
+        }
+
+        match std::str::from_utf8(&self.bytes) {
+            Ok(line) => self.line.push_str(line),
+            Err(_) => self.line.push_str(&String::from_utf8_lossy(&self.bytes)),
+        }
+
+        Ok(Some(self.line.trim_end_matches(['\n', '\r'])))
+    }
+}
+
+/// Lines of the reader as a stream, decoded as by `LineReader`.
+pub fn lossy_lines<R: AsyncRead + Unpin + Send>(reader: R) -> impl Stream<Item = String> + Send {
+    async_stream::stream! {
+        let mut lines = LineReader::with_capacity(DEFAULT_BUFFER_CAPACITY, reader);
+
+        while let Some(line) = lines.next_line().await {
+            yield line.to_owned();
+        }
+    }
+}
+
+/// The output stream of a child process that a line was read from.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+impl OutputStream {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            OutputStream::Stdout => "stdout",
+            OutputStream::Stderr => "stderr",
+        }
+    }
+}
+
+/// A line of output, exactly as the command wrote it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RawLine {
+    pub stream: OutputStream,
+    pub line: Box<str>,
+}
+
+impl RawLine {
+    pub fn new(stream: OutputStream, line: &str) -> Self {
+        Self {
+            stream,
+            line: line.into(),
+        }
+    }
+}
+
+/// Creates a command whose output will not be localized, so that it can be parsed.
+pub fn command(program: &str) -> Command {
+    let mut command = Command::new(program);
+    c_locale(&mut command);
+    command
+}
+
+/// A copy of the program, arguments, environment, and working directory of a command,
+/// so that it may be spawned more than once.
+pub fn clone_command(command: &Command) -> Command {
+    let std = command.as_std();
+    let mut clone = Command::new(std.get_program());
+    clone.args(std.get_args());
+
+    for (key, value) in std.get_envs() {
+        match value {
+            Some(value) => clone.env(key, value),
+            None => clone.env_remove(key),
+        };
+    }
+
+    if let Some(dir) = std.get_current_dir() {
+        clone.current_dir(dir);
+    }
+
+    clone
+}
+
+/// Ensures that the command's output is not localized.
+pub fn c_locale(command: &mut Command) {
+    command.env("LANG", "C").env("LC_ALL", "C");
+}
+
+/// Parses a size printed by apt, such as `1,234 kB` or `12.3 MB`, into bytes.
+pub fn parse_size(number: &str, unit: &str) -> Option<u64> {
+    let number = number.replace(',', "").parse::<f64>().ok()?;
+    let multiplier = match unit {
+        "B" => 1,
+        "kB" => 1_000,
+        "MB" => 1_000_000,
+        "GB" => 1_000_000_000,
+        _ => return None,
+    };
+
+    Some((number * multiplier as f64) as u64)
+}
+
 pub async fn spawn_with_stdout(mut command: Command) -> io::Result<(Child, ChildStdout)> {
+    c_locale(&mut command);
     command.stdout(Stdio::piped());
     command.stderr(Stdio::inherit());
     command.spawn().map(|mut child| {
@@ -13,3 +160,130 @@ pub async fn spawn_with_stdout(mut command: Command) -> io::Result<(Child, Child
         (child, stdout)
     })
 }
+
+/// Spawns the command, interleaving the lines of its stdout and stderr as they are read.
+pub async fn spawn_with_output(
+    mut command: Command,
+) -> io::Result<(Child, impl Stream<Item = (OutputStream, String)> + Send)> {
+    c_locale(&mut command);
+    command.stdout(Stdio::piped());
+    command.stderr(Stdio::piped());
+
+    let mut child = command.spawn()?;
+    let stdout = child.stdout.take().unwrap();
+    let stderr = child.stderr.take().unwrap();
+
+    let stdout = lossy_lines(stdout).map(|line| (OutputStream::Stdout, line));
+    let stderr = lossy_lines(stderr).map(|line| (OutputStream::Stderr, line));
+
+    Ok((child, stream::select(stdout, stderr)))
+}
+
+/// A directory which only its owner may enter, removed with its contents when dropped.
+#[derive(Debug)]
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// Creates a directory named `prefix` and a random suffix in the temporary directory,
+    /// with `mkdtemp`, which fails rather than reuse a path that already exists.
+    pub fn new(prefix: &str) -> io::Result<Self> {
+        let template = std::env::temp_dir().join([prefix, "-XXXXXX"].concat());
+        let mut template = CString::new(template.as_os_str().as_bytes())?.into_bytes_with_nul();
+
+        // SAFETY: the template is a nul-terminated buffer which mkdtemp modifies in place.
+        if unsafe { libc::mkdtemp(template.as_mut_ptr().cast()) }.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+
+        template.pop();
+
+        Ok(Self {
+            path: PathBuf::from(OsString::from_vec(template)),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+/// A directory for a test, named after it, which is removed when the test ends.
+#[cfg(test)]
+pub(crate) fn test_dir(name: &str) -> TempDir {
+    TempDir::new(&["apt-cmd-", name].concat()).expect("failed to create a test directory")
+}
+
+/// Whether the command sets `LANG` and `LC_ALL` to `C`.
+#[cfg(test)]
+pub(crate) fn is_c_locale(command: &Command) -> bool {
+    let envs = command.as_std().get_envs().collect::<Vec<_>>();
+    ["LANG", "LC_ALL"]
+        .iter()
+        .all(|var| envs.contains(&(std::ffi::OsStr::new(var), Some(std::ffi::OsStr::new("C")))))
+}
+
+#[cfg(test)]
+mod tests {
+    #[tokio::test]
+    async fn invalid_utf8_does_not_end_output() {
+        let input: &[u8] =
+            b"Setting up foo (1.0) ...\nMaintainer: Andr\xe9\nSetting up bar (2.0) ...\n";
+        let mut lines = super::LineReader::new(input);
+
+        assert_eq!(Some("Setting up foo (1.0) ..."), lines.next_line().await);
+        assert_eq!(Some("Maintainer: Andr\u{FFFD}"), lines.next_line().await);
+        assert_eq!(Some("Setting up bar (2.0) ..."), lines.next_line().await);
+        assert_eq!(None, lines.next_line().await);
+    }
+
+    #[test]
+    fn temp_dir_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = super::TempDir::new("apt-cmd-utils").unwrap();
+        let path = dir.path().to_owned();
+        let other = super::TempDir::new("apt-cmd-utils").unwrap();
+
+        assert_ne!(path, other.path());
+        assert_eq!(
+            0o700,
+            std::fs::metadata(&path).unwrap().permissions().mode() & 0o777
+        );
+
+        drop(dir);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn clone_command() {
+        let mut command = super::command("apt-cache");
+        command
+            .args(["-o", "Debug::NoLocking=1"])
+            .env_remove("DISPLAY");
+
+        let clone = super::clone_command(&command);
+        assert_eq!(command.as_std().get_program(), clone.as_std().get_program());
+        assert!(command.as_std().get_args().eq(clone.as_std().get_args()));
+        assert!(command.as_std().get_envs().eq(clone.as_std().get_envs()));
+    }
+
+    #[test]
+    fn wrappers_use_c_locale() {
+        use super::is_c_locale;
+
+        assert!(is_c_locale(&crate::AptCache::new()));
+        assert!(is_c_locale(&crate::AptGet::new()));
+        assert!(is_c_locale(&crate::AptMark::new()));
+        assert!(is_c_locale(&crate::Dpkg::new()));
+        assert!(is_c_locale(&crate::DpkgQuery::new()));
+        assert!(is_c_locale(&super::command("apt")));
+    }
+}
@@ -0,0 +1,170 @@
+// Copyright 2021-2022 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+//! Parses the `W:` warnings that apt and dpkg print, so that they can be acted upon.
+
+use std::fmt::{self, Display, Formatter};
+
+/// Known classes of warnings which a frontend may want to act upon.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WarningCode {
+    /// A repository key is stored in the deprecated `trusted.gpg` keyring.
+    LegacyKeyring,
+
+    /// A repository is configured in more than one sources file.
+    DuplicateSource,
+
+    /// A repository does not have a Release file.
+    MissingRelease,
+
+    /// A repository is signed with a key which is not installed.
+    MissingPublicKey,
+
+    /// An index or package failed to be fetched.
+    FetchFailed,
+
+    /// Some index files failed to download, and old ones were used instead.
+    IndexFilesFailed,
+
+    /// Firmware may be missing for a kernel module.
+    MissingFirmware,
+}
+
+impl WarningCode {
+    fn classify(message: &str) -> Option<Self> {
+        let code = if message.contains("legacy trusted.gpg keyring") {
+            WarningCode::LegacyKeyring
+        } else if message.contains("is configured multiple times") {
+            WarningCode::DuplicateSource
+        } else if message.contains("does not have a Release file") {
+            WarningCode::MissingRelease
+        } else if message.contains("NO_PUBKEY") {
+            WarningCode::MissingPublicKey
+        } else if message.starts_with("Failed to fetch") {
+            WarningCode::FetchFailed
+        } else if message.starts_with("Some index files failed to download") {
+            WarningCode::IndexFilesFailed
+        } else if message.starts_with("Possible missing firmware") {
+            WarningCode::MissingFirmware
+        } else {
+            return None;
+        };
+
+        Some(code)
+    }
+}
+
+/// A repository whose signing key is not installed.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MissingKey {
+    pub uri: String,
+    pub key_ids: Vec<String>,
+}
+
+/// A warning printed by apt or dpkg.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Warning {
+    pub code: Option<WarningCode>,
+    pub message: String,
+}
+
+impl Warning {
+    pub fn new(message: String) -> Self {
+        Self {
+            code: WarningCode::classify(&message),
+            message,
+        }
+    }
+
+    /// The repository and key IDs of a `NO_PUBKEY` warning, such as:
+    ///
+    /// `GPG error: http://ppa.launchpadcontent.net/example/ppa/ubuntu jammy InRelease: The
+    /// following signatures couldn't be verified because the public key is not available:
+    /// NO_PUBKEY 1234ABCD`
+    pub fn missing_key(&self) -> Option<MissingKey> {
+        if self.code != Some(WarningCode::MissingPublicKey) {
+            return None;
+        }
+
+        let uri = self
+            .message
+            .strip_prefix("GPG error: ")?
+            .split_ascii_whitespace()
+            .next()?;
+
+        let mut key_ids = Vec::new();
+        let mut fields = self.message.split_ascii_whitespace();
+        while let Some(field) = fields.next() {
+            if field == "NO_PUBKEY" {
+                key_ids.extend(fields.next().map(String::from));
+            }
+        }
+
+        Some(MissingKey {
+            uri: uri.to_owned(),
+            key_ids,
+        })
+    }
+
+    /// Parses a line of output, if it is a warning.
+    pub fn parse(line: &str) -> Option<Self> {
+        strip_prefix(line).map(|message| Self::new(message.to_owned()))
+    }
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// The message of a warning line, without its `W: ` or `dpkg: warning: ` prefix.
+pub(crate) fn strip_prefix(line: &str) -> Option<&str> {
+    line.strip_prefix("W: ")
+        .or_else(|| line.strip_prefix("dpkg: warning: "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Warning, WarningCode};
+
+    #[test]
+    fn parse_warnings() {
+        let warning = Warning::parse(
+            "W: http://apt.pop-os.org/proprietary/dists/jammy/InRelease: Key is stored in legacy trusted.gpg keyring (/etc/apt/trusted.gpg), see the DEPRECATION section in apt-key(8) for details.",
+        )
+        .unwrap();
+
+        assert_eq!(Some(WarningCode::LegacyKeyring), warning.code);
+        assert!(warning.message.starts_with("http://apt.pop-os.org/"));
+
+        assert_eq!(
+            Some(Warning {
+                code: None,
+                message: "something unexpected".into(),
+            }),
+            Warning::parse("W: something unexpected")
+        );
+
+        assert_eq!(
+            None,
+            Warning::parse("Hit:1 http://apt.pop-os.org/release jammy InRelease")
+        );
+    }
+
+    #[test]
+    fn missing_key() {
+        let warning = Warning::parse(
+            "W: GPG error: http://ppa.launchpadcontent.net/example/ppa/ubuntu jammy InRelease: The following signatures couldn't be verified because the public key is not available: NO_PUBKEY 1234ABCD5678EF90",
+        )
+        .unwrap();
+
+        assert_eq!(
+            Some(super::MissingKey {
+                uri: "http://ppa.launchpadcontent.net/example/ppa/ubuntu".into(),
+                key_ids: vec!["1234ABCD5678EF90".into()],
+            }),
+            warning.missing_key()
+        );
+    }
+}
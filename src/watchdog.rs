@@ -0,0 +1,169 @@
+// Copyright 2021-2022 System76 <info@system76.com>
+// SPDX-License-Identifier: MPL-2.0
+
+//! Detects maintainer scripts and triggers which have stopped making progress.
+//!
+//! Nothing is killed: a script may legitimately be slow, as when rebuilding an initramfs
+//! or DKMS modules. The stall is only reported, so that a daemon may alert the user.
+//!
+//! ```no_run
+//! use apt_cmd::watchdog::{watchdog, WatchdogEvent};
+//! use futures::StreamExt;
+//! use std::time::Duration;
+//!
+//! # async fn run() -> std::io::Result<()> {
+//! let (mut child, events) = apt_cmd::AptGet::new().stream_upgrade().await?;
+//! let events = watchdog(events, Duration::from_secs(300));
+//! futures::pin_mut!(events);
+//!
+//! while let Some(event) = events.next().await {
+//!     if let WatchdogEvent::Stalled { package, phase, idle } = event {
+//!         eprintln!("{} has been {} for {:?}", package, phase, idle);
+//!     }
+//! }
+//!
+//! child.wait().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::apt_get::UpgradeEvents;
+use crate::AptUpgradeEvent;
+use async_stream::stream;
+use futures::stream::{Stream, StreamExt};
+use std::fmt::{self, Display, Formatter};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// The phase of a package in which dpkg runs its scripts.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum StallPhase {
+    /// The package is being set up by its `postinst` script.
+    Configure,
+    /// The triggers of the package are being processed.
+    Triggers,
+}
+
+impl Display for StallPhase {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        fmt.write_str(match self {
+            StallPhase::Configure => "configuring",
+            StallPhase::Triggers => "processing triggers",
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum WatchdogEvent {
+    Upgrade(AptUpgradeEvent),
+
+    /// There has been no output for `idle` while `package` was in `phase`.
+    ///
+    /// Sent again each time the timeout elapses, until output resumes.
+    Stalled {
+        package: Box<str>,
+        phase: StallPhase,
+        idle: Duration,
+    },
+}
+
+/// Watches an upgrade from `AptGet::stream_upgrade` for output which stops for longer
+/// than `timeout` while a package is being configured, or its triggers processed.
+pub fn watchdog(mut events: UpgradeEvents, timeout: Duration) -> impl Stream<Item = WatchdogEvent> {
+    stream! {
+        let mut active: Option<(Box<str>, StallPhase)> = None;
+        let mut last_output = Instant::now();
+        let mut deadline = last_output + timeout;
+
+        loop {
+            let event = tokio::select! {
+                event = events.next() => match event {
+                    Some(event) => event,
+                    None => break,
+                },
+                _ = tokio::time::sleep_until(deadline), if active.is_some() => {
+                    deadline += timeout;
+
+                    if let Some((package, phase)) = &active {
+                        yield WatchdogEvent::Stalled {
+                            package: package.clone(),
+                            phase: *phase,
+                            idle: last_output.elapsed(),
+                        };
+                    }
+
+                    continue
+                }
+            };
+
+            last_output = Instant::now();
+            deadline = last_output + timeout;
+
+            match &event {
+                AptUpgradeEvent::SettingUp { package } => {
+                    active = Some((package.clone(), StallPhase::Configure));
+                }
+                AptUpgradeEvent::Processing { package }
+                | AptUpgradeEvent::ProcessingTriggers { package } => {
+                    active = Some((package.clone(), StallPhase::Triggers));
+                }
+                AptUpgradeEvent::Installing { .. }
+                | AptUpgradeEvent::Removing { .. }
+                | AptUpgradeEvent::Unpacking { .. } => active = None,
+                _ => (),
+            }
+
+            yield WatchdogEvent::Upgrade(event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn watchdog_reports_stalled_scripts() {
+        let events = stream! {
+            yield AptUpgradeEvent::SettingUp { package: "dkms".into() };
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            yield AptUpgradeEvent::Unpacking {
+                package: "firefox".into(),
+                version: "2.0".into(),
+                over: "1.0".into(),
+            };
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        };
+
+        let events = watchdog(Box::pin(events), Duration::from_millis(40))
+            .collect::<Vec<_>>()
+            .await;
+
+        let stalls: Vec<_> = events
+            .iter()
+            .filter_map(|event| match event {
+                WatchdogEvent::Stalled {
+                    package,
+                    phase,
+                    idle,
+                } => Some((&**package, *phase, *idle)),
+                _ => None,
+            })
+            .collect();
+
+        // The clock is paused, so it advances only to each timer in turn.
+        assert_eq!(
+            vec![
+                ("dkms", StallPhase::Configure, Duration::from_millis(40)),
+                ("dkms", StallPhase::Configure, Duration::from_millis(80)),
+            ],
+            stalls
+        );
+
+        // Nothing is reported while unpacking.
+        assert!(matches!(
+            events.last(),
+            Some(WatchdogEvent::Upgrade(AptUpgradeEvent::Unpacking { .. }))
+        ));
+    }
+}